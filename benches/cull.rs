@@ -0,0 +1,63 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Times repeated culls of a unique population, the register update being the part that grows
+//! with the population. The incremental update, which removes the culled agents' hashes, is timed
+//! beside the rebuild it replaced, which registered every kept agent again after each cull. Run
+//! with `cargo bench --bench cull`.
+
+extern crate xu;
+
+use std::time::{Duration, Instant};
+use xu::agent::Agent;
+use xu::population::Population;
+use xu::register::Register;
+
+const SIZE: usize = 100_000;
+const ROUNDS: usize = 20;
+
+/// Culls a tenth of the population each round, refilling it between culls, and returns the mean
+/// time of a cull. With rebuild, the population keeps no register of its own and one is rebuilt
+/// from the kept agents after each cull, as before the update was incremental.
+fn time_culls(rebuild: bool) -> Duration {
+    let mut population = Population::new_empty(!rebuild);
+    let mut register = Register::new();
+    let mut next: u64 = 0;
+    let mut culling = Duration::default();
+    for _ in 0..ROUNDS {
+        while population.len() < SIZE {
+            population.insert(next, Agent::from_genes(vec![next]));
+            next += 1;
+        }
+        // Cull a tenth, as a typical operation pipeline does each iteration.
+        let threshold = *population.get_agents().keys().nth(SIZE / 10).unwrap();
+        let start = Instant::now();
+        population.cull_all_below(threshold);
+        if rebuild {
+            register.clear();
+            for agent in population.get_agents().values() {
+                register.insert(agent.get_hash());
+            }
+        }
+        culling += start.elapsed();
+    }
+    culling / ROUNDS as u32
+}
+
+fn main() {
+    println!("{} culls of a tenth of {} agents:", ROUNDS, SIZE);
+    println!("rebuilding the register {:>9.1?} per cull", time_culls(true));
+    println!("incremental update      {:>9.1?} per cull", time_culls(false));
+}
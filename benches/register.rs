@@ -0,0 +1,84 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Times a unique population's register as it fills with agents, then looks up hashes that are
+//! mostly not registered, as when checking new children. Compares the single set with sharded,
+//! threaded and filtered registers. Run with `cargo bench --bench register`.
+//!
+//! Targets, checked after timing, failing the run if missed:
+//! - sharding does not make insertion more than a tenth slower than the single set;
+//! - the filter makes lookups faster than the same shards without it;
+//! - with more than one core, filling the shards on every core is faster than the single set.
+
+extern crate xu;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use xu::register::Register;
+
+const SIZE: u64 = 500_000;
+const LOOKUPS: u64 = 2_000_000;
+const REPEATS: usize = 3;
+
+// Spreads consecutive numbers over the hash space, as genome hashes are.
+fn hash(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// The fastest of the repeats of filling a fresh register and looking up hashes in it.
+fn time(name: &str, make: impl Fn() -> Register) -> (Duration, Duration) {
+    let hashes: Vec<u64> = (0..SIZE).map(hash).collect();
+    let mut best = (Duration::MAX, Duration::MAX);
+    for _ in 0..REPEATS {
+        let mut register = make();
+        let start = Instant::now();
+        assert_eq!(SIZE as usize, register.insert_all(&hashes));
+        let inserting = start.elapsed();
+
+        // One lookup in ten is of a registered hash.
+        let start = Instant::now();
+        let found = (0..LOOKUPS).filter(|i| register.contains(hash(if i % 10 == 0 { i % SIZE } else { SIZE + i }))).count();
+        let looking_up = start.elapsed();
+        assert_eq!(LOOKUPS as usize / 10, found);
+
+        best = (best.0.min(inserting), best.1.min(looking_up));
+    }
+
+    println!("{:<26} insert {:>9.1?}   lookup {:>9.1?}", name, best.0, best.1);
+    best
+}
+
+fn main() {
+    let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+    let single = time("single set", Register::new);
+    let sharded = time("16 shards", || Register::sharded(16));
+    let filtered = time("16 shards with filter", || Register::sharded(16).with_filter(SIZE as usize));
+    let threaded = time(&format!("16 shards on {} threads", cores), || Register::sharded(16).with_threads(cores));
+
+    let mut missed = Vec::new();
+    if sharded.0 > single.0 + single.0 / 10 {
+        missed.push("sharded insertion is more than a tenth slower than the single set");
+    }
+    if filtered.1 >= sharded.1 {
+        missed.push("the filter does not make lookups faster");
+    }
+    if cores > 1 && threaded.0 >= single.0 {
+        missed.push("threaded insertion is not faster than the single set");
+    }
+    for target in &missed {
+        println!("missed target: {}", target);
+    }
+    assert!(missed.is_empty(), "{} of the register targets missed", missed.len());
+}
@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[macro_use]
 extern crate xu;
-extern crate rand;
 
 use self::Gene::{
     MovePointerLeft,
@@ -23,15 +23,13 @@ use self::Gene::{
     CopyValueFromLeft,
     CopyValueFromRight
 };
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
-};
 use std::time::Instant;
 use xu::agent::{Agent};
 
-use xu::manager::create_manager;
-use xu::fitness::ScoreError;
+use xu::analysis::simplify;
+use xu::manager::{create_manager, Goal};
+use xu::fitness::{GeneralScoreProvider, ScoreError};
+use xu::phenotype::{decode, Phenotype};
 
 
 #[derive(Clone, PartialEq, Hash)]
@@ -44,6 +42,16 @@ enum Gene {
     CopyValueFromRight
 }
 
+/// The data after running the genes as instructions on it.
+#[derive(Debug)]
+struct Tape(Vec<u8>);
+
+impl Phenotype<Gene, Vec<u8>> for Tape {
+    fn decode(genes: &[Gene], data: &Vec<u8>) -> Self {
+        Tape(get_processed_data(genes, data))
+    }
+}
+
 pub fn main() {
     let now = Instant::now();
 
@@ -51,7 +59,14 @@ pub fn main() {
 
     let mut manager = create_manager(fitness_function, data.clone());
     manager.set_number_of_genes(30, false);
-    manager.run(9999);
+    manager.run(Goal::AtLeast(9999));
+    if let Some((score, agent)) = manager.get_true_best() {
+        println!("Best: {} {:?}", score, decode::<Tape, _, _>(agent, &data).0);
+
+        // Strip the genes that make no difference to the result.
+        let simplified = simplify(agent, &data, &mut GeneralScoreProvider::new(fitness_function, 0));
+        println!("Simplified from {} to {} genes", agent.get_genes().len(), simplified.get_genes().len());
+    }
     let agents = manager.get_population().get_agents();
 
     println!("Duration: {}", now.elapsed().as_secs() as f64 + now.elapsed().subsec_nanos() as f64 * 1e-9);
@@ -69,11 +84,11 @@ pub fn main() {
             break;
         }
         println!("{}", score_index);
-        println!("{:?}", get_processed_data(agent.get_genes(), &data));
+        println!("{:?}", decode::<Tape, _, _>(agent, &data).0);
     }
 }
 
-fn get_processed_data(genes: &Vec<Gene>, data: &Vec<u8>) -> Vec<u8> {
+fn get_processed_data(genes: &[Gene], data: &Vec<u8>) -> Vec<u8> {
     let mut copy = data.clone();
     let mut pointer = 0;
     for gene in genes {
@@ -133,18 +148,15 @@ fn copy_value_from_right(pointer: &mut usize, data: &mut Vec<u8>) {
     data[*pointer] = data[*pointer+1];
 }
 
-impl Distribution<Gene> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Gene {
-        match rng.gen_range(0, 6) {
-            0 => MovePointerLeft,
-            1 => MovePointerRight,
-            2 => IncreaseValueByOne,
-            3 => DecreaseValueByOne,
-            4 => CopyValueFromLeft,
-            _ => CopyValueFromRight
-        }
-    }
-}
+// Each instruction is equally likely when generating random genes.
+uniform_gene!(Gene {
+    MovePointerLeft,
+    MovePointerRight,
+    IncreaseValueByOne,
+    DecreaseValueByOne,
+    CopyValueFromLeft,
+    CopyValueFromRight
+});
 
 fn score_data(candidate: &Vec<u8>) -> u64 {
     let mut score = 1.0;
@@ -173,6 +185,6 @@ fn score_data(candidate: &Vec<u8>) -> u64 {
 }
 
 fn fitness_function(agent: &Agent<Gene>, data: &Vec<u8>) -> Result<u64, ScoreError> {
-    let processed = get_processed_data(agent.get_genes(), data);
-    Ok(score_data(&processed))
+    let tape: Tape = decode(agent, data);
+    Ok(score_data(&tape.0))
 }
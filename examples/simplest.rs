@@ -15,21 +15,24 @@
 extern crate xu;
 
 use xu::agent::Agent;
-use xu::manager::create_manager;
+use xu::manager::{create_manager, Goal};
 use xu::fitness::ScoreError;
 
 fn main() {
 
     let mut manager = create_manager(fitness_function, 0);
     manager.set_number_of_genes(5, true);
-    manager.run(1250);
+    manager.run(Goal::AtLeast(1250));
+    if let Some((score, agent)) = manager.get_true_best() {
+        println!("Best: {} {:?}", score, agent.get_genes());
+    }
     let agents = manager.get_population().get_agents();
 
     println!("Population: {}", agents.len());
 
     let mut viewing = 10;
     for (score_index, agent) in agents.iter().rev() {
-        println!("Score: {}", score_index);
+        println!("Score index: {}", score_index);
         println!("{:?}", agent.get_genes());
 
         viewing -= 1;
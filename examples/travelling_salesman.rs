@@ -124,6 +124,11 @@ pub fn main() {
     // 50 times over. Again, we need the data and scoring function references as these are used for scoring new agents.
     let population = run_iterations(population, 50, &data, &operations, &mut score_provider);
 
+    // The score keys have noise added, so ask the score provider for the actual best.
+    if let Some((score, agent)) = population.true_best(&mut score_provider, &data) {
+        println!("Best: {} {:?}", score, agent.get_genes());
+    }
+
     let agents = population.get_agents();
 
     println!("Population: {}", agents.len());
@@ -140,7 +145,7 @@ pub fn main() {
         if score_index < &(first_score - 20) {
             break;
         }
-        println!("Score: {}", score_index);
+        println!("Score index: {}", score_index);
         println!("{:?}", agent.get_genes());
     }
 }
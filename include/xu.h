@@ -0,0 +1,54 @@
+/*
+ * Copyright 2019 Brendan Cox
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/* C API for xu, available when the crate is built with the ffi feature. */
+
+#ifndef XU_H
+#define XU_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct XuManager XuManager;
+
+/* Writes the score for the genes and returns 0, or returns non-zero if they could not be scored.
+ * May be called from several threads at once. */
+typedef int (*xu_fitness_fn)(const uint8_t *genes, size_t length, void *user_data, uint64_t *score);
+
+XuManager *xu_manager_new(xu_fitness_fn fitness, void *user_data);
+void xu_manager_free(XuManager *manager);
+
+void xu_manager_set_number_of_genes(XuManager *manager, size_t number, bool strict);
+void xu_manager_set_initial_population_size(XuManager *manager, size_t size);
+void xu_manager_set_max_child_threads(XuManager *manager, uint8_t max_number);
+void xu_manager_set_iterations_per_cycle(XuManager *manager, size_t number);
+
+/* Runs until the fitness of the best agent in the population is at least the goal. */
+void xu_manager_run(XuManager *manager, uint64_t goal);
+
+/* Copies up to capacity genes of the fittest agent into buffer and returns its full gene count. */
+size_t xu_manager_best_genome(XuManager *manager, uint8_t *buffer, size_t capacity, uint64_t *score);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -14,16 +14,182 @@
 
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use super::random;
+use super::fingerprint::{self, Fingerprint, GeneCodec};
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
+/// The bounds the operations, evolution functions and Manager need of a gene type. Implemented
+/// for every type meeting them, so that signatures can say `Gene: GeneBounds` rather than
+/// repeating them.
+pub trait GeneBounds: Clone + Hash + Send + 'static {}
+
+impl <T: Clone + Hash + Send + 'static> GeneBounds for T {}
+
+/// GeneBounds for gene types that can also be compared for equality, as most fieldless enums can.
+pub trait EqGeneBounds: GeneBounds + Eq {}
+
+impl <T: GeneBounds + Eq> EqGeneBounds for T {}
+
+/// Lets a fieldless enum be used as a gene by generating its variants with equal probability, the
+/// most common case of the `Distribution<Gene> for Standard` implementation every gene type needs.
+/// Add `#[macro_use]` to `extern crate xu`, then:
+///
+/// ```ignore
+/// #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+/// enum Move { Left, Right, Stay }
+///
+/// uniform_gene!(Move { Left, Right, Stay });
+/// ```
+#[macro_export]
+macro_rules! uniform_gene {
+    ($gene:ident { $($variant:ident),+ $(,)* }) => {
+        impl $crate::__rand::Distribution<$gene> for $crate::__rand::Standard {
+            fn sample<R: $crate::__rand::Rng + ?Sized>(&self, rng: &mut R) -> $gene {
+                let variants = [$(stringify!($variant)),+].len();
+                let mut index = rng.gen_range(0, variants);
+                $(
+                    if index == 0 {
+                        return $gene::$variant;
+                    }
+                    index -= 1;
+                )+
+                unreachable!()
+            }
+        }
+    };
+}
+
+/// How agents hash their genes. Std, the default, uses the standard library's SipHash, which
+/// resists collisions. Fx is much faster for long genomes, but easy to make collide on purpose, so
+/// suits trusted genes. Neither hash is guaranteed to stay the same between Rust versions; use a
+/// fingerprint for hashes that are saved. Agents are given a hasher by the population they join,
+/// see Population::set_genome_hasher, and agents bred from them keep it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GenomeHasher {
+    #[default]
+    Std,
+    Fx
+}
+
+impl GenomeHasher {
+    /// Hashes a value with this strategy.
+    pub fn hash<T: Hash + ?Sized>(&self, value: &T) -> u64 {
+        match self {
+            GenomeHasher::Std => {
+                let mut s = DefaultHasher::new();
+                value.hash(&mut s);
+                s.finish()
+            },
+            GenomeHasher::Fx => {
+                let mut s = FxHasher::default();
+                value.hash(&mut s);
+                s.finish()
+            }
+        }
+    }
+}
+
+/// The multiply-rotate hash rustc uses internally, hashing a word at a time. Its output depends
+/// only on the bytes and integers written to it.
+#[derive(Clone, Debug, Default)]
+pub struct FxHasher {
+    hash: u64
+}
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Records which of two parents each gene of a child came from, one bit per locus.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    mask: Vec<u64>,
+    len: usize
+}
+
+impl Provenance {
+    /// Provenance of a child of the given length whose genes from the split point on came from the
+    /// second parent.
+    fn split(len: usize, point: usize) -> Self {
+        let mut mask = vec![0u64; len.div_ceil(64)];
+        for locus in point..len {
+            mask[locus / 64] |= 1 << (locus % 64);
+        }
+        Self { mask, len }
+    }
+
+    /// Whether the gene at the locus came from the second parent. Loci beyond the child's genes
+    /// are reported as from the first.
+    pub fn from_second_parent(&self, locus: usize) -> bool {
+        locus < self.len && self.mask[locus / 64] & (1 << (locus % 64)) != 0
+    }
+
+    /// The number of genes that came from the second parent.
+    pub fn second_parent_count(&self) -> usize {
+        self.mask.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// The bitmask, lowest locus in the lowest bit of the first word.
+    pub fn get_mask(&self) -> &Vec<u64> {
+        &self.mask
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
 /// Carries a set of genes.
 #[derive(Clone)]
 pub struct Agent <Gene> {
     genes: Vec<Gene>,
-    hash: u64
+    hash: u64,
+    hasher: GenomeHasher,
+    birth: u64,
+    provenance: Option<Provenance>
 }
 
 impl <Gene> Agent<Gene> {
@@ -35,13 +201,14 @@ impl <Gene> Agent<Gene> {
     Gene: Hash
     {
         let genes = Vec::new();
-        let mut s = DefaultHasher::new();
-        genes.hash(&mut s);
-        let hash = s.finish();
+        let hash = GenomeHasher::Std.hash(&genes);
 
         Self {
             genes: genes,
-            hash: hash
+            hash: hash,
+            hasher: GenomeHasher::Std,
+            birth: 0,
+            provenance: None
         }
     }
 
@@ -53,27 +220,80 @@ impl <Gene> Agent<Gene> {
     {
         let mut genes = Vec::with_capacity(number_of_genes);
         for _ in 0..number_of_genes {
-            genes.push(rand::random());
+            genes.push(random::random());
         }
 
-        let mut s = DefaultHasher::new();
-        genes.hash(&mut s);
-        let hash = s.finish();
+        let hash = GenomeHasher::Std.hash(&genes);
 
         Self {
             genes: genes,
-            hash: hash
+            hash: hash,
+            hasher: GenomeHasher::Std,
+            birth: 0,
+            provenance: None
         }
     }
 
+    /// Creates an agent with the given genes, such as genes restored from external storage.
+    pub fn from_genes(genes: Vec<Gene>) -> Self
+    where
+    Gene: Hash
+    {
+        let hash = GenomeHasher::Std.hash(&genes);
+
+        Self { genes, hash, hasher: GenomeHasher::Std, birth: 0, provenance: None }
+    }
+
     pub fn get_genes(&self) -> &Vec<Gene> {
         return &self.genes;
     }
 
+    /// Takes the genes, such as to reuse their buffer.
+    pub fn into_genes(self) -> Vec<Gene> {
+        self.genes
+    }
+
+    /// Copies the agent, putting the genes in the given buffer rather than a new one.
+    pub fn copy_into(&self, mut buffer: Vec<Gene>) -> Self where Gene: Clone {
+        buffer.clear();
+        buffer.extend_from_slice(&self.genes);
+        Self {
+            genes: buffer,
+            hash: self.hash,
+            hasher: self.hasher,
+            birth: self.birth,
+            provenance: self.provenance.clone()
+        }
+    }
+
     /// Chooses a random point on genes of self and uses that as its crossover point.
     /// Maintains the number of genes of self if the other has a different gene length.
     pub fn crossover_some_genes(&mut self, other: &Self) where Gene: Clone + Hash {
-        let mut rng = rand::thread_rng();
+        self.cross_at_random_point(other, 1);
+    }
+
+    /// Crosses over as crossover_some_genes, recording which of the two each gene came from.
+    /// See get_provenance.
+    pub fn crossover_some_genes_with_provenance(&mut self, other: &Self) where Gene: Clone + Hash {
+        self.crossover_records_with_provenance(other, 1);
+    }
+
+    /// Crosses over as crossover_some_genes, for genes that encode records of record_size genes
+    /// each. The crossover point falls between records, so no record is split.
+    pub fn crossover_records(&mut self, other: &Self, record_size: usize) where Gene: Clone + Hash {
+        self.cross_at_random_point(other, record_size);
+    }
+
+    /// Crosses over as crossover_records, recording which of the two each gene came from.
+    pub fn crossover_records_with_provenance(&mut self, other: &Self, record_size: usize) where Gene: Clone + Hash {
+        let point = self.cross_at_random_point(other, record_size);
+        self.provenance = Some(Provenance::split(self.genes.len(), point));
+    }
+
+    /// Crosses over as crossover_records, returning the position of the first gene taken from
+    /// the other.
+    fn cross_at_random_point(&mut self, other: &Self, record_size: usize) -> usize where Gene: Clone + Hash {
+        let mut rng = random::rng();
         
         let self_len = self.genes.len();
         let other_len = other.genes.len();
@@ -83,7 +303,8 @@ impl <Gene> Agent<Gene> {
             gene_count = other_len;
         }
 
-        let crossover_point = rng.gen_range(0, gene_count);
+        let record_size = record_size.max(1);
+        let crossover_point = rng.gen_range(0, (gene_count / record_size).max(1)) * record_size;
 
         let mut self_crossover_point = crossover_point;
         let mut other_crossover_point = crossover_point;
@@ -95,13 +316,11 @@ impl <Gene> Agent<Gene> {
         }
 
         self.genes.truncate(self_crossover_point);
-        let mut other_genes = other.get_genes().clone();
-        other_genes.drain(..other_crossover_point);
-        self.genes.append(&mut other_genes);
+        self.genes.extend_from_slice(&other.genes[other_crossover_point..]);
 
-        let mut s = DefaultHasher::new();
-        self.genes.hash(&mut s);
-        self.hash = s.finish();
+        self.hash = self.hasher.hash(&self.genes);
+        self.provenance = None;
+        self_crossover_point
     }
 
     pub fn mutate(&mut self)
@@ -109,28 +328,172 @@ impl <Gene> Agent<Gene> {
     Standard: Distribution<Gene>,
     Gene: Hash
     {
-        let mut rng = rand::thread_rng();
+        self.mutate_records(1);
+    }
 
-        let gene_count = self.genes.len();
+    /// Mutates as mutate, for genes that encode records of record_size genes each. Whole records
+    /// are removed and replaced by records of random genes, so no record is split. Genes after the
+    /// last whole record are left as they are.
+    pub fn mutate_records(&mut self, record_size: usize)
+    where
+    Standard: Distribution<Gene>,
+    Gene: Hash
+    {
+        let mut rng = random::rng();
+
+        let record_size = record_size.max(1);
+        let record_count = self.genes.len() / record_size;
+        if record_count == 0 {
+            return;
+        }
 
         for _ in 0..5 {
-           self.genes.remove(rng.gen_range(0, gene_count));
-           self.genes.insert(rng.gen_range(0, gene_count - 1), rand::random());
+            let removed = rng.gen_range(0, record_count) * record_size;
+            self.genes.drain(removed..removed + record_size);
+            let inserted = rng.gen_range(0, (record_count - 1).max(1)) * record_size;
+            let record: Vec<Gene> = (0..record_size).map(|_| random::random()).collect();
+            self.genes.splice(inserted..inserted, record);
         }
 
-        let mut s = DefaultHasher::new();
-        self.genes.hash(&mut s);
-        self.hash = s.finish();
+        self.hash = self.hasher.hash(&self.genes);
+        self.provenance = None;
+    }
+
+    /// Replaces the genes at five random positions, as mutate does, with genes from the proposal,
+    /// which is given the current genes and the position to replace.
+    pub fn mutate_with<F>(&mut self, mut propose: F)
+    where
+    F: FnMut(&[Gene], usize) -> Gene,
+    Gene: Hash
+    {
+        if self.genes.is_empty() {
+            return;
+        }
+        let mut rng = random::rng();
+        for _ in 0..5 {
+            let position = rng.gen_range(0, self.genes.len());
+            self.genes[position] = propose(&self.genes, position);
+        }
+
+        self.hash = self.hasher.hash(&self.genes);
+        self.provenance = None;
+    }
+
+    /// Changes the number of genes by one of: appending random genes, duplicating a segment in place
+    /// or deleting a segment, keeping the length between min and max inclusive. Segments are up
+    /// to a quarter of the genes long. Does nothing if the length cannot change within the bounds.
+    pub fn mutate_length(&mut self, min: usize, max: usize)
+    where
+    Standard: Distribution<Gene>,
+    Gene: Clone + Hash
+    {
+        let mut rng = random::rng();
+        let len = self.genes.len();
+        let segment = (len / 4).max(1);
+        let grow = max.saturating_sub(len).min(segment);
+        let shrink = len.saturating_sub(min).min(segment).min(len);
+
+        let mut choices = Vec::new();
+        if grow > 0 {
+            choices.push(0);
+            if len > 0 {
+                choices.push(1);
+            }
+        }
+        if shrink > 0 {
+            choices.push(2);
+        }
+        if choices.is_empty() {
+            return;
+        }
+
+        match choices[rng.gen_range(0, choices.len())] {
+            0 => {
+                let count = rng.gen_range(1, grow + 1);
+                self.genes.extend((0..count).map(|_| random::random::<Gene>()));
+            },
+            1 => {
+                let count = rng.gen_range(1, grow.min(len) + 1);
+                let start = rng.gen_range(0, len - count + 1);
+                let copy = self.genes[start..start + count].to_vec();
+                self.genes.splice(start..start, copy);
+            },
+            _ => {
+                let count = rng.gen_range(1, shrink + 1);
+                let start = rng.gen_range(0, len - count + 1);
+                self.genes.drain(start..start + count);
+            }
+        }
+
+        self.hash = self.hasher.hash(&self.genes);
+        self.provenance = None;
+    }
+
+    /// Replaces the gene at the given position.
+    pub fn set_gene(&mut self, position: usize, gene: Gene) where Gene: Hash {
+        self.genes[position] = gene;
+
+        self.hash = self.hasher.hash(&self.genes);
+        self.provenance = None;
     }
 
     pub fn has_same_genes(&self, other: &Self) -> bool {
         self.hash == other.hash
     }
 
+    /// Gets the generation of the population in which this agent was last inserted.
+    pub fn get_birth(&self) -> u64 {
+        self.birth
+    }
+
+    pub(crate) fn set_birth(&mut self, generation: u64) {
+        self.birth = generation;
+    }
+
+    /// Gets which parent each gene came from, if this agent was bred by crossover_with_provenance
+    /// and not mutated since.
+    pub fn get_provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
     /// Gets a hash representing this agents gene sequence.
     pub fn get_hash(&self) -> u64 {
         self.hash
     }
+
+    pub fn get_hasher(&self) -> GenomeHasher {
+        self.hasher
+    }
+
+    /// Hashes the genes with the hasher from now on, such as when joining a population that uses
+    /// it. Agents bred from this one keep the hasher.
+    pub fn set_hasher(&mut self, hasher: GenomeHasher) where Gene: Hash {
+        if self.hasher != hasher {
+            self.hasher = hasher;
+            self.hash = hasher.hash(&self.genes);
+        }
+    }
+
+    /// The hash the genes would have under the hasher, the agent's own hash if it already uses it.
+    pub(crate) fn hash_with(&self, hasher: GenomeHasher) -> u64 where Gene: Hash {
+        if self.hasher == hasher {
+            self.hash
+        } else {
+            hasher.hash(&self.genes)
+        }
+    }
+
+    /// Takes the hasher along with the hash it gives the genes, as found by hash_with.
+    pub(crate) fn adopt_hash(&mut self, hasher: GenomeHasher, hash: u64) {
+        self.hasher = hasher;
+        self.hash = hash;
+    }
+
+    /// Gets a fingerprint of this agents gene sequence that is stable across runs, machines and
+    /// Rust versions, unlike get_hash. Use this when storing or comparing agents externally.
+    pub fn fingerprint<C: GeneCodec<Gene>>(&self, codec: &C) -> Fingerprint {
+        fingerprint::fingerprint(&self.genes, codec)
+    }
 }
 
 pub fn crossover <Gene> (parent1: &Agent<Gene>, parent2: &Agent<Gene>) -> Agent<Gene> 
@@ -142,9 +505,130 @@ where Gene: Clone + Hash {
     return child;
 }
 
+/// Crosses over as crossover, recording in the child's provenance which parent each gene came from.
+pub fn crossover_with_provenance <Gene> (parent1: &Agent<Gene>, parent2: &Agent<Gene>) -> Agent<Gene>
+where Gene: Clone + Hash {
+    let mut child = parent1.clone();
+    child.crossover_some_genes_with_provenance(parent2);
+    child
+}
+
+/// Creates a child where each gene is chosen by a vote of the parents at that position, each
+/// parent's vote counting for its weight. Genes are compared by their hash. Positions beyond the
+/// length of some parents are voted on by the parents that have them.
+/// The child has the length of the longest parent and the first parent's hasher. Returns None if
+/// there are no parents.
+pub fn majority_crossover <Gene> (parents: &[(f64, &Agent<Gene>)]) -> Option<Agent<Gene>>
+where Gene: Clone + Hash {
+    let length = parents.iter().map(|(_, parent)| parent.genes.len()).max()?;
+    let mut genes = Vec::with_capacity(length);
+    for position in 0..length {
+        // (gene hash, total weight, gene), in order of first appearance so ties go to earlier parents.
+        let mut votes: Vec<(u64, f64, &Gene)> = Vec::new();
+        for (weight, parent) in parents {
+            if let Some(gene) = parent.genes.get(position) {
+                let gene_hash = GenomeHasher::Std.hash(&gene);
+                match votes.iter_mut().find(|(hash, _, _)| *hash == gene_hash) {
+                    Some(vote) => vote.1 += weight,
+                    None => votes.push((gene_hash, *weight, gene))
+                }
+            }
+        }
+
+        let mut winner = &votes[0];
+        for vote in &votes[1..] {
+            if vote.1 > winner.1 {
+                winner = vote;
+            }
+        }
+        genes.push(winner.2.clone());
+    }
+
+    let mut child = Agent::from_genes(genes);
+    child.set_hasher(parents[0].1.hasher);
+    Some(child)
+}
+
+/// Creates a child from contiguous segments of the parents: the genes are split at random points
+/// into as many segments as there are parents, and each segment is taken from one parent in turn.
+/// The child has the length of the first parent; where a parent is too short for its segment,
+/// the first parent's genes are used. The child keeps the first parent's hasher. Returns None if
+/// there are no parents.
+pub fn segment_crossover <Gene> (parents: &[&Agent<Gene>]) -> Option<Agent<Gene>>
+where Gene: Clone + Hash {
+    let first = parents.first()?;
+    let length = first.genes.len();
+    let mut rng = random::rng();
+    let mut cut_points: Vec<usize> = (1..parents.len()).map(|_| rng.gen_range(0, length + 1)).collect();
+    cut_points.sort();
+    cut_points.push(length);
+
+    let mut genes = Vec::with_capacity(length);
+    let mut segment = 0;
+    for position in 0..length {
+        while position >= cut_points[segment] {
+            segment += 1;
+        }
+        let gene = parents[segment].genes.get(position).unwrap_or(&first.genes[position]);
+        genes.push(gene.clone());
+    }
+
+    let mut child = Agent::from_genes(genes);
+    child.set_hasher(first.hasher);
+    Some(child)
+}
+
+/// Lists the positions where the genes of the two agents differ, with the gene from each.
+/// Only positions present in both agents are compared.
+pub fn diff <'a, Gene> (a: &'a Agent<Gene>, b: &'a Agent<Gene>) -> Vec<(usize, &'a Gene, &'a Gene)>
+where Gene: PartialEq {
+    a.genes.iter().zip(b.genes.iter())
+        .enumerate()
+        .filter(|(_, (gene_a, gene_b))| gene_a != gene_b)
+        .map(|(position, (gene_a, gene_b))| (position, gene_a, gene_b))
+        .collect()
+}
+
+/// The number of positions at which the genes differ. Each gene one agent has beyond
+/// the length of the other also counts as a difference.
+pub fn hamming_distance <Gene> (a: &Agent<Gene>, b: &Agent<Gene>) -> usize
+where Gene: PartialEq {
+    let length_difference = if a.genes.len() > b.genes.len() {
+        a.genes.len() - b.genes.len()
+    } else {
+        b.genes.len() - a.genes.len()
+    };
+
+    diff(a, b).len() + length_difference
+}
+
+/// A 64-bit SimHash of the genes and their positions. Agents differing in a few genes have
+/// SimHashes differing in few bits, so the number of differing bits estimates how similar long
+/// genomes are without comparing them gene by gene.
+pub fn simhash <Gene> (agent: &Agent<Gene>) -> u64
+where Gene: Hash {
+    let mut weights = [0i64; 64];
+    for feature in agent.genes.iter().enumerate() {
+        let hash = GenomeHasher::Std.hash(&feature);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights.iter().enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |simhash, (bit, _)| simhash | 1 << bit)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::collections::HashSet;
 
     #[test]
     fn new_no_genes() {
@@ -171,6 +655,50 @@ mod tests {
         assert_eq!(s.finish(), agent.get_hash());
     }
 
+    #[test]
+    fn from_genes_matches_fingerprint() {
+        let agent: Agent<u8> = Agent::with_genes(4);
+        let restored = Agent::from_genes(agent.get_genes().clone());
+
+        assert!(agent.has_same_genes(&restored));
+        assert_eq!(agent.fingerprint(&PrimitiveCodec), restored.fingerprint(&PrimitiveCodec));
+    }
+
+    #[test]
+    fn genome_hashers_hash_as_configured() {
+        assert_eq!(5u64.wrapping_mul(0x517c_c1b7_2722_0a95), GenomeHasher::Fx.hash(&5u64));
+
+        let genes = vec![1u8, 2, 3];
+        let mut s = DefaultHasher::new();
+        genes.hash(&mut s);
+        assert_eq!(s.finish(), GenomeHasher::Std.hash(&genes));
+
+        assert_ne!(GenomeHasher::Fx.hash(&genes), GenomeHasher::Fx.hash(&vec![1u8, 2, 4]));
+    }
+
+    #[test]
+    fn bred_agents_keep_the_hasher() {
+        let mut agent = Agent::from_genes(vec![1u8, 2, 3, 4]);
+        agent.set_hasher(GenomeHasher::Fx);
+        assert_eq!(GenomeHasher::Fx.hash(agent.get_genes()), agent.get_hash());
+
+        let mut child = agent.clone();
+        child.mutate();
+        child.crossover_some_genes(&Agent::from_genes(vec![5u8, 6, 7, 8]));
+        assert_eq!(GenomeHasher::Fx, child.get_hasher());
+        assert_eq!(GenomeHasher::Fx.hash(child.get_genes()), child.get_hash());
+
+        let other = Agent::from_genes(vec![5u8, 6, 7, 8]);
+        let children = vec![
+            majority_crossover(&[(1.0, &agent), (1.0, &other)]).unwrap(),
+            segment_crossover(&[&agent, &other]).unwrap()
+        ];
+        for child in children {
+            assert_eq!(GenomeHasher::Fx, child.get_hasher());
+            assert_eq!(GenomeHasher::Fx.hash(child.get_genes()), child.get_hash());
+        }
+    }
+
     #[test]
     fn mutate() {
         let mut agent: Agent<u8> = Agent::with_genes(2);
@@ -187,6 +715,56 @@ mod tests {
         assert_eq!(s.finish(), agent.get_hash());
     }
 
+    #[test]
+    fn records_are_not_split() {
+        let one = Agent::from_genes((0..16u8).map(|gene| gene / 2).collect());
+        let two = Agent::from_genes((0..16u8).map(|gene| 100 + gene / 2).collect());
+        for _ in 0..50 {
+            let mut child = one.clone();
+            child.crossover_records(&two, 2);
+            assert_eq!(16, child.get_genes().len());
+            assert!(child.get_genes().chunks(2).all(|record| record[0] == record[1]));
+        }
+
+        let mut agent = Agent::from_genes((0..24u8).collect::<Vec<u8>>());
+        agent.mutate_records(3);
+        let genes = agent.get_genes();
+        assert_eq!(24, genes.len());
+        let intact = genes.chunks(3).filter(|record| record[0] < 24 && record[0] % 3 == 0 && record[1] == record[0] + 1 && record[2] == record[0] + 2).count();
+        assert!(intact >= 3);
+        assert_eq!(Agent::from_genes(genes.clone()).get_hash(), agent.get_hash());
+    }
+
+    #[test]
+    fn mutate_with_uses_proposal() {
+        let mut agent = Agent::from_genes(vec![1u8, 2, 3, 4]);
+        agent.mutate_with(|genes, position| genes[position] + 10);
+        assert!(agent.get_genes().iter().any(|gene| *gene >= 10));
+        assert_eq!(Agent::from_genes(agent.get_genes().clone()).get_hash(), agent.get_hash());
+    }
+
+    #[test]
+    fn mutate_length_stays_in_bounds() {
+        let mut agent: Agent<u8> = Agent::with_genes(8);
+        let mut lengths = HashSet::new();
+        for _ in 0..200 {
+            agent.mutate_length(4, 12);
+            let genes = agent.get_genes();
+            assert!(genes.len() >= 4 && genes.len() <= 12);
+            lengths.insert(genes.len());
+
+            let mut s = DefaultHasher::new();
+            genes.hash(&mut s);
+            assert_eq!(s.finish(), agent.get_hash());
+        }
+        assert!(lengths.len() > 1);
+
+        let mut fixed: Agent<u8> = Agent::with_genes(3);
+        let genes = fixed.get_genes().clone();
+        fixed.mutate_length(3, 3);
+        assert_eq!(&genes, fixed.get_genes());
+    }
+
     #[test]
     fn crossover_some_genes_same_length_other() {
         let mut agent: Agent<u8> = Agent::with_genes(6);
@@ -238,6 +816,25 @@ mod tests {
         assert_eq!(s.finish(), agent.get_hash());
     }
 
+    #[test]
+    fn diff_lists_differing_positions() {
+        let a = Agent::from_genes(vec![1u8, 2, 3, 4]);
+        let b = Agent::from_genes(vec![1u8, 5, 3, 6, 7]);
+
+        assert_eq!(vec![(1, &2, &5), (3, &4, &6)], diff(&a, &b));
+        assert!(diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn hamming_distance_counts_length_difference() {
+        let a = Agent::from_genes(vec![1u8, 2, 3, 4]);
+        let b = Agent::from_genes(vec![1u8, 5, 3, 6, 7]);
+
+        assert_eq!(3, hamming_distance(&a, &b));
+        assert_eq!(3, hamming_distance(&b, &a));
+        assert_eq!(0, hamming_distance(&a, &a));
+    }
+
     #[test]
     fn crossover_parents() {
         let parent_one: Agent<u8> = Agent::with_genes(6);
@@ -254,4 +851,90 @@ mod tests {
         genes.hash(&mut s);
         assert_eq!(s.finish(), child.get_hash());
     }
-}
\ No newline at end of file
+
+    #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+    enum Move { Left, Right, Stay }
+
+    uniform_gene!(Move { Left, Right, Stay });
+
+    fn is_eq_gene<Gene: EqGeneBounds>(_agent: &Agent<Gene>) -> bool {
+        true
+    }
+
+    #[test]
+    fn uniform_gene_generates_every_variant() {
+        let agent: Agent<Move> = Agent::with_genes(300);
+        for variant in &[Move::Left, Move::Right, Move::Stay] {
+            assert!(agent.get_genes().contains(variant));
+        }
+        assert!(is_eq_gene(&agent));
+    }
+
+    #[test]
+    fn provenance_marks_genes_from_second_parent() {
+        let one = Agent::from_genes(vec![1u8; 70]);
+        let two = Agent::from_genes(vec![2u8; 70]);
+        let mut child = crossover_with_provenance(&one, &two);
+        let provenance = child.get_provenance().unwrap().clone();
+        assert_eq!(70, provenance.len());
+        for (locus, gene) in child.get_genes().iter().enumerate() {
+            assert_eq!(*gene == 2, provenance.from_second_parent(locus));
+        }
+        assert_eq!(child.get_genes().iter().filter(|gene| **gene == 2).count(), provenance.second_parent_count());
+
+        assert!(crossover(&one, &two).get_provenance().is_none());
+        child.set_gene(0, 3);
+        assert!(child.get_provenance().is_none());
+    }
+
+    #[test]
+    fn majority_crossover_follows_weighted_vote() {
+        let one = Agent::from_genes(vec![1u8, 1, 1]);
+        let two = Agent::from_genes(vec![2u8, 2, 1, 4]);
+        let three = Agent::from_genes(vec![2u8, 3]);
+
+        let child = majority_crossover(&[(1.0, &one), (1.0, &two), (1.0, &three)]).unwrap();
+        assert_eq!(&vec![2u8, 1, 1, 4], child.get_genes());
+
+        let child = majority_crossover(&[(5.0, &one), (1.0, &two), (1.0, &three)]).unwrap();
+        assert_eq!(&vec![1u8, 1, 1, 4], child.get_genes());
+
+        let none: Option<Agent<u8>> = majority_crossover(&[]);
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn segment_crossover_takes_genes_from_parents_in_order() {
+        let one = Agent::from_genes(vec![1u8; 20]);
+        let two = Agent::from_genes(vec![2u8; 20]);
+        let three = Agent::from_genes(vec![3u8; 10]);
+
+        for _ in 0..20 {
+            let child = segment_crossover(&[&one, &two, &three]).unwrap();
+            let genes = child.get_genes();
+            assert_eq!(20, genes.len());
+            // Parent order is kept, except where the short third parent falls back to the first.
+            let mut highest = 1;
+            for (position, gene) in genes.iter().enumerate() {
+                if position < 10 {
+                    assert!(*gene >= highest);
+                    highest = *gene;
+                } else {
+                    assert!(*gene == 1 || *gene == 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn simhash_is_close_for_similar_genes() {
+        let genes: Vec<u16> = (0..200).collect();
+        let mut similar = genes.clone();
+        similar[10] = 999;
+        let different: Vec<u16> = (500..700).collect();
+
+        let base = simhash(&Agent::from_genes(genes));
+        assert!((base ^ simhash(&Agent::from_genes(similar))).count_ones() <= 8);
+        assert!((base ^ simhash(&Agent::from_genes(different))).count_ones() > 8);
+    }
+}
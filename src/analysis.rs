@@ -0,0 +1,183 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tools for understanding and tidying evolved agents once a run has finished.
+//!
+//! Everything here scores agents through a score provider, so genomes it has already seen come
+//! from its cache rather than the fitness function.
+
+use super::random;
+use super::agent::Agent;
+use super::fitness::{Score, ScoreProvider};
+use rand::distributions::{Distribution, Standard};
+use std::fmt;
+use std::hash::Hash;
+
+/// How much the score depends on the gene at one position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneSensitivity {
+    pub position: usize,
+    /// The mean fall in true score when the gene is replaced. Negative if replacing it tends to help.
+    pub mean_drop: f64,
+    /// The largest fall in true score seen, or zero if no replacement lowered it.
+    pub worst_drop: Score,
+    /// The number of replacements that could be scored.
+    pub samples: usize
+}
+
+/// The sensitivity of each position of an agent, most sensitive first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensitivityReport {
+    pub base_score: Score,
+    pub positions: Vec<GeneSensitivity>
+}
+
+impl fmt::Display for SensitivityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "base score: {}", self.base_score)?;
+        writeln!(f, "position\tmean drop\tworst drop\tsamples")?;
+        for sensitivity in &self.positions {
+            writeln!(f, "{}\t{:.2}\t{}\t{}", sensitivity.position, sensitivity.mean_drop, sensitivity.worst_drop, sensitivity.samples)?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimates how much the agent's true score depends on each of its genes by replacing each gene
+/// in turn with random genes and rescoring. Replacements identical to the original gene are
+/// skipped. Returns None if the agent itself cannot be scored.
+pub fn sensitivity<Gene, Data, SP>(agent: &Agent<Gene>, samples: usize, data: &Data, score_provider: &mut SP) -> Option<SensitivityReport>
+where
+Standard: Distribution<Gene>,
+Gene: Clone + Hash,
+SP: ScoreProvider<Gene, Data> + ?Sized
+{
+    let base_score = rescore(agent.clone(), data, score_provider)?;
+
+    let mut positions: Vec<GeneSensitivity> = (0..agent.get_genes().len())
+        .map(|position| {
+            let drops: Vec<i64> = (0..samples)
+                .filter_map(|_| {
+                    let mut perturbed = agent.clone();
+                    perturbed.set_gene(position, random::random());
+                    if perturbed.has_same_genes(agent) {
+                        return None;
+                    }
+                    rescore(perturbed, data, score_provider).map(|score| base_score as i64 - score as i64)
+                })
+                .collect();
+
+            GeneSensitivity {
+                position,
+                mean_drop: if drops.is_empty() { 0.0 } else { drops.iter().sum::<i64>() as f64 / drops.len() as f64 },
+                worst_drop: drops.iter().cloned().max().unwrap_or(0).max(0) as Score,
+                samples: drops.len()
+            }
+        })
+        .collect();
+    positions.sort_by(|a, b| b.mean_drop.partial_cmp(&a.mean_drop).unwrap().then(a.position.cmp(&b.position)));
+
+    Some(SensitivityReport { base_score, positions })
+}
+
+/// Greedily removes genes whose removal does not lower the agent's true score, until no single
+/// gene can be removed, giving a smaller genome that scores at least as well. Useful for
+/// variable-length and program-like genomes, which tend to collect genes with no effect.
+/// At least one gene is kept. Returns the agent unchanged if it cannot be scored.
+pub fn simplify<Gene, Data, SP>(agent: &Agent<Gene>, data: &Data, score_provider: &mut SP) -> Agent<Gene>
+where
+Gene: Clone + Hash,
+SP: ScoreProvider<Gene, Data> + ?Sized
+{
+    let mut best = agent.clone();
+    let mut best_score = match rescore(agent.clone(), data, score_provider) {
+        Some(score) => score,
+        None => return best
+    };
+
+    let mut removed = true;
+    while removed {
+        removed = false;
+        // Working from the end keeps earlier positions stable as genes are removed.
+        let mut position = best.get_genes().len();
+        while position > 0 && best.get_genes().len() > 1 {
+            position -= 1;
+            let mut genes = best.get_genes().clone();
+            genes.remove(position);
+            let candidate = Agent::from_genes(genes);
+            if let Some(score) = rescore(candidate.clone(), data, score_provider) {
+                if score >= best_score {
+                    best = candidate;
+                    best_score = score;
+                    removed = true;
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// Scores the agent through the score provider's cache, returning its true score.
+fn rescore<Gene, Data, SP>(agent: Agent<Gene>, data: &Data, score_provider: &mut SP) -> Option<Score>
+where
+SP: ScoreProvider<Gene, Data> + ?Sized
+{
+    let agent = score_provider.evaluate_scores(vec![agent], data).ok()?.pop()?;
+    score_provider.true_score(&agent, data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::{GeneralScoreProvider, ScoreError};
+
+    // Only the first gene matters.
+    fn first_gene(agent: &Agent<u8>, _data: &()) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes()[0] as Score)
+    }
+
+    #[test]
+    fn sensitive_positions_rank_first() {
+        let mut score_provider = GeneralScoreProvider::new(first_gene, 0);
+        let agent = Agent::from_genes(vec![255u8, 7, 9]);
+        let report = sensitivity(&agent, 20, &(), &mut score_provider).unwrap();
+
+        assert_eq!(255, report.base_score);
+        assert_eq!(0, report.positions[0].position);
+        assert!(report.positions[0].mean_drop > 0.0 && report.positions[0].worst_drop > 0);
+        for unimportant in &report.positions[1..] {
+            assert_eq!(0.0, unimportant.mean_drop);
+            assert_eq!(0, unimportant.worst_drop);
+        }
+        assert_eq!(5, report.to_string().lines().count());
+    }
+
+    // Counts the ones, less one for each gene after the first five.
+    fn ones(agent: &Agent<u8>, _data: &()) -> Result<Score, ScoreError> {
+        let count = agent.get_genes().iter().filter(|gene| **gene == 1).count() as Score;
+        Ok((count + 5).saturating_sub(agent.get_genes().len().max(5) as Score - 5))
+    }
+
+    #[test]
+    fn simplify_removes_genes_without_effect() {
+        let mut score_provider = GeneralScoreProvider::new(first_gene, 0);
+        let simplified = simplify(&Agent::from_genes(vec![9u8, 1, 2, 3]), &(), &mut score_provider);
+        assert_eq!(&vec![9], simplified.get_genes());
+
+        let mut score_provider = GeneralScoreProvider::new(ones, 0);
+        let simplified = simplify(&Agent::from_genes(vec![0u8, 1, 0, 1, 1, 2, 0]), &(), &mut score_provider);
+        assert_eq!(&vec![1, 1, 1], simplified.get_genes());
+    }
+}
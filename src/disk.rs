@@ -0,0 +1,196 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A population kept on disk, for runs with more agents than fit in memory.
+//!
+//! Only the scores and the position of each agent's record in the file are held in memory. Every
+//! record is the same size, the genes encoded by a `GeneCodec` that always produces the given
+//! number of bytes, so space freed by removed agents is reused. Operations run on an in-memory
+//! `Population`, so load the agents to work on with `load_highest`, and store the results back.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::Score;
+use super::population::Population;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Agents stored in fixed-size records in a file, indexed by score.
+pub struct DiskPopulation <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    file: File,
+    codec: C,
+    record_size: usize,
+    slots: BTreeMap<Score, u64>,
+    free: Vec<u64>,
+    next_slot: u64,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> DiskPopulation <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene>
+{
+    /// Creates the file, replacing any existing one. record_size: the number of bytes the codec
+    /// encodes every agent's genes to.
+    pub fn create<P: AsRef<Path>>(path: P, codec: C, record_size: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        Ok(Self {
+            file,
+            codec,
+            record_size,
+            slots: BTreeMap::new(),
+            free: Vec::new(),
+            next_slot: 0,
+            gene: PhantomData
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The scores of the agents, lowest first.
+    pub fn get_scores(&self) -> Vec<Score> {
+        self.slots.keys().cloned().collect()
+    }
+
+    pub fn contains_score(&self, score: Score) -> bool {
+        self.slots.contains_key(&score)
+    }
+
+    /// Writes the agent under the score, replacing any agent already there. Returns an error if
+    /// the genes do not encode to the record size.
+    pub fn insert(&mut self, score: Score, agent: &Agent<Gene>) -> io::Result<()> {
+        let bytes = fingerprint::encode_genes(agent.get_genes(), &self.codec);
+        if bytes.len() != self.record_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("genes encoded to {} bytes rather than {}", bytes.len(), self.record_size)));
+        }
+
+        let slot = match self.slots.get(&score) {
+            Some(slot) => *slot,
+            None => match self.free.pop() {
+                Some(slot) => slot,
+                None => {
+                    self.next_slot += 1;
+                    self.next_slot - 1
+                }
+            }
+        };
+        self.file.seek(SeekFrom::Start(slot * self.record_size as u64))?;
+        self.file.write_all(&bytes)?;
+        self.slots.insert(score, slot);
+        Ok(())
+    }
+
+    /// Reads the agent with the score, if there is one.
+    pub fn get(&mut self, score: Score) -> io::Result<Option<Agent<Gene>>> {
+        let slot = match self.slots.get(&score) {
+            Some(slot) => *slot,
+            None => return Ok(None)
+        };
+        self.read(slot).map(Some)
+    }
+
+    /// Forgets the agent with the score, freeing its record for reuse. Returns whether there was one.
+    pub fn remove(&mut self, score: Score) -> bool {
+        match self.slots.remove(&score) {
+            Some(slot) => {
+                self.free.push(slot);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Removes every agent scored below the score.
+    pub fn cull_all_below(&mut self, score: Score) {
+        let kept = self.slots.split_off(&score);
+        self.free.extend(self.slots.values());
+        self.slots = kept;
+    }
+
+    /// Reads up to the number of highest scored agents into a population, for running operations.
+    pub fn load_highest(&mut self, number: usize, unique: bool) -> io::Result<Population<Gene>> {
+        let chosen: Vec<(Score, u64)> = self.slots.iter().rev().take(number).map(|(score, slot)| (*score, *slot)).collect();
+        let mut population = Population::new_empty(unique);
+        for (score, slot) in chosen {
+            population.insert(score, self.read(slot)?);
+        }
+        Ok(population)
+    }
+
+    /// Writes every agent of the population, under its score.
+    pub fn store(&mut self, population: &Population<Gene>) -> io::Result<()> {
+        for (score, agent) in population.get_agents() {
+            self.insert(*score, agent)?;
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, slot: u64) -> io::Result<Agent<Gene>> {
+        let mut bytes = vec![0; self.record_size];
+        self.file.seek(SeekFrom::Start(slot * self.record_size as u64))?;
+        self.file.read_exact(&mut bytes)?;
+        let genes = fingerprint::decode_genes(&bytes, &self.codec)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "genes could not be decoded"))?;
+        Ok(Agent::from_genes(genes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn stores_and_reuses_records() {
+        let path = env::temp_dir().join(format!("xu_disk_test_{}.bin", std::process::id()));
+        let mut disk = DiskPopulation::create(&path, PrimitiveCodec, 4).unwrap();
+
+        for score in 0..10u64 {
+            disk.insert(score, &Agent::from_genes(vec![score as u16, 1])).unwrap();
+        }
+        assert!(disk.insert(20, &Agent::from_genes(vec![1u16])).is_err());
+        assert_eq!(&vec![7u16, 1], disk.get(7).unwrap().unwrap().get_genes());
+
+        disk.cull_all_below(5);
+        assert_eq!(5, disk.len());
+        assert!(disk.get(3).unwrap().is_none());
+
+        // Freed records are reused rather than growing the file.
+        disk.insert(50, &Agent::from_genes(vec![50u16, 2])).unwrap();
+        assert!(disk.remove(9));
+        assert_eq!(40, fs::metadata(&path).unwrap().len());
+
+        let population = disk.load_highest(2, false).unwrap();
+        assert_eq!(vec![8, 50], population.get_scores());
+        assert_eq!(&vec![50u16, 2], population.get(50).unwrap().get_genes());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluation of fitness on other machines.
+//!
+//! A `Worker` runs in its own process, listening on a TCP port and scoring the genes sent to it.
+//! The coordinating process uses a `DistributedScoreProvider` in place of a `GeneralScoreProvider`,
+//! which sends agents to the workers and caches the scores they return.
+//!
+//! The protocol is line based text. For each agent the coordinator sends `EVAL <hex>` where `<hex>`
+//! is the genes encoded by the `GeneCodec`, and the worker replies with `SCORE <score>` or `ERROR <details>`.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::{FitnessFunction, InMemoryScoreCache, Score, ScoreCacheStore, ScoreError, ScoreProvider, jitter_score};
+use super::random::ThreadGenerator;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// The number of requests sent to each worker before waiting for replies.
+const REQUESTS_IN_FLIGHT: usize = 32;
+
+/// Scores genes sent from a DistributedScoreProvider.
+pub struct Worker <Gene, Data, C>
+where
+C: GeneCodec<Gene>
+{
+    fitness_function: FitnessFunction<Gene, Data>,
+    data: Data,
+    codec: C
+}
+
+impl <Gene, Data, C> Worker <Gene, Data, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene>
+{
+    pub fn new(fitness_function: FitnessFunction<Gene, Data>, data: Data, codec: C) -> Self {
+        Self {
+            fitness_function,
+            data,
+            codec
+        }
+    }
+
+    /// Handles connections one at a time, forever. Run several worker processes to score in parallel.
+    pub fn serve(&self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            // A coordinator disconnecting should not stop the worker.
+            let _ = self.handle_connection(stream?);
+        }
+        Ok(())
+    }
+
+    /// Answers requests on a single connection until the coordinator closes it.
+    pub fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let reader = BufReader::new(stream);
+        for line in reader.lines() {
+            let response = self.respond(&line?);
+            writer.write_all(response.as_bytes())?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request: &str) -> String {
+        let hex = match request.trim().strip_prefix("EVAL ") {
+            Some(hex) => hex,
+            None => return "ERROR unknown request\n".to_string()
+        };
+        let genes = match fingerprint::from_hex(hex).and_then(|bytes| fingerprint::decode_genes(&bytes, &self.codec)) {
+            Some(genes) => genes,
+            None => return "ERROR genes could not be decoded\n".to_string()
+        };
+        match (self.fitness_function)(&Agent::from_genes(genes), &self.data) {
+            Ok(score) => format!("SCORE {}\n", score),
+            Err(error) => format!("ERROR {}\n", error)
+        }
+    }
+}
+
+/// A ScoreProvider that sends agents to Worker processes for scoring.
+/// Agents are shared between the workers so that each batch is scored in parallel.
+pub struct DistributedScoreProvider <Gene, C>
+where
+C: GeneCodec<Gene> + Clone
+{
+    workers: Vec<String>,
+    connections: Vec<Option<(BufReader<TcpStream>, TcpStream)>>,
+    codec: C,
+    offset: Score,
+    score_cache: InMemoryScoreCache,
+    gene: std::marker::PhantomData<Gene>
+}
+
+impl <Gene, C> Clone for DistributedScoreProvider <Gene, C>
+where
+C: GeneCodec<Gene> + Clone
+{
+    /// Clones copy the score cache but open their own connections.
+    fn clone(&self) -> Self {
+        Self {
+            workers: self.workers.clone(),
+            connections: self.workers.iter().map(|_| None).collect(),
+            codec: self.codec.clone(),
+            offset: self.offset,
+            score_cache: self.score_cache.clone(),
+            gene: std::marker::PhantomData
+        }
+    }
+}
+
+impl <Gene, C> DistributedScoreProvider <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene> + Clone
+{
+    /// workers: the addresses of the Worker processes, e.g. "10.0.0.2:7878".
+    pub fn new(workers: Vec<String>, codec: C, offset: Score) -> Self {
+        let connections = workers.iter().map(|_| None).collect();
+        Self {
+            workers,
+            connections,
+            codec,
+            offset,
+            score_cache: InMemoryScoreCache::new(),
+            gene: std::marker::PhantomData
+        }
+    }
+
+    fn connection(&mut self, index: usize) -> Result<&mut (BufReader<TcpStream>, TcpStream), ScoreError> {
+        if self.connections[index].is_none() {
+            let stream = self.workers[index].to_socket_addrs()
+                .and_then(|mut addresses| addresses.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address")))
+                .and_then(TcpStream::connect)
+                .map_err(|error| ScoreError::new(&format!("could not connect to worker {}: {}", self.workers[index], error)))?;
+            let writer = stream.try_clone().map_err(|error| ScoreError::new(&error.to_string()))?;
+            self.connections[index] = Some((BufReader::new(stream), writer));
+        }
+        Ok(self.connections[index].as_mut().unwrap())
+    }
+
+    /// Sends agents to the workers and then collects the replies, a window at a time so that
+    /// neither side blocks on a full socket buffer. Agents the workers fail to score are given None.
+    /// After any error every connection is dropped, so replies still pending on the other workers
+    /// are never mistaken for replies to a later request.
+    fn request_scores(&mut self, agents: &[&Agent<Gene>]) -> Result<Vec<Option<Score>>, ScoreError> {
+        if self.workers.is_empty() {
+            return Err(ScoreError::new("no workers have been configured"));
+        }
+
+        let result = self.exchange(agents);
+        if result.is_err() {
+            for connection in self.connections.iter_mut() {
+                *connection = None;
+            }
+        }
+        result
+    }
+
+    fn exchange(&mut self, agents: &[&Agent<Gene>]) -> Result<Vec<Option<Score>>, ScoreError> {
+        let worker_count = self.workers.len();
+        let mut scores = Vec::with_capacity(agents.len());
+        for window in agents.chunks(worker_count * REQUESTS_IN_FLIGHT) {
+            for (index, agent) in window.iter().enumerate() {
+                let request = format!("EVAL {}\n", fingerprint::to_hex(&fingerprint::encode_genes(agent.get_genes(), &self.codec)));
+                self.connection(index % worker_count)?.1.write_all(request.as_bytes())
+                    .map_err(|error| ScoreError::new(&error.to_string()))?;
+            }
+
+            for index in 0..window.len() {
+                let mut line = String::new();
+                match self.connection(index % worker_count)?.0.read_line(&mut line) {
+                    Ok(0) | Err(_) => return Err(ScoreError::new("worker closed the connection")),
+                    Ok(_) => scores.push(line.trim().strip_prefix("SCORE ").and_then(|score| score.parse().ok()))
+                }
+            }
+        }
+
+        Ok(scores)
+    }
+}
+
+impl <Gene, Data, C> ScoreProvider<Gene, Data> for DistributedScoreProvider <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene> + Clone
+{
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, _data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        let uncached: Vec<&Agent<Gene>> = agents.iter().filter(|agent| self.score_cache.get(agent.get_hash()).is_none()).collect();
+        let scores = self.request_scores(&uncached)?;
+        for (agent, score) in uncached.iter().zip(scores) {
+            if let Some(score) = score {
+                self.score_cache.put(agent.get_hash(), score);
+            }
+        }
+
+        // Agents that could not be scored are skipped.
+        Ok(agents.into_iter().filter(|agent| self.score_cache.get(agent.get_hash()).is_some()).collect())
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        let score = self.true_score(agent, data)?;
+        Ok(jitter_score(score, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, _data: &Data) -> Result<Score, ScoreError> {
+        if let Some(score) = self.score_cache.get(agent.get_hash()) {
+            return Ok(score);
+        }
+
+        let score = self.request_scores(&[agent])?[0].ok_or_else(|| ScoreError::new("worker could not score the agent"))?;
+        self.score_cache.put(agent.get_hash(), score);
+        Ok(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::thread;
+
+    fn sum_genes(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        if agent.get_genes().is_empty() {
+            return Err(ScoreError::new("no genes"));
+        }
+        Ok(agent.get_genes().iter().map(|gene| *gene as Score).sum())
+    }
+
+    #[test]
+    fn scores_agents_on_worker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let worker = Worker::new(sum_genes, 0, PrimitiveCodec);
+            let (stream, _) = listener.accept().unwrap();
+            worker.handle_connection(stream).unwrap();
+        });
+
+        let mut provider = DistributedScoreProvider::new(vec![address], PrimitiveCodec, 0);
+        let agents = vec![Agent::from_genes(vec![1u8, 2]), Agent::from_genes(vec![]), Agent::from_genes(vec![10u8])];
+        let scored = provider.evaluate_scores(agents, &0).unwrap();
+
+        // The agent the worker could not score is skipped.
+        assert_eq!(2, scored.len());
+        let mut rng = super::super::random::rng();
+        assert_eq!(3, provider.get_score(&scored[0], &0, &mut rng).unwrap());
+        assert_eq!(10, provider.get_score(&scored[1], &0, &mut rng).unwrap());
+    }
+
+    #[test]
+    fn failed_worker_does_not_leave_stale_replies() {
+        let steady = TcpListener::bind("127.0.0.1:0").unwrap();
+        let failing = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addresses = vec![steady.local_addr().unwrap().to_string(), failing.local_addr().unwrap().to_string()];
+        thread::spawn(move || {
+            Worker::new(sum_genes, 0, PrimitiveCodec).serve(&steady).unwrap();
+        });
+        thread::spawn(move || {
+            // The first connection dies after reading a single request.
+            let (stream, _) = failing.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+
+            let (stream, _) = failing.accept().unwrap();
+            Worker::new(sum_genes, 0, PrimitiveCodec).handle_connection(stream).unwrap();
+        });
+
+        let mut provider = DistributedScoreProvider::new(addresses, PrimitiveCodec, 0);
+        let first: Vec<Agent<u8>> = (1..5u8).map(|gene| Agent::from_genes(vec![gene])).collect();
+        assert!(provider.evaluate_scores(first, &0).is_err());
+
+        let second: Vec<Agent<u8>> = (20..24u8).map(|gene| Agent::from_genes(vec![gene])).collect();
+        let scored = provider.evaluate_scores(second, &0).unwrap();
+        assert_eq!(4, scored.len());
+        for agent in &scored {
+            assert_eq!(agent.get_genes()[0] as Score, provider.true_score(agent, &0).unwrap());
+        }
+    }
+
+    #[test]
+    fn no_workers_is_an_error() {
+        let mut provider: DistributedScoreProvider<u8, PrimitiveCodec> = DistributedScoreProvider::new(Vec::new(), PrimitiveCodec, 0);
+        let result = provider.evaluate_scores(vec![Agent::from_genes(vec![1u8])], &0);
+        assert!(result.is_err());
+    }
+}
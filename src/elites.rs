@@ -0,0 +1,156 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An archive of the best agents ever found, kept apart from the population.
+//!
+//! Agents can leave a population through culls, lifespans or churn, and scores can leave a cache,
+//! so the best solution of a run may otherwise be lost. The archive keeps copies of the highest
+//! true scored agents it is offered. It is saved as text, one agent per line: the true score, a
+//! space, and the genes encoded by a `GeneCodec` as hexadecimal.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::Score;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The highest true scored agents offered, best first, without duplicates.
+#[derive(Clone)]
+pub struct EliteArchive <Gene> {
+    capacity: usize,
+    elites: Vec<(Score, Agent<Gene>)>
+}
+
+impl <Gene> EliteArchive <Gene> {
+    /// capacity: the number of agents kept.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            elites: Vec::new()
+        }
+    }
+
+    /// Keeps a copy of the agent if its true score is among the highest seen, returning whether it
+    /// was kept. Agents already archived are ignored.
+    pub fn offer(&mut self, score: Score, agent: &Agent<Gene>) -> bool
+    where
+    Gene: Clone
+    {
+        if self.elites.iter().any(|(_, elite)| elite.has_same_genes(agent)) {
+            return false;
+        }
+        let position = self.elites.iter().position(|(elite_score, _)| score > *elite_score).unwrap_or(self.elites.len());
+        if position >= self.capacity {
+            return false;
+        }
+        self.elites.insert(position, (score, agent.clone()));
+        self.elites.truncate(self.capacity);
+        true
+    }
+
+    /// The best agent ever offered, with its true score.
+    pub fn best(&self) -> Option<&(Score, Agent<Gene>)> {
+        self.elites.first()
+    }
+
+    /// The archived agents with their true scores, best first.
+    pub fn get_elites(&self) -> &[(Score, Agent<Gene>)] {
+        &self.elites
+    }
+
+    /// Forgets the archived agents, such as when their scores no longer apply.
+    pub fn clear(&mut self) {
+        self.elites.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.elites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elites.is_empty()
+    }
+
+    pub fn write<W: Write, C: GeneCodec<Gene>>(&self, writer: &mut W, codec: &C) -> io::Result<()> {
+        for (score, agent) in &self.elites {
+            writeln!(writer, "{} {}", score, fingerprint::to_hex(&fingerprint::encode_genes(agent.get_genes(), codec)))?;
+        }
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>, C: GeneCodec<Gene>>(&self, path: P, codec: &C) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, codec)?;
+        writer.flush()
+    }
+
+    /// Reads an archive written by write, keeping up to the capacity of agents.
+    pub fn read<R: BufRead, C: GeneCodec<Gene>>(reader: R, codec: &C, capacity: usize) -> io::Result<Self>
+    where
+    Gene: Clone + Hash
+    {
+        let mut archive = Self::new(capacity);
+        for line in reader.lines() {
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let score = parts.next().and_then(|score| score.parse::<Score>().ok());
+            let genes = parts.next()
+                .and_then(fingerprint::from_hex)
+                .and_then(|bytes| fingerprint::decode_genes(&bytes, codec));
+            match (score, genes) {
+                (Some(score), Some(genes)) => {
+                    archive.offer(score, &Agent::from_genes(genes));
+                },
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid elite: {}", line)))
+            }
+        }
+        Ok(archive)
+    }
+
+    pub fn load<P: AsRef<Path>, C: GeneCodec<Gene>>(path: P, codec: &C, capacity: usize) -> io::Result<Self>
+    where
+    Gene: Clone + Hash
+    {
+        Self::read(BufReader::new(File::open(path)?), codec, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+
+    #[test]
+    fn keeps_highest_scores_and_round_trips() {
+        let mut archive = EliteArchive::new(2);
+        assert!(archive.offer(5, &Agent::from_genes(vec![5u8])));
+        assert!(archive.offer(9, &Agent::from_genes(vec![9u8])));
+        assert!(!archive.offer(9, &Agent::from_genes(vec![9u8])));
+        assert!(!archive.offer(1, &Agent::from_genes(vec![1u8])));
+        assert!(archive.offer(7, &Agent::from_genes(vec![7u8])));
+        let scores: Vec<Score> = archive.get_elites().iter().map(|(score, _)| *score).collect();
+        assert_eq!(vec![9, 7], scores);
+
+        let mut text = Vec::new();
+        archive.write(&mut text, &PrimitiveCodec).unwrap();
+        assert_eq!("9 09\n7 07\n", String::from_utf8(text.clone()).unwrap());
+
+        let read = EliteArchive::<u8>::read(&text[..], &PrimitiveCodec, 2).unwrap();
+        let (score, best) = read.best().unwrap();
+        assert_eq!((9, &vec![9u8]), (*score, best.get_genes()));
+        assert!(EliteArchive::<u8>::read(&b"x"[..], &PrimitiveCodec, 2).is_err());
+    }
+}
@@ -16,14 +16,103 @@ use super::population::Population;
 use super::operations::{
     Operation
 };
-use super::fitness::{ScoreProvider};
+use super::fitness::{Score, ScoreError, ScoreProvider};
+use super::agent::{Agent, GeneBounds};
+use super::random::ThreadGenerator;
 use rand::{
     distributions::{Distribution, Standard}
 };
-use std::hash::Hash;
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Receives notifications as iterations are run.
+pub trait Observer<Gene> {
+    /// Called whenever the highest score in the population improves. This is checked after every
+    /// operation, so may be called part way through an iteration.
+    fn new_best(&mut self, _score: Score, _agent: &Agent<Gene>) {}
+
+    /// Called after each iteration has run all of its operations.
+    /// The iteration count starts from zero for each call to run_iterations_with_observer.
+    fn iteration_complete(&mut self, _iteration: usize, _population: &Population<Gene>) {}
+
+    /// Checked after each iteration. Returning true ends the run there, keeping the population as
+    /// it is, so that runs can be stopped from outside, such as by a Ctrl-C handler.
+    fn should_stop(&mut self) -> bool {
+        false
+    }
+}
+
+/// Observes nothing.
+impl <Gene> Observer<Gene> for () {}
+
+/// Sends a copy of each new best agent, for example to a thread rendering the current solution.
+impl <Gene: Clone> Observer<Gene> for Sender<(Score, Agent<Gene>)> {
+    fn new_best(&mut self, score: Score, agent: &Agent<Gene>) {
+        // The receiver may have stopped listening, which shouldn't stop the run.
+        let _ = self.send((score, agent.clone()));
+    }
+}
+
+/// Notifies each of the observers in turn.
+impl <Gene> Observer<Gene> for Vec<Box<dyn Observer<Gene> + Send>> {
+    fn new_best(&mut self, score: Score, agent: &Agent<Gene>) {
+        for observer in self.iter_mut() {
+            observer.new_best(score, agent);
+        }
+    }
+
+    fn iteration_complete(&mut self, iteration: usize, population: &Population<Gene>) {
+        for observer in self.iter_mut() {
+            observer.iteration_complete(iteration, population);
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.iter_mut().any(|observer| observer.should_stop())
+    }
+}
+
+/// Notifies a shared observer, so it can still be inspected after being given to a Manager.
+/// A poisoned lock is ignored.
+impl <Gene, O: Observer<Gene>> Observer<Gene> for Arc<Mutex<O>> {
+    fn new_best(&mut self, score: Score, agent: &Agent<Gene>) {
+        if let Ok(mut observer) = self.lock() {
+            observer.new_best(score, agent);
+        }
+    }
+
+    fn iteration_complete(&mut self, iteration: usize, population: &Population<Gene>) {
+        if let Ok(mut observer) = self.lock() {
+            observer.iteration_complete(iteration, population);
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        match self.lock() {
+            Ok(mut observer) => observer.should_stop(),
+            Err(_) => false
+        }
+    }
+}
+
+/// Stops the run once the flag is set, which can be done from any thread.
+impl <Gene> Observer<Gene> for Arc<AtomicBool> {
+    fn should_stop(&mut self) -> bool {
+        self.load(Ordering::SeqCst)
+    }
+}
+
+/// Calls the closure with each new best agent.
+impl <Gene, F: FnMut(Score, &Agent<Gene>)> Observer<Gene> for F {
+    fn new_best(&mut self, score: Score, agent: &Agent<Gene>) {
+        self(score, agent);
+    }
+}
 
 pub fn run_iterations<Gene, Data, SP>(
-    mut population: Population<Gene>,
+    population: Population<Gene>,
     iterations: usize,
     data: &Data,
     operations: &Vec<Operation<Gene, Data>>,
@@ -31,24 +120,356 @@ pub fn run_iterations<Gene, Data, SP>(
 ) -> Population<Gene>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static,
 SP: Clone + ScoreProvider<Gene, Data>
 {
-    for _ in 0..iterations {
-        for operation in operations.iter() {
-            population = operation.run(population, data, score_provider);
+    run_iterations_with_observer(population, iterations, data, operations, score_provider, &mut ())
+}
+
+/// As run_iterations, but notifies the observer of progress.
+pub fn run_iterations_with_observer<Gene, Data, SP, O>(
+    mut population: Population<Gene>,
+    iterations: usize,
+    data: &Data,
+    operations: &[Operation<Gene, Data>],
+    score_provider: &mut SP,
+    observer: &mut O
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + ScoreProvider<Gene, Data>,
+O: Observer<Gene>
+{
+    let mut highest = highest_score(&population);
+    for iteration in 0..iterations {
+        population = run_iteration(population, iteration, &mut highest, data, operations, score_provider, observer, None);
+        if observer.should_stop() {
+            break;
+        }
+    }
+
+    population
+}
+
+/// Changes the data of a run before an iteration, given the generation about to run, for problems
+/// whose environment drifts over time.
+pub type DataHook<Data> = fn(&mut Data, u64);
+
+/// As run_iterations_with_observer, but the hook may change the data before each iteration. The
+/// score provider is then told the data has changed, and the population is rescored.
+pub fn run_drifting_iterations<Gene, Data, SP, O>(
+    mut population: Population<Gene>,
+    iterations: usize,
+    data: &mut Data,
+    hook: DataHook<Data>,
+    operations: &[Operation<Gene, Data>],
+    score_provider: &mut SP,
+    observer: &mut O
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + ScoreProvider<Gene, Data>,
+O: Observer<Gene>
+{
+    for iteration in 0..iterations {
+        hook(data, population.get_generation());
+        score_provider.data_changed();
+        // A population that cannot be rescored keeps its old scores.
+        let _ = population.rescore(data, score_provider);
+        // Improvements are measured on the new data.
+        let mut highest = highest_score(&population);
+
+        population = run_iteration(population, iteration, &mut highest, data, operations, score_provider, observer, None);
+        if observer.should_stop() {
+            break;
         }
     }
 
     population
 }
 
+/// When an OperationThrottle skips an operation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottlePolicy {
+    /// The number of recent runs of an operation its yield is measured over.
+    pub window: usize,
+    /// The least rise in the highest score per evaluation an operation must yield over the window
+    /// to keep running.
+    pub min_yield: f64,
+    /// The number of generations an unproductive operation is skipped for.
+    pub cooldown: usize
+}
+
+/// How an operation has fared under an OperationThrottle.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OperationYield {
+    /// Times the operation ran.
+    pub runs: usize,
+    /// Times the operation was skipped while cooling down.
+    pub skips: usize,
+    /// Times the operation's yield fell below the policy's minimum.
+    pub throttled: usize,
+    /// Scores calculated while the operation ran, not counting those the provider had cached.
+    pub evaluations: usize,
+    /// The total rise in the best agent's score, without noise, while the operation ran.
+    pub improvement: Score,
+    recent: VecDeque<(usize, Score)>,
+    cooldown: usize
+}
+
+impl OperationYield {
+    /// The rise in the highest score per evaluation over the recent runs, or None if they needed
+    /// no evaluations, as culls do.
+    pub fn recent_yield(&self) -> Option<f64> {
+        let evaluations: usize = self.recent.iter().map(|&(evaluations, _)| evaluations).sum();
+        if evaluations == 0 {
+            return None;
+        }
+        let improvement: Score = self.recent.iter().map(|&(_, improvement)| improvement).sum();
+        Some(improvement as f64 / evaluations as f64)
+    }
+
+    /// Whether the operation is being skipped.
+    pub fn is_cooling_down(&self) -> bool {
+        self.cooldown > 0
+    }
+}
+
+/// Tracks how much each operation of a pipeline raises the highest score per evaluation, and
+/// skips operations whose recent yield falls below the policy's minimum for a cooldown, leaving
+/// the evaluations to the operations that are paying off. Operations needing no evaluations are
+/// never skipped, nor is an operation when every other one needing evaluations is already being
+/// skipped. See run_throttled_iterations and Manager::set_operation_throttle.
+#[derive(Clone, Debug)]
+pub struct OperationThrottle {
+    policy: ThrottlePolicy,
+    yields: Vec<OperationYield>
+}
+
+impl OperationThrottle {
+    pub fn new(policy: ThrottlePolicy) -> Self {
+        Self { policy, yields: Vec::new() }
+    }
+
+    pub fn get_policy(&self) -> ThrottlePolicy {
+        self.policy
+    }
+
+    /// The yield of each operation, in pipeline order.
+    pub fn get_yields(&self) -> &[OperationYield] {
+        &self.yields
+    }
+
+    /// Whether the operation at the index should run, counting a skip if not.
+    fn should_run(&mut self, index: usize, operations: usize) -> bool {
+        if self.yields.len() < operations {
+            self.yields.resize(operations, OperationYield::default());
+        }
+        let operation = &mut self.yields[index];
+        if operation.cooldown > 0 {
+            operation.cooldown -= 1;
+            operation.skips += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Records a run of the operation at the index, throttling it if its yield over the window
+    /// has fallen below the minimum.
+    fn record(&mut self, index: usize, evaluations: usize, improvement: Score) {
+        let window = self.policy.window.max(1);
+        let others_running = self.yields.iter().enumerate()
+            .any(|(other, operation)| other != index && operation.evaluations > 0 && !operation.is_cooling_down());
+
+        let operation = &mut self.yields[index];
+        operation.runs += 1;
+        operation.evaluations += evaluations;
+        operation.improvement += improvement;
+        operation.recent.push_back((evaluations, improvement));
+        if operation.recent.len() > window {
+            operation.recent.pop_front();
+        }
+
+        if operation.recent.len() == window && others_running {
+            if let Some(recent_yield) = operation.recent_yield() {
+                if recent_yield < self.policy.min_yield {
+                    operation.throttled += 1;
+                    operation.cooldown = self.policy.cooldown;
+                    operation.recent.clear();
+                }
+            }
+        }
+    }
+}
+
+/// Forwards to a score provider, counting the scores it calculates. Where the provider does not
+/// keep count, every agent sent to be scored is counted.
+struct CountingScoreProvider<'a, Gene: 'a, Data: 'a> {
+    inner: &'a mut dyn ScoreProvider<Gene, Data>,
+    sent: usize
+}
+
+impl <'a, Gene, Data> CountingScoreProvider<'a, Gene, Data> {
+    /// Runs the operation, returning the resulting population and the scores calculated for it.
+    fn count(
+        inner: &'a mut dyn ScoreProvider<Gene, Data>,
+        run: impl FnOnce(&mut dyn ScoreProvider<Gene, Data>) -> Population<Gene>
+    ) -> (Population<Gene>, usize) {
+        let before = inner.evaluations();
+        let mut counting = CountingScoreProvider { inner, sent: 0 };
+        let population = run(&mut counting);
+        let evaluations = match (before, counting.inner.evaluations()) {
+            (Some(before), Some(after)) => after.saturating_sub(before) as usize,
+            _ => counting.sent
+        };
+        (population, evaluations)
+    }
+}
+
+impl <'a, Gene, Data> ScoreProvider<Gene, Data> for CountingScoreProvider<'a, Gene, Data> {
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        self.sent += agents.len();
+        self.inner.evaluate_scores(agents, data)
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        self.inner.get_score(agent, data, rng)
+    }
+
+    fn evaluate_scores_in_threads(&mut self, agents: Vec<Agent<Gene>>, data: &Data, threads: usize) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        self.sent += agents.len();
+        self.inner.evaluate_scores_in_threads(agents, data, threads)
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.inner.true_score(agent, data)
+    }
+
+    fn population_updated(&mut self, population: &Population<Gene>) {
+        self.inner.population_updated(population);
+    }
+
+    fn data_changed(&mut self) {
+        self.inner.data_changed();
+    }
+
+    fn evaluations(&self) -> Option<u64> {
+        self.inner.evaluations()
+    }
+}
+
+/// As run_iterations_with_observer, skipping operations the throttle finds unproductive.
+pub fn run_throttled_iterations<Gene, Data, SP, O>(
+    mut population: Population<Gene>,
+    iterations: usize,
+    data: &Data,
+    operations: &[Operation<Gene, Data>],
+    score_provider: &mut SP,
+    observer: &mut O,
+    throttle: &mut OperationThrottle
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + ScoreProvider<Gene, Data>,
+O: Observer<Gene>
+{
+    let mut highest = highest_score(&population);
+    for iteration in 0..iterations {
+        population = run_iteration(population, iteration, &mut highest, data, operations, score_provider, observer, Some(&mut *throttle));
+        if observer.should_stop() {
+            break;
+        }
+    }
+
+    population
+}
+
+/// With the debug-validate feature, panics if the operation broke an invariant of the population.
+#[cfg(feature = "debug-validate")]
+fn check_invariants<Gene: GeneBounds>(population: &Population<Gene>, operation: usize, iteration: usize) {
+    if let Err(error) = population.validate() {
+        panic!("operation {} of iteration {} broke an invariant: {}", operation, iteration, error);
+    }
+}
+
+#[cfg(not(feature = "debug-validate"))]
+fn check_invariants<Gene>(_population: &Population<Gene>, _operation: usize, _iteration: usize) {}
+
+#[allow(clippy::too_many_arguments)]
+fn run_iteration<Gene, Data, SP, O>(
+    mut population: Population<Gene>,
+    iteration: usize,
+    highest: &mut Option<Score>,
+    data: &Data,
+    operations: &[Operation<Gene, Data>],
+    score_provider: &mut SP,
+    observer: &mut O,
+    mut throttle: Option<&mut OperationThrottle>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + ScoreProvider<Gene, Data>,
+O: Observer<Gene>
+{
+    for (index, operation) in operations.iter().enumerate() {
+        population = match throttle {
+            Some(ref mut throttle) => {
+                if !throttle.should_run(index, operations.len()) {
+                    continue;
+                }
+                let before = best_true_score(&population, data, score_provider);
+                let (population, evaluations) = CountingScoreProvider::count(score_provider, |counting| operation.run(population, data, counting));
+                let improvement = match (before, best_true_score(&population, data, score_provider)) {
+                    (Some(before), Some(after)) => after.saturating_sub(before),
+                    _ => 0
+                };
+                throttle.record(index, evaluations, improvement);
+                population
+            },
+            None => operation.run(population, data, score_provider)
+        };
+        check_invariants(&population, index, iteration);
+
+        let current = highest_score(&population);
+        if current > *highest {
+            *highest = current;
+            if let Some((score, agent)) = population.get_agents().iter().next_back() {
+                observer.new_best(*score, agent);
+            }
+        }
+    }
+    population.advance_generation();
+    score_provider.population_updated(&population);
+    observer.iteration_complete(iteration, &population);
+    population
+}
+
+fn highest_score<Gene>(population: &Population<Gene>) -> Option<Score> {
+    population.best_score()
+}
+
+/// The score of the best agent without noise, so that the throttle does not mistake a lucky
+/// score key for improvement.
+fn best_true_score<Gene, Data>(population: &Population<Gene>, data: &Data, score_provider: &mut dyn ScoreProvider<Gene, Data>) -> Option<Score> {
+    let (_, agent) = population.get_agents().iter().next_back()?;
+    score_provider.true_score(agent, data).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::super::agent::Agent;
-    use super::super::fitness::{Score, ScoreError, GeneralScoreProvider};
+    use super::super::fitness::{ScoreError, GeneralScoreProvider};
+    use super::super::operations::{OperationType, Selection, SelectionType};
 
     fn get_score_index(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
         let score = agent.get_genes()[0] as Score;
@@ -61,4 +482,106 @@ mod tests {
         let population = run_iterations(Population::new_empty(false), 0, &0, &Vec::new(), &mut score_provider);
         assert_eq!(0, population.len());
     }
+
+    #[test]
+    fn throttle_skips_unproductive_operations() {
+        fn constant(_agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            Ok(1)
+        }
+
+        let mut score_provider = GeneralScoreProvider::new(constant, 0);
+        let population = Population::new(10, 3, false, &0, &mut score_provider);
+        let operations = vec![
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.5)),
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.5)),
+            Operation::new(OperationType::Cull, Selection::new(SelectionType::LowestScore, 0.1))
+        ];
+        let mut throttle = OperationThrottle::new(ThrottlePolicy { window: 2, min_yield: 100.0, cooldown: 3 });
+        run_throttled_iterations(population, 10, &0, &operations, &mut score_provider, &mut (), &mut throttle);
+
+        let yields = throttle.get_yields();
+        // The two mutations take turns cooling down, as one is never skipped while the other is.
+        assert_eq!((6, 4, 2), (yields[0].runs, yields[0].skips, yields[0].throttled));
+        assert_eq!((7, 3, 1), (yields[1].runs, yields[1].skips, yields[1].throttled));
+        assert!(yields[1].recent_yield().unwrap() < 100.0);
+        assert_eq!((10, 0, None), (yields[2].runs, yields[2].evaluations, yields[2].recent_yield()));
+    }
+
+    #[test]
+    fn throttle_counts_calculated_scores_and_ignores_noise() {
+        fn constant(_agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            Ok(1)
+        }
+
+        let mut score_provider = GeneralScoreProvider::new(constant, 1000);
+        let population = Population::new(20, 1, false, &0, &mut score_provider);
+        let initial = score_provider.get_evaluations();
+        let operations = vec![
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.5))
+        ];
+        let mut throttle = OperationThrottle::new(ThrottlePolicy { window: 100, min_yield: 0.0, cooldown: 0 });
+        run_throttled_iterations(population, 50, &0, &operations, &mut score_provider, &mut (), &mut throttle);
+
+        let yields = throttle.get_yields();
+        // Single-gene agents soon repeat, and repeats are taken from the cache.
+        assert_eq!(score_provider.get_evaluations() - initial, yields[0].evaluations as u64);
+        assert!(yields[0].evaluations <= 256);
+        // The noise moves the score keys, but the fitness never rises.
+        assert_eq!(0, yields[0].improvement);
+    }
+
+    #[test]
+    fn run_iterations_observer_sees_increasing_best() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 25);
+        let population = Population::new(10, 3, false, &0, &mut score_provider);
+        let start = *population.get_agents().keys().next_back().unwrap();
+        let operations = vec![
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.5)),
+        ];
+
+        let mut seen = Vec::new();
+        let population = run_iterations_with_observer(population, 20, &0, &operations, &mut score_provider, &mut |score: Score, _agent: &Agent<u8>| seen.push(score));
+
+        let mut previous = start;
+        for score in &seen {
+            assert!(*score > previous);
+            previous = *score;
+        }
+        assert_eq!(previous, *population.get_agents().keys().next_back().unwrap());
+    }
+
+    #[test]
+    fn stop_signal_ends_run_after_iteration() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 0);
+        let population = Population::new(10, 3, false, &0, &mut score_provider);
+        let operations = vec![
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.5)),
+        ];
+
+        let mut stop = Arc::new(AtomicBool::new(true));
+        let population = run_iterations_with_observer(population, 20, &0, &operations, &mut score_provider, &mut stop);
+        assert_eq!(1, population.get_generation());
+    }
+
+    fn distance_to_target(agent: &Agent<u8>, target: &u8) -> Result<Score, ScoreError> {
+        Ok(255 - (agent.get_genes()[0] as i16 - *target as i16).unsigned_abs() as Score)
+    }
+
+    fn move_target(target: &mut u8, generation: u64) {
+        *target = (generation * 10) as u8;
+    }
+
+    #[test]
+    fn drifting_data_rescores_population() {
+        let mut score_provider = GeneralScoreProvider::new(distance_to_target, 0);
+        let mut population = Population::new_empty(false);
+        population.score_and_insert(vec![Agent::from_genes(vec![0u8]), Agent::from_genes(vec![50u8])], &0, &mut score_provider);
+        assert_eq!(vec![205, 255], population.get_scores());
+
+        let mut target = 0;
+        let population = run_drifting_iterations(population, 6, &mut target, move_target, &[], &mut score_provider, &mut ());
+        assert_eq!(50, target);
+        assert_eq!(vec![205, 255], population.get_scores());
+        assert_eq!(&vec![50u8], population.get(255).unwrap().get_genes());
+    }
 }
@@ -0,0 +1,306 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batch experiments, for comparing configurations such as operator settings.
+//!
+//! Each configuration is run once per seed by a trial function, which sets up and runs the
+//! evolution however it likes and reports how it went. The report summarises each configuration
+//! and compares pairs of them with a rank-sum test, since final scores are rarely normally
+//! distributed.
+
+use super::fitness::Score;
+use super::history::RunHistory;
+use std::fmt;
+
+/// The outcome of one run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trial {
+    pub final_score: Score,
+    /// The best score after each iteration, for any-time performance.
+    pub progress: Vec<Score>
+}
+
+impl Trial {
+    pub fn new(final_score: Score, progress: Vec<Score>) -> Self {
+        Self { final_score, progress }
+    }
+
+    /// Takes the progress from the best score of each iteration a RunHistory observed.
+    pub fn from_history(final_score: Score, history: &RunHistory) -> Self {
+        Self::new(final_score, history.get_stats().iter().map(|stats| stats.best).collect())
+    }
+
+    /// The mean of the best scores over the run, rewarding configurations that improve early.
+    /// Falls back to the final score if no progress was recorded.
+    pub fn anytime_score(&self) -> f64 {
+        if self.progress.is_empty() {
+            return self.final_score as f64;
+        }
+        mean(&self.progress.iter().map(|score| *score as f64).collect::<Vec<f64>>())
+    }
+}
+
+/// Runs a configuration with a seed. The seed tells repeats apart; it only controls randomness if
+/// the trial function uses it to do so.
+pub type TrialFunction<Config> = fn(&Config, u64) -> Trial;
+
+/// Named configurations to run with each of a set of seeds.
+pub struct Experiments <Config> {
+    trial_function: TrialFunction<Config>,
+    configurations: Vec<(String, Config)>,
+    seeds: Vec<u64>
+}
+
+impl <Config> Experiments <Config> {
+    /// Creates experiments that run each configuration once for each seed from 0 to repeats - 1.
+    pub fn new(trial_function: TrialFunction<Config>, repeats: u64) -> Self {
+        Self {
+            trial_function,
+            configurations: Vec::new(),
+            seeds: (0..repeats).collect()
+        }
+    }
+
+    pub fn set_seeds(&mut self, seeds: Vec<u64>) {
+        self.seeds = seeds;
+    }
+
+    pub fn add_configuration(&mut self, name: &str, configuration: Config) {
+        self.configurations.push((name.to_string(), configuration));
+    }
+
+    /// Runs every configuration with every seed, one after another.
+    pub fn run(&self) -> ExperimentReport {
+        let results = self.configurations.iter()
+            .map(|(name, configuration)| ConfigurationResult {
+                name: name.clone(),
+                trials: self.seeds.iter().map(|seed| (self.trial_function)(configuration, *seed)).collect()
+            })
+            .collect();
+        ExperimentReport { results }
+    }
+}
+
+/// The trials of one configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigurationResult {
+    pub name: String,
+    pub trials: Vec<Trial>
+}
+
+impl ConfigurationResult {
+    pub fn final_scores(&self) -> Vec<f64> {
+        self.trials.iter().map(|trial| trial.final_score as f64).collect()
+    }
+
+    pub fn anytime_scores(&self) -> Vec<f64> {
+        self.trials.iter().map(|trial| trial.anytime_score()).collect()
+    }
+
+    pub fn mean_final(&self) -> f64 {
+        mean(&self.final_scores())
+    }
+
+    pub fn median_final(&self) -> f64 {
+        median(&self.final_scores())
+    }
+
+    pub fn mean_anytime(&self) -> f64 {
+        mean(&self.anytime_scores())
+    }
+
+    pub fn median_anytime(&self) -> f64 {
+        median(&self.anytime_scores())
+    }
+}
+
+/// The result of a two-sided Mann-Whitney U test, using the normal approximation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RankSumTest {
+    /// The U statistic of the first sample.
+    pub u: f64,
+    pub z: f64,
+    /// The probability of a difference at least this large if both samples come from the same
+    /// distribution. The approximation is rough for fewer than about ten trials each.
+    pub p_value: f64
+}
+
+impl RankSumTest {
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+}
+
+/// Compares two samples with the Mann-Whitney U test, giving tied values their average rank.
+/// Returns None if either sample is empty or holds NaN, which has no rank.
+pub fn rank_sum_test(first: &[f64], second: &[f64]) -> Option<RankSumTest> {
+    if first.is_empty() || second.is_empty() || first.iter().chain(second).any(|value| value.is_nan()) {
+        return None;
+    }
+
+    let mut values: Vec<(f64, bool)> = first.iter().map(|value| (*value, true))
+        .chain(second.iter().map(|value| (*value, false)))
+        .collect();
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut first_rank_sum = 0.0;
+    let mut tie_correction = 0.0;
+    let mut start = 0;
+    while start < values.len() {
+        let mut end = start;
+        while end + 1 < values.len() && values[end + 1].0 == values[start].0 {
+            end += 1;
+        }
+        // Ranks start at one.
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        first_rank_sum += rank * values[start..=end].iter().filter(|(_, in_first)| *in_first).count() as f64;
+        let tied = (end - start + 1) as f64;
+        tie_correction += tied.powi(3) - tied;
+        start = end + 1;
+    }
+
+    let n1 = first.len() as f64;
+    let n2 = second.len() as f64;
+    let n = n1 + n2;
+    let u = first_rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    let z = if variance > 0.0 { (u - n1 * n2 / 2.0) / variance.sqrt() } else { 0.0 };
+
+    Some(RankSumTest {
+        u,
+        z,
+        p_value: erfc(z.abs() / 2f64.sqrt()).min(1.0)
+    })
+}
+
+/// The results of every configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExperimentReport {
+    pub results: Vec<ConfigurationResult>
+}
+
+impl ExperimentReport {
+    pub fn get(&self, name: &str) -> Option<&ConfigurationResult> {
+        self.results.iter().find(|result| result.name == name)
+    }
+
+    /// Compares the final scores of two configurations. Returns None if either is missing or has
+    /// no trials.
+    pub fn compare_final(&self, first: &str, second: &str) -> Option<RankSumTest> {
+        rank_sum_test(&self.get(first)?.final_scores(), &self.get(second)?.final_scores())
+    }
+
+    /// Compares the any-time scores of two configurations.
+    pub fn compare_anytime(&self, first: &str, second: &str) -> Option<RankSumTest> {
+        rank_sum_test(&self.get(first)?.anytime_scores(), &self.get(second)?.anytime_scores())
+    }
+}
+
+/// A table of the means and medians of each configuration, then the p-value of the final scores
+/// of each configuration against the first.
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "configuration\ttrials\tmean final\tmedian final\tmean any-time\tmedian any-time\tp vs first")?;
+        for result in &self.results {
+            write!(f, "{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t", result.name, result.trials.len(),
+                result.mean_final(), result.median_final(), result.mean_anytime(), result.median_anytime())?;
+            match self.results.first().and_then(|first| rank_sum_test(&first.final_scores(), &result.final_scores())) {
+                Some(test) => writeln!(f, "{:.4}", test.p_value)?,
+                None => writeln!(f, "-")?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// The complementary error function, accurate to about 1e-7 (Numerical Recipes' erfcc).
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let y = t * (-x * x - 1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96 + t * (0.096_784_18
+        + t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_203_98 + t * (1.488_515_87
+        + t * (-0.822_152_23 + t * 0.170_872_77))))))))).exp();
+    if x >= 0.0 { y } else { 2.0 - y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_statistics() {
+        assert_eq!(2.5, median(&[4.0, 1.0, 3.0, 2.0]));
+        assert_eq!(3.0, median(&[5.0, 3.0, 1.0]));
+        assert_eq!(2.0, Trial::new(9, vec![1, 2, 3]).anytime_score());
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        assert!((erfc(1.0) - 0.157_299_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rank_sum_detects_shifted_samples() {
+        let low: Vec<f64> = (0..10).map(|value| value as f64).collect();
+        let high: Vec<f64> = (20..30).map(|value| value as f64).collect();
+        let test = rank_sum_test(&low, &high).unwrap();
+        assert_eq!(0.0, test.u);
+        assert!(test.z < 0.0);
+        assert!(test.is_significant(0.01));
+
+        let same = rank_sum_test(&low, &low).unwrap();
+        assert_eq!(50.0, same.u);
+        assert!(!same.is_significant(0.05));
+        assert!(rank_sum_test(&low, &[]).is_none());
+        assert!(rank_sum_test(&low, &[1.0, f64::NAN]).is_none());
+        assert_eq!(0.0, rank_sum_test(&[f64::NEG_INFINITY], &[1.0, f64::INFINITY]).unwrap().u);
+    }
+
+    fn scaled(configuration: &u64, seed: u64) -> Trial {
+        Trial::new(configuration * 100 + seed, vec![seed, configuration * 100 + seed])
+    }
+
+    #[test]
+    fn runs_every_configuration_with_every_seed() {
+        let mut experiments = Experiments::new(scaled, 12);
+        experiments.add_configuration("small", 1);
+        experiments.add_configuration("large", 2);
+        let report = experiments.run();
+
+        let small = report.get("small").unwrap();
+        assert_eq!(12, small.trials.len());
+        assert_eq!(105.5, small.mean_final());
+        assert_eq!(105.5, small.median_final());
+        assert!(report.compare_final("small", "large").unwrap().is_significant(0.01));
+        assert!(report.compare_final("small", "missing").is_none());
+        assert_eq!(3, report.to_string().lines().count());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C API for embedding, enabled with the `ffi` feature. The declarations are in `include/xu.h`.
+//!
+//! Genes are bytes. The fitness callback receives the genes and the user data pointer given when
+//! creating the manager, writes the score and returns 0, or returns any other value if the genes
+//! could not be scored.
+
+use super::agent::Agent;
+use super::fitness::{GeneralScoreProvider, Score, ScoreError};
+use super::manager::{Goal, Manager, create_manager};
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::slice;
+
+/// The fitness callback: `int fitness(const uint8_t *genes, size_t length, void *user_data, uint64_t *score)`.
+pub type FfiFitnessFunction = extern "C" fn(*const u8, usize, *mut c_void, *mut Score) -> c_int;
+
+/// The data passed through the Rust core, carrying the callback and the caller's pointer.
+/// The pointer is held as an integer so the data can be sent to the Manager's threads; the caller
+/// is responsible for the user data being safe to use from several threads at once.
+#[derive(Clone)]
+pub struct FfiData {
+    fitness_function: FfiFitnessFunction,
+    user_data: usize
+}
+
+fn ffi_fitness(agent: &Agent<u8>, data: &FfiData) -> Result<Score, ScoreError> {
+    let genes = agent.get_genes();
+    let mut score = 0;
+    let status = (data.fitness_function)(genes.as_ptr(), genes.len(), data.user_data as *mut c_void, &mut score);
+    if status != 0 {
+        return Err(ScoreError::new(&format!("fitness callback returned {}", status)));
+    }
+    Ok(score)
+}
+
+/// An opaque handle to a Manager.
+pub struct XuManager {
+    manager: Manager<u8, FfiData, GeneralScoreProvider<u8, FfiData>>
+}
+
+/// Creates a manager. Free it with xu_manager_free.
+#[no_mangle]
+pub extern "C" fn xu_manager_new(fitness_function: FfiFitnessFunction, user_data: *mut c_void) -> *mut XuManager {
+    let data = FfiData {
+        fitness_function,
+        user_data: user_data as usize
+    };
+    Box::into_raw(Box::new(XuManager {
+        manager: create_manager(ffi_fitness, data)
+    }))
+}
+
+/// Frees a manager created by xu_manager_new.
+///
+/// # Safety
+/// The manager must have come from xu_manager_new and not already have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_free(manager: *mut XuManager) {
+    if !manager.is_null() {
+        drop(Box::from_raw(manager));
+    }
+}
+
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_set_number_of_genes(manager: *mut XuManager, number: usize, strict: bool) {
+    if let Some(manager) = manager.as_mut() {
+        manager.manager.set_number_of_genes(number, strict);
+    }
+}
+
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_set_initial_population_size(manager: *mut XuManager, size: usize) {
+    if let Some(manager) = manager.as_mut() {
+        manager.manager.set_initial_population_size(size);
+    }
+}
+
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_set_max_child_threads(manager: *mut XuManager, max_number: u8) {
+    if let Some(manager) = manager.as_mut() {
+        manager.manager.set_max_child_threads(max_number);
+    }
+}
+
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_set_iterations_per_cycle(manager: *mut XuManager, number: usize) {
+    if let Some(manager) = manager.as_mut() {
+        manager.manager.set_iterations_per_cycle(number);
+    }
+}
+
+/// Runs until the fitness of the best agent in the population is at least the goal.
+///
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_run(manager: *mut XuManager, goal: Score) {
+    if let Some(manager) = manager.as_mut() {
+        manager.manager.run(Goal::AtLeast(goal));
+    }
+}
+
+/// Copies the genes of the fittest agent into the buffer, up to its capacity, and writes its
+/// fitness if the score pointer is not null. Returns the full number of genes, so a return value
+/// greater than the capacity means the buffer was too small. Returns 0 if the population is empty.
+///
+/// # Safety
+/// The manager must be a valid pointer from xu_manager_new, the buffer must be valid for writing
+/// capacity bytes and the score must be null or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn xu_manager_best_genome(manager: *mut XuManager, buffer: *mut u8, capacity: usize, score: *mut Score) -> usize {
+    let manager = match manager.as_mut() {
+        Some(manager) => manager,
+        None => return 0
+    };
+    let (best_score, agent) = match manager.manager.get_true_best() {
+        Some(best) => best,
+        None => return 0
+    };
+
+    let genes = agent.get_genes();
+    if !buffer.is_null() {
+        let count = genes.len().min(capacity);
+        slice::from_raw_parts_mut(buffer, count).copy_from_slice(&genes[..count]);
+    }
+    if !score.is_null() {
+        ptr::write(score, best_score);
+    }
+    genes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn sum_genes(genes: *const u8, length: usize, user_data: *mut c_void, score: *mut Score) -> c_int {
+        let genes = unsafe { slice::from_raw_parts(genes, length) };
+        let multiplier = unsafe { *(user_data as *const Score) };
+        unsafe { *score = genes.iter().map(|gene| *gene as Score).sum::<Score>() * multiplier };
+        0
+    }
+
+    #[test]
+    fn run_and_fetch_best_genome() {
+        static MULTIPLIER: Score = 2;
+        unsafe {
+            let manager = xu_manager_new(sum_genes, &MULTIPLIER as *const Score as *mut c_void);
+            xu_manager_set_number_of_genes(manager, 4, true);
+            xu_manager_set_max_child_threads(manager, 0);
+            xu_manager_set_iterations_per_cycle(manager, 5);
+            xu_manager_run(manager, 100);
+
+            let mut buffer = [0u8; 2];
+            let mut score = 0;
+            let length = xu_manager_best_genome(manager, buffer.as_mut_ptr(), buffer.len(), &mut score);
+            assert_eq!(4, length);
+            assert!(score >= 100);
+
+            xu_manager_free(manager);
+        }
+    }
+}
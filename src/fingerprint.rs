@@ -0,0 +1,178 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable fingerprints for agents.
+//!
+//! The hash returned by `Agent::get_hash` uses the standard library's `DefaultHasher` unless
+//! the population sets another `GenomeHasher`, and its output may change between Rust versions.
+//! A fingerprint is instead calculated by encoding each gene to bytes with a `GeneCodec` and
+//! hashing those bytes with 64-bit FNV-1a, so the same genes always give the same fingerprint on
+//! any machine and in any run.
+
+/// A stable identifier for a set of genes.
+pub type Fingerprint = u64;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Converts genes to and from bytes.
+/// The encoding must be deterministic for fingerprints to be stable.
+pub trait GeneCodec<Gene> {
+    /// Appends the bytes for the gene to the output.
+    fn encode(&self, gene: &Gene, output: &mut Vec<u8>);
+
+    /// Reads a single gene from the start of the input, advancing the input past it.
+    /// Returns None if the input does not start with a valid gene.
+    fn decode(&self, input: &mut &[u8]) -> Option<Gene>;
+}
+
+/// Codec for primitive numeric and boolean genes, written as little-endian bytes.
+#[derive(Clone, Copy, Default)]
+pub struct PrimitiveCodec;
+
+macro_rules! primitive_codec {
+    ($($gene:ty),*) => {
+        $(
+            impl GeneCodec<$gene> for PrimitiveCodec {
+                fn encode(&self, gene: &$gene, output: &mut Vec<u8>) {
+                    output.extend_from_slice(&gene.to_le_bytes());
+                }
+
+                fn decode(&self, input: &mut &[u8]) -> Option<$gene> {
+                    const SIZE: usize = std::mem::size_of::<$gene>();
+                    if input.len() < SIZE {
+                        return None;
+                    }
+                    let mut bytes = [0; SIZE];
+                    bytes.copy_from_slice(&input[..SIZE]);
+                    *input = &input[SIZE..];
+                    Some(<$gene>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+primitive_codec!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl GeneCodec<bool> for PrimitiveCodec {
+    fn encode(&self, gene: &bool, output: &mut Vec<u8>) {
+        output.push(*gene as u8);
+    }
+
+    fn decode(&self, input: &mut &[u8]) -> Option<bool> {
+        let (first, rest) = input.split_first()?;
+        *input = rest;
+        match first {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None
+        }
+    }
+}
+
+/// Encodes all genes, in order, into a single byte vector.
+pub fn encode_genes<Gene, C: GeneCodec<Gene>>(genes: &[Gene], codec: &C) -> Vec<u8> {
+    let mut output = Vec::new();
+    for gene in genes {
+        codec.encode(gene, &mut output);
+    }
+    output
+}
+
+/// Decodes genes until the bytes are used up. Returns None if any gene fails to decode.
+pub fn decode_genes<Gene, C: GeneCodec<Gene>>(mut bytes: &[u8], codec: &C) -> Option<Vec<Gene>> {
+    let mut genes = Vec::new();
+    while !bytes.is_empty() {
+        genes.push(codec.decode(&mut bytes)?);
+    }
+    Some(genes)
+}
+
+/// Writes bytes as lowercase hexadecimal, for text based protocols.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+/// Reads bytes written by to_hex. Returns None if the text is not valid hexadecimal.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+/// Hashes bytes with 64-bit FNV-1a. This algorithm is fixed and will not change between versions.
+pub fn hash_bytes(bytes: &[u8]) -> Fingerprint {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Calculates the fingerprint of a set of genes.
+pub fn fingerprint<Gene, C: GeneCodec<Gene>>(genes: &[Gene], codec: &C) -> Fingerprint {
+    hash_bytes(&encode_genes(genes, codec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_bytes_known_values() {
+        // Reference values for 64-bit FNV-1a.
+        assert_eq!(0xcbf2_9ce4_8422_2325, hash_bytes(&[]));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, hash_bytes(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, hash_bytes(b"foobar"));
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let genes: Vec<u16> = vec![1, 300, 65535];
+        let bytes = encode_genes(&genes, &PrimitiveCodec);
+        assert_eq!(6, bytes.len());
+        assert_eq!(Some(genes), decode_genes(&bytes, &PrimitiveCodec));
+    }
+
+    #[test]
+    fn decode_incomplete_gene() {
+        let decoded: Option<Vec<u32>> = decode_genes(&[1, 2, 3], &PrimitiveCodec);
+        assert!(decoded.is_none());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!("00ff10", to_hex(&[0, 255, 16]));
+        assert_eq!(Some(vec![0, 255, 16]), from_hex("00ff10"));
+        assert_eq!(None, from_hex("0g"));
+        assert_eq!(None, from_hex("abc"));
+    }
+
+    #[test]
+    fn fingerprint_depends_on_order() {
+        let forwards: Vec<u8> = vec![1, 2, 3];
+        let backwards: Vec<u8> = vec![3, 2, 1];
+        assert_eq!(fingerprint(&forwards, &PrimitiveCodec), fingerprint(&[1u8, 2, 3], &PrimitiveCodec));
+        assert_ne!(fingerprint(&forwards, &PrimitiveCodec), fingerprint(&backwards, &PrimitiveCodec));
+    }
+}
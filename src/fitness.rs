@@ -1,19 +1,27 @@
 use super::agent::Agent;
+use super::population::Population;
+use super::fingerprint::Fingerprint;
 use std::hash::Hash;
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
-    prelude::ThreadRng
-};
-use std::collections::HashMap;
+use super::random::{self, ThreadGenerator};
+use rand::{Rng, RngCore};
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub struct ScoreError {
     details: String
 }
 
+impl ScoreError {
+    pub fn new(details: &str) -> Self {
+        Self {
+            details: details.to_string()
+        }
+    }
+}
+
 impl Display for ScoreError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         write!(f, "{}", self.details)
@@ -26,41 +34,340 @@ impl Error for ScoreError {
     }
 }
 
+/// Adds random noise of up to the offset either side of the score, without going below zero.
+pub(crate) fn jitter_score(score: Score, offset: Score, rng: &mut ThreadGenerator) -> Score {
+    if offset == 0 {
+        return score;
+    }
+    (score + rng.gen_range(0, offset * 2)).saturating_sub(offset)
+}
+
 pub type FitnessFunction<Gene, Data> = fn(&Agent<Gene>, &Data) -> Result<Score, ScoreError>;
 
 pub type Score = u64;
 
+/// What a ContextFitnessFunction is told about an evaluation besides the agent and data.
+pub struct EvalContext <'a> {
+    /// The generation of the population the agent is scored for.
+    pub generation: u64,
+    /// Identifies the agent: the hash of its genes.
+    pub agent_id: u64,
+    /// The generations since the agent's birth. A child not yet in a population has the birth of
+    /// the parent it was copied from.
+    pub age: u64,
+    /// The crate's generator, so stochastic evaluations are repeated by seeded runs.
+    pub rng: &'a mut dyn RngCore
+}
+
+/// A fitness function that also receives an EvalContext, for stochastic or generation-dependent
+/// evaluation. See ContextScoreProvider.
+pub type ContextFitnessFunction<Gene, Data> = fn(&Agent<Gene>, &Data, &mut EvalContext) -> Result<Score, ScoreError>;
+
 pub trait ScoreProvider <Gene, Data> {
     fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError>;
-    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadRng) -> Result<Score, ScoreError>;
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError>;
+
+    /// Evaluates as evaluate_scores, calculating scores on up to the number of threads where the
+    /// provider supports it. Defaults to evaluate_scores on the current thread.
+    fn evaluate_scores_in_threads(&mut self, agents: Vec<Agent<Gene>>, data: &Data, _threads: usize) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        self.evaluate_scores(agents, data)
+    }
+
+    /// Gets the fitness of the agent without any noise added, from the cache where possible.
+    /// Use this rather than population score keys when reporting results. Defaults to get_score,
+    /// which suits providers that add no noise; providers that do should override it.
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.get_score(agent, data, &mut random::rng())
+    }
+
+    /// Called after each iteration with the resulting population, allowing the provider to adapt.
+    fn population_updated(&mut self, _population: &Population<Gene>) {}
+
+    /// Called when scores will be wanted for different data, such as when a Manager is given a
+    /// new job. Providers whose scores depend on the data should forget their cached scores.
+    fn data_changed(&mut self) {}
+
+    /// The number of scores calculated so far, not counting those found in a cache, where the
+    /// provider keeps count.
+    fn evaluations(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// How the offset, the maximum noise added to scores, is chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OffsetPolicy {
+    /// Always use the offset the score provider was created with.
+    Fixed,
+    /// After each iteration, set the offset to this fraction of the standard deviation of the
+    /// fitness of the agents in the population, and at least 1.
+    SpreadFraction(f64),
+    /// Set the offset once, to this fraction of the standard deviation of the fitness of the first
+    /// population seen, such as the initial population of a run, and keep it. While every score
+    /// is the same, the offset is 1 and later populations are measured until one is spread.
+    /// Calibrates again when the data changes.
+    Calibrated(f64)
+}
+
+/// What a GeneralScoreProvider does when the fitness function fails for an agent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScoreErrorPolicy {
+    /// Leave the agent out: evaluate_scores drops it, and get_score and true_score return the
+    /// error for the caller to skip the agent.
+    Skip,
+    /// Score the agent as the floor, so it is kept but is among the first culled.
+    Floor(Score),
+    /// Return the error from every method, including evaluate_scores, for callers that stop on
+    /// the first failure. Operations skip the whole batch being scored.
+    Propagate
 }
 
+/// The fraction of the initial spread of scores create_manager calibrates the offset to.
+pub const DEFAULT_CALIBRATION_FRACTION: f64 = 0.25;
+
+/// Storage for scores that have already been calculated, keyed by agent fingerprint.
+/// Implement this to keep scores in a database or shared service, so that expensive fitness
+/// evaluations can be reused across program restarts or by other workers.
+pub trait ScoreCacheStore {
+    fn get(&self, fingerprint: Fingerprint) -> Option<Score>;
+    fn put(&mut self, fingerprint: Fingerprint, score: Score);
+
+    /// Forgets the stored scores, as they no longer apply when the data changes. Stores whose
+    /// fingerprints already tell different data apart can keep them, which is the default.
+    fn clear(&mut self) {}
+}
+
+/// Values kept in memory by genome hash or fingerprint, forgetting the oldest once there are more
+/// than the capacity. Used for scores and for decoded phenotypes.
 #[derive(Clone)]
-pub struct GeneralScoreProvider <Gene, Data>
+pub struct BoundedCache <V> {
+    capacity: Option<usize>,
+    values: HashMap<u64, V>,
+    order: VecDeque<u64>
+}
+
+impl <V> Default for BoundedCache <V> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl <V> BoundedCache <V> {
+    /// capacity: the most values kept, or None for no limit.
+    pub fn new(capacity: Option<usize>) -> Self {
+        Self {
+            capacity,
+            values: HashMap::new(),
+            order: VecDeque::new()
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<&V> {
+        self.values.get(&key)
+    }
+
+    pub fn insert(&mut self, key: u64, value: V) {
+        if self.values.insert(key, value).is_some() {
+            return;
+        }
+        if let Some(capacity) = self.capacity {
+            self.order.push_back(key);
+            while self.order.len() > capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.values.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The default score cache, held in memory for the duration of the run.
+#[derive(Clone, Default)]
+pub struct InMemoryScoreCache {
+    scores: BoundedCache<Score>
+}
+
+impl InMemoryScoreCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a cache that keeps at most the number of scores, forgetting the oldest.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { scores: BoundedCache::new(Some(capacity)) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+impl ScoreCacheStore for InMemoryScoreCache {
+    fn get(&self, fingerprint: Fingerprint) -> Option<Score> {
+        self.scores.get(fingerprint).cloned()
+    }
+
+    fn put(&mut self, fingerprint: Fingerprint, score: Score) {
+        self.scores.insert(fingerprint, score);
+    }
+
+    fn clear(&mut self) {
+        self.scores.clear();
+    }
+}
+
+/// Allows a store to be shared, for example between the threads of a Manager.
+impl <Store: ScoreCacheStore> ScoreCacheStore for Arc<Mutex<Store>> {
+    fn get(&self, fingerprint: Fingerprint) -> Option<Score> {
+        self.lock().unwrap().get(fingerprint)
+    }
+
+    fn put(&mut self, fingerprint: Fingerprint, score: Score) {
+        self.lock().unwrap().put(fingerprint, score);
+    }
+
+    fn clear(&mut self) {
+        self.lock().unwrap().clear();
+    }
+}
+
+#[derive(Clone)]
+pub struct GeneralScoreProvider <Gene, Data, Store = InMemoryScoreCache>
 where
-Standard: Distribution<Gene>,
-Gene: Clone + Hash
+Gene: Clone + Hash,
+Store: ScoreCacheStore
 {
     scoring_function: FitnessFunction<Gene, Data>,
     offset: Score,
-    score_cache: HashMap<u64, Score>
+    offset_policy: OffsetPolicy,
+    calibrated: bool,
+    score_cache: Store,
+    fingerprint_function: fn(&Agent<Gene>) -> Fingerprint,
+    constraint: Option<(FitnessFunction<Gene, Data>, Score)>,
+    error_policy: ScoreErrorPolicy,
+    evaluations: u64
 }
 
 impl <Gene, Data> GeneralScoreProvider <Gene, Data>
 where
-Standard: Distribution<Gene>,
 Gene: Clone + Hash
 {
     pub fn new(scoring_function: FitnessFunction<Gene, Data>, offset: Score) -> Self {
+        Self::with_store(scoring_function, offset, InMemoryScoreCache::new(), |agent| agent.get_hash())
+    }
+}
+
+impl <Gene, Data, Store> GeneralScoreProvider <Gene, Data, Store>
+where
+Gene: Clone + Hash,
+Store: ScoreCacheStore
+{
+    /// Creates a score provider that caches scores in the given store.
+    /// fingerprint_function: determines the key that scores are stored under. For a store that
+    /// persists between runs, this should use Agent::fingerprint rather than Agent::get_hash.
+    pub fn with_store(
+        scoring_function: FitnessFunction<Gene, Data>,
+        offset: Score,
+        store: Store,
+        fingerprint_function: fn(&Agent<Gene>) -> Fingerprint
+    ) -> Self {
         Self {
-            scoring_function: scoring_function,
-            offset: offset,
-            score_cache: HashMap::new()
+            scoring_function,
+            offset,
+            offset_policy: OffsetPolicy::Fixed,
+            calibrated: false,
+            score_cache: store,
+            fingerprint_function,
+            constraint: None,
+            error_policy: ScoreErrorPolicy::Skip,
+            evaluations: 0
         }
     }
 
+    /// Constrains a second objective for epsilon-constraint optimisation: the scoring function is
+    /// optimised only among agents whose constraint function gives at most the threshold.
+    /// Agents over the threshold score zero, so they are the first to be culled. Agents the
+    /// constraint function fails on are skipped, as with the scoring function.
+    pub fn set_epsilon_constraint(&mut self, constraint_function: FitnessFunction<Gene, Data>, threshold: Score) {
+        self.constraint = Some((constraint_function, threshold));
+    }
+
+    /// Whether the agent satisfies the epsilon constraint, which it always does when none is set.
+    pub fn is_feasible(&self, agent: &Agent<Gene>, data: &Data) -> Result<bool, ScoreError> {
+        self.scoring().is_feasible(agent, data)
+    }
+
+    /// Sets what happens when the fitness or constraint function fails for an agent, applied
+    /// alike by evaluate_scores, get_score and true_score. Defaults to Skip.
+    pub fn set_error_policy(&mut self, error_policy: ScoreErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    fn calculate_score(&self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.scoring().calculate(agent, data)
+    }
+
+    fn scoring(&self) -> Scoring<Gene, Data> {
+        Scoring {
+            scoring_function: self.scoring_function,
+            constraint: self.constraint,
+            error_policy: self.error_policy
+        }
+    }
+
+    /// Scores the agent from the cache or by calculating and caching it.
+    fn cached_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        let hash = (self.fingerprint_function)(agent);
+        if let Some(score) = self.score_cache.get(hash) {
+            return Ok(score);
+        }
+        self.evaluations += 1;
+        let score = self.calculate_score(agent, data)?;
+        self.score_cache.put(hash, score);
+        Ok(score)
+    }
+
+    /// The number of scores calculated, leaving out those found in the cache.
+    pub fn get_evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    pub fn set_offset_policy(&mut self, policy: OffsetPolicy) {
+        self.offset_policy = policy;
+        self.calibrated = false;
+    }
+
+    /// The current offset, which changes between iterations when the policy is not Fixed.
+    pub fn get_offset(&self) -> Score {
+        self.offset
+    }
+
+    pub fn get_store(&self) -> &Store {
+        &self.score_cache
+    }
+
+    /// The cached score under the fingerprint, raised by the offset and lowered by the provider's
+    /// own offset, or an error if the store holds no score for it.
     pub fn offset_cached_score(&self, hash: &u64, offset: Score) -> Result<Score, ScoreError> {
-        let score = self.score_cache[&hash] + offset;
+        let score = self.score_cache.get(*hash)
+            .ok_or_else(|| ScoreError::new("no score is cached for the fingerprint"))? + offset;
         if score <= self.offset {
             return Ok(0);
         } else {
@@ -69,43 +376,847 @@ Gene: Clone + Hash
     }
 }
 
-impl <Gene, Data> ScoreProvider<Gene, Data> for GeneralScoreProvider <Gene, Data>
+/// The functions a GeneralScoreProvider calculates scores with, apart from its cache, so that
+/// scores can be calculated on other threads.
+struct Scoring <Gene, Data> {
+    scoring_function: FitnessFunction<Gene, Data>,
+    constraint: Option<(FitnessFunction<Gene, Data>, Score)>,
+    error_policy: ScoreErrorPolicy
+}
+
+impl <Gene, Data> Scoring <Gene, Data> {
+    fn is_feasible(&self, agent: &Agent<Gene>, data: &Data) -> Result<bool, ScoreError> {
+        match self.constraint {
+            Some((constraint_function, threshold)) => Ok(constraint_function(agent, data)? <= threshold),
+            None => Ok(true)
+        }
+    }
+
+    fn calculate(&self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        let result = self.is_feasible(agent, data).and_then(|feasible| if feasible {
+            (self.scoring_function)(agent, data)
+        } else {
+            Ok(0)
+        });
+        match (result, self.error_policy) {
+            (Err(_), ScoreErrorPolicy::Floor(floor)) => Ok(floor),
+            (result, _) => result
+        }
+    }
+}
+
+impl <Gene, Data, Store> ScoreProvider<Gene, Data> for GeneralScoreProvider <Gene, Data, Store>
 where
-Standard: Distribution<Gene>,
-Gene: Clone + Hash
+Gene: Clone + Hash + Send,
+Data: Clone + Send,
+Store: ScoreCacheStore
 {
     fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
         let mut cached = Vec::new();
         
         for agent in agents {
-            let hash = agent.get_hash();
-            if self.score_cache.contains_key(&hash) {
-                cached.push(agent);
-            } else {
-                let result = (self.scoring_function)(&agent, data);
-                if result.is_ok() {
-                    self.score_cache.insert(hash, result.unwrap());
-                    cached.push(agent);
+            match self.cached_score(&agent, data) {
+                Ok(_) => cached.push(agent),
+                Err(error) => if self.error_policy == ScoreErrorPolicy::Propagate {
+                    return Err(error);
                 }
-                // else we simply skip the agent.
+                // Otherwise the agent is skipped.
             }
         }
 
         Ok(cached)
     }
 
-    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadRng) -> Result<Score, ScoreError> {
-        let hash = agent.get_hash();
-        let offset = rng.gen_range(0, self.offset * 2);
+    /// Agents not already cached are scored on the threads, each thread with its own copy of the
+    /// data.
+    fn evaluate_scores_in_threads(&mut self, agents: Vec<Agent<Gene>>, data: &Data, threads: usize) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        if threads < 2 {
+            return self.evaluate_scores(agents, data);
+        }
+        let chunk_size = agents.len().div_ceil(threads);
+        let mut agents = agents.into_iter()
+            .map(|agent| (self.score_cache.get((self.fingerprint_function)(&agent)).is_some(), agent));
+        let mut jobs = Vec::with_capacity(threads);
+        loop {
+            let chunk: Vec<(bool, Agent<Gene>)> = agents.by_ref().take(chunk_size.max(1)).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            jobs.push((data.clone(), chunk));
+        }
+        let scoring = self.scoring();
+        let results = random::map_in_threads(jobs, threads, |(data, chunk)| {
+            chunk.into_iter()
+                .map(|(cached, agent)| {
+                    let result = if cached { None } else { Some(scoring.calculate(&agent, &data)) };
+                    (agent, result)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut scored = Vec::new();
+        for (agent, result) in results.into_iter().flatten() {
+            if result.is_some() {
+                self.evaluations += 1;
+            }
+            match result {
+                Some(Ok(score)) => self.score_cache.put((self.fingerprint_function)(&agent), score),
+                Some(Err(error)) => if self.error_policy == ScoreErrorPolicy::Propagate {
+                    return Err(error);
+                } else {
+                    continue;
+                },
+                None => ()
+            }
+            scored.push(agent);
+        }
+        Ok(scored)
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        let score = self.cached_score(agent, data)?;
+        Ok(jitter_score(score, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.cached_score(agent, data)
+    }
+
+    fn population_updated(&mut self, population: &Population<Gene>) {
+        let fraction = match self.offset_policy {
+            OffsetPolicy::Fixed => return,
+            OffsetPolicy::SpreadFraction(fraction) => fraction,
+            OffsetPolicy::Calibrated(_) if self.calibrated => return,
+            OffsetPolicy::Calibrated(fraction) => fraction
+        };
+
+        let scores: Vec<f64> = population.get_agents().values()
+            .filter_map(|agent| self.score_cache.get((self.fingerprint_function)(agent)))
+            .map(|score| score as f64)
+            .collect();
+        if scores.len() < 2 {
+            return;
+        }
+
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        let variance = scores.iter().map(|score| (score - mean).powi(2)).sum::<f64>() / scores.len() as f64;
+        let offset = (variance.sqrt() * fraction).round() as Score;
+        // Without noise, agents with equal scores would share a score key.
+        self.offset = offset.max(1);
+        // Scores that are all alike say nothing of their spread, so calibration waits for more.
+        self.calibrated = offset > 0;
+    }
+
+    fn data_changed(&mut self) {
+        self.score_cache.clear();
+        self.calibrated = false;
+    }
+
+    fn evaluations(&self) -> Option<u64> {
+        Some(self.evaluations)
+    }
+}
+
+/// A layer of a score provider middleware chain, calculating raw scores without noise.
+/// Start a chain with `scorer`, wrap it in layers, innermost first, and finish with `with_noise`:
+/// `scorer(fitness).logged(log).penalised(penalty).cached().with_noise(25)` caches penalised
+/// scores, logging every calculation of the fitness function.
+pub trait Scorer<Gene, Data> {
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError>;
+
+    /// Passed down the chain when the data changes, so layers holding scores can forget them.
+    fn data_changed(&mut self) {}
+
+    /// Remembers the scores calculated by the chain so far, by agent hash. Errors are not kept.
+    fn cached(self) -> Cached<Self> where Self: Sized {
+        Cached { inner: self, scores: BoundedCache::new(None) }
+    }
+
+    /// As cached, keeping at most the number of scores.
+    fn cached_with_capacity(self, capacity: usize) -> Cached<Self> where Self: Sized {
+        Cached { inner: self, scores: BoundedCache::new(Some(capacity)) }
+    }
+
+    /// Subtracts the penalty for each agent from its score, stopping at zero.
+    fn penalised<F>(self, penalty: F) -> Penalised<Self, F>
+    where
+    Self: Sized,
+    F: Fn(&Agent<Gene>, &Data) -> Score
+    {
+        Penalised { inner: self, penalty }
+    }
+
+    /// Passes each agent and its result to the log function as it is calculated.
+    fn logged<F>(self, log: F) -> Logged<Self, F>
+    where
+    Self: Sized,
+    F: FnMut(&Agent<Gene>, &Result<Score, ScoreError>)
+    {
+        Logged { inner: self, log }
+    }
+
+    /// Finishes the chain as a score provider, adding noise of up to the offset to score keys.
+    fn with_noise(self, offset: Score) -> ChainScoreProvider<Self> where Self: Sized {
+        ChainScoreProvider { scorer: self, offset }
+    }
+}
+
+/// The base of a middleware chain, calling a fitness function.
+pub struct FunctionScorer <Gene, Data> {
+    function: FitnessFunction<Gene, Data>
+}
+
+impl <Gene, Data> Clone for FunctionScorer <Gene, Data> {
+    fn clone(&self) -> Self {
+        Self { function: self.function }
+    }
+}
+
+/// Starts a middleware chain with the fitness function.
+pub fn scorer<Gene, Data>(function: FitnessFunction<Gene, Data>) -> FunctionScorer<Gene, Data> {
+    FunctionScorer { function }
+}
+
+impl <Gene, Data> Scorer<Gene, Data> for FunctionScorer <Gene, Data> {
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        (self.function)(agent, data)
+    }
+}
+
+/// See Scorer::cached.
+#[derive(Clone)]
+pub struct Cached <S> {
+    inner: S,
+    scores: BoundedCache<Score>
+}
+
+impl <Gene, Data, S: Scorer<Gene, Data>> Scorer<Gene, Data> for Cached <S> {
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        if let Some(score) = self.scores.get(agent.get_hash()) {
+            return Ok(*score);
+        }
+        let score = self.inner.score(agent, data)?;
+        self.scores.insert(agent.get_hash(), score);
+        Ok(score)
+    }
+
+    fn data_changed(&mut self) {
+        self.scores.clear();
+        self.inner.data_changed();
+    }
+}
+
+/// See Scorer::penalised.
+#[derive(Clone)]
+pub struct Penalised <S, F> {
+    inner: S,
+    penalty: F
+}
+
+impl <Gene, Data, S, F> Scorer<Gene, Data> for Penalised <S, F>
+where
+S: Scorer<Gene, Data>,
+F: Fn(&Agent<Gene>, &Data) -> Score
+{
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        Ok(self.inner.score(agent, data)?.saturating_sub((self.penalty)(agent, data)))
+    }
+
+    fn data_changed(&mut self) {
+        self.inner.data_changed();
+    }
+}
+
+/// See Scorer::logged.
+#[derive(Clone)]
+pub struct Logged <S, F> {
+    inner: S,
+    log: F
+}
+
+impl <Gene, Data, S, F> Scorer<Gene, Data> for Logged <S, F>
+where
+S: Scorer<Gene, Data>,
+F: FnMut(&Agent<Gene>, &Result<Score, ScoreError>)
+{
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        let result = self.inner.score(agent, data);
+        (self.log)(agent, &result);
+        result
+    }
+
+    fn data_changed(&mut self) {
+        self.inner.data_changed();
+    }
+}
+
+/// A score provider made from a middleware chain. See Scorer::with_noise.
+#[derive(Clone)]
+pub struct ChainScoreProvider <S> {
+    scorer: S,
+    offset: Score
+}
+
+impl <S> ChainScoreProvider <S> {
+    pub fn get_scorer(&self) -> &S {
+        &self.scorer
+    }
+}
+
+impl <Gene, Data, S: Scorer<Gene, Data>> ScoreProvider<Gene, Data> for ChainScoreProvider <S> {
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        Ok(agents.into_iter().filter(|agent| self.scorer.score(agent, data).is_ok()).collect())
+    }
 
-        if self.score_cache.contains_key(&hash) {
-            return self.offset_cached_score(&hash, offset);
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        Ok(jitter_score(self.scorer.score(agent, data)?, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.scorer.score(agent, data)
+    }
+
+    fn data_changed(&mut self) {
+        self.scorer.data_changed();
+    }
+}
+
+/// Scores agents with a ContextFitnessFunction. The generation is taken from the population after
+/// each iteration, and scores are only cached until the generation changes, so an agent's true
+/// score is for the current generation.
+#[derive(Clone)]
+pub struct ContextScoreProvider <Gene, Data> {
+    scoring_function: ContextFitnessFunction<Gene, Data>,
+    offset: Score,
+    generation: u64,
+    scores: HashMap<u64, Score>
+}
+
+impl <Gene, Data> ContextScoreProvider <Gene, Data> {
+    pub fn new(scoring_function: ContextFitnessFunction<Gene, Data>, offset: Score) -> Self {
+        Self {
+            scoring_function,
+            offset,
+            generation: 0,
+            scores: HashMap::new()
         }
+    }
 
-        let score = (self.scoring_function)(agent, data).unwrap();
-        self.score_cache.insert(hash, score);
+    /// The generation agents are currently scored for.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
 
-        return self.offset_cached_score(&hash, offset);
+    fn calculate_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        if let Some(score) = self.scores.get(&agent.get_hash()) {
+            return Ok(*score);
+        }
+        let mut context = EvalContext {
+            generation: self.generation,
+            agent_id: agent.get_hash(),
+            age: self.generation.saturating_sub(agent.get_birth()),
+            rng: &mut random::rng()
+        };
+        let score = (self.scoring_function)(agent, data, &mut context)?;
+        self.scores.insert(agent.get_hash(), score);
+        Ok(score)
     }
 }
 
+impl <Gene, Data> ScoreProvider<Gene, Data> for ContextScoreProvider <Gene, Data> {
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        Ok(agents.into_iter().filter(|agent| self.calculate_score(agent, data).is_ok()).collect())
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        let score = self.calculate_score(agent, data)?;
+        Ok(jitter_score(score, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.calculate_score(agent, data)
+    }
+
+    fn population_updated(&mut self, population: &Population<Gene>) {
+        if population.get_generation() != self.generation {
+            self.generation = population.get_generation();
+            self.scores.clear();
+        }
+    }
+
+    fn data_changed(&mut self) {
+        self.scores.clear();
+    }
+}
+
+/// The samples taken of a noisy agent's fitness, from AveragedScoreProvider.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SampleStats {
+    pub samples: usize,
+    pub mean: f64,
+    // The sum of squared differences from the mean, updated with Welford's method.
+    squares: f64
+}
+
+impl SampleStats {
+    fn add(&mut self, score: Score) {
+        self.samples += 1;
+        let delta = score as f64 - self.mean;
+        self.mean += delta / self.samples as f64;
+        self.squares += delta * (score as f64 - self.mean);
+    }
+
+    /// The sample standard deviation, zero with fewer than two samples.
+    pub fn std_dev(&self) -> f64 {
+        if self.samples < 2 {
+            return 0.0;
+        }
+        (self.squares / (self.samples - 1) as f64).sqrt()
+    }
+
+    /// The approximate 95% confidence interval of the mean.
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        let margin = 1.96 * self.std_dev() / (self.samples.max(1) as f64).sqrt();
+        (self.mean - margin, self.mean + margin)
+    }
+}
+
+/// Scores agents with a stochastic fitness function, such as a simulation with randomness, by
+/// the mean of several evaluations. The samples are cached per genome, so an agent's score does
+/// not rest on the first noisy sample forever: the highest scored agents can be sampled again
+/// after each iteration with set_elite_resampling, refining the means the run relies on most.
+/// With set_racing, new agents that are clearly worse than the incumbents stop being evaluated
+/// before all their samples are taken.
+#[derive(Clone)]
+pub struct AveragedScoreProvider <Gene, Data> {
+    scoring_function: FitnessFunction<Gene, Data>,
+    samples: usize,
+    offset: Score,
+    elites: usize,
+    elite_samples: usize,
+    racing: Option<(usize, usize)>,
+    incumbent_bound: Option<f64>,
+    evaluations: u64,
+    raced_out: u64,
+    data: Option<Data>,
+    stats: HashMap<u64, SampleStats>
+}
+
+impl <Gene, Data: Clone> AveragedScoreProvider <Gene, Data> {
+    /// Creates a provider evaluating each new agent the number of samples times.
+    pub fn new(scoring_function: FitnessFunction<Gene, Data>, samples: usize, offset: Score) -> Self {
+        Self {
+            scoring_function,
+            samples: samples.max(1),
+            offset,
+            elites: 0,
+            elite_samples: 0,
+            racing: None,
+            incumbent_bound: None,
+            evaluations: 0,
+            raced_out: 0,
+            data: None,
+            stats: HashMap::new()
+        }
+    }
+
+    /// After each iteration, evaluates the given number of the highest scored agents the number
+    /// of samples more times. Their population score keys are left as they are, but their true
+    /// scores, and their keys when next inserted, use the refined means. Defaults to none.
+    pub fn set_elite_resampling(&mut self, elites: usize, samples: usize) {
+        self.elites = elites;
+        self.elite_samples = samples;
+    }
+
+    /// Races new agents against the given number of the highest scored agents, the incumbents.
+    /// Once an agent has had min_samples evaluations, it gets no more if the upper bound of its
+    /// confidence interval is below the lowest bound of the incumbents', and is scored by the mean
+    /// so far. The incumbents are taken from the population after each iteration.
+    pub fn set_racing(&mut self, min_samples: usize, incumbents: usize) {
+        self.racing = Some((min_samples.max(2), incumbents.max(1)));
+    }
+
+    /// The number of times the fitness function has been called.
+    pub fn get_evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    /// The number of agents whose evaluation was cut short by racing.
+    pub fn get_raced_out(&self) -> u64 {
+        self.raced_out
+    }
+
+    /// The samples taken of the agent, if it has been scored.
+    pub fn get_stats(&self, agent: &Agent<Gene>) -> Option<SampleStats> {
+        self.stats.get(&agent.get_hash()).cloned()
+    }
+
+    /// Evaluates the agent the number of samples more times.
+    fn sample(&mut self, agent: &Agent<Gene>, data: &Data, samples: usize) -> Result<SampleStats, ScoreError> {
+        let mut stats = self.stats.get(&agent.get_hash()).cloned().unwrap_or_default();
+        for _ in 0..samples {
+            stats.add((self.scoring_function)(agent, data)?);
+            self.evaluations += 1;
+        }
+        self.stats.insert(agent.get_hash(), stats);
+        Ok(stats)
+    }
+
+    /// Evaluates a new agent, stopping early if racing shows it is clearly below the incumbents.
+    fn race(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<SampleStats, ScoreError> {
+        let (min_samples, bound) = match (self.racing, self.incumbent_bound) {
+            (Some((min_samples, _)), Some(bound)) => (min_samples, bound),
+            _ => return self.sample(agent, data, self.samples)
+        };
+        let mut stats = self.sample(agent, data, min_samples.min(self.samples))?;
+        while stats.samples < self.samples {
+            if stats.confidence_interval().1 < bound {
+                self.raced_out += 1;
+                break;
+            }
+            stats = self.sample(agent, data, 1)?;
+        }
+        Ok(stats)
+    }
+
+    fn calculate_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        if self.data.is_none() {
+            self.data = Some(data.clone());
+        }
+        let stats = match self.stats.get(&agent.get_hash()) {
+            Some(stats) => *stats,
+            None => self.race(agent, data)?
+        };
+        Ok(stats.mean.round() as Score)
+    }
+}
+
+impl <Gene, Data: Clone> ScoreProvider<Gene, Data> for AveragedScoreProvider <Gene, Data> {
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        Ok(agents.into_iter().filter(|agent| self.calculate_score(agent, data).is_ok()).collect())
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        let score = self.calculate_score(agent, data)?;
+        Ok(jitter_score(score, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        self.calculate_score(agent, data)
+    }
+
+    fn population_updated(&mut self, population: &Population<Gene>) {
+        if self.elites > 0 && self.elite_samples > 0 {
+            if let Some(data) = self.data.take() {
+                for (_, agent) in population.top_k(self.elites) {
+                    // An agent that fails is left with the samples it had.
+                    let _ = self.sample(agent, &data, self.elite_samples);
+                }
+                self.data = Some(data);
+            }
+        }
+        if let Some((_, incumbents)) = self.racing {
+            self.incumbent_bound = population.top_k(incumbents).iter()
+                .filter_map(|(_, agent)| self.stats.get(&agent.get_hash()))
+                .map(|stats| stats.confidence_interval().0)
+                .fold(None, |lowest: Option<f64>, bound| Some(lowest.map_or(bound, |lowest| lowest.min(bound))));
+        }
+    }
+
+    fn data_changed(&mut self) {
+        self.stats.clear();
+        self.incumbent_bound = None;
+        self.data = None;
+    }
+
+    fn evaluations(&self) -> Option<u64> {
+        Some(self.evaluations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use super::super::random;
+
+    // Later generations score higher, with a little noise from the context's generator.
+    fn generation_bonus(agent: &Agent<u8>, _data: &u8, context: &mut EvalContext) -> Result<Score, ScoreError> {
+        let sum: Score = agent.get_genes().iter().map(|gene| *gene as Score).sum();
+        Ok(sum + context.generation * 1000 + context.rng.next_u32() as Score % 2)
+    }
+
+    #[test]
+    fn context_provider_scores_for_the_generation() {
+        let mut provider = ContextScoreProvider::new(generation_bonus, 0);
+        let mut population = Population::new_empty(false);
+        population.insert(5, Agent::from_genes(vec![2u8, 3]));
+        let agent = Agent::from_genes(vec![2u8, 3]);
+        let first = provider.true_score(&agent, &0).unwrap();
+        assert!(first == 5 || first == 6);
+        assert_eq!(first, provider.true_score(&agent, &0).unwrap());
+
+        population.advance_generation();
+        population.advance_generation();
+        provider.population_updated(&population);
+        assert_eq!(2, provider.get_generation());
+        assert!(provider.true_score(&agent, &0).unwrap() >= 2005);
+        assert_eq!(1, provider.evaluate_scores(vec![agent], &0).unwrap().len());
+    }
+
+    // The sum of the genes, plus or minus up to 20.
+    fn noisy_sum(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        let sum: Score = agent.get_genes().iter().map(|gene| *gene as Score).sum();
+        Ok(sum + 100 + random::rng().gen_range(0, 41) - 20)
+    }
+
+    #[test]
+    fn averaged_provider_refines_elites() {
+        let mut provider = AveragedScoreProvider::new(noisy_sum, 4, 0);
+        provider.set_elite_resampling(1, 8);
+        let best = Agent::from_genes(vec![200u8]);
+        let worst = Agent::from_genes(vec![10u8]);
+        let score = provider.true_score(&best, &0).unwrap();
+        assert!((280..=320).contains(&score));
+        assert_eq!(score, provider.true_score(&best, &0).unwrap());
+        provider.true_score(&worst, &0).unwrap();
+        assert_eq!(4, provider.get_stats(&best).unwrap().samples);
+
+        let mut population = Population::new_empty(false);
+        population.insert(score, best.clone());
+        population.insert(110, worst.clone());
+        provider.population_updated(&population);
+        let stats = provider.get_stats(&best).unwrap();
+        assert_eq!(12, stats.samples);
+        assert_eq!(4, provider.get_stats(&worst).unwrap().samples);
+        let (low, high) = stats.confidence_interval();
+        assert!(low <= stats.mean && stats.mean <= high && high - low < 40.0);
+
+        provider.data_changed();
+        assert!(provider.get_stats(&best).is_none());
+    }
+
+    #[test]
+    fn racing_cuts_short_clearly_worse_agents() {
+        random::seed(3);
+        let mut provider = AveragedScoreProvider::new(noisy_sum, 20, 0);
+        provider.set_racing(3, 1);
+        let best = Agent::from_genes(vec![200u8, 200]);
+        let mut population = Population::new_empty(false);
+        population.insert(provider.true_score(&best, &0).unwrap(), best);
+        provider.population_updated(&population);
+        assert_eq!(20, provider.get_evaluations());
+
+        // Far below the incumbent, so three samples are enough.
+        let worse = Agent::from_genes(vec![1u8]);
+        provider.true_score(&worse, &0).unwrap();
+        assert_eq!(3, provider.get_stats(&worse).unwrap().samples);
+        assert_eq!(1, provider.get_raced_out());
+
+        // Better than the incumbent, so it is sampled fully.
+        let better = Agent::from_genes(vec![200u8, 200, 20]);
+        provider.true_score(&better, &0).unwrap();
+        assert_eq!(20, provider.get_stats(&better).unwrap().samples);
+        assert_eq!(43, provider.get_evaluations());
+    }
+
+    // Fails for agents whose first gene is zero.
+    fn score_nonzero(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        match agent.get_genes()[0] {
+            0 => Err(ScoreError::new("zero")),
+            gene => Ok(gene as Score)
+        }
+    }
+
+    #[test]
+    fn error_policy_applies_to_every_path() {
+        let failing = Agent::from_genes(vec![0u8]);
+        let passing = Agent::from_genes(vec![5u8]);
+        let mut rng = random::rng();
+
+        let mut provider = GeneralScoreProvider::new(score_nonzero, 0);
+        assert_eq!(1, provider.evaluate_scores(vec![failing.clone(), passing.clone()], &0).unwrap().len());
+        assert!(provider.get_score(&failing, &0, &mut rng).is_err());
+        assert!(provider.true_score(&failing, &0).is_err());
+        assert_eq!(5, provider.true_score(&passing, &0).unwrap());
+
+        provider.set_error_policy(ScoreErrorPolicy::Floor(1));
+        assert_eq!(2, provider.evaluate_scores(vec![failing.clone(), passing.clone()], &0).unwrap().len());
+        assert_eq!(1, provider.get_score(&failing, &0, &mut rng).unwrap());
+
+        let mut provider = GeneralScoreProvider::new(score_nonzero, 0);
+        provider.set_error_policy(ScoreErrorPolicy::Propagate);
+        assert!(provider.evaluate_scores(vec![passing.clone(), failing.clone()], &0).is_err());
+        assert!(provider.evaluate_scores_in_threads(vec![passing.clone(), failing.clone()], &0, 2).is_err());
+        assert!(provider.true_score(&failing, &0).is_err());
+        assert_eq!(5, provider.true_score(&passing, &0).unwrap());
+    }
+
+    fn sum_genes(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().map(|gene| *gene as Score).sum())
+    }
+
+    #[test]
+    fn spread_fraction_offset_follows_population() {
+        let mut provider = GeneralScoreProvider::new(sum_genes, 25);
+        provider.set_offset_policy(OffsetPolicy::SpreadFraction(0.5));
+
+        let mut population = Population::new_empty(false);
+        for genes in [vec![10u8], vec![30u8]] {
+            let agent = Agent::from_genes(genes);
+            let agent = provider.evaluate_scores(vec![agent], &0).unwrap().pop().unwrap();
+            population.insert(agent.get_genes()[0] as Score, agent);
+        }
+
+        // Standard deviation of 10 and 30 is 10.
+        provider.population_updated(&population);
+        assert_eq!(5, provider.get_offset());
+
+        // With a fixed policy the offset is left alone.
+        provider.set_offset_policy(OffsetPolicy::Fixed);
+        population.insert(200, provider.evaluate_scores(vec![Agent::from_genes(vec![200u8])], &0).unwrap().pop().unwrap());
+        provider.population_updated(&population);
+        assert_eq!(5, provider.get_offset());
+    }
+
+    #[test]
+    fn calibrated_offset_is_set_once() {
+        let mut provider = GeneralScoreProvider::new(sum_genes, 0);
+        provider.set_offset_policy(OffsetPolicy::Calibrated(0.5));
+
+        let mut population = Population::new_empty(false);
+        for gene in [10u8, 30u8] {
+            let agent = provider.evaluate_scores(vec![Agent::from_genes(vec![gene])], &0).unwrap().pop().unwrap();
+            population.insert(gene as Score, agent);
+        }
+        provider.population_updated(&population);
+        assert_eq!(5, provider.get_offset());
+
+        population.insert(200, provider.evaluate_scores(vec![Agent::from_genes(vec![200u8])], &0).unwrap().pop().unwrap());
+        provider.population_updated(&population);
+        assert_eq!(5, provider.get_offset());
+
+        provider.data_changed();
+        provider.evaluate_scores(population.get_agents().values().cloned().collect(), &0).unwrap();
+        provider.population_updated(&population);
+        assert!(provider.get_offset() > 5);
+    }
+
+    #[test]
+    fn calibration_waits_for_spread_scores() {
+        let mut provider = GeneralScoreProvider::new(sum_genes, 0);
+        provider.set_offset_policy(OffsetPolicy::Calibrated(0.5));
+
+        let mut population = Population::new_empty(false);
+        for (score, genes) in [(1, vec![10u8, 0]), (2, vec![0u8, 10])] {
+            let agent = provider.evaluate_scores(vec![Agent::from_genes(genes)], &0).unwrap().pop().unwrap();
+            population.insert(score, agent);
+        }
+        provider.population_updated(&population);
+        assert_eq!(1, provider.get_offset());
+
+        population.insert(3, provider.evaluate_scores(vec![Agent::from_genes(vec![30u8, 0])], &0).unwrap().pop().unwrap());
+        provider.population_updated(&population);
+        let calibrated = provider.get_offset();
+        assert!(calibrated > 1);
+
+        population.insert(4, provider.evaluate_scores(vec![Agent::from_genes(vec![200u8, 0])], &0).unwrap().pop().unwrap());
+        provider.population_updated(&population);
+        assert_eq!(calibrated, provider.get_offset());
+    }
+
+    #[test]
+    fn true_score_has_no_noise() {
+        let mut provider = GeneralScoreProvider::new(sum_genes, 25);
+        let agent = Agent::from_genes(vec![40u8, 2]);
+        for _ in 0..10 {
+            assert_eq!(42, provider.true_score(&agent, &0).unwrap());
+        }
+    }
+
+    #[test]
+    fn shared_store_reused_by_new_provider() {
+        let store = Arc::new(Mutex::new(InMemoryScoreCache::new()));
+        let agent = Agent::from_genes(vec![1u8, 2, 3]);
+
+        let mut first = GeneralScoreProvider::with_store(sum_genes, 1, store.clone(), |agent| agent.fingerprint(&PrimitiveCodec));
+        first.evaluate_scores(vec![agent.clone()], &0).unwrap();
+        assert_eq!(1, store.lock().unwrap().len());
+        assert_eq!(Some(6), store.get(agent.fingerprint(&PrimitiveCodec)));
+
+        // A second provider finds the stored score rather than calling its fitness function.
+        fn never_called(_agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            panic!("score should have come from the store");
+        }
+        let mut second = GeneralScoreProvider::with_store(never_called, 1, store.clone(), |agent| agent.fingerprint(&PrimitiveCodec));
+        let score = second.get_score(&agent, &0, &mut random::rng()).unwrap();
+        assert!((5..=6).contains(&score));
+    }
+
+    #[test]
+    fn epsilon_constraint_zeroes_infeasible_agents() {
+        // Maximise the sum while keeping the first gene at most 2.
+        fn first_gene(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            Ok(agent.get_genes()[0] as Score)
+        }
+        let mut score_provider = GeneralScoreProvider::new(sum_genes, 0);
+        score_provider.set_epsilon_constraint(first_gene, 2);
+
+        let feasible = Agent::from_genes(vec![2u8, 5]);
+        let infeasible = Agent::from_genes(vec![3u8, 5]);
+        assert!(score_provider.is_feasible(&feasible, &0).unwrap());
+        assert!(!score_provider.is_feasible(&infeasible, &0).unwrap());
+
+        let scored = score_provider.evaluate_scores(vec![feasible.clone(), infeasible.clone()], &0).unwrap();
+        assert_eq!(2, scored.len());
+        assert_eq!(7, score_provider.true_score(&feasible, &0).unwrap());
+        assert_eq!(0, score_provider.true_score(&infeasible, &0).unwrap());
+    }
+
+    fn one_per_gene(agent: &Agent<u8>, _data: &u8) -> Score {
+        agent.get_genes().len() as Score
+    }
+
+    #[test]
+    fn middleware_chain_layers_compose() {
+        let calls = std::cell::Cell::new(0);
+        let mut provider = scorer(sum_genes)
+            .logged(|_agent: &Agent<u8>, _result: &Result<Score, ScoreError>| calls.set(calls.get() + 1))
+            .penalised(one_per_gene)
+            .cached()
+            .with_noise(0);
+
+        let agent = Agent::from_genes(vec![10u8, 20]);
+        assert_eq!(28, provider.true_score(&agent, &0).unwrap());
+        assert_eq!(28, provider.get_score(&agent, &0, &mut random::rng()).unwrap());
+        assert_eq!(1, provider.evaluate_scores(vec![agent.clone()], &0).unwrap().len());
+        assert_eq!(1, calls.get());
+
+        provider.data_changed();
+        provider.true_score(&agent, &0).unwrap();
+        assert_eq!(2, calls.get());
+    }
+
+    #[test]
+    fn bounded_cache_forgets_oldest() {
+        let mut cache = InMemoryScoreCache::with_capacity(2);
+        cache.put(1, 10);
+        cache.put(2, 20);
+        cache.put(1, 11);
+        cache.put(3, 30);
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get(1));
+        assert_eq!(Some(30), cache.get(3));
+    }
+
+    #[test]
+    fn offset_cached_score_needs_a_cached_score() {
+        let mut provider = GeneralScoreProvider::new(sum_genes, 5);
+        let agent = Agent::from_genes(vec![4u8, 6]);
+        assert!(provider.offset_cached_score(&agent.get_hash(), 0).is_err());
+
+        provider.true_score(&agent, &0).unwrap();
+        assert_eq!(8, provider.offset_cached_score(&agent.get_hash(), 3).unwrap());
+        assert_eq!(5, provider.offset_cached_score(&agent.get_hash(), 0).unwrap());
+    }
+}
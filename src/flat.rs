@@ -0,0 +1,149 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Genomes laid out in one contiguous buffer, for fitness evaluation on a GPU or with SIMD.
+//!
+//! Export a population's genomes, evaluate the buffer however is fastest, and import the scores
+//! back by index. Genes are converted to a primitive numeric type such as f32 on export.
+
+use super::agent::Agent;
+use super::fitness::Score;
+use super::population::Population;
+
+/// The genes of several agents one after another, with the position each agent starts at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlatGenomes <T> {
+    /// Every gene of every agent, in agent order.
+    pub genes: Vec<T>,
+    /// The index in genes where each agent starts, followed by the total number of genes, so agent
+    /// i has the genes from offsets[i] up to offsets[i + 1].
+    pub offsets: Vec<usize>,
+    /// The score key of each agent, when exported from a population.
+    pub keys: Vec<Score>
+}
+
+impl <T> FlatGenomes <T> {
+    /// Exports the agents of the population in ascending score order.
+    pub fn from_population<Gene>(population: &Population<Gene>) -> Self
+    where
+    Gene: Clone + Into<T>
+    {
+        let mut flat = Self::from_agents(population.get_agents().values());
+        flat.keys = population.get_scores();
+        flat
+    }
+
+    /// Exports agents that have not been scored yet, such as a batch of children.
+    pub fn from_agents<'a, Gene, I>(agents: I) -> Self
+    where
+    Gene: Clone + Into<T> + 'a,
+    I: IntoIterator<Item = &'a Agent<Gene>>
+    {
+        let mut genes = Vec::new();
+        let mut offsets = vec![0];
+        for agent in agents {
+            genes.extend(agent.get_genes().iter().cloned().map(Into::into));
+            offsets.push(genes.len());
+        }
+        Self { genes, offsets, keys: Vec::new() }
+    }
+
+    /// The number of agents.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The genes of the agent at the index.
+    pub fn genome(&self, index: usize) -> &[T] {
+        &self.genes[self.offsets[index]..self.offsets[index + 1]]
+    }
+
+    /// The number of genes each agent has, if they all have the same number, so the buffer can be
+    /// treated as a matrix with a fixed stride.
+    pub fn stride(&self) -> Option<usize> {
+        let stride = self.offsets.get(1)? - self.offsets[0];
+        if self.offsets.windows(2).all(|pair| pair[1] - pair[0] == stride) {
+            Some(stride)
+        } else {
+            None
+        }
+    }
+
+    /// Moves each exported agent of the population to the score at the same index, for scores
+    /// calculated from the buffer. Agents no longer in the population are skipped, as are indexes
+    /// without a score. Returns the number of agents moved.
+    pub fn import_scores<Gene>(&self, population: &mut Population<Gene>, scores: &[Score]) -> usize
+    where
+    Gene: Clone
+    {
+        // Remove every agent first, so a new score cannot replace an agent yet to be moved.
+        let moving: Vec<(Score, Agent<Gene>)> = self.keys.iter().zip(scores.iter())
+            .filter_map(|(key, score)| population.remove(*key).map(|agent| (*score, agent)))
+            .collect();
+        let moved = moving.len();
+        for (score, agent) in moving {
+            population.insert_ranked(score, agent);
+        }
+        moved
+    }
+}
+
+/// Inserts agents exported with FlatGenomes::from_agents into the population with the scores
+/// calculated for them, by index. Agents without a score are dropped, and agents with equal scores
+/// are all kept.
+pub fn insert_scored<Gene>(population: &mut Population<Gene>, agents: Vec<Agent<Gene>>, scores: &[Score]) {
+    for (agent, score) in agents.into_iter().zip(scores.iter()) {
+        population.insert_ranked(*score, agent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_import() {
+        let mut population = Population::new_empty(false);
+        population.insert(20, Agent::from_genes(vec![3u8, 4]));
+        population.insert(10, Agent::from_genes(vec![1u8, 2]));
+
+        let flat: FlatGenomes<f32> = FlatGenomes::from_population(&population);
+        assert_eq!(vec![1.0, 2.0, 3.0, 4.0], flat.genes);
+        assert_eq!(vec![0, 2, 4], flat.offsets);
+        assert_eq!(Some(2), flat.stride());
+        assert_eq!(&[3.0, 4.0], flat.genome(1));
+
+        // Scores swap the agents' order.
+        let scores: Vec<Score> = (0..flat.len()).map(|index| 100 - flat.genome(index)[0] as Score).collect();
+        assert_eq!(2, flat.import_scores(&mut population, &scores));
+        assert_eq!(vec![97, 99], population.get_scores());
+        assert_eq!(&vec![3u8, 4], population.get(97).unwrap().get_genes());
+    }
+
+    #[test]
+    fn unscored_agents() {
+        let agents = vec![Agent::from_genes(vec![1u8]), Agent::from_genes(vec![2u8, 3])];
+        let flat: FlatGenomes<u32> = FlatGenomes::from_agents(&agents);
+        assert_eq!(None, flat.stride());
+        assert_eq!(&[2, 3], flat.genome(1));
+
+        let mut population = Population::new_empty(false);
+        insert_scored(&mut population, agents, &[7]);
+        assert_eq!(vec![7], population.get_scores());
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::agent::Provenance;
+use super::evolution::Observer;
+use super::fitness::Score;
+use super::operations::OperationType;
+use super::population::Population;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+
+/// Statistics about the population at the end of an iteration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterationStats {
+    pub iteration: u64,
+    pub population_size: usize,
+    pub best: Score,
+    pub worst: Score,
+    pub mean: f64,
+    /// The proportion of agents with distinct genes, from 0.0 to 1.0.
+    pub diversity: f64,
+    /// The highest best score of this and earlier iterations.
+    pub best_so_far: Score,
+    /// The best score, exponentially smoothed over earlier iterations.
+    pub smoothed_best: f64,
+    /// The mean score, exponentially smoothed over earlier iterations.
+    pub smoothed_mean: f64
+}
+
+impl IterationStats {
+    /// Calculates the statistics for a population. Returns None if the population is empty.
+    pub fn from_population<Gene>(iteration: u64, population: &Population<Gene>) -> Option<Self> {
+        let agents = population.get_agents();
+        let best = population.best_score()?;
+        let worst = population.worst_score()?;
+        let mean = agents.keys().map(|score| *score as f64).sum::<f64>() / agents.len() as f64;
+        let distinct: HashSet<u64> = agents.values().map(|agent| agent.get_hash()).collect();
+
+        Some(Self {
+            iteration,
+            population_size: agents.len(),
+            best,
+            worst,
+            mean,
+            diversity: distinct.len() as f64 / agents.len() as f64,
+            best_so_far: best,
+            smoothed_best: best as f64,
+            smoothed_mean: mean
+        })
+    }
+
+    /// Carries the best score and smoothed scores on from the previous iteration's statistics.
+    /// smoothing: the weight of this iteration's scores, from 0.0 to 1.0, the rest going to the
+    /// previous smoothed scores.
+    pub fn follow(mut self, previous: &IterationStats, smoothing: f64) -> Self {
+        self.best_so_far = self.best.max(previous.best_so_far);
+        self.smoothed_best = smoothing * self.best as f64 + (1.0 - smoothing) * previous.smoothed_best;
+        self.smoothed_mean = smoothing * self.mean + (1.0 - smoothing) * previous.smoothed_mean;
+        self
+    }
+}
+
+/// An Observer that keeps the statistics of every iteration, counted across all runs it observes.
+/// Smoothed scores and the best so far run on across runs too.
+#[derive(Clone)]
+pub struct RunHistory {
+    iteration: u64,
+    smoothing: f64,
+    stats: Vec<IterationStats>
+}
+
+impl Default for RunHistory {
+    fn default() -> Self {
+        Self {
+            iteration: 0,
+            smoothing: 0.1,
+            stats: Vec::new()
+        }
+    }
+}
+
+impl RunHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the weight each iteration's scores have in the smoothed scores, from 0.0 to 1.0.
+    /// Lower values smooth more. Defaults to 0.1.
+    pub fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+    }
+
+    pub fn get_stats(&self) -> &Vec<IterationStats> {
+        &self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+}
+
+impl <Gene> Observer<Gene> for RunHistory {
+    fn iteration_complete(&mut self, _iteration: usize, population: &Population<Gene>) {
+        self.iteration += 1;
+        if let Some(stats) = IterationStats::from_population(self.iteration, population) {
+            let stats = match self.stats.last() {
+                Some(previous) => stats.follow(previous, self.smoothing),
+                None => stats
+            };
+            self.stats.push(stats);
+        }
+    }
+}
+
+/// The proportion of agents carrying each allele at one locus, among those long enough to have it.
+pub type LocusFrequencies<Gene> = HashMap<Gene, f64>;
+
+/// An Observer that records the allele frequencies at every locus after each iteration, so that
+/// drift curves show which loci selection acts on, and which merely hitchhike with them.
+#[derive(Clone)]
+pub struct AlleleHistory <Gene> {
+    iteration: u64,
+    interval: u64,
+    iterations: Vec<u64>,
+    frequencies: Vec<Vec<LocusFrequencies<Gene>>>
+}
+
+impl <Gene> Default for AlleleHistory <Gene> {
+    fn default() -> Self {
+        Self {
+            iteration: 0,
+            interval: 1,
+            iterations: Vec::new(),
+            frequencies: Vec::new()
+        }
+    }
+}
+
+impl <Gene: Hash + Eq + Clone> AlleleHistory <Gene> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records every interval iterations rather than every one, for long genomes or runs.
+    /// Defaults to 1.
+    pub fn set_interval(&mut self, interval: u64) {
+        self.interval = interval.max(1);
+    }
+
+    /// The iterations recorded, counted across all runs observed.
+    pub fn get_iterations(&self) -> &[u64] {
+        &self.iterations
+    }
+
+    /// The frequencies at each locus for each recorded iteration.
+    pub fn get_frequencies(&self) -> &Vec<Vec<LocusFrequencies<Gene>>> {
+        &self.frequencies
+    }
+
+    /// The frequency of the allele at the locus in each recorded iteration.
+    pub fn drift_curve(&self, locus: usize, allele: &Gene) -> Vec<f64> {
+        self.frequencies.iter()
+            .map(|loci| loci.get(locus).and_then(|alleles| alleles.get(allele)).cloned().unwrap_or(0.0))
+            .collect()
+    }
+
+    /// How far the frequencies at each locus have moved between the first and last recorded
+    /// iterations, as the total variation distance from 0.0, unchanged, to 1.0, no allele in
+    /// common. A locus only one of them has, as when genomes grow, has drifted 1.0. Loci under
+    /// selection drift furthest.
+    pub fn locus_drift(&self) -> Vec<f64> {
+        let (first, last) = match (self.frequencies.first(), self.frequencies.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec::new()
+        };
+        (0..first.len().max(last.len()))
+            .map(|locus| {
+                let (before, after) = match (first.get(locus), last.get(locus)) {
+                    (Some(before), Some(after)) => (before, after),
+                    _ => return 1.0
+                };
+                let alleles: HashSet<&Gene> = before.keys().chain(after.keys()).collect();
+                alleles.into_iter()
+                    .map(|allele| (before.get(allele).unwrap_or(&0.0) - after.get(allele).unwrap_or(&0.0)).abs())
+                    .sum::<f64>() / 2.0
+            })
+            .collect()
+    }
+}
+
+impl <Gene: Hash + Eq + Clone> Observer<Gene> for AlleleHistory <Gene> {
+    fn iteration_complete(&mut self, _iteration: usize, population: &Population<Gene>) {
+        self.iteration += 1;
+        if self.iteration % self.interval != 0 {
+            return;
+        }
+        let mut counts: Vec<HashMap<Gene, usize>> = Vec::new();
+        for agent in population.get_agents().values() {
+            let genes = agent.get_genes();
+            if counts.len() < genes.len() {
+                counts.resize(genes.len(), HashMap::new());
+            }
+            for (locus, gene) in genes.iter().enumerate() {
+                *counts[locus].entry(gene.clone()).or_insert(0) += 1;
+            }
+        }
+        let frequencies = counts.into_iter()
+            .map(|alleles| {
+                let carriers = alleles.values().sum::<usize>() as f64;
+                alleles.into_iter().map(|(allele, count)| (allele, count as f64 / carriers)).collect()
+            })
+            .collect();
+        self.iterations.push(self.iteration);
+        self.frequencies.push(frequencies);
+    }
+}
+
+/// How an agent joined a population, as recorded by a Lineage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ancestry {
+    /// The generation the agent joined the population in.
+    pub generation: u64,
+    /// The operation that bred it, or None if it was created without one, such as a random or
+    /// heuristic agent, an immigrant or one inserted directly.
+    pub operation: Option<OperationType>,
+    /// The genome hashes of its parents, the first being the one it was copied from.
+    pub parents: Vec<u64>,
+    /// Which of two parents each gene came from, for crossovers that record provenance.
+    /// See Operation::set_record_provenance.
+    pub provenance: Option<Provenance>
+}
+
+impl Ancestry {
+    /// The ancestry of an agent created without parents in the generation.
+    pub fn created(generation: u64) -> Self {
+        Self { generation, operation: None, parents: Vec::new(), provenance: None }
+    }
+}
+
+/// How every agent that joined a population was made, by genome hash, so that the mutations
+/// and crossovers that built an agent can be traced. Only the first time a genome joins is
+/// recorded. See Population::set_record_lineage.
+#[derive(Clone, Debug, Default)]
+pub struct Lineage {
+    ancestries: HashMap<u64, Ancestry>,
+    // Noted as operations breed children, before insertions can move or remove their parents.
+    expected: HashMap<u64, Ancestry>
+}
+
+impl Lineage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records how the genome joined, unless it already has.
+    pub fn record(&mut self, hash: u64, ancestry: Ancestry) {
+        self.ancestries.entry(hash).or_insert(ancestry);
+    }
+
+    /// Notes how a child was bred, to be recorded if it joins. The first note for a genome is kept.
+    pub(crate) fn expect(&mut self, hash: u64, ancestry: Ancestry) {
+        self.expected.entry(hash).or_insert(ancestry);
+    }
+
+    /// Records the genome as it was noted with expect, or as created in the generation if it was
+    /// not.
+    pub(crate) fn record_joined(&mut self, hash: u64, generation: u64) {
+        let ancestry = self.expected.remove(&hash).unwrap_or_else(|| Ancestry::created(generation));
+        self.record(hash, ancestry);
+    }
+
+    /// Forgets the notes for children that did not join.
+    pub(crate) fn clear_expected(&mut self) {
+        self.expected.clear();
+    }
+
+    pub fn get(&self, hash: u64) -> Option<&Ancestry> {
+        self.ancestries.get(&hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ancestries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ancestries.is_empty()
+    }
+
+    /// Forgets the genomes that are not among the living or their ancestors, so long runs only
+    /// keep what can still be traced.
+    pub fn prune<I: IntoIterator<Item = u64>>(&mut self, living: I) {
+        let mut kept = HashSet::new();
+        let mut pending: Vec<u64> = living.into_iter().collect();
+        while let Some(hash) = pending.pop() {
+            if kept.insert(hash) {
+                if let Some(ancestry) = self.ancestries.get(&hash) {
+                    pending.extend(ancestry.parents.iter().cloned());
+                }
+            }
+        }
+        self.ancestries.retain(|hash, _| kept.contains(hash));
+    }
+
+    /// The recorded steps that built the genome: the genome and every ancestor, oldest first and
+    /// parents before their children. Ancestors joined before recording began, or since pruned,
+    /// end the trace there.
+    pub fn trace(&self, hash: u64) -> Trace {
+        let mut steps = Vec::new();
+        let mut visited = HashSet::new();
+        // Each genome is taken twice: first to queue its parents, then to add it after them.
+        let mut pending = vec![(hash, false)];
+        while let Some((hash, parents_added)) = pending.pop() {
+            let ancestry = match self.ancestries.get(&hash) {
+                Some(ancestry) => ancestry,
+                None => continue
+            };
+            if parents_added {
+                steps.push(TraceStep { hash, ancestry: ancestry.clone() });
+            } else if visited.insert(hash) {
+                pending.push((hash, true));
+                pending.extend(ancestry.parents.iter().rev().map(|parent| (*parent, false)));
+            }
+        }
+        // Stable, so parents stay before children of the same generation.
+        steps.sort_by_key(|step| step.ancestry.generation);
+        Trace { steps }
+    }
+}
+
+/// One agent of a Trace and how it was made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    pub hash: u64,
+    pub ancestry: Ancestry
+}
+
+/// How an agent was built, from Lineage::trace. Displays as one line per step, such as
+/// `generation 4: crossover of 3f2a... and 91c0... made 5d7e...`, followed for crossovers that
+/// recorded provenance by the parent, 1 or 2, each gene came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>
+}
+
+/// Names the operation as a trace describes it.
+fn describe(operation: OperationType) -> &'static str {
+    match operation {
+        OperationType::Mutate => "mutation",
+        OperationType::LengthMutate => "length mutation",
+        OperationType::CoordinateSweep => "coordinate sweep",
+        OperationType::Crossover => "crossover",
+        OperationType::SpeciatedCrossover => "speciated crossover",
+        OperationType::CrowdingCrossover => "crowding crossover",
+        OperationType::ForeignCrossover => "foreign crossover",
+        OperationType::Cull | OperationType::ParetoCull | OperationType::Restart => "operation"
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for step in &self.steps {
+            let ancestry = &step.ancestry;
+            let operation = match ancestry.operation {
+                Some(operation) => operation,
+                None => {
+                    writeln!(f, "generation {}: created {:016x}", ancestry.generation, step.hash)?;
+                    continue;
+                }
+            };
+            let parents: Vec<String> = ancestry.parents.iter().map(|parent| format!("{:016x}", parent)).collect();
+            write!(f, "generation {}: {} of {} made {:016x}", ancestry.generation, describe(operation), parents.join(" and "), step.hash)?;
+            if let Some(ref provenance) = ancestry.provenance {
+                let loci: String = (0..provenance.len()).map(|locus| if provenance.from_second_parent(locus) { '2' } else { '1' }).collect();
+                write!(f, ", genes from parents {}", loci)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::agent::{self, Agent};
+
+    fn bred(generation: u64, operation: OperationType, parents: Vec<u64>) -> Ancestry {
+        Ancestry { generation, operation: Some(operation), parents, provenance: None }
+    }
+
+    #[test]
+    fn lineage_traces_how_an_agent_was_built() {
+        let mut lineage = Lineage::new();
+        lineage.record(1, Ancestry::created(0));
+        lineage.record(2, Ancestry::created(0));
+        lineage.record(3, bred(2, OperationType::Mutate, vec![1]));
+        lineage.record(4, bred(5, OperationType::Crossover, vec![3, 2]));
+        lineage.record(4, bred(6, OperationType::Mutate, vec![1]));
+        lineage.record(5, bred(6, OperationType::Mutate, vec![1]));
+
+        let trace = lineage.trace(4);
+        let steps: Vec<(u64, u64, Option<OperationType>)> = trace.steps.iter().map(|step| (step.ancestry.generation, step.hash, step.ancestry.operation)).collect();
+        assert_eq!(vec![(0, 1, None), (0, 2, None), (2, 3, Some(OperationType::Mutate)), (5, 4, Some(OperationType::Crossover))], steps);
+        assert_eq!(
+            "generation 0: created 0000000000000001\n\
+             generation 0: created 0000000000000002\n\
+             generation 2: mutation of 0000000000000001 made 0000000000000003\n\
+             generation 5: crossover of 0000000000000003 and 0000000000000002 made 0000000000000004\n",
+            trace.to_string()
+        );
+
+        lineage.prune(vec![3]);
+        assert_eq!(2, lineage.len());
+        assert!(lineage.get(1).is_some() && lineage.get(3).is_some());
+    }
+
+    #[test]
+    fn traces_show_crossover_provenance() {
+        let first = Agent::from_genes(vec![1u8, 1, 1, 1]);
+        let second = Agent::from_genes(vec![2u8, 2, 2, 2]);
+        let child = agent::crossover_with_provenance(&first, &second);
+        let provenance = child.get_provenance().unwrap().clone();
+        let loci: String = child.get_genes().iter().map(|gene| if *gene == 1 { '1' } else { '2' }).collect();
+
+        let mut lineage = Lineage::new();
+        lineage.record(child.get_hash(), Ancestry { provenance: Some(provenance), ..bred(1, OperationType::Crossover, vec![first.get_hash(), second.get_hash()]) });
+        let trace = lineage.trace(child.get_hash());
+        assert!(trace.to_string().ends_with(&format!("made {:016x}, genes from parents {}\n", child.get_hash(), loci)));
+    }
+
+    #[test]
+    fn stats_from_population() {
+        let mut population = Population::new_empty(false);
+        let agent = Agent::from_genes(vec![1u8, 2]);
+        population.insert(2, agent.clone());
+        population.insert(4, agent);
+        population.insert(9, Agent::from_genes(vec![3u8, 4]));
+
+        let stats = IterationStats::from_population(7, &population).unwrap();
+        assert_eq!(7, stats.iteration);
+        assert_eq!(3, stats.population_size);
+        assert_eq!(9, stats.best);
+        assert_eq!(2, stats.worst);
+        assert_eq!(5.0, stats.mean);
+        assert!((stats.diversity - 2.0 / 3.0).abs() < 1e-9);
+
+        let empty: Population<u8> = Population::new_empty(false);
+        assert!(IterationStats::from_population(0, &empty).is_none());
+    }
+
+    #[test]
+    fn allele_history_finds_drifting_loci() {
+        let mut before = Population::new_empty(false);
+        before.insert(1, Agent::from_genes(vec![0u8, 5]));
+        before.insert(2, Agent::from_genes(vec![1u8, 5]));
+        let mut after = Population::new_empty(false);
+        after.insert(1, Agent::from_genes(vec![1u8, 5]));
+        after.insert(2, Agent::from_genes(vec![1u8, 5, 7]));
+
+        let mut history = AlleleHistory::new();
+        history.iteration_complete(0, &before);
+        history.iteration_complete(0, &after);
+
+        assert_eq!(&[1, 2], history.get_iterations());
+        assert_eq!(vec![0.5, 1.0], history.drift_curve(0, &1));
+        assert_eq!(vec![0.0, 1.0], history.drift_curve(2, &7));
+        // The third locus is new, so has drifted all the way.
+        assert_eq!(vec![0.5, 0.0, 1.0], history.locus_drift());
+    }
+
+    #[test]
+    fn history_counts_iterations_across_runs() {
+        let mut population = Population::new_empty(false);
+        population.insert(3, Agent::from_genes(vec![1u8]));
+
+        let mut history = RunHistory::new();
+        history.iteration_complete(0, &population);
+        history.iteration_complete(0, &population);
+
+        assert_eq!(vec![1, 2], history.get_stats().iter().map(|stats| stats.iteration).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn history_smooths_scores() {
+        let mut high = Population::new_empty(false);
+        high.insert(10, Agent::from_genes(vec![1u8]));
+        let mut low = Population::new_empty(false);
+        low.insert(0, Agent::from_genes(vec![1u8]));
+
+        let mut history = RunHistory::new();
+        history.set_smoothing(0.5);
+        history.iteration_complete(0, &high);
+        history.iteration_complete(0, &low);
+        history.iteration_complete(0, &low);
+
+        let last = history.get_stats().last().unwrap();
+        assert_eq!(0, last.best);
+        assert_eq!(10, last.best_so_far);
+        assert_eq!(2.5, last.smoothed_best);
+        assert_eq!(2.5, last.smoothed_mean);
+    }
+}
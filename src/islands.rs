@@ -0,0 +1,227 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An island model: several populations evolved apart, exchanging their best agents in a ring.
+//!
+//! Islands that have stalled, with a best true score that has not improved for a number of
+//! migrations, can be restarted from random agents and a few migrants from the best island, so
+//! compute is not wasted on islands stuck on a local optimum.
+
+use super::agent::{Agent, GeneBounds};
+use super::evolution::run_iterations;
+use super::fitness::{Score, ScoreProvider};
+use super::operations::Operation;
+use super::population::Population;
+use rand::distributions::{Distribution, Standard};
+
+/// A set of islands, each a population evolved with the same operations.
+pub struct IslandModel <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    islands: Vec<Population<Gene>>,
+    operations: Vec<Operation<Gene, Data>>,
+    island_size: usize,
+    number_of_genes: usize,
+    migrants: usize,
+    iterations_per_migration: usize,
+    restart_after: Option<usize>,
+    best: Vec<Option<Score>>,
+    stalled: Vec<usize>,
+    restarts: usize
+}
+
+impl <Gene, Data> IslandModel <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    /// Creates the number of islands, each of island_size random agents. By default two agents
+    /// migrate after every 10 iterations, and islands are never restarted.
+    pub fn new<SP>(
+        islands: usize,
+        island_size: usize,
+        number_of_genes: usize,
+        operations: Vec<Operation<Gene, Data>>,
+        data: &Data,
+        score_provider: &mut SP
+    ) -> Self
+    where
+    SP: ScoreProvider<Gene, Data>
+    {
+        Self {
+            islands: (0..islands).map(|_| Population::new(island_size, number_of_genes, false, data, score_provider)).collect(),
+            operations,
+            island_size,
+            number_of_genes,
+            migrants: 2,
+            iterations_per_migration: 10,
+            restart_after: None,
+            best: vec![None; islands],
+            stalled: vec![0; islands],
+            restarts: 0
+        }
+    }
+
+    /// Sets the number of best agents each island sends to the next.
+    pub fn set_migrants(&mut self, migrants: usize) {
+        self.migrants = migrants;
+    }
+
+    pub fn set_iterations_per_migration(&mut self, iterations: usize) {
+        self.iterations_per_migration = iterations;
+    }
+
+    /// Restarts islands whose best true score has not improved for the number of migrations.
+    pub fn set_restart_after(&mut self, migrations: usize) {
+        self.restart_after = Some(migrations.max(1));
+    }
+
+    pub fn get_islands(&self) -> &Vec<Population<Gene>> {
+        &self.islands
+    }
+
+    /// The number of islands restarted so far.
+    pub fn get_restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Evolves every island, then migrates, the number of times.
+    pub fn run<SP>(&mut self, migrations: usize, data: &Data, score_provider: &mut SP)
+    where
+    SP: Clone + ScoreProvider<Gene, Data>
+    {
+        for _ in 0..migrations {
+            let islands = std::mem::take(&mut self.islands);
+            self.islands = islands.into_iter()
+                .map(|island| run_iterations(island, self.iterations_per_migration, data, &self.operations, score_provider))
+                .collect();
+            self.migrate(data, score_provider);
+            self.restart_stalled(data, score_provider);
+        }
+    }
+
+    /// The agent with the highest true score on any island.
+    pub fn true_best<SP>(&self, score_provider: &mut SP, data: &Data) -> Option<(Score, &Agent<Gene>)>
+    where
+    SP: ScoreProvider<Gene, Data>
+    {
+        let mut best: Option<(Score, &Agent<Gene>)> = None;
+        for island in &self.islands {
+            if let Some((score, agent)) = island.true_best(score_provider, data) {
+                if best.is_none_or(|(highest, _)| score > highest) {
+                    best = Some((score, agent));
+                }
+            }
+        }
+        best
+    }
+
+    /// Each island sends copies of its highest scored agents to the next, in a ring.
+    fn migrate<SP>(&mut self, data: &Data, score_provider: &mut SP)
+    where
+    SP: ScoreProvider<Gene, Data>
+    {
+        if self.islands.len() < 2 {
+            return;
+        }
+        let emigrants: Vec<Vec<Agent<Gene>>> = self.islands.iter()
+            .map(|island| island.get_agents().values().rev().take(self.migrants).cloned().collect())
+            .collect();
+        let count = self.islands.len();
+        for (index, agents) in emigrants.into_iter().enumerate() {
+            self.islands[(index + 1) % count].score_and_insert(agents, data, score_provider);
+        }
+    }
+
+    fn restart_stalled<SP>(&mut self, data: &Data, score_provider: &mut SP)
+    where
+    SP: ScoreProvider<Gene, Data>
+    {
+        let restart_after = match self.restart_after {
+            Some(restart_after) => restart_after,
+            None => return
+        };
+
+        let bests: Vec<Option<Score>> = self.islands.iter()
+            .map(|island| island.true_best(score_provider, data).map(|(score, _)| score))
+            .collect();
+        let leader = (0..bests.len()).max_by_key(|index| bests[*index]);
+        for (index, best) in bests.iter().enumerate() {
+            if *best > self.best[index] {
+                self.best[index] = *best;
+                self.stalled[index] = 0;
+                continue;
+            }
+            self.stalled[index] += 1;
+            if self.stalled[index] < restart_after || Some(index) == leader {
+                continue;
+            }
+
+            let mut island = Population::new(self.island_size, self.number_of_genes, false, data, score_provider);
+            if let Some(leader) = leader {
+                let migrants = self.islands[leader].get_agents().values().rev().take(self.migrants).cloned().collect();
+                island.score_and_insert(migrants, data, score_provider);
+            }
+            self.islands[index] = island;
+            self.best[index] = None;
+            self.stalled[index] = 0;
+            self.restarts += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::{GeneralScoreProvider, ScoreError};
+    use super::super::operations::{OperationType, Selection, SelectionType};
+
+    fn sum_genes(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().map(|gene| *gene as Score).sum())
+    }
+
+    #[test]
+    fn stalled_islands_restart() {
+        let mut score_provider = GeneralScoreProvider::new(sum_genes, 0);
+        // Without operations no island can improve on its own.
+        let mut islands = IslandModel::new(3, 10, 4, Vec::new(), &0, &mut score_provider);
+        islands.set_restart_after(2);
+        islands.run(6, &0, &mut score_provider);
+        assert!(islands.get_restarts() > 0);
+        assert_eq!(3, islands.get_islands().len());
+    }
+
+    #[test]
+    fn migration_spreads_best_agents() {
+        let mut score_provider = GeneralScoreProvider::new(sum_genes, 0);
+        let operations = vec![
+            Operation::new(OperationType::Mutate, Selection::new(SelectionType::HighestScore, 0.2)),
+            Operation::new(OperationType::Cull, Selection::new(SelectionType::LowestScore, 0.2)),
+        ];
+        let mut islands = IslandModel::new(3, 10, 4, operations, &0, &mut score_provider);
+        islands.run(1, &0, &mut score_provider);
+        let (best, _) = islands.true_best(&mut score_provider, &0).unwrap();
+
+        // After enough migrations, every island has an agent at least as good as the best was.
+        islands.set_iterations_per_migration(0);
+        islands.run(3, &0, &mut score_provider);
+        for island in islands.get_islands() {
+            assert!(island.true_best(&mut score_provider, &0).unwrap().0 >= best);
+        }
+    }
+}
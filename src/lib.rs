@@ -13,6 +13,17 @@
 // limitations under the License.
 
 extern crate rand;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "viz")]
+extern crate plotters;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+// The pyo3 macros refer to ::core, which the 2015 edition only provides as an extern crate.
+#[cfg(feature = "python")]
+extern crate core;
 
 pub mod agent;
 pub mod operations;
@@ -20,3 +31,47 @@ pub mod population;
 pub mod evolution;
 pub mod manager;
 pub mod fitness;
+pub mod fingerprint;
+pub mod distributed;
+#[cfg(feature = "serde")]
+pub mod remote;
+pub mod snapshot;
+pub mod history;
+pub mod speciation;
+pub mod rules;
+pub mod subset;
+pub mod schedule;
+pub mod pareto;
+pub mod experiments;
+pub mod tiers;
+pub mod analysis;
+pub mod processes;
+pub mod flat;
+pub mod numeric;
+pub mod disk;
+pub mod strategies;
+pub mod migration;
+pub mod elites;
+pub mod islands;
+pub mod phenotype;
+pub mod testing;
+pub mod pool;
+pub mod register;
+pub mod meta;
+pub mod random;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "viz")]
+pub mod viz;
+#[cfg(feature = "neat")]
+pub mod neat;
+
+pub use operations::{Pipeline, Selection};
+
+// Used by the uniform_gene macro, so that crates using it need not name rand themselves.
+#[doc(hidden)]
+pub mod __rand {
+    pub use rand::{distributions::{Distribution, Standard}, Rng};
+}
@@ -12,25 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::fitness::{Score, ScoreProvider, GeneralScoreProvider, FitnessFunction};
-use super::population::Population;
-use super::evolution::run_iterations;
+use super::fitness::{Score, ScoreError, ScoreProvider, GeneralScoreProvider, FitnessFunction, OffsetPolicy, DEFAULT_CALIBRATION_FRACTION};
+use super::population::{AcceptancePolicy, Population};
+use super::tiers::ExploitTier;
+use super::migration::Migration;
+use super::elites::EliteArchive;
+use super::testing::{Simulation, VirtualSchedule};
+use super::random;
+use super::evolution::{run_iterations, run_iterations_with_observer, run_drifting_iterations, run_throttled_iterations, DataHook, Observer, OperationThrottle, ThrottlePolicy};
 use rand::{
-    distributions::{Distribution, Standard}
+    distributions::{Distribution, Standard},
+    RngCore
 };
-use std::hash::Hash;
 use super::operations::{
     Operation,
     OperationType,
+    Pipeline,
     Selection,
     SelectionType,
     cull_lowest_agents
 };
 use std::thread; 
 use std::sync::mpsc::channel;
-use super::agent::Agent;
+use super::agent::{Agent, GeneBounds, GenomeHasher};
 use std::collections::BTreeMap;
 use std::sync::mpsc::{Sender, Receiver};
+use std::sync::Arc;
+use std::marker::PhantomData;
+use std::cmp::Reverse;
+use std::mem;
+use std::io;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Returns a Manager object that will run the genetic algorithm.
 /// Use this function if you're just writing a fitness function and not 
@@ -39,24 +54,324 @@ use std::sync::mpsc::{Sender, Receiver};
 /// data: additional immutable data to be used by during the run of the algorithm. Could be used as
 /// a cache containing pre-calculated values or an initial state for data that will be changed when reading
 /// the genes. Just use 0 if you have no other use for this argument.
+/// The noise added to score keys is calibrated to the spread of scores in the initial population.
+/// See OffsetPolicy::Calibrated.
 pub fn create_manager<Gene, Data> (
     fitness_function: FitnessFunction<Gene, Data>,
     data: Data
 ) -> Manager<Gene, Data, GeneralScoreProvider<Gene, Data>>
 where 
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static
 {
-    let score_provider = GeneralScoreProvider::new(fitness_function, 25);
+    let mut score_provider = GeneralScoreProvider::new(fitness_function, 0);
+    score_provider.set_offset_policy(OffsetPolicy::Calibrated(DEFAULT_CALIBRATION_FRACTION));
     let manager = Manager::new(score_provider, data);
     manager 
 }
 
+/// Starts building a manager with the fitness function and data, as create_manager does. The
+/// number of genes must be set before the manager can be built, so forgetting it is a compile
+/// error rather than a run of 10-gene agents:
+///
+/// ```compile_fail
+/// use xu::agent::Agent;
+/// use xu::fitness::{Score, ScoreError};
+/// use xu::manager::manager_builder;
+///
+/// fn fitness(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+///     Ok(agent.get_genes().len() as Score)
+/// }
+///
+/// let manager = manager_builder(fitness, 0).build();
+/// ```
+pub fn manager_builder<Gene, Data> (
+    fitness_function: FitnessFunction<Gene, Data>,
+    data: Data
+) -> ManagerBuilder<Gene, Data, GeneralScoreProvider<Gene, Data>, GenesUnset>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    ManagerBuilder::from_manager(create_manager(fitness_function, data))
+}
+
+/// Marks a ManagerBuilder whose number of genes has not been set.
+pub struct GenesUnset;
+
+/// Marks a ManagerBuilder whose number of genes has been set, so it can build.
+pub struct GenesSet;
+
+/// Configures a Manager, only building it once the number of genes has been set.
+/// See manager_builder.
+pub struct ManagerBuilder <Gene, Data, SP, State>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + Send + ScoreProvider<Gene, Data> + 'static
+{
+    manager: Manager<Gene, Data, SP>,
+    state: PhantomData<State>
+}
+
+impl <Gene, Data, SP> ManagerBuilder <Gene, Data, SP, GenesUnset>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + Send + ScoreProvider<Gene, Data> + 'static
+{
+    /// Starts building a manager with a score provider of your own.
+    pub fn new(score_provider: SP, data: Data) -> Self {
+        Self::from_manager(Manager::new(score_provider, data))
+    }
+
+    fn from_manager(manager: Manager<Gene, Data, SP>) -> Self {
+        Self { manager, state: PhantomData }
+    }
+
+    /// Sets the number of genes of the agents created. See Manager::set_number_of_genes.
+    pub fn number_of_genes(mut self, number: usize, strict: bool) -> ManagerBuilder<Gene, Data, SP, GenesSet> {
+        self.manager.set_number_of_genes(number, strict);
+        ManagerBuilder { manager: self.manager, state: PhantomData }
+    }
+}
+
+impl <Gene, Data, SP, State> ManagerBuilder <Gene, Data, SP, State>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + Send + ScoreProvider<Gene, Data> + 'static
+{
+    /// Changes any other setting of the manager being built.
+    pub fn configure<F>(mut self, configure: F) -> Self
+    where
+    F: FnOnce(&mut Manager<Gene, Data, SP>)
+    {
+        configure(&mut self.manager);
+        self
+    }
+}
+
+impl <Gene, Data, SP> ManagerBuilder <Gene, Data, SP, GenesSet>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static,
+SP: Clone + Send + ScoreProvider<Gene, Data> + 'static
+{
+    pub fn build(self) -> Manager<Gene, Data, SP> {
+        self.manager
+    }
+}
+
+/// Builds the genes of a good starting agent from domain knowledge, such as a nearest neighbour
+/// tour, using the random number generator to vary its choices.
+pub type Heuristic<Gene, Data> = fn(&Data, &mut dyn RngCore) -> Vec<Gene>;
+
+/// When a Manager's run is finished, judged by the true score of the best agent in the main
+/// population.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Goal {
+    /// Finished once the best true score is at least this.
+    AtLeast(Score),
+    /// Finished once the best true score is at most this. Scores are always maximised, so as the
+    /// run's goal this stops a run as soon as the best agent scores no higher than this, not once
+    /// a falling score gets low enough. It suits measures given to Manager::add_goal, such as a
+    /// count of constraint violations.
+    AtMost(Score),
+    /// Never finished by score, so the run ends only at its cycle limit.
+    /// See Manager::set_max_cycles.
+    Never
+}
+
+impl Goal {
+    pub fn is_met(&self, best: Score) -> bool {
+        match *self {
+            Goal::AtLeast(goal) => best >= goal,
+            Goal::AtMost(goal) => best <= goal,
+            Goal::Never => false
+        }
+    }
+}
+
+/// The seeds a run's random numbers came from, enough to replay it with Manager::replay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RngAudit {
+    /// The seed of the generator on the thread that ran the main population.
+    pub master_seed: u64,
+    /// The seed of each child population, in the order they were started. These are drawn from
+    /// the master generator, so a replay derives them again.
+    pub stream_seeds: Vec<u64>,
+    pub goal: Goal
+}
+
+/// A prediction of the work a Manager's run will do, from Manager::estimate_cost.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostEstimate {
+    /// Children, each scored once, that the operations breed in a generation of a population at
+    /// its maximum size. Coordinate sweeps count an evaluation per gene of each selected agent.
+    pub evaluations_per_generation: usize,
+    /// Generations of the main population, warmup included, or None without a cycle limit.
+    pub generations: Option<usize>,
+    /// Evaluations of the main population and of the most child populations started each cycle.
+    pub total_evaluations: Option<usize>,
+    /// The time the main population's evaluations take. Child populations run on other threads
+    /// alongside it, so this is the run's time when there are cores for them.
+    pub runtime: Option<Duration>
+}
+
+/// Turns an agent evolved in one stage of a StagePlan into genes for the next stage's genome, such
+/// as by splitting each coarse gene into several finer ones.
+pub type ReEncoder<Gene, Data> = fn(&Agent<Gene>, &Data) -> Vec<Gene>;
+
+/// One run of a StagePlan.
+#[derive(Clone)]
+pub struct Stage <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    number_of_genes: usize,
+    goal: Goal,
+    max_cycles: Option<usize>,
+    operations: Option<Vec<Operation<Gene, Data>>>,
+    re_encoder: Option<ReEncoder<Gene, Data>>,
+    scores_change: bool
+}
+
+impl <Gene, Data> Stage <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    /// A stage evolving genomes of the number of genes until the goal is met.
+    pub fn new(number_of_genes: usize, goal: Goal) -> Self {
+        Self {
+            number_of_genes,
+            goal,
+            max_cycles: None,
+            operations: None,
+            re_encoder: None,
+            scores_change: false
+        }
+    }
+
+    /// Limits the stage's cycles. Without a limit the stage keeps the manager's.
+    pub fn set_max_cycles(&mut self, cycles: usize) {
+        self.max_cycles = Some(cycles);
+    }
+
+    /// Sets the stage's operations. Without them the stage keeps the manager's.
+    pub fn set_operations(&mut self, operations: Vec<Operation<Gene, Data>>) {
+        self.operations = Some(operations);
+    }
+
+    /// Sets how agents carried over from the previous stage are re-encoded. Without one they are
+    /// carried over as they are.
+    pub fn set_re_encoder(&mut self, re_encoder: ReEncoder<Gene, Data>) {
+        self.re_encoder = Some(re_encoder);
+    }
+
+    /// Sets whether the same genes score differently in this stage than in the last, such as when
+    /// the fitness function reads the genome at a finer resolution. The score provider then
+    /// forgets its cached scores before the stage. Defaults to false, keeping the cache, which
+    /// stays valid while a genome scores the same in every stage.
+    pub fn set_scores_change(&mut self, scores_change: bool) {
+        self.scores_change = scores_change;
+    }
+}
+
+/// Stages of optimisation run one after another by Manager::run_stages, such as evolving a
+/// coarse genome and then refining the best results in a finer one.
+#[derive(Clone)]
+pub struct StagePlan <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    stages: Vec<Stage<Gene, Data>>,
+    carry_over: usize
+}
+
+impl <Gene, Data> StagePlan <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    /// An empty plan carrying the number of agents with the highest true scores from each stage to
+    /// the next.
+    pub fn new(carry_over: usize) -> Self {
+        Self { stages: Vec::new(), carry_over }
+    }
+
+    /// Adds a stage to run after the others.
+    pub fn then(mut self, stage: Stage<Gene, Data>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+}
+
+/// What happens to child threads still running when a Manager's run finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildShutdown {
+    /// Leaves them running. Their results are collected by the next run, or lost if there is
+    /// none.
+    Detach,
+    /// Waits for them to finish their iterations and merges their agents into the main population.
+    Merge,
+    /// Asks them to stop at the end of their current iteration, waits for them and discards
+    /// their agents.
+    Cancel
+}
+
+/// When a Manager starts child threads, each running a population of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnPolicy {
+    /// Tops the children up to the maximum every cycle.
+    Always,
+    /// Starts children only after a cycle in which the best true score of the main population
+    /// changed by less than this, so helpers are only spent when the main population is stuck.
+    /// Running children are still collected.
+    WhenChangeBelow(Score)
+}
+
+/// A Manager's operations and settings with its seed: a definition of an experiment that, with the
+/// serde feature, can be saved and loaded to reproduce a run. Settings not held here, such as
+/// functions, observers and policies, must be configured again. See Manager::get_experiment.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Experiment <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    pub operations: Vec<Operation<Gene, Data>>,
+    pub warmup_operations: Vec<Operation<Gene, Data>>,
+    pub warmup_generations: usize,
+    pub number_of_genes: usize,
+    pub strict_gene_length: bool,
+    pub initial_population_size: usize,
+    pub max_population: Option<usize>,
+    pub iterations_per_cycle: usize,
+    pub max_child_threads: u8,
+    pub max_cycles: Option<usize>,
+    pub seed: Option<u64>
+}
+
 pub struct Manager <Gene, Data, SP>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static,
 SP: Clone + ScoreProvider<Gene, Data> + 'static
 {
@@ -65,20 +380,53 @@ SP: Clone + ScoreProvider<Gene, Data> + 'static
     number_of_genes: usize,
     strict_gene_length: bool,
     initial_population_size: usize,
+    heuristic: Option<(Heuristic<Gene, Data>, usize)>,
+    max_population: Option<usize>,
+    buffer_pool_capacity: usize,
+    genome_hasher: GenomeHasher,
     current_highest: Score,
     agent_sender: Sender<BTreeMap<Score, Agent<Gene>>>,
     agent_receiver: Receiver<BTreeMap<Score, Agent<Gene>>>,
     number_of_child_threads: u8,
     max_child_threads: u8,
+    child_handles: Vec<thread::JoinHandle<()>>,
+    child_shutdown: ChildShutdown,
+    spawn_policy: SpawnPolicy,
+    last_change: Option<Score>,
+    cancel_children: Arc<AtomicBool>,
     operations: Vec<Operation<Gene, Data>>,
+    warmup_generations: usize,
+    warmup_operations: Vec<Operation<Gene, Data>>,
     iterations_per_cycle: usize,
-    score_provider: SP
+    score_provider: SP,
+    observers: Vec<Box<dyn Observer<Gene> + Send>>,
+    lifespan: Option<(u64, usize)>,
+    acceptance_policy: Option<Arc<dyn AcceptancePolicy<Gene>>>,
+    record_rejections: Option<usize>,
+    record_lineage: bool,
+    exploit_tier: Option<ExploitTier<Gene, Data>>,
+    max_cycles: Option<usize>,
+    operation_throttle: Option<OperationThrottle>,
+    goals: Vec<(FitnessFunction<Gene, Data>, Goal)>,
+    restart_on_stagnation: Option<(usize, f64)>,
+    stagnation: (Score, usize),
+    stop_signal: Option<Arc<AtomicBool>>,
+    migration: Option<(Box<dyn Migration<Gene>>, usize)>,
+    migration_error: Option<io::Error>,
+    score_error: Option<ScoreError>,
+    data_hook: Option<DataHook<Data>>,
+    elite_archive: EliteArchive<Gene>,
+    simulation: Option<Simulation<Gene>>,
+    seed: Option<u64>,
+    record_seeds: bool,
+    rng_audit: Option<RngAudit>,
+    next_step_operation: Option<usize>
 }
 
 impl <Gene, Data, SP> Manager <Gene, Data, SP>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static,
 SP: Clone + Send + ScoreProvider<Gene, Data>
 {
@@ -86,12 +434,8 @@ SP: Clone + Send + ScoreProvider<Gene, Data>
 
         let (tx, rx) = channel::<BTreeMap<Score, Agent<Gene>>>();
 
-        let operations = vec![
-            Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 0.1)),
-            Operation::new(OperationType::Crossover, Selection::new(SelectionType::HighestScore, 0.2)),
-            Operation::new(OperationType::Crossover, Selection::new(SelectionType::RandomAny, 0.2)),
-            Operation::new(OperationType::Cull, Selection::new(SelectionType::LowestScore, 0.1)),
-        ];
+        let operations = Pipeline::balanced().into_operations();
+        let warmup_operations = Pipeline::aggressive_explore().into_operations();
 
         Self {
             main_population: Population::new_empty(false),
@@ -99,14 +443,47 @@ SP: Clone + Send + ScoreProvider<Gene, Data>
             number_of_genes: 10,
             strict_gene_length: false,
             initial_population_size: 100,
+            heuristic: None,
+            max_population: None,
+            buffer_pool_capacity: 0,
+            genome_hasher: GenomeHasher::Std,
             current_highest: 0,
             agent_sender: tx,
             agent_receiver: rx,
             number_of_child_threads: 0,
             max_child_threads: 3,
+            child_handles: Vec::new(),
+            child_shutdown: ChildShutdown::Detach,
+            spawn_policy: SpawnPolicy::Always,
+            last_change: None,
+            cancel_children: Arc::new(AtomicBool::new(false)),
             operations: operations,
+            warmup_generations: 0,
+            warmup_operations,
             iterations_per_cycle: 100,
-            score_provider: score_provider
+            score_provider: score_provider,
+            observers: Vec::new(),
+            lifespan: None,
+            acceptance_policy: None,
+            record_rejections: None,
+            record_lineage: false,
+            exploit_tier: None,
+            max_cycles: None,
+            operation_throttle: None,
+            goals: Vec::new(),
+            restart_on_stagnation: None,
+            stagnation: (0, 0),
+            stop_signal: None,
+            migration: None,
+            migration_error: None,
+            score_error: None,
+            data_hook: None,
+            elite_archive: EliteArchive::new(10),
+            simulation: None,
+            seed: None,
+            record_seeds: false,
+            rng_audit: None,
+            next_step_operation: None
         }
     }
 
@@ -119,47 +496,785 @@ SP: Clone + Send + ScoreProvider<Gene, Data>
         self.initial_population_size = size;
     }
 
+    /// Limits every population the manager runs to the number of agents, removing the lowest
+    /// scored between iterations. Defaults to ten times the initial population size, as the
+    /// default operations add more agents each iteration than they cull. A size of zero leaves
+    /// populations empty after every iteration.
+    pub fn set_max_population(&mut self, size: usize) {
+        self.max_population = Some(size);
+    }
+
+    /// Sets how many gene buffers of culled agents each population keeps to reuse for children,
+    /// cutting allocations when populations or genomes are large. Defaults to zero, keeping none.
+    /// See Population::set_buffer_pool_capacity.
+    pub fn set_buffer_pool_capacity(&mut self, capacity: usize) {
+        self.buffer_pool_capacity = capacity;
+    }
+
+    /// Sets how the agents of every population hash their genes. Defaults to GenomeHasher::Std.
+    /// See Population::set_genome_hasher.
+    pub fn set_genome_hasher(&mut self, hasher: GenomeHasher) {
+        self.genome_hasher = hasher;
+    }
+
+    fn get_max_population(&self) -> usize {
+        self.max_population.unwrap_or(self.initial_population_size.saturating_mul(10))
+    }
+
     pub fn set_operations(&mut self, operations: Vec<Operation<Gene, Data>>) {
         self.operations = operations;
     }
 
+    /// Sets the operations to those of the pipeline. Defaults to Pipeline::balanced.
+    pub fn set_pipeline(&mut self, pipeline: Pipeline<Gene, Data>) {
+        self.operations = pipeline.into_operations();
+    }
+
+    /// Runs only the warmup operations on the main population for the number of generations at the
+    /// start of every run, before the main operations, to keep early convergence from narrowing
+    /// the search. Defaults to no warmup.
+    pub fn set_warmup_generations(&mut self, generations: usize) {
+        self.warmup_generations = generations;
+    }
+
+    /// Restarts the main population whenever its best true score has not improved for the number
+    /// of cycles, keeping the proportion of agents with the highest scores. See restart.
+    pub fn set_restart_on_stagnation(&mut self, cycles: usize, keep: f64) {
+        self.restart_on_stagnation = Some((cycles.max(1), keep));
+    }
+
+    /// Keeps the proportion of the main population with the highest scores, at least one agent,
+    /// and replaces the rest with random agents. See OperationType::Restart.
+    pub fn restart(&mut self, keep: f64) {
+        let operation = Operation::new(OperationType::Restart, Selection::with_values(SelectionType::HighestScore, keep, 1));
+        let population = self.main_population.clone();
+        self.main_population = operation.run(population, &self.data, &mut self.score_provider);
+    }
+
+    /// Starts each run with count agents built by the heuristic, and random agents for the rest of
+    /// the initial population. Mixing the two keeps the diversity random agents bring while the
+    /// heuristic agents give the search a head start.
+    pub fn seed_with_heuristic(&mut self, heuristic: Heuristic<Gene, Data>, count: usize) {
+        self.heuristic = Some((heuristic, count));
+    }
+
+    /// Sets the operations run during warmup. The default mutates half the population, crosses over
+    /// agents chosen at random and culls the lowest scored.
+    pub fn set_warmup_operations(&mut self, operations: Vec<Operation<Gene, Data>>) {
+        self.warmup_operations = operations;
+    }
+
+    /// Runs child populations on the calling thread instead of spawning threads, delivering their
+    /// agents after the cycles given by the schedule, so runs interleave the same way every time.
+    /// See the testing module.
+    pub fn set_virtual_threads(&mut self, schedule: VirtualSchedule) {
+        self.simulation = Some(Simulation::new(schedule));
+    }
+
+    /// Seeds the random numbers of each run, which records an RngAudit. A run is only repeated
+    /// exactly when child populations run on virtual threads or not at all, as real threads
+    /// return their agents at different times.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Sets whether unseeded runs draw a seed from entropy and record it in an RngAudit, so any
+    /// run can be replayed. Defaults to false.
+    pub fn set_record_seeds(&mut self, record_seeds: bool) {
+        self.record_seeds = record_seeds;
+    }
+
+    /// The operations and settings an Experiment holds, with the seed set with set_seed. Runs
+    /// that drew their seed from entropy are reproduced from get_rng_audit instead.
+    pub fn get_experiment(&self) -> Experiment<Gene, Data> {
+        Experiment {
+            operations: self.operations.clone(),
+            warmup_operations: self.warmup_operations.clone(),
+            warmup_generations: self.warmup_generations,
+            number_of_genes: self.number_of_genes,
+            strict_gene_length: self.strict_gene_length,
+            initial_population_size: self.initial_population_size,
+            max_population: self.max_population,
+            iterations_per_cycle: self.iterations_per_cycle,
+            max_child_threads: self.max_child_threads,
+            max_cycles: self.max_cycles,
+            seed: self.seed
+        }
+    }
+
+    /// Configures the manager as the experiment describes, such as one saved from another manager.
+    pub fn set_experiment(&mut self, experiment: Experiment<Gene, Data>) {
+        self.operations = experiment.operations;
+        self.warmup_operations = experiment.warmup_operations;
+        self.warmup_generations = experiment.warmup_generations;
+        self.number_of_genes = experiment.number_of_genes;
+        self.strict_gene_length = experiment.strict_gene_length;
+        self.initial_population_size = experiment.initial_population_size;
+        self.max_population = experiment.max_population;
+        self.iterations_per_cycle = experiment.iterations_per_cycle;
+        self.max_child_threads = experiment.max_child_threads;
+        self.max_cycles = experiment.max_cycles;
+        self.seed = experiment.seed;
+    }
+
+    /// Predicts the evaluations and time a run will take without running it, given the time one
+    /// evaluation takes, such as from sample_evaluation_cost. The operations are previewed on a
+    /// population of random agents at the maximum population size, the size runs soon reach,
+    /// and runs are assumed to reach their cycle limit.
+    pub fn estimate_cost(&self, per_evaluation: Duration) -> CostEstimate {
+        let per_generation = |operations: &[Operation<Gene, Data>]| {
+            let mut population = Population::new_empty(false);
+            for key in 0..self.get_max_population() {
+                population.insert(key as Score, Agent::with_genes(self.number_of_genes));
+            }
+            operations.iter()
+                .map(|operation| {
+                    let offspring = operation.preview(&population, &self.data).offspring;
+                    match operation.get_operation_type() {
+                        OperationType::CoordinateSweep => offspring * self.number_of_genes,
+                        _ => offspring
+                    }
+                })
+                .sum::<usize>()
+        };
+        let evaluations_per_generation = per_generation(&self.operations);
+        let warmup = self.initial_population_size + self.warmup_generations * per_generation(&self.warmup_operations);
+
+        let generations = self.max_cycles.map(|cycles| self.warmup_generations + cycles * self.iterations_per_cycle);
+        let main_evaluations = self.max_cycles.map(|cycles| warmup + cycles * self.iterations_per_cycle * evaluations_per_generation);
+        let child_evaluations = self.max_cycles.map(|cycles| {
+            cycles * self.max_child_threads as usize * (self.initial_population_size + self.iterations_per_cycle * evaluations_per_generation)
+        });
+
+        CostEstimate {
+            evaluations_per_generation,
+            generations,
+            total_evaluations: main_evaluations.and_then(|main| child_evaluations.map(|children| main + children)),
+            runtime: main_evaluations.map(|evaluations| per_evaluation.mul_f64(evaluations as f64))
+        }
+    }
+
+    /// Times the true score of the number of random agents and returns the mean time one took, for
+    /// estimate_cost. Agents that cannot be scored are counted too. None if samples is zero.
+    pub fn sample_evaluation_cost(&mut self, samples: usize) -> Option<Duration> {
+        if samples == 0 {
+            return None;
+        }
+        let agents: Vec<Agent<Gene>> = (0..samples).map(|_| Agent::with_genes(self.number_of_genes)).collect();
+        let start = Instant::now();
+        for agent in &agents {
+            let _ = self.score_provider.true_score(agent, &self.data);
+        }
+        Some(start.elapsed().div_f64(samples as f64))
+    }
+
+    /// The seeds of the last run, if it was seeded or recorded.
+    pub fn get_rng_audit(&self) -> Option<&RngAudit> {
+        self.rng_audit.as_ref()
+    }
+
+    /// Runs again from the seed and goal of an audited run. The manager must be configured as it
+    /// was for that run.
+    pub fn replay(&mut self, audit: &RngAudit) {
+        let seed = self.seed.replace(audit.master_seed);
+        self.run(audit.goal);
+        self.seed = seed;
+    }
+
     pub fn set_max_child_threads(&mut self, max_number: u8) {
         self.max_child_threads = max_number;
     }
 
+    /// Sets what happens to child threads still running when a run finishes. Defaults to Detach.
+    pub fn set_child_shutdown(&mut self, shutdown: ChildShutdown) {
+        self.child_shutdown = shutdown;
+    }
+
+    /// Sets when child threads are started. Defaults to Always.
+    pub fn set_spawn_policy(&mut self, policy: SpawnPolicy) {
+        self.spawn_policy = policy;
+    }
+
     pub fn set_iterations_per_cycle(&mut self, number: usize) {
         self.iterations_per_cycle = number;
     }
 
-    pub fn run(&mut self, goal: Score) {
-        self.main_population = Population::new(self.initial_population_size, self.number_of_genes, false, &self.data, &mut self.score_provider);
+    /// Limits the number of cycles of iterations each run may take before it finishes, whether or
+    /// not the goal has been met.
+    pub fn set_max_cycles(&mut self, cycles: usize) {
+        self.max_cycles = Some(cycles);
+    }
+
+    /// Skips operations of the main population whose recent rise in the highest score per
+    /// evaluation falls below the policy's minimum, for its cooldown, so evaluations go to the
+    /// operations paying off. Warmup generations and runs with a data hook are not throttled.
+    /// See OperationThrottle.
+    pub fn set_operation_throttle(&mut self, policy: ThrottlePolicy) {
+        self.operation_throttle = Some(OperationThrottle::new(policy));
+    }
+
+    /// The throttle's record of each operation's yield over the current or last run.
+    pub fn get_operation_throttle(&self) -> Option<&OperationThrottle> {
+        self.operation_throttle.as_ref()
+    }
+
+    /// Adds a goal on another measure of an agent, such as its number of constraint violations.
+    /// Runs then finish only once one agent in the main population meets the run's goal by its
+    /// true score and every added goal by their measures. Agents a measure fails on meet no goals.
+    pub fn add_goal(&mut self, measure: FitnessFunction<Gene, Data>, goal: Goal) {
+        self.goals.push((measure, goal));
+    }
+
+    /// Removes the goals added with add_goal.
+    pub fn clear_goals(&mut self) {
+        self.goals.clear();
+    }
+
+    /// Stops runs at the end of the next iteration once the flag is set, for example from a Ctrl-C
+    /// handler, leaving the best agents found so far in the main population. Child threads stop too.
+    /// The flag is not cleared, so reset it before running again.
+    pub fn set_stop_signal(&mut self, signal: Arc<AtomicBool>) {
+        self.add_observer(signal.clone());
+        self.stop_signal = Some(signal);
+    }
+
+    /// Exchanges agents with other runs every number of cycles, such as through a DropBox: the
+    /// best agents of the main population are sent, and those received are scored and inserted.
+    pub fn set_migration<M>(&mut self, migration: M, cycles: usize)
+    where
+    M: Migration<Gene> + 'static
+    {
+        self.migration = Some((Box::new(migration), cycles.max(1)));
+    }
+
+    /// Takes the last error that occurred while migrating, if any. Runs carry on when migration
+    /// fails.
+    pub fn take_migration_error(&mut self) -> Option<io::Error> {
+        self.migration_error.take()
+    }
+
+    /// Takes the error from the last run or step that could not start because the score provider
+    /// failed to score the initial population, if any.
+    pub fn take_score_error(&mut self) -> Option<ScoreError> {
+        self.score_error.take()
+    }
+
+    /// Changes the data before each iteration of the main population, for environments that drift
+    /// over time. The score provider is told the data has changed and the population is rescored
+    /// each time, and agents from child threads are rescored as they arrive.
+    pub fn set_data_hook(&mut self, hook: DataHook<Data>) {
+        self.data_hook = Some(hook);
+    }
+
+    /// Sets the number of best agents kept in the elite archive, replacing the archive.
+    /// Defaults to 10.
+    pub fn set_elite_archive_size(&mut self, size: usize) {
+        self.elite_archive = EliteArchive::new(size);
+    }
+
+    /// The best agents found by the manager's runs, with their true scores, whether or not they are
+    /// still in the population. The archive is cleared when solve is given new data.
+    pub fn get_elite_archive(&self) -> &EliteArchive<Gene> {
+        &self.elite_archive
+    }
+
+    /// Limits how many generations agents live for in every population the manager runs.
+    /// See Population::set_lifespan.
+    pub fn set_lifespan(&mut self, lifespan: u64, protected_elites: usize) {
+        self.lifespan = Some((lifespan, protected_elites));
+    }
+
+    /// Sets a policy that agents must pass to join any population the manager runs.
+    /// See Population::set_acceptance_policy.
+    pub fn set_acceptance_policy<P>(&mut self, policy: P)
+    where
+    P: AcceptancePolicy<Gene> + 'static
+    {
+        self.acceptance_policy = Some(Arc::new(policy));
+    }
+
+    /// Records why agents are not added to the main population, keeping the first examples of
+    /// each reason. Read them from get_population after the run. See Population::set_record_rejections.
+    pub fn set_record_rejections(&mut self, examples_per_reason: usize) {
+        self.record_rejections = Some(examples_per_reason);
+    }
+
+    /// Records the parents of agents joining the main population, so get_population().trace can
+    /// show how a result was built. Agents from child populations show as created when they
+    /// join. The lineage is pruned to the living agents' ancestors each cycle.
+    /// See Population::set_record_lineage.
+    pub fn set_record_lineage(&mut self, record: bool) {
+        self.record_lineage = record;
+    }
+
+    /// Adds an elite tier that refines the best agents of the main population each cycle, leaving
+    /// the main population to explore. See ExploitTier.
+    pub fn set_exploit_tier(&mut self, tier: ExploitTier<Gene, Data>) {
+        self.exploit_tier = Some(tier);
+    }
+
+    pub fn get_exploit_tier(&self) -> Option<&ExploitTier<Gene, Data>> {
+        self.exploit_tier.as_ref()
+    }
+
+    /// Sends each new best agent of the main population as soon as it is found, so the current best
+    /// solution can be displayed while the run continues.
+    pub fn set_best_agent_sender(&mut self, sender: Sender<(Score, Agent<Gene>)>) {
+        self.add_observer(sender);
+    }
+
+    /// Adds an observer that is notified of progress on the main population.
+    pub fn add_observer<O: Observer<Gene> + Send + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Runs until the goal is met by the true fitness of the best agent in the main population, or
+    /// the cycle limit is reached. See add_goal for goals on other measures. If the score provider
+    /// cannot score the initial population, the run does not start; see take_score_error.
+    pub fn run(&mut self, goal: Goal) {
+        let master_seed = match self.seed {
+            Some(seed) => Some(seed),
+            None if self.record_seeds => Some(random::entropy_seed()),
+            None => None
+        };
+        self.rng_audit = master_seed.map(|master_seed| RngAudit { master_seed, stream_seeds: Vec::new(), goal });
+        // A seeded run leaves the calling thread's generator as it found it.
+        let mut run = || {
+            match self.create_initial_population() {
+                Ok(population) => {
+                    self.main_population = population;
+                    self.evolve(goal);
+                },
+                Err(error) => self.score_error = Some(error)
+            }
+        };
+        match master_seed {
+            Some(master_seed) => random::with_seed(master_seed, run),
+            None => run()
+        }
+    }
+
+    /// Runs the main population's operations on the calling thread for about the time slice, then
+    /// returns the number of generations completed, so games and interactive applications can
+    /// evolve a little each frame without stalling. Operations run one at a time until the slice
+    /// is used up, and the next step carries on from the operation after the last one run, so a
+    /// step overruns the slice by at most one operation. The first step creates the initial
+    /// population, as does the first step after run or solve. Child threads, warmup, the data
+    /// hook and goals are not used; check get_true_best between steps to decide when to stop.
+    pub fn step(&mut self, slice: Duration) -> usize {
+        let start = Instant::now();
+        let mut next = match self.next_step_operation {
+            Some(next) => next,
+            None => match self.create_initial_population() {
+                Ok(population) => {
+                    self.main_population = population;
+                    self.prepare_main_population();
+                    0
+                },
+                Err(error) => {
+                    self.score_error = Some(error);
+                    return 0;
+                }
+            }
+        };
+        self.next_step_operation = Some(next);
+        if self.operations.is_empty() {
+            return 0;
+        }
 
-        while self.current_highest < goal {
+        let mut generations = 0;
+        while start.elapsed() < slice {
+            let population = mem::replace(&mut self.main_population, Population::new_empty(false));
+            self.main_population = self.operations[next].run(population, &self.data, &mut self.score_provider);
+            next = (next + 1) % self.operations.len();
+            self.next_step_operation = Some(next);
+            if next == 0 {
+                self.main_population.advance_generation();
+                self.score_provider.population_updated(&self.main_population);
+                self.observers.iteration_complete(generations, &self.main_population);
+                self.notify_if_new_best();
+                generations += 1;
+            }
+        }
+        generations
+    }
+
+    /// Creates the initial population from the heuristic, if any, and random agents, or returns
+    /// the error if the score provider cannot score them.
+    fn create_initial_population(&mut self) -> Result<Population<Gene>, ScoreError> {
+        let heuristic_count = self.heuristic.map_or(0, |(_, count)| count.min(self.initial_population_size));
+        let mut population = Population::try_new(self.initial_population_size - heuristic_count, self.number_of_genes, false, &self.data, &mut self.score_provider)?;
+        if let Some((heuristic, _)) = self.heuristic {
+            let mut rng = random::rng();
+            let agents = (0..heuristic_count).map(|_| Agent::from_genes(heuristic(&self.data, &mut rng))).collect();
+            population.score_and_insert(agents, &self.data, &mut self.score_provider);
+        }
+        Ok(population)
+    }
 
-            if self.number_of_child_threads < self.max_child_threads {
+    /// Solves a new instance of the problem, starting from the population left by the previous
+    /// run rather than from scratch, which helps when instances are similar. The surviving agents
+    /// are rescored on the new data, topped up with random agents to the initial population size,
+    /// and evolved until the goal is met. The score provider is told the data has changed.
+    /// Returns the best agent found, with its true score.
+    pub fn solve(&mut self, data: Data, goal: Goal) -> Option<(Score, Agent<Gene>)> {
+        self.discard_child_results();
+        let agents: Vec<Agent<Gene>> = self.main_population.get_agents().values().cloned().collect();
+        self.data = data;
+        self.score_provider.data_changed();
+        self.elite_archive.clear();
+
+        self.main_population = self.population_from(agents);
+        self.evolve(goal);
+
+        self.get_true_best().map(|(score, agent)| (score, agent.clone()))
+    }
+
+    /// Runs the stages of the plan in turn, each starting from the best agents of the stage
+    /// before, re-encoded for its genome. The first stage runs as run does. The manager keeps the
+    /// settings of the last stage. Returns the best agent of each stage, with its true score.
+    pub fn run_stages(&mut self, plan: &StagePlan<Gene, Data>) -> Vec<Option<(Score, Agent<Gene>)>> {
+        let mut results = Vec::with_capacity(plan.stages.len());
+        for (index, stage) in plan.stages.iter().enumerate() {
+            // The first stage starts from random agents, so nothing is carried into it.
+            let carried = if index == 0 { Vec::new() } else { self.carry_over(plan.carry_over, stage.re_encoder) };
+
+            self.number_of_genes = stage.number_of_genes;
+            if let Some(cycles) = stage.max_cycles {
+                self.max_cycles = Some(cycles);
+            }
+            if let Some(ref operations) = stage.operations {
+                self.operations = operations.clone();
+            }
+
+            if index == 0 {
+                self.run(stage.goal);
+            } else {
+                self.discard_child_results();
+                if stage.scores_change {
+                    self.score_provider.data_changed();
+                }
+                if stage.re_encoder.is_some() {
+                    // Agents of the previous encoding would not fit the new genome.
+                    self.elite_archive.clear();
+                }
+                self.main_population = self.population_from(carried);
+                self.evolve(stage.goal);
+            }
+            results.push(self.get_true_best().map(|(score, agent)| (score, agent.clone())));
+        }
+        results
+    }
+
+    /// The agents of the main population with the highest true scores, re-encoded for the next
+    /// stage if it has a re-encoder.
+    fn carry_over(&mut self, count: usize, re_encoder: Option<ReEncoder<Gene, Data>>) -> Vec<Agent<Gene>> {
+        let mut ranked: Vec<(Score, &Agent<Gene>)> = Vec::new();
+        for agent in self.main_population.get_agents().values() {
+            if let Ok(score) = self.score_provider.true_score(agent, &self.data) {
+                ranked.push((score, agent));
+            }
+        }
+        ranked.sort_by_key(|&(score, _)| Reverse(score));
+        ranked.into_iter()
+            .take(count)
+            .map(|(_, agent)| match re_encoder {
+                Some(re_encode) => Agent::from_genes(re_encode(agent, &self.data)),
+                None => agent.clone()
+            })
+            .collect()
+    }
+
+    /// Scores the agents into a new population, topped up with random agents to the initial
+    /// population size. No agent replaces another whose score key it shares.
+    fn population_from(&mut self, agents: Vec<Agent<Gene>>) -> Population<Gene> {
+        let mut population = Population::new_empty(false);
+        let mut rng = random::rng();
+        if let Ok(agents) = self.score_provider.evaluate_scores(agents, &self.data) {
+            for agent in agents {
+                if let Ok(score) = self.score_provider.get_score(&agent, &self.data, &mut rng) {
+                    population.insert_ranked(score, agent);
+                }
+            }
+        }
+        if population.len() < self.initial_population_size {
+            let random = Population::new(self.initial_population_size - population.len(), self.number_of_genes, false, &self.data, &mut self.score_provider);
+            for (score, agent) in random.get_agents() {
+                population.insert_ranked(*score, agent.clone());
+            }
+        }
+        population
+    }
+
+    /// Moves the manager to a thread of its own, where it solves the jobs submitted to the
+    /// returned service one after another, keeping its population between them.
+    pub fn into_service(self) -> ManagerService<Gene, Data> {
+        let (job_sender, job_receiver) = channel::<Job<Gene, Data>>();
+        let mut manager = self;
+        let handle = thread::spawn(move || {
+            for (data, goal, result_sender) in job_receiver {
+                // The submitter may have stopped waiting for the result.
+                let _ = result_sender.send(manager.solve(data, goal));
+            }
+        });
+
+        ManagerService {
+            job_sender: Some(job_sender),
+            handle: Some(handle)
+        }
+    }
+
+    fn evolve(&mut self, goal: Goal) {
+        self.next_step_operation = None;
+        self.prepare_main_population();
+
+        if self.warmup_generations > 0 {
+            let generations = self.warmup_generations;
+            self.run_main_iterations(generations, true);
+            self.notify_if_new_best();
+        }
+
+        self.stagnation = (self.current_highest, 0);
+        self.last_change = None;
+        let mut cycles = 0;
+        while !self.is_finished(goal, cycles) {
+            cycles += 1;
+
+            if self.number_of_child_threads < self.max_child_threads && self.should_spawn() {
                 for _ in 0..(self.max_child_threads - self.number_of_child_threads) {
                     self.spawn_population_in_new_thread();
                 }
             }
 
-            let cloned_population = self.main_population.clone();
-            self.main_population = run_iterations(cloned_population, self.iterations_per_cycle, &self.data, &self.operations, &mut self.score_provider);
+            let iterations = self.iterations_per_cycle;
+            let cycle_start = self.current_highest;
+            self.run_main_iterations(iterations, false);
+            // Improvements during the iterations have already been sent by the observer.
+            if let Some((highest, agent)) = self.main_population.true_best(&mut self.score_provider, &self.data) {
+                self.current_highest = highest;
+                self.elite_archive.offer(highest, agent);
+            }
+            self.last_change = Some(self.current_highest.abs_diff(cycle_start));
+            // Agents returned from the tier are checked for improvements below.
+            if let Some(ref mut tier) = self.exploit_tier {
+                tier.run_cycle(&mut self.main_population, self.iterations_per_cycle, &self.data, &mut self.score_provider);
+            }
+
+            if let Some(ref mut simulation) = self.simulation {
+                for agents in simulation.advance() {
+                    let _ = self.agent_sender.send(agents);
+                }
+            }
+
+            while let Ok(agents) = self.agent_receiver.try_recv() {
+                self.merge_child_agents(agents);
+            }
+
+            if let Some((ref mut migration, interval)) = self.migration {
+                if cycles % interval == 0 {
+                    if let Err(error) = migration.emigrate(&self.main_population) {
+                        self.migration_error = Some(error);
+                    }
+                    match migration.immigrate() {
+                        Ok(immigrants) => self.main_population.score_and_insert(immigrants, &self.data, &mut self.score_provider),
+                        Err(error) => self.migration_error = Some(error)
+                    }
+                }
+            }
+
+            self.notify_if_new_best();
+            self.restart_if_stagnant();
+            self.main_population.prune_lineage();
+        }
+
+        self.shut_down_children();
+    }
+
+    /// Applies the manager's settings to the main population before it evolves.
+    fn prepare_main_population(&mut self) {
+        if let Some((lifespan, protected_elites)) = self.lifespan {
+            self.main_population.set_lifespan(lifespan, protected_elites);
+        }
+        self.main_population.set_shared_acceptance_policy(self.acceptance_policy.clone());
+        if let Some(examples_per_reason) = self.record_rejections {
+            if self.main_population.get_rejection_stats().is_none() {
+                self.main_population.set_record_rejections(examples_per_reason);
+            }
+        }
+        self.main_population.set_record_lineage(self.record_lineage);
+        self.main_population.set_max_size(self.get_max_population());
+        self.main_population.set_buffer_pool_capacity(self.buffer_pool_capacity);
+        self.main_population.set_genome_hasher(self.genome_hasher);
+        if self.strict_gene_length {
+            self.main_population.set_strict_gene_length(self.number_of_genes);
+        }
+        if let Some(ref mut tier) = self.exploit_tier {
+            tier.set_genome_hasher(self.genome_hasher);
+            tier.clear();
+        }
+        if let Some(ref mut throttle) = self.operation_throttle {
+            *throttle = OperationThrottle::new(throttle.get_policy());
+        }
+        // Lets score providers calibrate to the starting population.
+        self.score_provider.population_updated(&self.main_population);
+        self.current_highest = 0;
+        self.notify_if_new_best();
+    }
+
+    /// Inserts the agents a child population returned.
+    fn merge_child_agents(&mut self, agents: BTreeMap<Score, Agent<Gene>>) {
+        if self.data_hook.is_some() {
+            // Scored on data that has since changed.
+            self.main_population.score_and_insert(agents.into_values().collect(), &self.data, &mut self.score_provider);
+        } else {
+            for (score, agent) in agents {
+                self.main_population.insert(score, agent);
+            }
+        }
+        self.number_of_child_threads -= 1;
+    }
 
-            let mut check_messages = true;
-            while check_messages {
-                let result = self.agent_receiver.try_recv();
-                if result.is_ok() {
-                    for (score, agent) in result.ok().unwrap() {
-                        self.main_population.insert(score, agent);
+    /// Deals with child threads still running at the end of a run, as set by set_child_shutdown.
+    fn shut_down_children(&mut self) {
+        match self.child_shutdown {
+            ChildShutdown::Detach => (),
+            ChildShutdown::Merge => {
+                if let Some(ref mut simulation) = self.simulation {
+                    for agents in simulation.finish_all() {
+                        let _ = self.agent_sender.send(agents);
                     }
-                    self.number_of_child_threads -= 1;
-                } else {
-                    check_messages = false;
                 }
+                while let Some(agents) = self.receive_child_agents() {
+                    self.merge_child_agents(agents);
+                }
+                self.join_children();
+                self.notify_if_new_best();
+            },
+            ChildShutdown::Cancel => {
+                self.cancel_children.store(true, Ordering::SeqCst);
+                self.discard_child_results();
+                self.cancel_children.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Waits for the agents of the next child to finish, or gives None once no child is left to
+    /// send any. Children that finish without sending their agents panicked, and stop being counted.
+    fn receive_child_agents(&mut self) -> Option<BTreeMap<Score, Agent<Gene>>> {
+        while self.number_of_child_threads > 0 {
+            // Counted before the channel is checked, as a child sends its agents before it finishes.
+            let running = self.child_handles.iter().filter(|handle| !handle.is_finished()).count();
+            match self.agent_receiver.try_recv() {
+                Ok(agents) => return Some(agents),
+                Err(_) if running < self.number_of_child_threads as usize => self.number_of_child_threads = running as u8,
+                Err(_) => if let Ok(agents) = self.agent_receiver.recv_timeout(CHILD_POLL_INTERVAL) {
+                    return Some(agents);
+                }
+            }
+        }
+        None
+    }
+
+    /// Waits for the threads of children whose results have been received.
+    fn join_children(&mut self) {
+        for handle in self.child_handles.drain(..) {
+            // A child that panicked has nothing left to deliver.
+            let _ = handle.join();
+        }
+    }
+
+    /// Whether to start child threads this cycle, according to the spawn policy. Under
+    /// WhenChangeBelow, none are started until a cycle has been measured.
+    fn should_spawn(&self) -> bool {
+        match self.spawn_policy {
+            SpawnPolicy::Always => true,
+            SpawnPolicy::WhenChangeBelow(threshold) => self.last_change.is_some_and(|change| change < threshold)
+        }
+    }
+
+    /// Counts the cycles since the best true score last improved, restarting once there are too many.
+    fn restart_if_stagnant(&mut self) {
+        if let Some((cycles, keep)) = self.restart_on_stagnation {
+            let (best, stagnant_cycles) = self.stagnation;
+            if self.current_highest > best {
+                self.stagnation = (self.current_highest, 0);
+            } else if stagnant_cycles + 1 >= cycles {
+                self.restart(keep);
+                self.stagnation = (best, 0);
+            } else {
+                self.stagnation = (best, stagnant_cycles + 1);
+            }
+        }
+    }
+
+    /// Runs the main or warmup operations on the main population.
+    fn run_main_iterations(&mut self, iterations: usize, warmup: bool) {
+        let operations = if warmup { &self.warmup_operations } else { &self.operations };
+        let cloned_population = self.main_population.clone();
+        let throttle = if warmup { None } else { self.operation_throttle.as_mut() };
+        self.main_population = match (self.data_hook, throttle) {
+            (Some(hook), _) => run_drifting_iterations(cloned_population, iterations, &mut self.data, hook, operations, &mut self.score_provider, &mut self.observers),
+            (None, Some(throttle)) => run_throttled_iterations(cloned_population, iterations, &self.data, operations, &mut self.score_provider, &mut self.observers, throttle),
+            (None, None) if self.observers.is_empty() => run_iterations(cloned_population, iterations, &self.data, operations, &mut self.score_provider),
+            (None, None) => run_iterations_with_observer(cloned_population, iterations, &self.data, operations, &mut self.score_provider, &mut self.observers)
+        };
+    }
+
+    fn is_finished(&mut self, goal: Goal, cycles: usize) -> bool {
+        if let Some(ref signal) = self.stop_signal {
+            if signal.load(Ordering::SeqCst) {
+                return true;
+            }
+        }
+        match self.max_cycles {
+            Some(max_cycles) if cycles >= max_cycles => true,
+            _ if !self.goals.is_empty() => self.meets_all_goals(goal),
+            _ => match self.main_population.true_best(&mut self.score_provider, &self.data) {
+                Some((best, _)) => goal.is_met(best),
+                None => false
+            }
+        }
+    }
+
+    /// Whether any agent in the main population meets the goal and every added goal.
+    fn meets_all_goals(&mut self, goal: Goal) -> bool {
+        let data = &self.data;
+        let goals = &self.goals;
+        let score_provider = &mut self.score_provider;
+        self.main_population.get_agents().values().any(|agent| {
+            let score_met = match score_provider.true_score(agent, data) {
+                Ok(score) => goal.is_met(score),
+                Err(_) => false
+            };
+            score_met && goals.iter().all(|&(measure, goal)| match measure(agent, data) {
+                Ok(value) => goal.is_met(value),
+                Err(_) => false
+            })
+        })
+    }
+
+    /// Waits for the populations of running child threads and ignores them, as they were scored on
+    /// the previous data.
+    fn discard_child_results(&mut self) {
+        if let Some(ref mut simulation) = self.simulation {
+            for agents in simulation.finish_all() {
+                let _ = self.agent_sender.send(agents);
             }
+        }
+        while self.receive_child_agents().is_some() {
+            self.number_of_child_threads -= 1;
+        }
+        self.join_children();
+    }
 
-            let (highest, _) = self.main_population.get_agents().iter().rev().next().unwrap();
-            self.current_highest = *highest;
+    /// Updates the current highest true score, sending the best agent if it has improved.
+    fn notify_if_new_best(&mut self) {
+        if let Some((highest, agent)) = self.main_population.true_best(&mut self.score_provider, &self.data) {
+            if highest > self.current_highest {
+                self.observers.new_best(highest, agent);
+            }
+            self.elite_archive.offer(highest, agent);
+            self.current_highest = highest;
         }
     }
 
@@ -167,6 +1282,11 @@ SP: Clone + Send + ScoreProvider<Gene, Data>
         return &self.main_population;
     }
 
+    /// Gets the agent in the main population with the highest fitness, without the noise in the score keys.
+    pub fn get_true_best(&mut self) -> Option<(Score, &Agent<Gene>)> {
+        self.main_population.true_best(&mut self.score_provider, &self.data)
+    }
+
     fn spawn_population_in_new_thread(&mut self) {
         let initial_population_size = self.initial_population_size;
         let number_of_genes = self.number_of_genes;
@@ -174,18 +1294,514 @@ SP: Clone + Send + ScoreProvider<Gene, Data>
         let operations = self.operations.clone();
         let iterations_per_cycle = self.iterations_per_cycle;
         let mut score_provider = self.score_provider.clone();
+        let lifespan = self.lifespan;
+        let max_population = self.get_max_population();
+        let buffer_pool_capacity = self.buffer_pool_capacity;
+        let genome_hasher = self.genome_hasher;
+        let strict_gene_length = self.strict_gene_length;
+        let acceptance_policy = self.acceptance_policy.clone();
+        let stop_signal = self.stop_signal.clone().unwrap_or_else(|| Arc::new(AtomicBool::new(false)));
+        let mut stop: Vec<Box<dyn Observer<Gene> + Send>> = vec![Box::new(stop_signal), Box::new(self.cancel_children.clone())];
 
-        let tx = self.agent_sender.clone();
+        let stream_seed = self.rng_audit.as_mut().map(|audit| {
+            let seed = random::random::<u64>();
+            audit.stream_seeds.push(seed);
+            seed
+        });
 
-        thread::spawn(move || {
-            let population = run_iterations(Population::new(initial_population_size, number_of_genes, false, &data, &mut score_provider), iterations_per_cycle, &data, &operations, &mut score_provider);
-            let population = cull_lowest_agents(population, 0.5, 1);
-            match tx.send(population.get_agents().clone()) {
-                Ok(()) => (),
-                Err(_) => () // The parent thread probably finished its run. That doesn't really matter.
+        let run_population = move || {
+            let mut population = Population::new(initial_population_size, number_of_genes, false, &data, &mut score_provider);
+            if let Some((lifespan, protected_elites)) = lifespan {
+                population.set_lifespan(lifespan, protected_elites);
             }
-        });
+            population.set_shared_acceptance_policy(acceptance_policy);
+            population.set_max_size(max_population);
+            population.set_buffer_pool_capacity(buffer_pool_capacity);
+            population.set_genome_hasher(genome_hasher);
+            if strict_gene_length {
+                population.set_strict_gene_length(number_of_genes);
+            }
+            let population = run_iterations_with_observer(population, iterations_per_cycle, &data, &operations, &mut score_provider, &mut stop);
+            cull_lowest_agents(population, 0.5, 1).get_agents().clone()
+        };
+        let run_child = move || match stream_seed {
+            Some(seed) => random::with_seed(seed, run_population),
+            None => run_population()
+        };
+
+        match self.simulation {
+            Some(ref mut simulation) => simulation.start(run_child()),
+            None => {
+                let tx = self.agent_sender.clone();
+                self.child_handles.retain(|handle| !handle.is_finished());
+                self.child_handles.push(thread::spawn(move || {
+                    match tx.send(run_child()) {
+                        Ok(()) => (),
+                        Err(_) => () // The parent thread probably finished its run. That doesn't really matter.
+                    }
+                }));
+            }
+        }
 
         self.number_of_child_threads += 1;
     }
-}
\ No newline at end of file
+}
+
+/// How often a manager waiting for its children checks whether any have died.
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Data to solve, the goal, and where to send the result.
+type Job<Gene, Data> = (Data, Goal, Sender<Option<(Score, Agent<Gene>)>>);
+
+/// A Manager running on its own thread, solving jobs as they are submitted.
+/// See Manager::into_service. Dropping the service waits for the current job to finish.
+pub struct ManagerService <Gene, Data> {
+    job_sender: Option<Sender<Job<Gene, Data>>>,
+    handle: Option<thread::JoinHandle<()>>
+}
+
+impl <Gene, Data> ManagerService <Gene, Data> {
+    /// Queues a job to evolve a solution for the data until the goal is met.
+    /// The best agent and its true score are sent on the returned receiver once it is solved.
+    pub fn submit(&self, data: Data, goal: Goal) -> Receiver<Option<(Score, Agent<Gene>)>> {
+        let (result_sender, result_receiver) = channel();
+        if let Some(ref job_sender) = self.job_sender {
+            // If the manager's thread has died the receiver reports it as disconnected.
+            let _ = job_sender.send((data, goal, result_sender));
+        }
+        result_receiver
+    }
+}
+
+impl <Gene, Data> Drop for ManagerService <Gene, Data> {
+    fn drop(&mut self) {
+        self.job_sender = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::ScoreErrorPolicy;
+    #[cfg(feature = "serde")]
+    use super::super::operations::CrossoverMode;
+
+    // Genes score higher the closer they are to the target.
+    fn closeness(agent: &Agent<u8>, target: &u8) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().map(|gene| 255 - (*gene as i16 - *target as i16).unsigned_abs() as Score).sum())
+    }
+
+    #[test]
+    fn service_solves_jobs_in_turn() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(1);
+        let service = manager.into_service();
+
+        let first = service.submit(100, Goal::AtLeast(1000));
+        let second = service.submit(150, Goal::AtLeast(1000));
+        let (score, agent) = first.recv().unwrap().unwrap();
+        assert!(score >= 1000);
+        assert_eq!(score, closeness(&agent, &100).unwrap());
+        let (score, agent) = second.recv().unwrap().unwrap();
+        assert_eq!(score, closeness(&agent, &150).unwrap());
+    }
+
+    #[test]
+    fn child_threads_finish_with_the_run() {
+        for shutdown in [ChildShutdown::Merge, ChildShutdown::Cancel] {
+            let mut manager = create_manager(closeness, 0);
+            manager.set_number_of_genes(4, true);
+            manager.set_max_child_threads(2);
+            manager.set_iterations_per_cycle(5);
+            manager.set_max_cycles(2);
+            manager.set_child_shutdown(shutdown);
+            manager.run(Goal::Never);
+
+            assert_eq!(0, manager.number_of_child_threads);
+            assert!(manager.child_handles.is_empty());
+            assert!(!manager.cancel_children.load(Ordering::SeqCst));
+        }
+    }
+
+    // Panics on child threads, which are unnamed.
+    fn closeness_on_main_thread(agent: &Agent<u8>, target: &u8) -> Result<Score, ScoreError> {
+        if thread::current().name().is_none() {
+            panic!("child thread");
+        }
+        closeness(agent, target)
+    }
+
+    #[test]
+    fn panicking_children_do_not_hang_the_run() {
+        for shutdown in [ChildShutdown::Merge, ChildShutdown::Cancel] {
+            let mut manager = create_manager(closeness_on_main_thread, 0);
+            manager.set_number_of_genes(4, true);
+            manager.set_max_child_threads(2);
+            manager.set_iterations_per_cycle(5);
+            manager.set_max_cycles(2);
+            manager.set_child_shutdown(shutdown);
+            manager.run(Goal::Never);
+
+            assert_eq!(0, manager.number_of_child_threads);
+            assert!(manager.child_handles.is_empty());
+        }
+    }
+
+    #[test]
+    fn spawn_policy_waits_for_a_stuck_cycle() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_spawn_policy(SpawnPolicy::WhenChangeBelow(10));
+        assert!(!manager.should_spawn());
+        manager.last_change = Some(50);
+        assert!(!manager.should_spawn());
+        manager.last_change = Some(3);
+        assert!(manager.should_spawn());
+
+        manager.set_number_of_genes(4, true);
+        manager.set_max_cycles(3);
+        manager.set_child_shutdown(ChildShutdown::Cancel);
+        manager.run(Goal::Never);
+        assert_eq!(0, manager.number_of_child_threads);
+    }
+
+    #[test]
+    fn builder_sets_genes_before_building() {
+        let mut manager = manager_builder(closeness, 100)
+            .configure(|manager| manager.set_max_child_threads(0))
+            .number_of_genes(3, true)
+            .configure(|manager| manager.set_max_cycles(50))
+            .build();
+        manager.run(Goal::AtLeast(760));
+
+        let (_, best) = manager.get_true_best().unwrap();
+        assert_eq!(3, best.get_genes().len());
+        assert_eq!(0, manager.max_child_threads);
+    }
+
+    #[test]
+    fn seeded_runs_replay_exactly() {
+        let configure = || {
+            let mut manager = create_manager(closeness, 100);
+            manager.set_number_of_genes(4, true);
+            manager.set_virtual_threads(VirtualSchedule::new(vec![1, 2]));
+            manager.set_max_child_threads(2);
+            manager.set_iterations_per_cycle(5);
+            manager.set_max_cycles(4);
+            manager
+        };
+
+        let mut recorded = configure();
+        recorded.set_record_seeds(true);
+        recorded.run(Goal::Never);
+        let audit = recorded.get_rng_audit().unwrap().clone();
+        assert_eq!(Goal::Never, audit.goal);
+        assert!(!audit.stream_seeds.is_empty());
+
+        let mut replayed = configure();
+        replayed.replay(&audit);
+        assert_eq!(recorded.get_population().get_scores(), replayed.get_population().get_scores());
+        assert_eq!(Some(&audit), replayed.get_rng_audit());
+        assert!(configure().get_rng_audit().is_none());
+
+        // The caller's generator is left as it was.
+        random::seed(1);
+        let expected: u64 = random::random();
+        random::seed(1);
+        replayed.replay(&audit);
+        assert_eq!(expected, random::random::<u64>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_experiments_reproduce_runs() {
+        let mut original = create_manager(closeness, 100);
+        original.set_number_of_genes(6, true);
+        original.set_max_child_threads(0);
+        original.set_iterations_per_cycle(5);
+        original.set_max_cycles(3);
+        original.set_seed(11);
+        let mut crossover = Operation::new(OperationType::Crossover, Selection::top(0.3).and(Selection::random(0.5)));
+        crossover.set_crossover_mode(CrossoverMode::Segments(3));
+        crossover.set_record_size(2);
+        original.set_pipeline(Pipeline::new()
+            .then(crossover)
+            .then(Operation::new(OperationType::Mutate, Selection::bands(1, 3, 4)))
+            .then(Operation::new(OperationType::Cull, Selection::bottom(0.2))));
+
+        let saved = serde_json::to_string(&original.get_experiment()).unwrap();
+        let mut loaded = create_manager(closeness, 100);
+        loaded.set_experiment(serde_json::from_str(&saved).unwrap());
+        assert_eq!(saved, serde_json::to_string(&loaded.get_experiment()).unwrap());
+
+        original.run(Goal::Never);
+        loaded.run(Goal::Never);
+        assert_eq!(15, loaded.get_population().get_generation());
+        assert_eq!(original.get_population().get_scores(), loaded.get_population().get_scores());
+    }
+
+    #[test]
+    fn cost_estimate_counts_evaluations() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_initial_population_size(20);
+        manager.set_max_population(100);
+        manager.set_max_child_threads(1);
+        manager.set_iterations_per_cycle(5);
+        manager.set_pipeline(Pipeline::new()
+            .then(Operation::new(OperationType::Mutate, Selection::top(0.1)))
+            .then(Operation::new(OperationType::CoordinateSweep, Selection::top(0.02)))
+            .then(Operation::new(OperationType::Cull, Selection::bottom(0.1))));
+
+        let unbounded = manager.estimate_cost(Duration::from_millis(1));
+        assert_eq!(18, unbounded.evaluations_per_generation);
+        assert_eq!(None, unbounded.total_evaluations);
+
+        manager.set_max_cycles(2);
+        let estimate = manager.estimate_cost(Duration::from_millis(1));
+        assert_eq!(Some(10), estimate.generations);
+        assert_eq!(Some(20 + 10 * 18 + 2 * (20 + 5 * 18)), estimate.total_evaluations);
+        assert_eq!(Some(Duration::from_millis(200)), estimate.runtime);
+        assert!(manager.sample_evaluation_cost(3).is_some());
+    }
+
+    #[test]
+    fn steps_resume_where_they_stopped() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_initial_population_size(20);
+        assert_eq!(0, manager.step(Duration::from_secs(0)));
+        assert_eq!(0, manager.get_population().get_generation());
+        assert!(manager.get_population().len() > 0);
+
+        let mut generations = 0;
+        while generations < 3 {
+            generations += manager.step(Duration::from_millis(5));
+        }
+        assert_eq!(generations as u64, manager.get_population().get_generation());
+
+        manager.set_max_cycles(1);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(2);
+        manager.run(Goal::Never);
+        assert_eq!(2, manager.get_population().get_generation());
+        manager.step(Duration::from_secs(0));
+        assert_eq!(0, manager.get_population().get_generation());
+    }
+
+    #[test]
+    fn stages_carry_re_encoded_agents_over() {
+        fn split_genes(agent: &Agent<u8>, _: &u8) -> Vec<u8> {
+            agent.get_genes().iter().flat_map(|gene| vec![*gene, *gene]).collect()
+        }
+
+        let mut manager = create_manager(closeness, 100);
+        manager.set_number_of_genes(2, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(1);
+        let mut fine = Stage::new(4, Goal::Never);
+        fine.set_re_encoder(split_genes);
+        fine.set_scores_change(true);
+        let plan = StagePlan::new(5).then(Stage::new(2, Goal::Never)).then(fine);
+
+        let results = manager.run_stages(&plan);
+        assert_eq!(2, results.len());
+        let (coarse_score, _) = results[0].clone().unwrap();
+        let (_, fine_agent) = results[1].clone().unwrap();
+        assert_eq!(4, fine_agent.get_genes().len());
+        assert!(manager.get_population().get_agents().values().all(|agent| agent.get_genes().len() == 4));
+        // The best coarse agent, split into pairs of genes, starts the fine stage with double the
+        // score, and the archive, cleared for the new encoding, keeps it even if the run loses it.
+        let (archived_score, archived) = manager.get_elite_archive().best().unwrap().clone();
+        assert!(archived_score >= 2 * coarse_score);
+        assert_eq!(4, archived.get_genes().len());
+    }
+
+    #[test]
+    fn goals_and_cycle_limit() {
+        assert!(Goal::AtLeast(5).is_met(5) && !Goal::AtLeast(5).is_met(4));
+        assert!(Goal::AtMost(5).is_met(5) && !Goal::AtMost(5).is_met(6));
+        assert!(!Goal::Never.is_met(Score::MAX));
+
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(2);
+        manager.run(Goal::Never);
+        assert_eq!(10, manager.get_population().get_generation());
+    }
+
+    #[test]
+    fn runs_finish_when_every_goal_is_met() {
+        fn gene_count(agent: &Agent<u8>, _: &u8) -> Result<Score, ScoreError> {
+            Ok(agent.get_genes().len() as Score)
+        }
+
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(2);
+        manager.add_goal(gene_count, Goal::AtLeast(5));
+        manager.run(Goal::AtLeast(0));
+        assert_eq!(10, manager.get_population().get_generation());
+
+        manager.clear_goals();
+        manager.add_goal(gene_count, Goal::AtMost(4));
+        manager.run(Goal::AtLeast(0));
+        assert_eq!(0, manager.get_population().get_generation());
+    }
+
+    #[test]
+    fn warmup_runs_before_cycles() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(1);
+        manager.set_warmup_generations(7);
+        manager.set_warmup_operations(vec![Operation::new(OperationType::Mutate, Selection::new(SelectionType::RandomAny, 1.0))]);
+        manager.run(Goal::Never);
+        assert_eq!(12, manager.get_population().get_generation());
+    }
+
+    #[test]
+    fn stop_signal_ends_run() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_iterations_per_cycle(5);
+        let signal = Arc::new(AtomicBool::new(true));
+        manager.set_stop_signal(signal.clone());
+        manager.run(Goal::Never);
+        assert_eq!(0, manager.get_population().get_generation());
+    }
+
+    #[test]
+    fn max_population_bounds_growth() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_initial_population_size(20);
+        manager.set_max_population(30);
+        manager.set_iterations_per_cycle(20);
+        manager.set_max_cycles(1);
+        manager.run(Goal::Never);
+        assert!(manager.get_population().len() <= 30);
+    }
+
+    fn next_target(target: &mut u8, _generation: u64) {
+        *target = target.wrapping_add(1);
+    }
+
+    #[test]
+    fn data_hook_changes_data_each_iteration() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(2);
+        manager.set_data_hook(next_target);
+        manager.run(Goal::Never);
+        assert_eq!(10, manager.data);
+    }
+
+    #[test]
+    fn restart_keeps_best_agent() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_initial_population_size(20);
+        manager.set_iterations_per_cycle(2);
+        manager.set_max_cycles(3);
+        manager.set_restart_on_stagnation(1, 0.1);
+        manager.run(Goal::Never);
+
+        let size = manager.get_population().len();
+        let (&highest, agent) = manager.get_population().get_agents().iter().next_back().unwrap();
+        let agent = agent.clone();
+        manager.restart(0.0);
+        assert!(manager.get_population().get(highest).unwrap().has_same_genes(&agent));
+        assert!(manager.get_population().len() <= size);
+    }
+
+    struct Unreachable;
+
+    impl Migration<u8> for Unreachable {
+        fn emigrate(&mut self, _population: &Population<u8>) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::NotFound, "no shared directory"))
+        }
+
+        fn immigrate(&mut self) -> io::Result<Vec<Agent<u8>>> {
+            Ok(vec![Agent::from_genes(vec![7; 4])])
+        }
+    }
+
+    #[test]
+    fn migration_errors_are_kept_for_the_caller() {
+        let mut manager = create_manager(closeness, 7);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_max_cycles(2);
+        manager.set_migration(Unreachable, 1);
+        manager.run(Goal::Never);
+
+        assert_eq!(io::ErrorKind::NotFound, manager.take_migration_error().unwrap().kind());
+        assert!(manager.take_migration_error().is_none());
+        // Agents are still received when sending fails.
+        assert!(manager.get_population().get_agents().values().any(|agent| agent.get_genes() == &vec![7; 4]));
+    }
+
+    #[test]
+    fn score_errors_stop_the_run_before_it_starts() {
+        let mut score_provider = GeneralScoreProvider::new(|_: &Agent<u8>, _: &u8| Err(ScoreError::new("down")), 0);
+        score_provider.set_error_policy(ScoreErrorPolicy::Propagate);
+        let mut manager = Manager::new(score_provider, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.run(Goal::Never);
+
+        assert_eq!("down", manager.take_score_error().unwrap().to_string());
+        assert!(manager.take_score_error().is_none());
+        assert_eq!(0, manager.step(Duration::from_millis(1)));
+        assert!(manager.take_score_error().is_some());
+    }
+
+    fn on_target(target: &u8, _rng: &mut dyn RngCore) -> Vec<u8> {
+        vec![*target; 4]
+    }
+
+    #[test]
+    fn heuristic_seeds_initial_population() {
+        let mut manager = create_manager(closeness, 7);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_initial_population_size(10);
+        manager.set_iterations_per_cycle(1);
+        manager.set_max_cycles(1);
+        manager.seed_with_heuristic(on_target, 3);
+        manager.run(Goal::AtLeast(1020));
+
+        let (best, agent) = manager.get_true_best().unwrap();
+        assert_eq!(1020, best);
+        assert_eq!(&vec![7u8; 4], agent.get_genes());
+    }
+
+    #[test]
+    fn elite_archive_keeps_best_found() {
+        let mut manager = create_manager(closeness, 0);
+        manager.set_number_of_genes(4, true);
+        manager.set_max_child_threads(0);
+        manager.set_iterations_per_cycle(5);
+        manager.set_max_cycles(3);
+        manager.run(Goal::Never);
+
+        let score = manager.get_elite_archive().best().unwrap().0;
+        let (best, _) = manager.get_true_best().unwrap();
+        assert!(score >= best);
+    }
+}
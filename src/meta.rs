@@ -0,0 +1,197 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Hyper-heuristic search: evolving the operation pipeline itself.
+//!
+//! A pipeline is encoded as an `Agent<OperationGene>`, one gene per operation in order, and scored
+//! by the best true score an inner run with it reaches from a random population. The outer search
+//! is an ordinary run over these genomes, so it uses the same populations and operations.
+
+use super::agent::{Agent, GeneBounds};
+use super::evolution::run_iterations;
+use super::fitness::{FitnessFunction, GeneralScoreProvider, Score, ScoreError};
+use super::operations::{Operation, OperationType, Pipeline, Selection};
+use super::population::Population;
+use rand::distributions::{Distribution, Standard};
+use rand::Rng;
+
+/// The most a decoded Cull removes, so that a pipeline cannot empty its population.
+const MAX_CULL: f64 = 0.5;
+
+/// One operation of an encoded pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OperationGene {
+    /// 0 for Mutate, 1 for Crossover, 2 for Cull.
+    pub kind: u8,
+    /// 0 for the highest scored agents, 1 for the lowest, 2 for random agents. Culls always take
+    /// the lowest.
+    pub selection: u8,
+    /// The proportion selected, in tenths from 1 to 10.
+    pub tenths: u8
+}
+
+impl Distribution<OperationGene> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> OperationGene {
+        OperationGene {
+            kind: rng.gen_range(0, 3),
+            selection: rng.gen_range(0, 3),
+            tenths: rng.gen_range(1, 11)
+        }
+    }
+}
+
+impl OperationGene {
+    /// The operation the gene encodes.
+    pub fn to_operation<Gene, Data>(&self) -> Operation<Gene, Data>
+    where
+    Standard: Distribution<Gene>,
+    Gene: GeneBounds,
+    Data: Clone + Send + 'static
+    {
+        let proportion = f64::from(self.tenths.clamp(1, 10)) / 10.0;
+        match self.kind % 3 {
+            0 => Operation::new(OperationType::Mutate, self.selection(proportion)),
+            1 => Operation::new(OperationType::Crossover, self.selection(proportion)),
+            _ => Operation::new(OperationType::Cull, Selection::bottom(proportion.min(MAX_CULL)))
+        }
+    }
+
+    fn selection(&self, proportion: f64) -> Selection {
+        match self.selection % 3 {
+            0 => Selection::top(proportion),
+            1 => Selection::bottom(proportion),
+            _ => Selection::random(proportion)
+        }
+    }
+}
+
+/// Decodes a pipeline genome into its operations.
+pub fn decode_pipeline<Gene, Data>(pipeline: &Agent<OperationGene>) -> Pipeline<Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    pipeline.get_genes().iter().fold(Pipeline::new(), |decoded, gene| decoded.then(gene.to_operation()))
+}
+
+/// The problem a pipeline is tried on, used as the Data when scoring pipeline genomes.
+#[derive(Clone)]
+pub struct InnerRun <Gene, Data> {
+    fitness: FitnessFunction<Gene, Data>,
+    data: Data,
+    population_size: usize,
+    number_of_genes: usize,
+    iterations: usize,
+    repeats: usize
+}
+
+impl <Gene, Data> InnerRun <Gene, Data> {
+    pub fn new(fitness: FitnessFunction<Gene, Data>, data: Data, population_size: usize, number_of_genes: usize, iterations: usize) -> Self {
+        Self {
+            fitness,
+            data,
+            population_size: population_size.max(1),
+            number_of_genes,
+            iterations,
+            repeats: 1
+        }
+    }
+
+    /// Sets how many inner runs each pipeline is scored by, taking the mean of their best scores.
+    /// More runs give a less noisy score at a proportional cost. Defaults to one.
+    pub fn set_repeats(&mut self, repeats: usize) {
+        self.repeats = repeats.max(1);
+    }
+}
+
+/// A fitness function for pipeline genomes: the mean best true score of inner runs using the
+/// decoded pipeline. The inner population is kept to its starting size.
+pub fn pipeline_fitness<Gene, Data>(pipeline: &Agent<OperationGene>, run: &InnerRun<Gene, Data>) -> Result<Score, ScoreError>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let operations = decode_pipeline(pipeline).into_operations();
+    let mut total = 0;
+    for _ in 0..run.repeats {
+        let mut score_provider = GeneralScoreProvider::new(run.fitness, 0);
+        let mut population = Population::new(run.population_size, run.number_of_genes, false, &run.data, &mut score_provider);
+        population.set_max_size(run.population_size);
+        let population = run_iterations(population, run.iterations, &run.data, &operations, &mut score_provider);
+        match population.true_best(&mut score_provider, &run.data) {
+            Some((score, _)) => total += score,
+            None => return Err(ScoreError::new("the inner run left no agents that could be scored"))
+        }
+    }
+    Ok(total / run.repeats as Score)
+}
+
+/// Evolves pipelines of the given number of operations for the inner run, returning the best
+/// found with its score. The outer run uses the balanced pipeline over pipeline genomes.
+pub fn evolve_pipeline<Gene, Data>(run: InnerRun<Gene, Data>, pipelines: usize, pipeline_length: usize, iterations: usize) -> Option<(Score, Pipeline<Gene, Data>)>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut score_provider = GeneralScoreProvider::new(pipeline_fitness::<Gene, Data>, 0);
+    let mut population = Population::<OperationGene>::new(pipelines.max(1), pipeline_length, true, &run, &mut score_provider);
+    population.set_max_size(pipelines.max(1));
+    let operations = Pipeline::<OperationGene, InnerRun<Gene, Data>>::balanced().into_operations();
+    let population = run_iterations::<OperationGene, _, _>(population, iterations, &run, &operations, &mut score_provider);
+    population.true_best(&mut score_provider, &run)
+        .map(|(score, best)| (score, decode_pipeline::<Gene, Data>(best)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::random;
+
+    fn count_ones(agent: &Agent<u8>, _data: &()) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().map(|gene| gene.count_ones() as Score).sum())
+    }
+
+    #[test]
+    fn genes_decode_to_operations() {
+        let pipeline = Agent::from_genes(vec![
+            OperationGene { kind: 0, selection: 2, tenths: 3 },
+            OperationGene { kind: 2, selection: 0, tenths: 9 }
+        ]);
+        let decoded: Pipeline<u8, ()> = decode_pipeline(&pipeline);
+        let operations = decoded.get_operations();
+        assert_eq!(2, operations.len());
+        assert_eq!(OperationType::Mutate, operations[0].get_operation_type());
+        assert_eq!(0.3, operations[0].get_selection().proportion());
+        // Culls take the lowest scored agents, at most half of them.
+        assert_eq!(OperationType::Cull, operations[1].get_operation_type());
+        assert_eq!(0.5, operations[1].get_selection().proportion());
+    }
+
+    #[test]
+    fn evolves_a_pipeline() {
+        let mut run = InnerRun::new(count_ones, (), 10, 4, 5);
+        run.set_repeats(4);
+        // Without operations the inner runs keep their best random agent.
+        let (empty, (score, pipeline)) = random::with_seed(5, || {
+            let empty = pipeline_fitness(&Agent::from_genes(Vec::new()), &run).unwrap();
+            (empty, evolve_pipeline(run, 6, 3, 2).unwrap())
+        });
+        assert_eq!(3, pipeline.get_operations().len());
+        assert!(score > empty, "evolved pipeline scored {}, the empty pipeline {}", score, empty);
+    }
+}
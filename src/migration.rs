@@ -0,0 +1,168 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migration of agents between runs, such as runs on machines sharing a filesystem.
+//!
+//! A `DropBox` writes the best agents of its run to a file of its own in a shared directory, and
+//! reads the agents other runs have written there. Each file holds one agent per line, its genes
+//! encoded by a `GeneCodec` and written as hexadecimal. Files are written under a temporary name
+//! and renamed, so readers never see a file half written.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::population::Population;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+const EXTENSION: &str = "agents";
+
+/// Exchanges agents with other runs. Set it on a Manager with Manager::set_migration, which
+/// keeps the last error for Manager::take_migration_error.
+pub trait Migration<Gene>: Send {
+    /// Sends agents from the population to the other runs.
+    fn emigrate(&mut self, population: &Population<Gene>) -> io::Result<()>;
+
+    /// Receives the agents sent by other runs since the last call. They are scored on arrival.
+    fn immigrate(&mut self) -> io::Result<Vec<Agent<Gene>>>;
+}
+
+/// Migration through files in a directory shared by the runs.
+pub struct DropBox <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    directory: PathBuf,
+    name: String,
+    codec: C,
+    emigrants: usize,
+    seen: HashMap<PathBuf, u64>,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> DropBox <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene>
+{
+    /// directory: shared by every run, and created if needed. name: unique to this run, naming the
+    /// file it writes. Sends the 10 best agents by default.
+    pub fn new<P: Into<PathBuf>>(directory: P, name: &str, codec: C) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            name: name.to_string(),
+            codec,
+            emigrants: 10,
+            seen: HashMap::new(),
+            gene: PhantomData
+        })
+    }
+
+    /// Sets the number of highest scored agents written each time.
+    pub fn set_emigrants(&mut self, number: usize) {
+        self.emigrants = number;
+    }
+
+    /// Replaces this run's file with the highest scored agents of the population.
+    pub fn send(&self, population: &Population<Gene>) -> io::Result<()> {
+        let mut contents = String::new();
+        for agent in population.get_agents().values().rev().take(self.emigrants) {
+            contents.push_str(&fingerprint::to_hex(&fingerprint::encode_genes(agent.get_genes(), &self.codec)));
+            contents.push('\n');
+        }
+
+        let temporary = self.directory.join(format!("{}.tmp", self.name));
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, self.directory.join(format!("{}.{}", self.name, EXTENSION)))
+    }
+
+    /// Reads the agents in files written by other runs that have changed since they were last
+    /// read. Lines that cannot be decoded are skipped.
+    pub fn receive(&mut self) -> io::Result<Vec<Agent<Gene>>> {
+        let own = self.directory.join(format!("{}.{}", self.name, EXTENSION));
+        let mut agents = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path == own || path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            // The other run may have replaced the file since it was listed.
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(_) => continue
+            };
+            let hash = fingerprint::hash_bytes(contents.as_bytes());
+            if self.seen.insert(path, hash) == Some(hash) {
+                continue;
+            }
+            agents.extend(contents.lines()
+                .filter_map(|line| fingerprint::from_hex(line.trim()))
+                .filter_map(|bytes| fingerprint::decode_genes(&bytes, &self.codec))
+                .map(Agent::from_genes));
+        }
+        Ok(agents)
+    }
+}
+
+impl <Gene, C> Migration<Gene> for DropBox <Gene, C>
+where
+Gene: Hash + Send,
+C: GeneCodec<Gene> + Send
+{
+    fn emigrate(&mut self, population: &Population<Gene>) -> io::Result<()> {
+        self.send(population)
+    }
+
+    fn immigrate(&mut self) -> io::Result<Vec<Agent<Gene>>> {
+        self.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::env;
+
+    #[test]
+    fn runs_exchange_best_agents() {
+        let directory = env::temp_dir().join(format!("xu_migration_test_{}", std::process::id()));
+        let mut one = DropBox::new(&directory, "one", PrimitiveCodec).unwrap();
+        let mut two = DropBox::new(&directory, "two", PrimitiveCodec).unwrap();
+        one.set_emigrants(2);
+
+        let mut population = Population::new_empty(false);
+        for score in 1..5u8 {
+            population.insert(score as u64, Agent::from_genes(vec![score, score]));
+        }
+        one.send(&population).unwrap();
+
+        assert!(one.receive().unwrap().is_empty());
+        let received: Vec<Vec<u8>> = two.receive().unwrap().iter().map(|agent| agent.get_genes().clone()).collect();
+        assert_eq!(vec![vec![4, 4], vec![3, 3]], received);
+
+        // Files are only read again once they change.
+        assert!(two.receive().unwrap().is_empty());
+        population.insert(9, Agent::from_genes(vec![9u8, 9]));
+        one.emigrate(&population).unwrap();
+        assert_eq!(2, two.immigrate().unwrap().len());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
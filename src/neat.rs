@@ -0,0 +1,506 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NEAT-style neural network genomes, enabled with the `neat` feature.
+//!
+//! A genome is an `Agent<ConnectionGene>`, its genes being the connections of the network ordered
+//! by innovation number. Nodes are numbered with the inputs first, then a bias node that always
+//! outputs 1.0, then the outputs, then any hidden nodes added by mutation.
+//!
+//! The fitness function builds a `Network` from the agent and activates it on the problem's inputs.
+//! `Neat::reproduce` creates offspring by crossover aligned on innovation numbers and by weight and
+//! structural mutation; cull the population as usual to keep its size down.
+
+use super::random;
+use super::agent::Agent;
+use super::fitness::ScoreProvider;
+use super::population::Population;
+use rand::Rng;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// A weighted connection between two nodes. The innovation number identifies the structural change
+/// that created the connection, so that matching connections can be lined up in crossover.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionGene {
+    pub innovation: u64,
+    pub from: usize,
+    pub to: usize,
+    pub weight: f64,
+    pub enabled: bool
+}
+
+impl Hash for ConnectionGene {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.innovation.hash(state);
+        self.from.hash(state);
+        self.to.hash(state);
+        self.weight.to_bits().hash(state);
+        self.enabled.hash(state);
+    }
+}
+
+/// The numbers of input and output nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub inputs: usize,
+    pub outputs: usize
+}
+
+impl Layout {
+    pub fn bias(&self) -> usize {
+        self.inputs
+    }
+
+    /// The number of nodes before any hidden nodes: the inputs, the bias and the outputs.
+    pub fn fixed_nodes(&self) -> usize {
+        self.inputs + 1 + self.outputs
+    }
+
+    pub fn is_output(&self, node: usize) -> bool {
+        node > self.inputs && node < self.fixed_nodes()
+    }
+
+    /// Whether the node only ever sends values, being an input or the bias.
+    pub fn is_source(&self, node: usize) -> bool {
+        node <= self.inputs
+    }
+}
+
+/// Hands out innovation numbers and hidden node numbers, so the same structural change gets the
+/// same numbers in every genome it appears in.
+#[derive(Clone)]
+pub struct InnovationTracker {
+    next_innovation: u64,
+    next_node: usize,
+    connections: HashMap<(usize, usize), u64>,
+    splits: HashMap<u64, usize>
+}
+
+impl InnovationTracker {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            next_innovation: 0,
+            next_node: layout.fixed_nodes(),
+            connections: HashMap::new(),
+            splits: HashMap::new()
+        }
+    }
+
+    /// The innovation number of the connection between the two nodes.
+    pub fn connection(&mut self, from: usize, to: usize) -> u64 {
+        let next = &mut self.next_innovation;
+        *self.connections.entry((from, to)).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    /// The hidden node added by splitting the connection with the given innovation number.
+    pub fn split(&mut self, innovation: u64) -> usize {
+        let next = &mut self.next_node;
+        *self.splits.entry(innovation).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+}
+
+/// Creates and reproduces network genomes.
+pub struct Neat {
+    layout: Layout,
+    innovations: InnovationTracker,
+    weight_mutation_rate: f64,
+    weight_power: f64,
+    add_connection_rate: f64,
+    add_node_rate: f64
+}
+
+impl Neat {
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        let layout = Layout { inputs, outputs };
+        Self {
+            layout,
+            innovations: InnovationTracker::new(layout),
+            weight_mutation_rate: 0.8,
+            weight_power: 0.5,
+            add_connection_rate: 0.05,
+            add_node_rate: 0.03
+        }
+    }
+
+    pub fn get_layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Sets the chance that a child's weights are mutated, and the largest change to a weight.
+    pub fn set_weight_mutation(&mut self, rate: f64, power: f64) {
+        self.weight_mutation_rate = rate;
+        self.weight_power = power;
+    }
+
+    /// Sets the chances that a child gains a new connection and a new hidden node.
+    pub fn set_structural_mutation(&mut self, add_connection_rate: f64, add_node_rate: f64) {
+        self.add_connection_rate = add_connection_rate;
+        self.add_node_rate = add_node_rate;
+    }
+
+    /// Creates a genome connecting every input and the bias to every output, with random weights.
+    pub fn initial_genome(&mut self) -> Agent<ConnectionGene> {
+        let mut rng = random::rng();
+        let mut genes = Vec::new();
+        for from in 0..=self.layout.inputs {
+            for to in (self.layout.inputs + 1)..self.layout.fixed_nodes() {
+                genes.push(ConnectionGene {
+                    innovation: self.innovations.connection(from, to),
+                    from,
+                    to,
+                    weight: rng.gen_range(-1.0, 1.0),
+                    enabled: true
+                });
+            }
+        }
+        Agent::from_genes(genes)
+    }
+
+    /// Creates a scored population of initial genomes.
+    pub fn initial_population<Data, SP>(&mut self, size: usize, data: &Data, score_provider: &mut SP) -> Population<ConnectionGene>
+    where
+    SP: ScoreProvider<ConnectionGene, Data>
+    {
+        let agents = (0..size).map(|_| self.initial_genome()).collect();
+        let mut population = Population::new_empty(false);
+        population.score_and_insert(agents, data, score_provider);
+        population
+    }
+
+    /// Adds offspring to the population. Parents are chosen from the higher scored half, crossed
+    /// over with the fitter parent's structure kept, then mutated.
+    pub fn reproduce<Data, SP>(&mut self, mut population: Population<ConnectionGene>, offspring: usize, data: &Data, score_provider: &mut SP) -> Population<ConnectionGene>
+    where
+    SP: ScoreProvider<ConnectionGene, Data>
+    {
+        population.breed_from_upper_half(offspring, data, score_provider, |fitter, other| self.mutate(&crossover(fitter, other)));
+        population
+    }
+
+    /// Returns a mutated copy of the genome.
+    pub fn mutate(&mut self, genome: &Agent<ConnectionGene>) -> Agent<ConnectionGene> {
+        let mut rng = random::rng();
+        let mut genes = genome.get_genes().clone();
+
+        if rng.gen::<f64>() < self.weight_mutation_rate {
+            for gene in genes.iter_mut() {
+                if rng.gen::<f64>() < 0.1 {
+                    gene.weight = rng.gen_range(-1.0, 1.0);
+                } else {
+                    gene.weight += rng.gen_range(-self.weight_power, self.weight_power);
+                }
+            }
+        }
+        if rng.gen::<f64>() < self.add_connection_rate {
+            self.add_connection(&mut genes);
+        }
+        if rng.gen::<f64>() < self.add_node_rate {
+            self.add_node(&mut genes);
+        }
+
+        Agent::from_genes(genes)
+    }
+
+    /// Connects two unconnected nodes, without creating a cycle.
+    fn add_connection(&mut self, genes: &mut Vec<ConnectionGene>) {
+        let mut rng = random::rng();
+        let node_count = count_nodes(genes, self.layout);
+        // Only output and hidden nodes can be connected to, and there may be none.
+        if node_count <= self.layout.inputs + 1 {
+            return;
+        }
+        for _ in 0..20 {
+            let from = rng.gen_range(0, node_count);
+            let to = rng.gen_range(self.layout.inputs + 1, node_count);
+            if from == to || self.layout.is_output(from)
+                || genes.iter().any(|gene| gene.from == from && gene.to == to)
+                || reaches(genes, to, from) {
+                continue;
+            }
+
+            genes.push(ConnectionGene {
+                innovation: self.innovations.connection(from, to),
+                from,
+                to,
+                weight: rng.gen_range(-1.0, 1.0),
+                enabled: true
+            });
+            genes.sort_by_key(|gene| gene.innovation);
+            return;
+        }
+    }
+
+    /// Splits an enabled connection with a hidden node. The connection into the node has weight 1.0
+    /// and the connection out keeps the old weight, so the network's behaviour barely changes.
+    fn add_node(&mut self, genes: &mut Vec<ConnectionGene>) {
+        let mut rng = random::rng();
+        let enabled: Vec<usize> = (0..genes.len()).filter(|index| genes[*index].enabled).collect();
+        if enabled.is_empty() {
+            return;
+        }
+
+        let split = genes[enabled[rng.gen_range(0, enabled.len())]];
+        let node = self.innovations.split(split.innovation);
+        if genes.iter().any(|gene| gene.from == node || gene.to == node) {
+            return;
+        }
+
+        for gene in genes.iter_mut() {
+            if gene.innovation == split.innovation {
+                gene.enabled = false;
+            }
+        }
+        genes.push(ConnectionGene {
+            innovation: self.innovations.connection(split.from, node),
+            from: split.from,
+            to: node,
+            weight: 1.0,
+            enabled: true
+        });
+        genes.push(ConnectionGene {
+            innovation: self.innovations.connection(node, split.to),
+            from: node,
+            to: split.to,
+            weight: split.weight,
+            enabled: true
+        });
+        genes.sort_by_key(|gene| gene.innovation);
+    }
+}
+
+/// Crosses over two genomes, lining connections up by innovation number. Matching connections are
+/// taken from either parent at random; connections only the fitter parent has are kept, and those
+/// only the other parent has are dropped.
+pub fn crossover(fitter: &Agent<ConnectionGene>, other: &Agent<ConnectionGene>) -> Agent<ConnectionGene> {
+    let mut rng = random::rng();
+    let others: HashMap<u64, &ConnectionGene> = other.get_genes().iter().map(|gene| (gene.innovation, gene)).collect();
+    let genes = fitter.get_genes().iter()
+        .map(|gene| match others.get(&gene.innovation) {
+            Some(matching) if rng.gen::<bool>() => **matching,
+            _ => *gene
+        })
+        .collect();
+    Agent::from_genes(genes)
+}
+
+/// The number of connections only one genome has, plus the mean weight difference of the matching
+/// connections, rounded. Suitable as the distance function of a Speciation.
+pub fn species_distance(a: &Agent<ConnectionGene>, b: &Agent<ConnectionGene>) -> usize {
+    let b_genes: HashMap<u64, &ConnectionGene> = b.get_genes().iter().map(|gene| (gene.innovation, gene)).collect();
+    let mut matching = 0;
+    let mut weight_difference = 0.0;
+    for gene in a.get_genes() {
+        if let Some(other) = b_genes.get(&gene.innovation) {
+            matching += 1;
+            weight_difference += (gene.weight - other.weight).abs();
+        }
+    }
+
+    let unmatched = a.get_genes().len() + b.get_genes().len() - 2 * matching;
+    let mean_difference = if matching > 0 { weight_difference / matching as f64 } else { 0.0 };
+    unmatched + mean_difference.round() as usize
+}
+
+/// A network built from a genome, ready to be activated.
+pub struct Network {
+    layout: Layout,
+    /// Enabled connections into each node, as (from, weight).
+    incoming: Vec<Vec<(usize, f64)>>,
+    /// The nodes to calculate, in order.
+    order: Vec<usize>,
+    values: Vec<f64>
+}
+
+impl Network {
+    pub fn new(genome: &Agent<ConnectionGene>, layout: Layout) -> Self {
+        let genes = genome.get_genes();
+        let node_count = count_nodes(genes, layout);
+        let mut incoming = vec![Vec::new(); node_count];
+        for gene in genes.iter().filter(|gene| gene.enabled) {
+            incoming[gene.to].push((gene.from, gene.weight));
+        }
+
+        // Calculate each node after the nodes feeding it. Nodes in a cycle are left to the end and
+        // use the values from the previous activation for connections from later nodes.
+        let mut order = Vec::new();
+        let mut done: Vec<bool> = (0..node_count).map(|node| layout.is_source(node)).collect();
+        loop {
+            let ready: Vec<usize> = (0..node_count)
+                .filter(|node| !done[*node] && incoming[*node].iter().all(|(from, _)| done[*from]))
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for node in ready {
+                done[node] = true;
+                order.push(node);
+            }
+        }
+        order.extend((0..node_count).filter(|node| !done[*node]));
+
+        Self {
+            layout,
+            incoming,
+            order,
+            values: vec![0.0; node_count]
+        }
+    }
+
+    /// Feeds the inputs through the network and returns the values of the output nodes.
+    /// Missing inputs are treated as 0.0.
+    pub fn activate(&mut self, inputs: &[f64]) -> Vec<f64> {
+        for input in 0..self.layout.inputs {
+            self.values[input] = inputs.get(input).cloned().unwrap_or(0.0);
+        }
+        self.values[self.layout.bias()] = 1.0;
+
+        for node in &self.order {
+            let sum: f64 = self.incoming[*node].iter().map(|(from, weight)| self.values[*from] * weight).sum();
+            self.values[*node] = sigmoid(sum);
+        }
+
+        self.values[(self.layout.inputs + 1)..self.layout.fixed_nodes()].to_vec()
+    }
+}
+
+fn sigmoid(value: f64) -> f64 {
+    1.0 / (1.0 + (-4.9 * value).exp())
+}
+
+fn count_nodes(genes: &[ConnectionGene], layout: Layout) -> usize {
+    genes.iter()
+        .map(|gene| gene.from.max(gene.to) + 1)
+        .max()
+        .unwrap_or(0)
+        .max(layout.fixed_nodes())
+}
+
+/// Whether there is a path of enabled connections from one node to another.
+fn reaches(genes: &[ConnectionGene], from: usize, to: usize) -> bool {
+    let mut stack = vec![from];
+    let mut visited = Vec::new();
+    while let Some(node) = stack.pop() {
+        if node == to {
+            return true;
+        }
+        if visited.contains(&node) {
+            continue;
+        }
+        visited.push(node);
+        stack.extend(genes.iter().filter(|gene| gene.enabled && gene.from == node).map(|gene| gene.to));
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::{GeneralScoreProvider, Score, ScoreError};
+
+    fn connection(innovation: u64, from: usize, to: usize, weight: f64) -> ConnectionGene {
+        ConnectionGene { innovation, from, to, weight, enabled: true }
+    }
+
+    #[test]
+    fn initial_genome_connects_inputs_and_bias_to_outputs() {
+        let mut neat = Neat::new(3, 2);
+        let genome = neat.initial_genome();
+        assert_eq!(8, genome.get_genes().len());
+
+        // Innovation numbers are shared between genomes.
+        let other = neat.initial_genome();
+        let innovations = |agent: &Agent<ConnectionGene>| agent.get_genes().iter().map(|gene| gene.innovation).collect::<Vec<u64>>();
+        assert_eq!(innovations(&genome), innovations(&other));
+    }
+
+    #[test]
+    fn network_activation() {
+        let layout = Layout { inputs: 2, outputs: 1 };
+        // Inputs 0 and 1, bias 2, output 3 and hidden node 4.
+        let genome = Agent::from_genes(vec![
+            connection(0, 0, 4, 1.0),
+            connection(1, 1, 4, 1.0),
+            connection(2, 4, 3, 2.0),
+            connection(3, 2, 3, -1.0)
+        ]);
+        let mut network = Network::new(&genome, layout);
+
+        let hidden = sigmoid(2.0);
+        let output = network.activate(&[1.0, 1.0]);
+        assert_eq!(1, output.len());
+        assert!((output[0] - sigmoid(hidden * 2.0 - 1.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn add_node_splits_connection() {
+        let mut neat = Neat::new(1, 1);
+        let mut genes = neat.initial_genome().get_genes().clone();
+        let before = genes.len();
+        neat.add_node(&mut genes);
+
+        assert_eq!(before + 2, genes.len());
+        assert_eq!(1, genes.iter().filter(|gene| !gene.enabled).count());
+        assert!(genes.iter().any(|gene| gene.to == 3 && gene.weight == 1.0));
+
+        // The same split in another genome gets the same numbers.
+        let mut other = neat.initial_genome().get_genes().clone();
+        other.retain(|gene| gene.innovation == genes.iter().find(|gene| !gene.enabled).unwrap().innovation);
+        neat.add_node(&mut other);
+        assert!(other.iter().all(|gene| genes.iter().any(|existing| (existing.innovation, existing.from, existing.to) == (gene.innovation, gene.from, gene.to))));
+    }
+
+    #[test]
+    fn mutation_without_outputs_adds_nothing() {
+        let mut neat = Neat::new(2, 0);
+        neat.set_structural_mutation(1.0, 1.0);
+        let genome = neat.initial_genome();
+        assert!(neat.mutate(&genome).get_genes().is_empty());
+    }
+
+    #[test]
+    fn crossover_keeps_fitter_structure() {
+        let fitter = Agent::from_genes(vec![connection(0, 0, 2, 1.0), connection(2, 1, 2, 1.0)]);
+        let other = Agent::from_genes(vec![connection(0, 0, 2, 5.0), connection(1, 0, 3, 5.0)]);
+        let child = crossover(&fitter, &other);
+
+        let innovations: Vec<u64> = child.get_genes().iter().map(|gene| gene.innovation).collect();
+        assert_eq!(vec![0, 2], innovations);
+        assert_eq!(1.0, child.get_genes()[1].weight);
+        assert_eq!(6, species_distance(&fitter, &other));
+    }
+
+    fn output_near_one(agent: &Agent<ConnectionGene>, layout: &Layout) -> Result<Score, ScoreError> {
+        let output = Network::new(agent, *layout).activate(&[1.0])[0];
+        Ok((output * 1000.0) as Score)
+    }
+
+    #[test]
+    fn reproduce_adds_offspring() {
+        let mut neat = Neat::new(1, 1);
+        let layout = neat.get_layout();
+        let mut score_provider = GeneralScoreProvider::new(output_near_one, 5);
+        let population = neat.initial_population(10, &layout, &mut score_provider);
+        let size = population.len();
+
+        let population = neat.reproduce(population, 20, &layout, &mut score_provider);
+        assert!(population.len() > size);
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Crossover and mutation over slices of f32 or f64 genes, for very long real-valued genomes such
+//! as those of parameter tuning. Children are written into a single buffer, and mutation and
+//! clamping work on the genes in place.
+
+use super::random;
+use rand::distributions::{Distribution, Normal};
+use rand::Rng;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Mul, Sub};
+
+/// A floating point gene type the operators work on.
+pub trait FloatGene: Copy + Default + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(value: f64) -> Self;
+}
+
+impl FloatGene for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl FloatGene for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// A parameter an operator cannot work with, such as a negative standard deviation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterError {
+    details: String
+}
+
+impl ParameterError {
+    pub fn new(details: &str) -> Self {
+        Self {
+            details: details.to_string()
+        }
+    }
+}
+
+impl Display for ParameterError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for ParameterError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Replaces each value of the output with the function of it and the genes of a and b at the same
+/// position. The slices must be the same length.
+fn combine<T: FloatGene, F: Fn(T, T, T) -> T>(output: &mut [T], a: &[T], b: &[T], function: F) {
+    for ((output, a), b) in output.iter_mut().zip(a).zip(b) {
+        *output = function(*output, *a, *b);
+    }
+}
+
+/// Each child gene is the weighted mean weight × a + (1 − weight) × b. Genes beyond the shorter
+/// parent are not included.
+pub fn arithmetic_crossover<T: FloatGene>(a: &[T], b: &[T], weight: T) -> Vec<T> {
+    let length = a.len().min(b.len());
+    let mut child = vec![T::default(); length];
+    combine(&mut child, &a[..length], &b[..length], |_, a, b| b + (a - b) * weight);
+    child
+}
+
+/// Blend crossover (BLX-α): each child gene is drawn uniformly from the range between the parent
+/// genes, extended by alpha times its width on both sides.
+pub fn blend_crossover<T: FloatGene>(a: &[T], b: &[T], alpha: f64) -> Vec<T> {
+    let length = a.len().min(b.len());
+    let mut rng = random::rng();
+    let alpha = T::from_f64(alpha);
+    let one = T::from_f64(1.0);
+    let two = T::from_f64(2.0);
+    // Uniform draws from -alpha to 1 + alpha, as a proportion of the way from a to b, replaced by
+    // the child genes.
+    let mut child: Vec<T> = (0..length)
+        .map(|_| T::from_f64(rng.gen::<f64>()) * (one + two * alpha) - alpha)
+        .collect();
+    combine(&mut child, &a[..length], &b[..length], |position, a, b| a + (b - a) * position);
+    child
+}
+
+/// Adds normally distributed noise with the standard deviation to each gene with the probability
+/// of the rate. Returns an error, leaving the genes unchanged, if the standard deviation is
+/// negative or not a number.
+pub fn gaussian_mutation<T: FloatGene>(genes: &mut [T], standard_deviation: f64, rate: f64) -> Result<(), ParameterError> {
+    if standard_deviation.is_nan() || standard_deviation < 0.0 {
+        return Err(ParameterError::new(&format!("invalid standard deviation {}", standard_deviation)));
+    }
+    let mut rng = random::rng();
+    let normal = Normal::new(0.0, standard_deviation);
+    for gene in genes.iter_mut() {
+        if rng.gen::<f64>() < rate {
+            *gene = *gene + T::from_f64(normal.sample(&mut rng));
+        }
+    }
+    Ok(())
+}
+
+/// Limits each gene to the range from minimum to maximum.
+pub fn clamp<T: FloatGene>(genes: &mut [T], minimum: T, maximum: T) {
+    for gene in genes.iter_mut() {
+        if *gene < minimum {
+            *gene = minimum;
+        } else if *gene > maximum {
+            *gene = maximum;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossovers_stay_between_parents() {
+        let a: Vec<f64> = (0..21).map(|gene| gene as f64).collect();
+        let b: Vec<f64> = (0..21).map(|gene| 100.0 + gene as f64).collect();
+
+        let child = arithmetic_crossover(&a, &b, 0.25);
+        assert_eq!(21, child.len());
+        assert_eq!(75.0, child[0]);
+        assert_eq!(95.0, child[20]);
+
+        let child = blend_crossover(&a, &b[..15], 0.5);
+        assert_eq!(15, child.len());
+        for (position, gene) in child.iter().enumerate() {
+            assert!(*gene >= position as f64 - 50.0 && *gene <= position as f64 + 150.0);
+        }
+    }
+
+    #[test]
+    fn mutation_and_clamping() {
+        let mut genes = vec![0.5f32; 19];
+        gaussian_mutation(&mut genes, 1.0, 0.0).unwrap();
+        assert!(genes.iter().all(|gene| *gene == 0.5));
+
+        assert!(gaussian_mutation(&mut genes, -1.0, 1.0).is_err());
+        assert!(gaussian_mutation(&mut genes, f64::NAN, 1.0).is_err());
+        assert!(genes.iter().all(|gene| *gene == 0.5));
+
+        gaussian_mutation(&mut genes, 10.0, 1.0).unwrap();
+        assert!(genes.iter().any(|gene| *gene != 0.5));
+
+        clamp(&mut genes, 0.0, 1.0);
+        assert!(genes.iter().all(|gene| *gene >= 0.0 && *gene <= 1.0));
+    }
+}
@@ -12,38 +12,116 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::agent::{Agent, crossover};
-use super::population::Population;
+use super::random;
+use super::agent::{self, Agent, GeneBounds};
+use super::population::{Population, band_bounds};
+use super::pareto::{self, ObjectivesFunction};
+use super::speciation::{self, DistanceFunction, Speciation};
 use std::hash::Hash;
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
+    RngCore
 };
 use std::marker::{Send, PhantomData};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
 use super::fitness::{Score, ScoreProvider};
+use super::pool::BufferPool;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OperationType {
     Mutate,
     Crossover,
-    Cull
+    Cull,
+    /// Replaces each gene of the selected agents in turn with a random gene, keeping the change
+    /// whenever it improves the score. Best used on a small selection of the highest scored agents.
+    CoordinateSweep,
+    /// Divides the population into species, removes stagnant species, and creates as many children
+    /// as the selection would select by crossover within each species, shared in proportion to
+    /// the species' adjusted fitness. See Operation::set_speciation.
+    SpeciatedCrossover,
+    /// Deterministic crowding: pairs of selected agents are crossed over and each child competes
+    /// only against the parent most similar to it, replacing that parent if it scores higher.
+    /// This keeps distinct niches alive far better than inserting children and culling.
+    /// See Operation::set_distance.
+    CrowdingCrossover,
+    /// Keeps the selected agents and replaces all others with random agents of the length of the
+    /// highest scored agent, keeping the population size. Select the highest scored agents to
+    /// reset diversity while keeping the best found, as when a long run has stagnated.
+    Restart,
+    /// Crosses each selected agent with a random agent from a secondary population, such as a hall
+    /// of fame or the elites of a previous run. See Operation::set_mates.
+    ForeignCrossover,
+    /// Removes as many agents as the selection counts, dominated agents first. Agents are ranked
+    /// into non-dominated fronts by the objectives set with Operation::set_objectives, and the
+    /// last fronts are removed first, lowest scored first within a front, which keeps agents
+    /// trading the objectives off differently. The highest scored agent is never removed.
+    /// Without objectives, this removes the lowest scored agents.
+    ParetoCull,
+    /// Grows or shrinks copies of the selected agents by appending random genes, duplicating a
+    /// segment or deleting a segment, so variable-length problems can search for the right genome
+    /// length. See Operation::set_length_bounds.
+    LengthMutate
+}
+
+/// How the Crossover operation creates children.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CrossoverMode {
+    /// Each child joins the start of one parent to the end of another.
+    TwoParent,
+    /// Each child takes every gene by a vote of this many parents, weighted by their score.
+    MajorityVote(usize),
+    /// Each child is made of one contiguous segment from each of this many parents.
+    Segments(usize)
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SelectionType {
     RandomAny,
     HighestScore,
-    LowestScore
+    LowestScore,
+    /// Stochastic universal sampling: fitness proportionate selection that spins the wheel once,
+    /// with as many evenly spaced pointers as agents to select. This has lower variance than
+    /// repeated roulette draws. Agents picked by several pointers are selected once.
+    StochasticUniversal,
+    /// Divides the agents into `of` bands of equal size by score, lowest first, and selects every
+    /// agent in the bands from `from` up to but not including `to`. The proportion is ignored.
+    /// See Selection::bands.
+    Bands { from: usize, to: usize, of: usize },
+    /// Selects each agent by a tournament among this many agents drawn at random, with
+    /// replacement, from those not yet selected, the highest scored winning. Larger tournaments
+    /// favour high scores more; a size of one selects at random. This depends only on the order
+    /// of scores, but puts less pressure on them than taking the highest scored.
+    /// See Selection::tournament.
+    Tournament { size: usize }
 }
 
 /// Allows definition of parameters for selecting some agents from a population.
-#[derive(Clone, Copy)]
+/// Selections can be combined with and, or, not and within, which are evaluated against the
+/// population each time the agents are selected.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Selection {
     selection_type: SelectionType,
     proportion: f64,
-    preferred_minimum: usize
+    preferred_minimum: usize,
+    scope: Option<Box<Selection>>,
+    combinations: Vec<Combination>
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Combination {
+    And(Selection),
+    Or(Selection),
+    Not
 }
 
 impl Selection {
@@ -51,7 +129,9 @@ impl Selection {
         Self {
             selection_type: selection_type,
             proportion: proportion,
-            preferred_minimum: preferred_minimum
+            preferred_minimum: preferred_minimum,
+            scope: None,
+            combinations: Vec::new()
         }
     }
 
@@ -59,10 +139,81 @@ impl Selection {
         Self {
             selection_type: selection_type,
             proportion: proportion,
-            preferred_minimum: 1
+            preferred_minimum: 1,
+            scope: None,
+            combinations: Vec::new()
         }
     }
 
+    /// Selects the proportion of agents with the highest scores, at least one.
+    pub fn top(proportion: f64) -> Self {
+        Self::new(SelectionType::HighestScore, proportion)
+    }
+
+    /// Selects the proportion of agents with the lowest scores, at least one.
+    pub fn bottom(proportion: f64) -> Self {
+        Self::new(SelectionType::LowestScore, proportion)
+    }
+
+    /// Selects the proportion of agents at random, at least one.
+    pub fn random(proportion: f64) -> Self {
+        Self::new(SelectionType::RandomAny, proportion)
+    }
+
+    /// Selects the tenth of agents with the highest scores.
+    pub fn top_decile() -> Self {
+        Self::top(0.1)
+    }
+
+    /// Selects a tenth of the agents at random.
+    pub fn random_tenth() -> Self {
+        Self::random(0.1)
+    }
+
+    /// Selects the agents in score bands from up to but not including to, of the given number of
+    /// equally sized bands. For example, bands(1, 3, 4) selects the middle 50% of agents, which
+    /// can be combined using within to operate on mid-tier agents without touching the elites.
+    pub fn bands(from: usize, to: usize, of: usize) -> Self {
+        Self::with_values(SelectionType::Bands { from, to, of }, 1.0, 0)
+    }
+
+    /// Selects the proportion of agents, at least one, by tournaments of the size.
+    pub fn tournament(size: usize, proportion: f64) -> Self {
+        Self::new(SelectionType::Tournament { size }, proportion)
+    }
+
+    /// Selects only the agents selected by both this and the other selection.
+    pub fn and(mut self, other: Selection) -> Self {
+        self.combinations.push(Combination::And(other));
+        self
+    }
+
+    /// Selects the agents selected by either this or the other selection.
+    pub fn or(mut self, other: Selection) -> Self {
+        self.combinations.push(Combination::Or(other));
+        self
+    }
+
+    /// Selects the agents in the population that this selection does not select.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(mut self) -> Self {
+        self.combinations.push(Combination::Not);
+        self
+    }
+
+    /// Applies the selection type and proportion of this selection to the agents selected by the
+    /// outer selection rather than the whole population, e.g. a random 10% of the lowest half.
+    /// Any and, or and not combinations are still evaluated against the whole population.
+    pub fn within(mut self, outer: Selection) -> Self {
+        self.scope = Some(Box::new(outer));
+        self
+    }
+
+    /// Whether this selection has no combinations, so selects by its type and proportion alone.
+    pub fn is_simple(&self) -> bool {
+        self.scope.is_none() && self.combinations.is_empty()
+    }
+
     pub fn selection_type(&self) -> SelectionType {
         self.selection_type
     }
@@ -76,31 +227,167 @@ impl Selection {
     }
 
     pub fn agents <'a, Gene> (&self, population: &'a Population<Gene>) -> BTreeMap<Score, &'a Agent<Gene>>
-    where
-    Gene: Clone
     {
-        match self.selection_type {
-            SelectionType::RandomAny => get_random_subset(population.get_agents(), self.proportion, self.preferred_minimum),
-            SelectionType::HighestScore => get_highest_scored_agents(population.get_agents(), self.proportion, self.preferred_minimum),
-            SelectionType::LowestScore => get_lowest_scored_agents(population.get_agents(), self.proportion, self.preferred_minimum)
+        let candidates = match self.scope {
+            Some(ref outer) => outer.agents(population),
+            None => all_agents(population)
+        };
+        let mut selected = match self.selection_type {
+            SelectionType::RandomAny => get_random_subset(&candidates, self.proportion, self.preferred_minimum),
+            SelectionType::HighestScore => get_highest_scored_agents(&candidates, self.proportion, self.preferred_minimum),
+            SelectionType::LowestScore => get_lowest_scored_agents(&candidates, self.proportion, self.preferred_minimum),
+            SelectionType::StochasticUniversal => get_stochastic_universal_subset(&candidates, self.proportion, self.preferred_minimum),
+            SelectionType::Bands { from, to, of } => get_band_agents(&candidates, from, to, of),
+            SelectionType::Tournament { size } => get_tournament_winners(&candidates, size, self.proportion, self.preferred_minimum)
+        };
+
+        for combination in &self.combinations {
+            match *combination {
+                Combination::And(ref other) => {
+                    let other = other.agents(population);
+                    selected.retain(|score, _| other.contains_key(score));
+                },
+                Combination::Or(ref other) => selected.extend(other.agents(population)),
+                Combination::Not => {
+                    let mut remaining = all_agents(population);
+                    remaining.retain(|score, _| !selected.contains_key(score));
+                    selected = remaining;
+                }
+            }
         }
+
+        selected
     }
 
     pub fn count <Gene> (&self, population: &Population<Gene>) -> usize {
-        rate_to_number(population.len(), self.proportion, self.preferred_minimum)
+        if self.is_simple() && !matches!(self.selection_type, SelectionType::Bands { .. }) {
+            rate_to_number(population.len(), self.proportion, self.preferred_minimum)
+        } else {
+            self.agents(population).len()
+        }
+    }
+}
+
+/// A sequence of operations run in order each iteration, with presets for common cases.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Pipeline <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    operations: Vec<Operation<Gene, Data>>
+}
+
+impl <Gene, Data> Pipeline <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    /// Creates an empty pipeline. See then.
+    pub fn new() -> Self {
+        Self { operations: Vec::new() }
+    }
+
+    /// The Manager's default: mutates a random tenth, crosses over the top fifth and a random
+    /// fifth, and culls the lowest tenth.
+    pub fn balanced() -> Self {
+        Self::new()
+            .then(Operation::new(OperationType::Mutate, Selection::random_tenth()))
+            .then(Operation::new(OperationType::Crossover, Selection::top(0.2)))
+            .then(Operation::new(OperationType::Crossover, Selection::random(0.2)))
+            .then(Operation::new(OperationType::Cull, Selection::bottom(0.1)))
+    }
+
+    /// Mutates half the population and crosses over agents chosen at random, ignoring score
+    /// except when culling the lowest tenth. Suits the start of a run, or problems where the
+    /// population converges too soon. The Manager's default warmup.
+    pub fn aggressive_explore() -> Self {
+        Self::new()
+            .then(Operation::new(OperationType::Mutate, Selection::random(0.5)))
+            .then(Operation::new(OperationType::Crossover, Selection::random(0.3)))
+            .then(Operation::new(OperationType::Cull, Selection::bottom(0.1)))
+    }
+
+    /// Adds an operation to run after the others.
+    pub fn then(mut self, operation: Operation<Gene, Data>) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    pub fn get_operations(&self) -> &Vec<Operation<Gene, Data>> {
+        &self.operations
+    }
+
+    pub fn into_operations(self) -> Vec<Operation<Gene, Data>> {
+        self.operations
+    }
+
+    /// Streams children bred from the population by the pipeline's operations. See Offspring.
+    pub fn offspring<'a>(&'a self, population: &Population<Gene>, data: &'a Data, score_provider: &'a mut dyn ScoreProvider<Gene, Data>) -> Offspring<'a, Gene, Data> {
+        Offspring::new(population, &self.operations, data, score_provider)
     }
 }
 
+impl <Gene, Data> Default for Pipeline <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What an operation would do to a population, from Operation::preview. Random selections are
+/// drawn afresh for each preview, so a plan shows one possible outcome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationPlan {
+    pub operation_type: OperationType,
+    /// The score keys of the agents the selection chose.
+    pub selected: Vec<Score>,
+    /// The most children that would be bred. Fewer may join the population, as children are
+    /// only kept if they can be scored and are accepted.
+    pub offspring: usize,
+    /// The score keys of the agents that would be removed. Crowding crossover replaces parents
+    /// depending on the scores of their children, so these are not listed for it.
+    pub culled: Vec<Score>
+}
+
+/// Breeds a child from two parents, given a copy of the first to turn into the child.
+type Breed<'a, Gene> = &'a (dyn Fn(&mut Agent<Gene>, &Agent<Gene>) + Sync);
+
+/// Proposes a replacement for the gene at a position of the genes, such as a city near its
+/// neighbours in a tour, so that Mutate draws from a better distribution than uniform genes.
+pub type MutationProposal<Gene, Data> = fn(&[Gene], usize, &Data, &mut dyn RngCore) -> Gene;
+
+/// Decides whether two agents may be crossed over.
+pub type MateCompatibility<Gene, Data> = fn(&Agent<Gene>, &Agent<Gene>, &Data) -> bool;
+
 /// Modifies a selection of a population.
 #[derive(Clone)]
 pub struct Operation <Gene, Data>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static
 {
     selection: Selection,
     operation_type: OperationType,
+    crossover_mode: CrossoverMode,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    speciation: Speciation<Gene>,
+    distance: DistanceFunction<Gene>,
+    record_provenance: bool,
+    mates: Vec<Agent<Gene>>,
+    objectives: Option<ObjectivesFunction<Gene, Data>>,
+    length_bounds: (usize, usize),
+    mutation_proposal: Option<MutationProposal<Gene, Data>>,
+    record_size: usize,
+    threads: usize,
     gene: PhantomData<Gene>,
     data: PhantomData<Data>
 }
@@ -108,7 +395,7 @@ Data: Clone + Send + 'static
 impl <Gene, Data> Operation <Gene, Data>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static
 {
     pub fn with_values(
@@ -118,6 +405,17 @@ Data: Clone + Send + 'static
         Self {
             selection: selection,
             operation_type: operation_type,
+            crossover_mode: CrossoverMode::TwoParent,
+            mate_compatibility: None,
+            speciation: Speciation::new(1),
+            distance: speciation::gene_hash_distance,
+            record_provenance: false,
+            mates: Vec::new(),
+            objectives: None,
+            length_bounds: (1, usize::MAX),
+            mutation_proposal: None,
+            record_size: 1,
+            threads: 1,
             gene: PhantomData,
             data: PhantomData
         }
@@ -130,199 +428,1025 @@ Data: Clone + Send + 'static
         Self {
             selection: selection,
             operation_type: operation_type,
+            crossover_mode: CrossoverMode::TwoParent,
+            mate_compatibility: None,
+            speciation: Speciation::new(1),
+            distance: speciation::gene_hash_distance,
+            record_provenance: false,
+            mates: Vec::new(),
+            objectives: None,
+            length_bounds: (1, usize::MAX),
+            mutation_proposal: None,
+            record_size: 1,
+            threads: 1,
             gene: PhantomData,
             data: PhantomData
         }
     }
 
+    pub fn get_operation_type(&self) -> OperationType {
+        self.operation_type
+    }
+
+    pub fn get_selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    /// Sets how children are created by a Crossover operation. Defaults to TwoParent.
+    pub fn set_crossover_mode(&mut self, crossover_mode: CrossoverMode) {
+        self.crossover_mode = crossover_mode;
+    }
+
+    /// Sets a function deciding whether two agents may be crossed over, consulted when a Crossover
+    /// operation chooses parents. Use it to prevent incompatible genomes mating, or to make agents
+    /// prefer similar mates.
+    pub fn set_mate_compatibility(&mut self, mate_compatibility: MateCompatibility<Gene, Data>) {
+        self.mate_compatibility = Some(mate_compatibility);
+    }
+
+    /// Sets how a SpeciatedCrossover operation divides the population into species.
+    /// Defaults to Speciation::new(1), with no stagnation limit.
+    pub fn set_speciation(&mut self, speciation: Speciation<Gene>) {
+        self.speciation = speciation;
+    }
+
+    /// Sets how a CrowdingCrossover operation measures the distance between a child and its
+    /// parents. Defaults to gene_hash_distance.
+    pub fn set_distance(&mut self, distance: DistanceFunction<Gene>) {
+        self.distance = distance;
+    }
+
+    /// Sets whether children bred from two parents record which parent each gene came from.
+    /// See Agent::get_provenance. Defaults to false.
+    pub fn set_record_provenance(&mut self, record_provenance: bool) {
+        self.record_provenance = record_provenance;
+    }
+
+    /// Sets the agents a ForeignCrossover operation crosses the selected agents with. They are not
+    /// added to the population.
+    pub fn set_mates(&mut self, mates: Vec<Agent<Gene>>) {
+        self.mates = mates;
+    }
+
+    /// Sets the agents of another population as the mates of a ForeignCrossover operation.
+    pub fn set_mates_from(&mut self, population: &Population<Gene>) {
+        self.set_mates(population.get_agents().values().cloned().collect());
+    }
+
+    pub fn get_mates(&self) -> &[Agent<Gene>] {
+        &self.mates
+    }
+
+    /// Sets how a ParetoCull operation scores agents on each objective, higher being better.
+    pub fn set_objectives(&mut self, objectives: ObjectivesFunction<Gene, Data>) {
+        self.objectives = Some(objectives);
+    }
+
+    /// Sets the fewest and most genes a LengthMutate operation allows. Defaults to at least one
+    /// gene, with no maximum.
+    pub fn set_length_bounds(&mut self, min: usize, max: usize) {
+        self.length_bounds = (min, max.max(min));
+    }
+
+    /// Sets a function a Mutate operation draws replacement genes from, instead of random genes.
+    pub fn set_mutation_proposal(&mut self, mutation_proposal: MutationProposal<Gene, Data>) {
+        self.mutation_proposal = Some(mutation_proposal);
+    }
+
+    /// Sets how many genes make up a record, for genes that encode structs flattened into the
+    /// genes. Two-parent crossover and random mutation then work on whole records rather than
+    /// single genes. Defaults to 1.
+    pub fn set_record_size(&mut self, record_size: usize) {
+        self.record_size = record_size.max(1);
+    }
+
+    /// Sets how many threads Mutate and two-parent Crossover operations create and score children
+    /// on, for large populations with expensive fitness functions. Children are still inserted one
+    /// at a time, and a seeded run repeats for the same number of threads. Score providers that
+    /// cannot score on several threads score on the current one. Defaults to 1.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Describes which agents the operation would select, how many children it would breed and
+    /// which agents it would cull, without changing the population or scoring anything.
+    pub fn preview(&self, population: &Population<Gene>, data: &Data) -> OperationPlan {
+        let mut selected: Vec<Score> = selection_keys(&self.selection, population);
+        let mut culled = Vec::new();
+        let offspring = match self.operation_type {
+            OperationType::Mutate | OperationType::CoordinateSweep | OperationType::LengthMutate => selected.len(),
+            OperationType::Crossover | OperationType::CrowdingCrossover => {
+                if self.operation_type == OperationType::CrowdingCrossover || self.crossover_mode == CrossoverMode::TwoParent {
+                    create_random_pairs(self.selection.agents(population), |one, two| match self.mate_compatibility {
+                        Some(compatible) => compatible(one, two, data),
+                        None => true
+                    }, &mut BufferPool::new(0)).len()
+                } else {
+                    selected.len()
+                }
+            },
+            OperationType::ForeignCrossover => {
+                foreign_pairs(self.selection.agents(population), &self.mates, self.mate_compatibility, data).len()
+            },
+            OperationType::ParetoCull => {
+                culled = pareto_cull_keys(population, &self.selection, self.objectives, data);
+                0
+            },
+            OperationType::Restart => {
+                culled = population.get_scores().into_iter().filter(|score| selected.binary_search(score).is_err()).collect();
+                culled.len()
+            },
+            OperationType::Cull => {
+                culled = if culls_selection(&self.selection) {
+                    selected.clone()
+                } else {
+                    cull_keys(population, &self.selection)
+                };
+                0
+            },
+            OperationType::SpeciatedCrossover => {
+                let highest = population.best_score();
+                let (live, expired): (Vec<_>, Vec<_>) = self.speciation.species(population).into_iter()
+                    .partition(|species| Some(species.best().0) == highest || !self.speciation.is_stagnant(species, population));
+                for species in expired {
+                    culled.extend(species.get_members().iter().map(|(score, _)| *score));
+                }
+                selected = live.iter().flat_map(|species| species.get_members().iter().map(|(score, _)| *score)).collect();
+                selected.sort();
+                speciation::offspring_quotas(&live, self.selection.count(population)).iter().sum()
+            }
+        };
+
+        OperationPlan { operation_type: self.operation_type, selected, offspring, culled }
+    }
+
     pub fn run (&self, population: Population<Gene>, data: &Data, score_provider: &mut ScoreProvider<Gene, Data>) -> Population<Gene>
     {
-        match self.operation_type {
-            OperationType::Mutate => mutate_agents(population, self.selection, data, score_provider),
-            OperationType::Crossover => crossover_agents(population, self.selection, data, score_provider),
-            OperationType::Cull => cull_agents(population, self.selection)
+        let record_size = self.record_size;
+        let record_provenance = self.record_provenance;
+        let breed = move |child: &mut Agent<Gene>, mate: &Agent<Gene>| if record_provenance {
+            child.crossover_records_with_provenance(mate, record_size)
+        } else {
+            child.crossover_records(mate, record_size)
+        };
+        let breed: Breed<Gene> = &breed;
+        let mut population = match self.operation_type {
+            OperationType::Mutate => mutate_agents(population, &self.selection, self.mutation_proposal, self.record_size, self.threads, data, score_provider),
+            OperationType::LengthMutate => length_mutate_agents(population, &self.selection, self.length_bounds, data, score_provider),
+            OperationType::Crossover => match self.crossover_mode {
+                CrossoverMode::TwoParent => crossover_agents(population, &self.selection, breed, self.mate_compatibility, self.threads, data, score_provider),
+                mode => multi_parent_crossover_agents(population, &self.selection, mode, self.mate_compatibility, data, score_provider)
+            },
+            OperationType::Cull => cull_agents(population, &self.selection),
+            OperationType::Restart => restart_agents(population, &self.selection, data, score_provider),
+            OperationType::ParetoCull => {
+                let mut population = population;
+                for score in pareto_cull_keys(&population, &self.selection, self.objectives, data) {
+                    population.discard(score);
+                }
+                population
+            },
+            OperationType::ForeignCrossover => foreign_crossover_agents(population, &self.selection, &self.mates, breed, self.mate_compatibility, data, score_provider),
+            OperationType::CoordinateSweep => sweep_agents(population, &self.selection, data, score_provider),
+            OperationType::SpeciatedCrossover => speciated_crossover_agents(population, &self.selection, &self.speciation, breed, self.mate_compatibility, data, score_provider),
+            OperationType::CrowdingCrossover => crowding_crossover_agents(population, &self.selection, self.distance, breed, self.mate_compatibility, data, score_provider)
+        };
+        population.forget_noted_ancestries();
+        population
+    }
+}
+
+/// The settings of an Operation that can be saved. Functions, mates and speciation are not, so
+/// an operation loaded from them uses the defaults until they are set again.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct OperationSettings {
+    selection: Selection,
+    operation_type: OperationType,
+    crossover_mode: CrossoverMode,
+    record_provenance: bool,
+    length_bounds: (usize, usize),
+    record_size: usize,
+    threads: usize
+}
+
+#[cfg(feature = "serde")]
+impl <Gene, Data> Serialize for Operation <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OperationSettings {
+            selection: self.selection.clone(),
+            operation_type: self.operation_type,
+            crossover_mode: self.crossover_mode,
+            record_provenance: self.record_provenance,
+            length_bounds: self.length_bounds,
+            record_size: self.record_size,
+            threads: self.threads
+        }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'de, Gene, Data> Deserialize<'de> for Operation <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let settings = OperationSettings::deserialize(deserializer)?;
+        let mut operation = Operation::new(settings.operation_type, settings.selection);
+        operation.set_crossover_mode(settings.crossover_mode);
+        operation.set_record_provenance(settings.record_provenance);
+        operation.set_length_bounds(settings.length_bounds.0, settings.length_bounds.1);
+        operation.set_record_size(settings.record_size);
+        operation.set_threads(settings.threads);
+        Ok(operation)
+    }
+}
+
+/// An iterator of scored children bred from a snapshot of a population, for callers that filter
+/// and insert candidates at their own pace, such as from an async task or an actor. The
+/// operations are run in turn on a copy of the parents, and the agents each adds are yielded
+/// with the score keys they were given there. The caller's population is not changed; call set_parents after inserting
+/// to breed from the updated population. Operations that add no agents, such as culls, are
+/// passed over, and the iterator ends once a whole pass of the operations yields nothing.
+pub struct Offspring<'a, Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    parents: Population<Gene>,
+    operations: &'a [Operation<Gene, Data>],
+    data: &'a Data,
+    score_provider: &'a mut dyn ScoreProvider<Gene, Data>,
+    next_operation: usize,
+    pending: VecDeque<(Score, Agent<Gene>)>
+}
+
+impl <'a, Gene, Data> Offspring<'a, Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    pub fn new(population: &Population<Gene>, operations: &'a [Operation<Gene, Data>], data: &'a Data, score_provider: &'a mut dyn ScoreProvider<Gene, Data>) -> Self {
+        Self {
+            parents: population.clone(),
+            operations,
+            data,
+            score_provider,
+            next_operation: 0,
+            pending: VecDeque::new()
         }
     }
+
+    /// Replaces the parents children are bred from, such as after inserting some of them. Children
+    /// already bred are still yielded, and the operations continue from where they were.
+    pub fn set_parents(&mut self, population: &Population<Gene>) {
+        self.parents = population.clone();
+    }
+
+    /// Runs the next operation on a copy of the parents, queueing the agents it added.
+    fn breed_next(&mut self) {
+        let operation = &self.operations[self.next_operation];
+        self.next_operation = (self.next_operation + 1) % self.operations.len();
+        let known: HashSet<u64> = self.parents.get_agents().values().map(|agent| agent.get_hash()).collect();
+        let bred = operation.run(self.parents.clone(), self.data, self.score_provider);
+        self.pending.extend(bred.get_agents().iter()
+            .filter(|(_, agent)| !known.contains(&agent.get_hash()))
+            .map(|(score, agent)| (*score, agent.clone())));
+    }
+}
+
+impl <'a, Gene, Data> Iterator for Offspring<'a, Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    type Item = (Score, Agent<Gene>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for _ in 0..self.operations.len() {
+            if !self.pending.is_empty() {
+                break;
+            }
+            self.breed_next();
+        }
+        self.pending.pop_front()
+    }
 }
 
 fn mutate_agents<Gene, Data>(
     mut population: Population<Gene>,
-    selection: Selection,
+    selection: &Selection,
+    mutation_proposal: Option<MutationProposal<Gene, Data>>,
+    record_size: usize,
+    threads: usize,
     data: &Data,
     score_provider: &mut ScoreProvider<Gene, Data>
 ) -> Population<Gene>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static
 {
-    let children = get_mutated_agents(selection.agents(&population));
-    let children = score_provider.evaluate_scores(children, data).unwrap();
-    let mut rng = rand::thread_rng();
-    for agent in children {
-        let score_index = score_provider.get_score(&agent, data, &mut rng).unwrap();
-        population.insert(score_index, agent);
-    }
+    let mut buffers = population.take_buffer_pool();
+    let children = match mutation_proposal {
+        Some(propose) => {
+            let mut rng = random::rng();
+            selection.agents(&population).into_iter()
+                .map(|(score, agent)| {
+                    let mut child = buffers.copy(agent);
+                    child.mutate_with(|genes, position| propose(genes, position, data, &mut rng));
+                    (vec![score], vec![agent.get_hash()], child)
+                })
+                .collect()
+        },
+        None => get_mutated_agents(selection.agents(&population), record_size, threads, &mut buffers)
+    };
+    population.restore_buffer_pool(buffers);
+    insert_children(&mut population, OperationType::Mutate, children, threads, data, score_provider);
+    population
+}
 
+fn length_mutate_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    (min, max): (usize, usize),
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut buffers = population.take_buffer_pool();
+    let children = selection.agents(&population).into_iter()
+        .map(|(score, agent)| {
+            let mut child = buffers.copy(agent);
+            child.mutate_length(min, max);
+            (vec![score], vec![agent.get_hash()], child)
+        })
+        .collect();
+    population.restore_buffer_pool(buffers);
+    insert_children(&mut population, OperationType::LengthMutate, children, 1, data, score_provider);
     population
 }
 
 fn crossover_agents<Gene, Data>(
     mut population: Population<Gene>,
-    selection: Selection,
+    selection: &Selection,
+    breed: Breed<Gene>,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    threads: usize,
     data: &Data,
     score_provider: &mut ScoreProvider<Gene, Data>
 ) -> Population<Gene>
 where
 Standard: Distribution<Gene>,
-Gene: Clone + Hash + Send + 'static,
+Gene: GeneBounds,
 Data: Clone + Send + 'static
 {
+    let mut buffers = population.take_buffer_pool();
     let pairs = create_random_pairs(
-        selection.agents(&population)
+        selection.agents(&population),
+        |one, two| match mate_compatibility {
+            Some(compatible) => compatible(one, two, data),
+            None => true
+        },
+        &mut buffers
     );
 
-    let children = create_children_from_crossover(pairs, data, score_provider);
-    for (score_index, agent) in children {
-        population.insert(score_index, agent);
+    let bred = random::map_in_threads(pairs, threads, |((one_score, mut child), (two_score, two))| {
+        let hashes = vec![child.get_hash(), two.get_hash()];
+        breed(&mut child, &two);
+        ((vec![one_score, two_score], hashes, child), two)
+    });
+    let children = bred.into_iter()
+        .map(|(child, two)| {
+            buffers.recycle(two);
+            child
+        })
+        .collect();
+    population.restore_buffer_pool(buffers);
+    insert_children(&mut population, OperationType::Crossover, children, threads, data, score_provider);
+    population
+}
+
+/// Pairs each selected agent with a random compatible mate, skipping agents with none.
+fn foreign_pairs<'a, Gene, Data>(
+    selected: BTreeMap<Score, &'a Agent<Gene>>,
+    mates: &'a [Agent<Gene>],
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    data: &Data
+) -> Vec<(Score, &'a Agent<Gene>, &'a Agent<Gene>)> {
+    let mut rng = random::rng();
+    selected.into_iter()
+        .filter_map(|(score, agent)| {
+            let compatible: Vec<&Agent<Gene>> = mates.iter()
+                .filter(|mate| match mate_compatibility {
+                    Some(compatible) => compatible(agent, mate, data),
+                    None => true
+                })
+                .collect();
+            if compatible.is_empty() {
+                None
+            } else {
+                Some((score, agent, compatible[rng.gen_range(0, compatible.len())]))
+            }
+        })
+        .collect()
+}
+
+fn foreign_crossover_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    mates: &[Agent<Gene>],
+    breed: Breed<Gene>,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut buffers = population.take_buffer_pool();
+    let children = foreign_pairs(selection.agents(&population), mates, mate_compatibility, data).into_iter()
+        .map(|(score, agent, mate)| {
+            let mut child = buffers.copy(agent);
+            breed(&mut child, mate);
+            (vec![score], vec![agent.get_hash(), mate.get_hash()], child)
+        })
+        .collect();
+    population.restore_buffer_pool(buffers);
+    insert_children(&mut population, OperationType::ForeignCrossover, children, 1, data, score_provider);
+    population
+}
+
+fn multi_parent_crossover_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    crossover_mode: CrossoverMode,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let selected: Vec<(Score, &Agent<Gene>)> = selection.agents(&population).into_iter().collect();
+    if selected.is_empty() {
+        return population;
+    }
+
+    let mut rng = random::rng();
+    let mut children = Vec::new();
+    for _ in 0..selected.len() {
+        let mut parents: Vec<(Score, &Agent<Gene>)> = match crossover_mode {
+            CrossoverMode::MajorityVote(count) | CrossoverMode::Segments(count) => {
+                (0..count.max(2)).map(|_| selected[rng.gen_range(0, selected.len())]).collect()
+            },
+            CrossoverMode::TwoParent => Vec::new()
+        };
+        // Every other parent must be a compatible mate for the first.
+        if let Some(compatible) = mate_compatibility {
+            let first = parents[0].1;
+            parents.retain(|(_, parent)| std::ptr::eq(*parent, first) || compatible(first, parent, data));
+            if parents.len() < 2 {
+                continue;
+            }
+        }
+        let child = match crossover_mode {
+            CrossoverMode::MajorityVote(_) => {
+                let weighted: Vec<(f64, &Agent<Gene>)> = parents.iter().map(|(score, parent)| (*score as f64 + 1.0, *parent)).collect();
+                agent::majority_crossover(&weighted)
+            },
+            CrossoverMode::Segments(_) => {
+                let parents: Vec<&Agent<Gene>> = parents.iter().map(|(_, parent)| *parent).collect();
+                agent::segment_crossover(&parents)
+            },
+            CrossoverMode::TwoParent => None
+        };
+        if let Some(child) = child {
+            if !parents.iter().any(|(_, parent)| parent.has_same_genes(&child)) {
+                let hashes = parents.iter().map(|(_, parent)| parent.get_hash()).collect();
+                children.push((parents.iter().map(|(score, _)| *score).collect(), hashes, child));
+            }
+        }
+    }
+
+    insert_children(&mut population, OperationType::Crossover, children, 1, data, score_provider);
+    population
+}
+
+fn crowding_crossover_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    distance: DistanceFunction<Gene>,
+    breed: Breed<Gene>,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut buffers = population.take_buffer_pool();
+    let pairs = create_random_pairs(
+        selection.agents(&population),
+        |one, two| match mate_compatibility {
+            Some(compatible) => compatible(one, two, data),
+            None => true
+        },
+        &mut buffers
+    );
+
+    // Each child is matched with its closest parent before scoring.
+    // The hash of the other parent is kept for the child's lineage.
+    let mut parents: HashMap<u64, (Score, Agent<Gene>, u64)> = HashMap::new();
+    let mut children = Vec::with_capacity(pairs.len());
+    for ((one_score, one), (two_score, two)) in pairs {
+        let mut child = buffers.copy(&one);
+        breed(&mut child, &two);
+        let closest = if distance(&child, &one) <= distance(&child, &two) {
+            let other = two.get_hash();
+            buffers.recycle(two);
+            (one_score, one, other)
+        } else {
+            let other = one.get_hash();
+            buffers.recycle(one);
+            (two_score, two, other)
+        };
+        parents.entry(child.get_hash()).or_insert(closest);
+        children.push(child);
+    }
+
+    let children = score_provider.evaluate_scores(children, data).unwrap_or_default();
+    let mut rng = random::rng();
+    for child in children {
+        let (parent_score, parent, other) = match parents.remove(&child.get_hash()) {
+            Some(closest) => closest,
+            None => continue
+        };
+        // The parent may already have been replaced by an earlier child.
+        match population.get(parent_score) {
+            Some(current) if current.has_same_genes(&parent) => (),
+            _ => continue
+        }
+
+        let improves = match (score_provider.true_score(&child, data), score_provider.true_score(&parent, data)) {
+            (Ok(child_fitness), Ok(parent_fitness)) => child_fitness > parent_fitness,
+            _ => false
+        };
+        if !improves {
+            continue;
+        }
+        // The child replaces no agent but its parent.
+        let score_index = match score_provider.get_score(&child, data, &mut rng) {
+            Ok(score_index) => score_index,
+            Err(_) => continue
+        };
+        population.note_ancestry(&child, OperationType::CrowdingCrossover, vec![parent.get_hash(), other]);
+        // The parent makes way for the child, and is put back if the child is not accepted.
+        let removed = population.remove(parent_score);
+        let inserted = population.insert_ranked_child(score_index, child, &[parent_score]);
+        if let Some(removed) = removed {
+            match inserted {
+                Some(_) => buffers.recycle(removed),
+                None => population.restore(parent_score, removed)
+            }
+        }
     }
 
+    population.restore_buffer_pool(buffers);
     population
 }
 
+fn speciated_crossover_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    speciation: &Speciation<Gene>,
+    breed: Breed<Gene>,
+    mate_compatibility: Option<MateCompatibility<Gene, Data>>,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut rng = random::rng();
+    let mut stagnant = Vec::new();
+    let mut children = Vec::new();
+    let mut buffers = population.take_buffer_pool();
+    {
+        let highest = population.best_score();
+        let (live, expired): (Vec<_>, Vec<_>) = speciation.species(&population).into_iter()
+            .partition(|species| Some(species.best().0) == highest || !speciation.is_stagnant(species, &population));
+        for species in expired {
+            stagnant.extend(species.get_members().iter().map(|(score, _)| *score));
+        }
+
+        let quotas = speciation::offspring_quotas(&live, selection.count(&population));
+        for (species, quota) in live.iter().zip(quotas) {
+            let members = species.get_members();
+            for _ in 0..quota {
+                let (one_score, one) = members[rng.gen_range(0, members.len())];
+                let (two_score, two) = members[rng.gen_range(0, members.len())];
+                let compatible = match mate_compatibility {
+                    Some(compatible) => compatible(one, two, data),
+                    None => true
+                };
+                if !one.has_same_genes(two) && compatible {
+                    let mut child = buffers.copy(one);
+                    breed(&mut child, two);
+                    children.push((vec![one_score, two_score], vec![one.get_hash(), two.get_hash()], child));
+                }
+            }
+        }
+    }
+
+    population.restore_buffer_pool(buffers);
+    for score in stagnant {
+        population.discard(score);
+    }
+    insert_children(&mut population, OperationType::SpeciatedCrossover, children, 1, data, score_provider);
+    population
+}
+
+fn selection_keys<Gene>(selection: &Selection, population: &Population<Gene>) -> Vec<Score> {
+    selection.agents(population).keys().cloned().collect()
+}
+
+/// Whether a cull removes exactly the selected agents, rather than culling by a score threshold.
+fn culls_selection(selection: &Selection) -> bool {
+    !selection.is_simple() || matches!(selection.selection_type(), SelectionType::StochasticUniversal | SelectionType::Bands { .. } | SelectionType::Tournament { .. })
+}
+
+/// The score keys a threshold cull would remove. A RandomAny cull of a simple selection removes
+/// nothing, as it is not supported.
+fn cull_keys<Gene>(population: &Population<Gene>, selection: &Selection) -> Vec<Score> {
+    let cull_number = selection.count(population);
+    if cull_number >= population.len() {
+        return Vec::new();
+    }
+    match selection.selection_type() {
+        SelectionType::LowestScore => population.bottom_k(cull_number).into_iter().map(|(score, _)| score).collect(),
+        SelectionType::HighestScore => {
+            let mut keys: Vec<Score> = population.top_k(population.len() - cull_number).into_iter().map(|(score, _)| score).collect();
+            keys.reverse();
+            keys
+        },
+        _ => Vec::new()
+    }
+}
+
+/// The score keys a ParetoCull would remove, in ascending order.
+fn pareto_cull_keys<Gene, Data>(
+    population: &Population<Gene>,
+    selection: &Selection,
+    objectives: Option<ObjectivesFunction<Gene, Data>>,
+    data: &Data
+) -> Vec<Score> {
+    let cull_number = selection.count(population).min(population.len().saturating_sub(1));
+    let mut ranked: Vec<(usize, Score)> = match objectives {
+        Some(objectives) => {
+            let scored: Vec<Vec<Score>> = population.get_agents().values().map(|agent| objectives(agent, data)).collect();
+            pareto::front_ranks(&scored).into_iter().zip(population.get_agents().keys().cloned()).collect()
+        },
+        None => population.get_agents().keys().map(|score| (0, *score)).collect()
+    };
+    // Keep the highest scored agent, then remove the worst front first, lowest scored first.
+    ranked.pop();
+    ranked.sort_by(|one, two| two.0.cmp(&one.0).then(one.1.cmp(&two.1)));
+    let mut keys: Vec<Score> = ranked.into_iter().take(cull_number).map(|(_, score)| score).collect();
+    keys.sort();
+    keys
+}
+
 fn cull_agents<Gene>(
     mut population: Population<Gene>,
-    selection: Selection,
+    selection: &Selection,
 ) -> Population<Gene>
+where
+Gene: Clone
 {
-    let keys: Vec<Score> = population.get_agents().keys().map(|k| *k).collect();
-    let cull_number = selection.count(&population);
-    if cull_number >= keys.len() {
+    if culls_selection(selection) {
+        let selected = selection_keys(selection, &population);
+        for score in selected {
+            population.discard(score);
+        }
         return population;
     }
-    
+
+    let cull_number = selection.count(&population);
+    let threshold = match population.get_agents().keys().nth(cull_number) {
+        Some(score) => *score,
+        None => return population
+    };
+
     match selection.selection_type() {
-        SelectionType::LowestScore => population.cull_all_below(keys[cull_number]),
-        SelectionType::HighestScore => population.cull_all_above(keys[cull_number]),
-        SelectionType::RandomAny => panic!("RandomAny selection not yet implemented for cull agents")
+        SelectionType::LowestScore => population.cull_all_below(threshold),
+        SelectionType::HighestScore => population.cull_all_above(threshold),
+        SelectionType::RandomAny => panic!("RandomAny selection not yet implemented for cull agents"),
+        SelectionType::StochasticUniversal | SelectionType::Bands { .. } | SelectionType::Tournament { .. } => unreachable!()
+    };
+    population
+}
+
+fn restart_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let number_of_genes = match population.get_agents().values().next_back() {
+        Some(agent) => agent.get_genes().len(),
+        None => return population
     };
+    let kept = selection_keys(selection, &population);
+    let replaced: Vec<Score> = population.get_scores().into_iter().filter(|score| kept.binary_search(score).is_err()).collect();
+    for score in &replaced {
+        population.discard(*score);
+    }
+
+    // New agents are ranked in, so none of them replaces a kept agent that shares its score key.
+    let agents: Vec<Agent<Gene>> = (0..replaced.len()).map(|_| Agent::with_genes(number_of_genes)).collect();
+    let offered = population.copies_for_rejections(&agents);
+    let agents = score_provider.evaluate_scores(agents, data).unwrap_or_default();
+    population.record_unscored(offered, &agents);
+
+    let mut rng = random::rng();
+    for agent in agents {
+        match score_provider.get_score(&agent, data, &mut rng) {
+            Ok(score) => {
+                population.insert_ranked(score, agent);
+            },
+            Err(_) => population.record_score_error(&agent)
+        }
+    }
+    population
+}
+
+fn sweep_agents<Gene, Data>(
+    mut population: Population<Gene>,
+    selection: &Selection,
+    data: &Data,
+    score_provider: &mut dyn ScoreProvider<Gene, Data>
+) -> Population<Gene>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let agents: Vec<(Score, Agent<Gene>)> = selection.agents(&population).into_iter().map(|(score, agent)| (score, agent.clone())).collect();
+    let mut rng = random::rng();
+    for (parent_score, agent) in agents {
+        let parent_hash = agent.get_hash();
+        let mut best = agent;
+        let mut best_score = match score_provider.true_score(&best, data) {
+            Ok(score) => score,
+            Err(_) => continue
+        };
+        let mut improved = false;
+
+        for position in 0..best.get_genes().len() {
+            let mut candidate = best.clone();
+            candidate.set_gene(position, rng.gen());
+            if candidate.has_same_genes(&best) {
+                continue;
+            }
+
+            // Scores are cached, so revisiting a genome is cheap.
+            let candidate = match score_provider.evaluate_scores(vec![candidate], data) {
+                Ok(mut scored) => match scored.pop() {
+                    Some(candidate) => candidate,
+                    None => continue
+                },
+                Err(_) => continue
+            };
+            if let Ok(score) = score_provider.true_score(&candidate, data) {
+                if score > best_score {
+                    best = candidate;
+                    best_score = score;
+                    improved = true;
+                }
+            }
+        }
+
+        if improved {
+            if let Ok(score_index) = score_provider.get_score(&best, data, &mut rng) {
+                population.note_ancestry(&best, OperationType::CoordinateSweep, vec![parent_hash]);
+                population.insert_child(score_index, best, &[parent_score]);
+            }
+        }
+    }
+
     population
 }
 
 fn get_mutated_agents<Gene>(
     agents: BTreeMap<Score, &Agent<Gene>>,
-) -> Vec<Agent<Gene>>
+    record_size: usize,
+    threads: usize,
+    buffers: &mut BufferPool<Gene>
+) -> Vec<Child<Gene>>
 where Standard: Distribution<Gene>,
 Gene: Clone + Hash + Send
 {
-    let mut children = Vec::new();
-    for (_, mut agent) in agents {
-        let mut clone = agent.clone();
-        clone.mutate();
-        children.push(clone);
-    }
-    children
+    let copies: Vec<(Score, Agent<Gene>)> = agents.into_iter().map(|(score, agent)| (score, buffers.copy(agent))).collect();
+    random::map_in_threads(copies, threads, |(score, mut child)| {
+        let hash = child.get_hash();
+        child.mutate_records(record_size);
+        (vec![score], vec![hash], child)
+    })
 }
 
-fn create_children_from_crossover<Gene, Data>(
-    pairs: Vec<(Agent<Gene>, Agent<Gene>)>,
+/// A bred child with the score keys of its parents, for the acceptance policy, and their genome
+/// hashes, taken as it was bred, for the population's lineage.
+type Child<Gene> = (Vec<Score>, Vec<u64>, Agent<Gene>);
+
+/// Scores the children on up to the number of threads and inserts them, giving the population's
+/// acceptance policy the score keys of their parents.
+fn insert_children<Gene, Data>(
+    population: &mut Population<Gene>,
+    operation: OperationType,
+    children: Vec<Child<Gene>>,
+    threads: usize,
     data: &Data,
-    score_provider: &mut ScoreProvider<Gene, Data>,
-) -> Vec<(Score, Agent<Gene>)>
+    score_provider: &mut dyn ScoreProvider<Gene, Data>,
+)
 where
-Standard: Distribution<Gene>,
 Gene: Clone + Hash
 {
-    let mut children = Vec::new();
-
-    for (parent_one, parent_two) in pairs {
-        let child = crossover(&parent_one, &parent_two);
-        children.push(child);
+    let mut parents: HashMap<u64, Vec<Score>> = HashMap::new();
+    let mut agents = Vec::with_capacity(children.len());
+    for (parent_scores, parent_hashes, child) in children {
+        if let Entry::Vacant(entry) = parents.entry(child.get_hash()) {
+            population.note_ancestry(&child, operation, parent_hashes);
+            entry.insert(parent_scores);
+        }
+        agents.push(child);
     }
-    let children = score_provider.evaluate_scores(children, data).unwrap();
+    let offered = population.copies_for_rejections(&agents);
+    // Agents that cannot be scored are skipped, whatever the score provider's error policy.
+    let agents = score_provider.evaluate_scores_in_threads(agents, data, threads).unwrap_or_default();
+    population.record_unscored(offered, &agents);
 
-    let mut agents = Vec::new();
-    let mut rng = rand::thread_rng();
-    for agent in children {
-        let score_index = score_provider.get_score(&agent, data, &mut rng).unwrap();
-        agents.push((score_index, agent));
+    let mut rng = random::rng();
+    for agent in agents {
+        let parent_scores = parents.remove(&agent.get_hash()).unwrap_or_default();
+        match score_provider.get_score(&agent, data, &mut rng) {
+            Ok(score_index) => population.insert_child(score_index, agent, &parent_scores),
+            Err(_) => population.record_score_error(&agent)
+        }
     }
-    return agents;
 }
 
-fn get_random_subset<Gene>(
-    agents: &BTreeMap<Score, Agent<Gene>>,
+fn get_random_subset<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
     rate: f64,
     preferred_minimum: usize
-) -> BTreeMap<Score, &Agent<Gene>>
-where Gene: Clone
+) -> BTreeMap<Score, &'a Agent<Gene>>
 {
     let number = rate_to_number(agents.len(), rate, preferred_minimum);
     let keys: Vec<Score> = agents.keys().map(|k| *k).collect();
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     let mut subset = BTreeMap::new();
     for _ in 0..number {
         let key = keys[rng.gen_range(0, keys.len())];
         let agent = agents.get(&key);
         if agent.is_some() {
-            subset.insert(key, agent.unwrap());
+            subset.insert(key, *agent.unwrap());
         }
     }
 
     subset
 }
 
-fn get_highest_scored_agents<Gene>(
-    agents: &BTreeMap<Score, Agent<Gene>>,
+fn get_stochastic_universal_subset<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
     rate: f64,
     preferred_minimum: usize
-) -> BTreeMap<Score, &Agent<Gene>>
-where Gene: Clone
+) -> BTreeMap<Score, &'a Agent<Gene>>
 {
     let number = rate_to_number(agents.len(), rate, preferred_minimum);
-    let mut keys: Vec<Score> = agents.keys().map(|k| *k).collect();
-    let keys_len = keys.len();
-    keys.drain(0..(keys_len - number));
+    let total: f64 = agents.keys().map(|score| *score as f64).sum();
+    if number == 0 || total <= 0.0 {
+        return get_random_subset(agents, rate, preferred_minimum);
+    }
+
+    let spacing = total / number as f64;
+    let mut pointer = random::rng().gen::<f64>() * spacing;
+    let mut cumulative = 0.0;
     let mut subset = BTreeMap::new();
-    for key in keys {
-        let agent = agents.get(&key);
-        if agent.is_some() {
-            subset.insert(key, agent.unwrap());
+    for (score, agent) in agents {
+        cumulative += *score as f64;
+        while pointer < cumulative {
+            subset.insert(*score, *agent);
+            pointer += spacing;
         }
     }
 
     subset
 }
 
-fn get_lowest_scored_agents<Gene>(
-    agents: &BTreeMap<Score, Agent<Gene>>,
+fn get_tournament_winners<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
+    size: usize,
     rate: f64,
     preferred_minimum: usize
-) -> BTreeMap<Score, &Agent<Gene>>
-where Gene: Clone
+) -> BTreeMap<Score, &'a Agent<Gene>>
 {
     let number = rate_to_number(agents.len(), rate, preferred_minimum);
-    let mut keys: Vec<Score> = agents.keys().map(|k| *k).collect();
-    keys.truncate(number);
-    let mut subset = BTreeMap::new();
-    for key in keys {
-        let agent = agents.get(&key);
-        if agent.is_some() {
-            subset.insert(key, agent.unwrap());
-        }
+    let mut remaining: Vec<(Score, &'a Agent<Gene>)> = agents.iter().map(|(score, agent)| (*score, *agent)).collect();
+    let mut rng = random::rng();
+    let mut winners = BTreeMap::new();
+    for _ in 0..number.min(remaining.len()) {
+        let winner = (0..size.max(1))
+            .map(|_| rng.gen_range(0, remaining.len()))
+            .max_by_key(|index| remaining[*index].0)
+            .unwrap();
+        let (score, agent) = remaining.swap_remove(winner);
+        winners.insert(score, agent);
     }
 
-    subset
+    winners
+}
+
+fn get_band_agents<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
+    from: usize,
+    to: usize,
+    of: usize
+) -> BTreeMap<Score, &'a Agent<Gene>>
+{
+    if to <= from {
+        return BTreeMap::new();
+    }
+    let start = band_bounds(agents.len(), from, of).start;
+    let end = band_bounds(agents.len(), to.max(1) - 1, of).end;
+    agents.iter().skip(start).take(end.saturating_sub(start)).map(|(score, agent)| (*score, *agent)).collect()
+}
+
+fn get_highest_scored_agents<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
+    rate: f64,
+    preferred_minimum: usize
+) -> BTreeMap<Score, &'a Agent<Gene>>
+{
+    let number = rate_to_number(agents.len(), rate, preferred_minimum);
+    agents.iter().rev().take(number).map(|(score, agent)| (*score, *agent)).collect()
 }
 
-fn create_random_pairs<Gene>(
+fn get_lowest_scored_agents<'a, Gene>(
+    agents: &BTreeMap<Score, &'a Agent<Gene>>,
+    rate: f64,
+    preferred_minimum: usize
+) -> BTreeMap<Score, &'a Agent<Gene>>
+{
+    let number = rate_to_number(agents.len(), rate, preferred_minimum);
+    agents.iter().take(number).map(|(score, agent)| (*score, *agent)).collect()
+}
+
+fn all_agents<Gene>(population: &Population<Gene>) -> BTreeMap<Score, &Agent<Gene>> {
+    population.get_agents().iter().map(|(score, agent)| (*score, agent)).collect()
+}
+
+/// Two agents to cross over, with their score keys.
+type ScoredPair<Gene> = ((Score, Agent<Gene>), (Score, Agent<Gene>));
+
+fn create_random_pairs<Gene, F>(
     agents: BTreeMap<Score, &Agent<Gene>>,
-) -> Vec<(Agent<Gene>, Agent<Gene>)> 
+    compatible: F,
+    buffers: &mut BufferPool<Gene>
+) -> Vec<ScoredPair<Gene>>
 where
-Gene: Clone
+Gene: Clone,
+F: Fn(&Agent<Gene>, &Agent<Gene>) -> bool
 {
     let keys: Vec<&Score> = agents.keys().collect();
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     let mut pairs = Vec::new();
     let count = keys.len();
     for _ in 0..count {
@@ -334,8 +1458,8 @@ Gene: Clone
         if one_agent.is_some() && two_agent.is_some() {
             let one_agent = *one_agent.unwrap();
             let two_agent = *two_agent.unwrap();
-            if !one_agent.has_same_genes(two_agent) {
-                pairs.push((one_agent.clone(), two_agent.clone()));
+            if !one_agent.has_same_genes(two_agent) && compatible(one_agent, two_agent) {
+                pairs.push(((*one_key, buffers.copy(one_agent)), (*two_key, buffers.copy(two_agent))));
             }
         }
     }
@@ -350,12 +1474,12 @@ pub fn cull_lowest_agents<Gene>(
     preferred_minimum: usize
 ) -> Population<Gene>
 {
-    let keys: Vec<Score> = population.get_agents().keys().map(|k| *k).collect();
-    let cull_number = rate_to_number(keys.len(), rate, preferred_minimum);
-    if cull_number >= keys.len() {
-        return population;
-    }
-    population.cull_all_below(keys[cull_number]);
+    let cull_number = rate_to_number(population.len(), rate, preferred_minimum);
+    let threshold = match population.get_agents().keys().nth(cull_number) {
+        Some(score) => *score,
+        None => return population
+    };
+    population.cull_all_below(threshold);
     population
 }
 
@@ -375,6 +1499,8 @@ fn rate_to_number(population: usize, rate: f64, preferred_minimum: usize) -> usi
 mod tests {
     use super::*;
     use super::super::fitness::{GeneralScoreProvider, ScoreError};
+    use std::sync::Mutex;
+    use std::thread;
 
     fn get_score_index(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
         let score = agent.get_genes()[0] as Score;
@@ -423,6 +1549,497 @@ mod tests {
         assert!(agent_map.contains_key(score));
     }
 
+    fn scored_population(size: u8) -> Population<u8> {
+        let mut population = Population::new_empty(false);
+        for gene in 0..size {
+            population.insert(gene as Score, Agent::from_genes(vec![gene]));
+        }
+        population
+    }
+
+    #[test]
+    fn selection_combinators() {
+        let population = scored_population(10);
+        let highest = Selection::with_values(SelectionType::HighestScore, 0.5, 0);
+        let lowest = Selection::with_values(SelectionType::LowestScore, 0.2, 0);
+        let top = Selection::with_values(SelectionType::HighestScore, 0.2, 0);
+
+        let excluding_top = highest.clone().and(top.clone().not());
+        assert_eq!(vec![5, 6, 7], excluding_top.agents(&population).keys().cloned().collect::<Vec<Score>>());
+
+        let extremes = lowest.or(top);
+        assert_eq!(vec![0, 1, 8, 9], extremes.agents(&population).keys().cloned().collect::<Vec<Score>>());
+        assert_eq!(4, extremes.count(&population));
+
+        let middle = extremes.not();
+        assert_eq!(vec![2, 3, 4, 5, 6, 7], middle.agents(&population).keys().cloned().collect::<Vec<Score>>());
+    }
+
+    #[test]
+    fn selection_within_outer_selection() {
+        let population = scored_population(20);
+        let lowest_half = Selection::with_values(SelectionType::LowestScore, 0.5, 0);
+        let selection = Selection::with_values(SelectionType::RandomAny, 0.2, 0).within(lowest_half);
+
+        let agents = selection.agents(&population);
+        assert!(!agents.is_empty() && agents.len() <= 2);
+        assert!(agents.keys().all(|score| *score < 10));
+    }
+
+    #[test]
+    fn cull_combined_selection_removes_exactly_selected() {
+        let population = scored_population(10);
+        let selection = Selection::with_values(SelectionType::LowestScore, 0.2, 0)
+            .or(Selection::with_values(SelectionType::HighestScore, 0.1, 0));
+        let population = cull_agents(population, &selection);
+
+        assert_eq!(vec![2, 3, 4, 5, 6, 7, 8], population.get_scores());
+    }
+
+    #[test]
+    fn multi_parent_crossover_adds_children() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        for mode in [CrossoverMode::MajorityVote(3), CrossoverMode::Segments(3)] {
+            let mut population = Population::new_empty(false);
+            for gene in 0..10u8 {
+                let agent = Agent::from_genes(vec![gene % 2, gene % 3, gene / 5, gene % 4]);
+                population.insert(1000 + gene as Score, agent);
+            }
+
+            let mut operation = Operation::new(OperationType::Crossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+            operation.set_crossover_mode(mode);
+            // A child identical to one of its parents is discarded, so allow a few rounds.
+            for _ in 0..5 {
+                population = operation.run(population, &0, &mut score_provider);
+            }
+
+            assert!(population.len() > 10);
+            assert!(population.get_agents().values().all(|agent| agent.get_genes().len() == 4));
+        }
+    }
+
+    #[test]
+    fn selection_presets_and_pipelines() {
+        let mut population = Population::new_empty(false);
+        for score in 0..20 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        assert_eq!(vec![18, 19], selection_keys(&Selection::top_decile(), &population));
+        assert_eq!(vec![0], selection_keys(&Selection::bottom(0.05), &population));
+        assert!(Selection::random_tenth().count(&population) == 2);
+
+        let pipeline: Pipeline<u8, u8> = Pipeline::balanced();
+        let types: Vec<OperationType> = pipeline.get_operations().iter().map(|operation| operation.operation_type).collect();
+        assert_eq!(vec![OperationType::Mutate, OperationType::Crossover, OperationType::Crossover, OperationType::Cull], types);
+        assert_eq!(4, Pipeline::<u8, u8>::aggressive_explore().then(Operation::new(OperationType::Cull, Selection::bottom(0.1))).into_operations().len());
+    }
+
+    #[test]
+    fn offspring_streams_new_agents() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new(10, 6, true, &0, &mut score_provider);
+        let before = population.get_agents().clone();
+        let pipeline = Pipeline::new()
+            .then(Operation::new(OperationType::Cull, Selection::bottom(0.5)))
+            .then(Operation::new(OperationType::Mutate, Selection::random(0.5)));
+        let children: Vec<(Score, Agent<u8>)> = pipeline.offspring(&population, &0, &mut score_provider).take(12).collect();
+
+        assert_eq!(12, children.len());
+        assert!(children.iter().all(|(_, agent)| before.values().all(|parent| !parent.has_same_genes(agent))));
+        assert_eq!(before.len(), population.len());
+
+        let mut offspring = pipeline.offspring(&population, &0, &mut score_provider);
+        let (score, child) = offspring.next().unwrap();
+        population.insert(score, child);
+        offspring.set_parents(&population);
+        assert!(offspring.next().is_some());
+
+        let culls: Pipeline<u8, u8> = Pipeline::new().then(Operation::new(OperationType::Cull, Selection::bottom(0.5)));
+        assert!(culls.offspring(&population, &0, &mut score_provider).next().is_none());
+    }
+
+    #[test]
+    fn foreign_crossover_breeds_with_mates() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        for score in 1..6 {
+            population.insert(score, Agent::from_genes(vec![0u8; 4]));
+        }
+        let mut operation = Operation::new(OperationType::ForeignCrossover, Selection::with_values(SelectionType::HighestScore, 1.0, 0));
+        assert_eq!(0, operation.preview(&population, &0).offspring);
+        assert_eq!(5, operation.run(population.clone(), &0, &mut score_provider).len());
+
+        let mut hall_of_fame = Population::new_empty(false);
+        hall_of_fame.insert(100, Agent::from_genes(vec![9u8; 4]));
+        operation.set_mates_from(&hall_of_fame);
+        assert_eq!(5, operation.preview(&population, &0).offspring);
+        for _ in 0..5 {
+            population = operation.run(population, &0, &mut score_provider);
+        }
+        assert!(population.get_agents().values().any(|agent| agent.get_genes().contains(&9)));
+        assert!(population.get_agents().values().all(|agent| agent.get_genes().iter().all(|gene| *gene == 0 || *gene == 9)));
+    }
+
+    // The first objective prefers a high first gene, the second a high second gene.
+    fn both_genes(agent: &Agent<u8>, _data: &u8) -> Vec<Score> {
+        vec![agent.get_genes()[0] as Score, agent.get_genes()[1] as Score]
+    }
+
+    #[test]
+    fn pareto_cull_removes_dominated_agents_first() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        population.insert(1, Agent::from_genes(vec![9u8, 0]));
+        population.insert(2, Agent::from_genes(vec![0u8, 9]));
+        population.insert(3, Agent::from_genes(vec![3u8, 3]));
+        population.insert(4, Agent::from_genes(vec![4u8, 4]));
+        population.insert(5, Agent::from_genes(vec![5u8, 5]));
+
+        let mut operation = Operation::new(OperationType::ParetoCull, Selection::with_values(SelectionType::LowestScore, 0.4, 0));
+        assert_eq!(vec![1, 2], operation.preview(&population, &0).culled);
+        operation.set_objectives(both_genes);
+        assert_eq!(vec![3, 4], operation.preview(&population, &0).culled);
+
+        let population = operation.run(population, &0, &mut score_provider);
+        assert_eq!(vec![1, 2, 5], population.get_scores());
+    }
+
+    // Proposes the data as every gene.
+    fn propose_data(_genes: &[u8], _position: usize, data: &u8, _rng: &mut dyn RngCore) -> u8 {
+        *data
+    }
+
+    #[test]
+    fn mutate_draws_from_proposal() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        population.insert(1, Agent::from_genes(vec![0u8; 6]));
+        let mut operation = Operation::new(OperationType::Mutate, Selection::with_values(SelectionType::HighestScore, 1.0, 0));
+        operation.set_mutation_proposal(propose_data);
+        let population = operation.run(population, &7, &mut score_provider);
+
+        assert_eq!(2, population.len());
+        assert!(population.get_agents().values().all(|agent| agent.get_genes().iter().all(|gene| *gene == 0 || *gene == 7)));
+    }
+
+    #[test]
+    fn crossover_keeps_records_whole() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(true);
+        for score in 0..6u8 {
+            population.insert(score as Score, Agent::from_genes((0..12u8).map(|gene| score * 20 + gene / 2).collect()));
+        }
+        let mut operation = Operation::new(OperationType::Crossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+        operation.set_record_size(2);
+        operation.set_record_provenance(true);
+        for _ in 0..3 {
+            population = operation.run(population, &0, &mut score_provider);
+        }
+
+        let genes: Vec<&Vec<u8>> = population.get_agents().values().map(|agent| agent.get_genes()).collect();
+        assert!(genes.iter().all(|genes| genes.chunks(2).all(|record| record[0] == record[1])));
+        assert!(genes.iter().any(|genes| genes[0] / 20 != genes[11] / 20));
+    }
+
+    static SCORING_THREADS: Mutex<Vec<thread::ThreadId>> = Mutex::new(Vec::new());
+
+    fn get_score_index_noting_thread(agent: &Agent<u8>, data: &u8) -> Result<Score, ScoreError> {
+        SCORING_THREADS.lock().unwrap().push(thread::current().id());
+        get_score_index(agent, data)
+    }
+
+    #[test]
+    fn threaded_operations_repeat_when_seeded() {
+        let run = |seed: u64| {
+            random::seed(seed);
+            let mut score_provider = GeneralScoreProvider::new(get_score_index_noting_thread, 0);
+            let mut population = Population::new(40, 8, true, &0, &mut score_provider);
+            let mut mutate = Operation::new(OperationType::Mutate, Selection::random(0.5));
+            let mut crossover = Operation::new(OperationType::Crossover, Selection::random(0.5));
+            mutate.set_threads(4);
+            crossover.set_threads(4);
+            population = mutate.run(population, &0, &mut score_provider);
+            let population = crossover.run(population, &0, &mut score_provider);
+            population.get_agents().iter().map(|(score, agent)| (*score, agent.get_genes().clone())).collect::<Vec<(Score, Vec<u8>)>>()
+        };
+        let first = run(11);
+        assert!(first.len() > 40);
+        assert_eq!(first, run(11));
+
+        let threads: HashSet<thread::ThreadId> = SCORING_THREADS.lock().unwrap().iter().cloned().collect();
+        assert!(threads.len() > 1);
+    }
+
+    #[test]
+    fn length_mutate_changes_lengths_within_bounds() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new(10, 6, false, &0, &mut score_provider);
+        let mut operation = Operation::new(OperationType::LengthMutate, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+        operation.set_length_bounds(4, 8);
+        for _ in 0..5 {
+            population = operation.run(population, &0, &mut score_provider);
+        }
+
+        let lengths: Vec<usize> = population.get_agents().values().map(|agent| agent.get_genes().len()).collect();
+        assert!(lengths.iter().all(|len| *len >= 4 && *len <= 8));
+        assert!(lengths.iter().any(|len| *len != 6));
+    }
+
+    #[test]
+    fn restart_keeps_selected_and_replaces_the_rest() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        for gene in 0..10u8 {
+            population.insert(1000 + gene as Score, Agent::from_genes(vec![gene; 3]));
+        }
+
+        let operation = Operation::new(OperationType::Restart, Selection::with_values(SelectionType::HighestScore, 0.2, 0));
+        let plan = operation.preview(&population, &0);
+        assert_eq!(vec![1008, 1009], plan.selected);
+        assert_eq!(8, plan.offspring);
+
+        let population = operation.run(population, &0, &mut score_provider);
+        assert!(population.contains_score(1008) && population.contains_score(1009));
+        assert!(population.get_scores().iter().filter(|score| **score >= 1000).count() == 2);
+        assert!(population.get_agents().values().all(|agent| agent.get_genes().len() == 3));
+    }
+
+    fn get_score_of_kept(_agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        Ok(1009)
+    }
+
+    #[test]
+    fn restart_keeps_every_elite() {
+        // Every new agent scores the same as the best kept agent.
+        let mut score_provider = GeneralScoreProvider::new(get_score_of_kept, 0);
+        let mut population = Population::new_empty(false);
+        for gene in 0..10u8 {
+            population.insert(1000 + gene as Score, Agent::from_genes(vec![gene; 3]));
+        }
+
+        let operation = Operation::new(OperationType::Restart, Selection::with_values(SelectionType::HighestScore, 0.3, 0));
+        let population = operation.run(population, &0, &mut score_provider);
+        for gene in 7..10u8 {
+            assert!(population.get_agents().values().any(|agent| agent.get_genes() == &vec![gene; 3]), "elite {} was lost", gene);
+        }
+        assert_eq!(10, population.len());
+    }
+
+    #[test]
+    fn crossover_records_provenance() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        for gene in 0..10u8 {
+            population.insert(1000 + gene as Score, Agent::from_genes(vec![gene; 4]));
+        }
+
+        population.set_record_lineage(true);
+
+        let mut operation = Operation::new(OperationType::Crossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+        operation.set_record_provenance(true);
+        population = operation.run(population, &0, &mut score_provider);
+
+        let children: Vec<(Score, &Agent<u8>)> = population.get_agents().iter()
+            .filter(|(score, _)| **score < 1000)
+            .map(|(score, agent)| (*score, agent))
+            .collect();
+        assert!(!children.is_empty());
+        assert!(children.iter().all(|(_, child)| child.get_provenance().map(|provenance| provenance.len()) == Some(4)));
+
+        // The lineage names the parent each gene came from. Children that copy a parent whole
+        // share its genome, which was recorded as created.
+        for (score, child) in children.into_iter().filter(|(_, child)| child.get_provenance().unwrap().second_parent_count() % 4 != 0) {
+            let trace = population.trace(score).unwrap();
+            let ancestry = &trace.steps.last().unwrap().ancestry;
+            assert_eq!(Some(OperationType::Crossover), ancestry.operation);
+            assert_eq!(child.get_provenance(), ancestry.provenance.as_ref());
+            for (locus, gene) in child.get_genes().iter().enumerate() {
+                let parent = ancestry.parents[child.get_provenance().unwrap().from_second_parent(locus) as usize];
+                assert_eq!(Agent::from_genes(vec![*gene; 4]).get_hash(), parent);
+            }
+        }
+    }
+
+    fn same_parity(one: &Agent<u8>, two: &Agent<u8>, _data: &u8) -> bool {
+        one.get_genes()[0] % 2 == two.get_genes()[0] % 2
+    }
+
+    #[test]
+    fn crossover_respects_mate_compatibility() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        for mode in [CrossoverMode::TwoParent, CrossoverMode::Segments(3)] {
+            let mut population = Population::new_empty(false);
+            for gene in 0..10u8 {
+                population.insert(1000 + gene as Score, Agent::from_genes(vec![gene, gene, gene, gene]));
+            }
+
+            let mut operation = Operation::new(OperationType::Crossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+            operation.set_crossover_mode(mode);
+            operation.set_mate_compatibility(same_parity);
+            for _ in 0..5 {
+                population = operation.run(population, &0, &mut score_provider);
+            }
+
+            for agent in population.get_agents().values() {
+                let parity = agent.get_genes()[0] % 2;
+                assert!(agent.get_genes().iter().all(|gene| gene % 2 == parity));
+            }
+        }
+    }
+
+    #[test]
+    fn speciated_crossover_removes_stagnant_species() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let mut population = Population::new_empty(false);
+        population.insert(200, Agent::from_genes(vec![200u8, 200, 200, 200]));
+        population.insert(190, Agent::from_genes(vec![200u8, 200, 200, 190]));
+        population.insert(10, Agent::from_genes(vec![10u8, 10, 10, 10]));
+        population.insert(11, Agent::from_genes(vec![10u8, 10, 10, 11]));
+        population.advance_generation();
+        population.advance_generation();
+
+        let mut speciation = Speciation::new(1);
+        speciation.set_stagnation_limit(2);
+        let mut operation = Operation::new(OperationType::SpeciatedCrossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+        operation.set_speciation(speciation);
+        let population = operation.run(population, &0, &mut score_provider);
+
+        // The low species was stagnant; the species holding the best agent is kept.
+        assert!(!population.contains_score(10) && !population.contains_score(11));
+        assert!(population.contains_score(200) && population.contains_score(190));
+        assert!(population.get_agents().values().all(|agent| agent.get_genes()[0] == 200));
+    }
+
+    #[test]
+    fn tournament_size_sets_selection_pressure() {
+        random::seed(5);
+        let mut population = Population::new_empty(false);
+        for score in 1..=100 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        let mean_key = |size: usize| {
+            let selected = Selection::tournament(size, 0.2).agents(&population);
+            assert_eq!(20, selected.len());
+            selected.keys().sum::<Score>() as f64 / 20.0
+        };
+        let (random, small, large) = (mean_key(1), mean_key(2), mean_key(20));
+        assert!(random < small && small < large);
+        assert!(large > 80.0);
+
+        let culled = Operation::new(OperationType::Cull, Selection::tournament(3, 0.1)).run(population, &0, &mut GeneralScoreProvider::new(get_score_index, 0));
+        assert_eq!(90, culled.len());
+    }
+
+    #[test]
+    fn stochastic_universal_selection() {
+        let selection = Selection::with_values(SelectionType::StochasticUniversal, 0.5, 0);
+        let mut population = Population::new_empty(false);
+        for score in 10..20 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        // Each agent is narrower than the pointer spacing, so no agent is picked twice.
+        assert_eq!(5, selection.agents(&population).len());
+
+        population.insert(1000, Agent::from_genes(vec![0u8]));
+        for _ in 0..10 {
+            assert!(selection.agents(&population).contains_key(&1000));
+        }
+
+        let cull = Operation::new(OperationType::Cull, selection);
+        let population = cull.run(population, &0, &mut GeneralScoreProvider::new(get_score_index, 0));
+        assert!(!population.contains_score(1000));
+    }
+
+    #[test]
+    fn stochastic_universal_selection_is_proportional_to_score() {
+        // Scores 1 to 20 total 210, so five pointers are 42 apart and each agent, narrower than
+        // that, is picked at most once, with a chance of its score in 42.
+        let selection = Selection::with_values(SelectionType::StochasticUniversal, 0.25, 0);
+        let mut population = Population::new_empty(false);
+        for score in 1..=20 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+
+        let trials = 4200;
+        let mut counts: HashMap<Score, usize> = HashMap::new();
+        random::with_seed(11, || {
+            for _ in 0..trials {
+                let selected = selection.agents(&population);
+                assert_eq!(5, selected.len());
+                for score in selected.keys() {
+                    *counts.entry(*score).or_insert(0) += 1;
+                }
+            }
+        });
+        for score in 5..=20 {
+            let expected = (trials as Score * score / 42) as f64;
+            let count = *counts.get(&score).unwrap_or(&0) as f64;
+            assert!((count - expected).abs() < expected * 0.15, "score {} picked {} times, expected {}", score, count, expected);
+        }
+    }
+
+    #[test]
+    fn band_selection_leaves_elites() {
+        let population = scored_population(10);
+        let middle = Selection::bands(1, 3, 4);
+        assert_eq!(vec![2, 3, 4, 5, 6], middle.agents(&population).keys().cloned().collect::<Vec<Score>>());
+        assert_eq!(5, middle.count(&population));
+        assert!(Selection::bands(2, 2, 4).agents(&population).is_empty());
+
+        let cull = Operation::new(OperationType::Cull, Selection::with_values(SelectionType::HighestScore, 0.4, 0).within(middle));
+        let population = cull.run(population, &0, &mut GeneralScoreProvider::new(get_score_index, 0));
+        assert_eq!(vec![0, 1, 2, 3, 4, 7, 8, 9], population.get_scores());
+    }
+
+    #[test]
+    fn crowding_children_only_replace_worse_parents() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 0);
+        let mut population = Population::new(12, 3, false, &0, &mut score_provider);
+        let operation = Operation::new(OperationType::CrowdingCrossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+
+        for _ in 0..10 {
+            let size = population.len();
+            let total: Score = population.get_scores().iter().sum();
+            population = operation.run(population, &0, &mut score_provider);
+            assert!(population.len() <= size);
+            assert!(population.get_scores().iter().sum::<Score>() >= total);
+        }
+    }
+
+    #[test]
+    fn rejected_crowding_children_leave_their_parents() {
+        fn sum_genes(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            Ok(agent.get_genes().iter().map(|gene| *gene as Score).sum())
+        }
+
+        let mut score_provider = GeneralScoreProvider::new(sum_genes, 0);
+        let mut population = Population::new_empty(true);
+        // Any child of the first two that beats its closer parent has the genes of the third.
+        population.insert(9, Agent::from_genes(vec![0u8, 9]));
+        population.insert(8, Agent::from_genes(vec![8u8, 0]));
+        population.insert(17, Agent::from_genes(vec![8u8, 9]));
+        let operation = Operation::new(OperationType::CrowdingCrossover, Selection::with_values(SelectionType::RandomAny, 1.0, 0));
+
+        random::with_seed(4, || {
+            for _ in 0..20 {
+                population = operation.run(population, &0, &mut score_provider);
+                assert_eq!(vec![8, 9, 17], population.get_scores());
+            }
+        });
+    }
+
+    #[test]
+    fn coordinate_sweep_does_not_lower_best() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 1);
+        let population = Population::new(8, 3, false, &0, &mut score_provider);
+        let best_before = *population.get_agents().keys().next_back().unwrap();
+
+        let operation = Operation::new(OperationType::CoordinateSweep, Selection::with_values(SelectionType::HighestScore, 0.0, 1));
+        let population = operation.run(population, &0, &mut score_provider);
+
+        assert!(*population.get_agents().keys().next_back().unwrap() >= best_before);
+    }
+
     #[test]
     fn rate_to_number_standard_proportion() {
         assert_eq!(16, rate_to_number(20, 0.8, 0));
@@ -460,4 +2077,24 @@ mod tests {
     fn rate_to_number_minimum_preference_greater_than_population() {
         assert_eq!(4, rate_to_number(4, 0.5, 5));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn preview_leaves_population_unchanged() {
+        let mut population = Population::new_empty(false);
+        for score in 1..11 {
+            population.insert(score, Agent::from_genes(vec![score as u8, 0]));
+        }
+
+        let cull: Operation<u8, u8> = Operation::new(OperationType::Cull, Selection::with_values(SelectionType::LowestScore, 0.3, 0));
+        let plan = cull.preview(&population, &0);
+        assert_eq!(vec![1, 2, 3], plan.culled);
+        assert_eq!(0, plan.offspring);
+
+        let mutate: Operation<u8, u8> = Operation::new(OperationType::Mutate, Selection::with_values(SelectionType::HighestScore, 0.2, 0));
+        let plan = mutate.preview(&population, &0);
+        assert_eq!(vec![9, 10], plan.selected);
+        assert_eq!(2, plan.offspring);
+        assert!(plan.culled.is_empty());
+        assert_eq!(10, population.len());
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An archive of the non-dominated agents seen during a run, kept outside the population.
+//!
+//! The population is ordered by a single score, which can hide the trade-offs between the parts of
+//! a problem. The archive scores each agent it sees on several objectives, higher being better for
+//! each, and keeps every agent that no other agent seen beats on all of them.
+
+use super::agent::Agent;
+use super::evolution::Observer;
+use super::fitness::Score;
+use super::population::Population;
+use std::collections::HashSet;
+
+/// Scores an agent on each objective, higher being better.
+pub type ObjectivesFunction<Gene, Data> = fn(&Agent<Gene>, &Data) -> Vec<Score>;
+
+/// Whether the first scores are at least as good as the second on every objective and better on
+/// at least one.
+pub fn dominates(a: &[Score], b: &[Score]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|(a, b)| a >= b)
+        && a.iter().zip(b.iter()).any(|(a, b)| a > b)
+}
+
+/// Ranks each list of objective scores by the non-dominated front it falls in: 0 for scores no
+/// others dominate, 1 for those only dominated by front 0, and so on.
+pub fn front_ranks(objectives: &[Vec<Score>]) -> Vec<usize> {
+    let mut ranks = vec![0; objectives.len()];
+    let mut dominated_by = vec![0; objectives.len()];
+    let mut dominates_list: Vec<Vec<usize>> = vec![Vec::new(); objectives.len()];
+    for (one, one_scores) in objectives.iter().enumerate() {
+        for (two, two_scores) in objectives.iter().enumerate().skip(one + 1) {
+            if dominates(one_scores, two_scores) {
+                dominates_list[one].push(two);
+                dominated_by[two] += 1;
+            } else if dominates(two_scores, one_scores) {
+                dominates_list[two].push(one);
+                dominated_by[one] += 1;
+            }
+        }
+    }
+
+    let mut front: Vec<usize> = (0..objectives.len()).filter(|index| dominated_by[*index] == 0).collect();
+    let mut rank = 0;
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for index in front {
+            ranks[index] = rank;
+            for dominated in &dominates_list[index] {
+                dominated_by[*dominated] -= 1;
+                if dominated_by[*dominated] == 0 {
+                    next.push(*dominated);
+                }
+            }
+        }
+        front = next;
+        rank += 1;
+    }
+    ranks
+}
+
+/// Keeps the non-dominated agents it is given, with their objective scores.
+/// Add it to a run as an Observer to archive agents from the population after every iteration;
+/// share it through an `Arc<Mutex<_>>` to query it once a Manager has finished.
+pub struct ParetoArchive <Gene, Data> {
+    objectives_function: ObjectivesFunction<Gene, Data>,
+    data: Data,
+    front: Vec<(Vec<Score>, Agent<Gene>)>,
+    seen: HashSet<u64>
+}
+
+impl <Gene, Data> ParetoArchive <Gene, Data> {
+    pub fn new(objectives_function: ObjectivesFunction<Gene, Data>, data: Data) -> Self {
+        Self {
+            objectives_function,
+            data,
+            front: Vec::new(),
+            seen: HashSet::new()
+        }
+    }
+
+    /// Scores the agent and archives it if it is not dominated, removing any archived agents it
+    /// dominates. Agents already seen are ignored. Returns whether the agent was archived.
+    pub fn add(&mut self, agent: &Agent<Gene>) -> bool
+    where
+    Gene: Clone
+    {
+        if !self.seen.insert(agent.get_hash()) {
+            return false;
+        }
+        let objectives = (self.objectives_function)(agent, &self.data);
+        self.insert(objectives, agent.clone())
+    }
+
+    /// Archives an agent with objective scores already calculated, if it is not dominated.
+    /// An agent with the same scores as an archived one is kept too, as an equal trade-off.
+    pub fn insert(&mut self, objectives: Vec<Score>, agent: Agent<Gene>) -> bool {
+        if self.front.iter().any(|(archived, existing)| dominates(archived, &objectives) || existing.has_same_genes(&agent)) {
+            return false;
+        }
+        self.front.retain(|(archived, _)| !dominates(&objectives, archived));
+        self.seen.insert(agent.get_hash());
+        self.front.push((objectives, agent));
+        true
+    }
+
+    /// The archived agents and their objective scores, in the order they were archived.
+    pub fn get_front(&self) -> &Vec<(Vec<Score>, Agent<Gene>)> {
+        &self.front
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+}
+
+impl <Gene: Clone, Data> Observer<Gene> for ParetoArchive <Gene, Data> {
+    fn iteration_complete(&mut self, _iteration: usize, population: &Population<Gene>) {
+        for agent in population.get_agents().values() {
+            self.add(agent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first objective prefers high genes, the second low genes.
+    fn high_and_low(agent: &Agent<u8>, _data: &()) -> Vec<Score> {
+        let genes = agent.get_genes();
+        vec![genes[0] as Score, 255 - genes[1] as Score]
+    }
+
+    #[test]
+    fn dominance() {
+        assert!(dominates(&[2, 2], &[1, 2]));
+        assert!(!dominates(&[2, 2], &[2, 2]));
+        assert!(!dominates(&[3, 1], &[1, 3]));
+        assert!(!dominates(&[3], &[1, 1]));
+    }
+
+    #[test]
+    fn ranks_fronts() {
+        let objectives = vec![vec![1, 1], vec![3, 1], vec![1, 3], vec![2, 2], vec![0, 0]];
+        assert_eq!(vec![1, 0, 0, 0, 2], front_ranks(&objectives));
+        assert!(front_ranks(&[]).is_empty());
+    }
+
+    #[test]
+    fn archive_keeps_non_dominated_agents() {
+        let mut archive = ParetoArchive::new(high_and_low, ());
+        assert!(archive.add(&Agent::from_genes(vec![5u8, 5])));
+        assert!(archive.add(&Agent::from_genes(vec![9u8, 9])));
+        assert!(!archive.add(&Agent::from_genes(vec![4u8, 6])));
+        assert!(!archive.add(&Agent::from_genes(vec![5u8, 5])));
+        assert_eq!(2, archive.len());
+
+        // Dominates both.
+        let mut population = Population::new_empty(false);
+        population.insert(1, Agent::from_genes(vec![9u8, 1]));
+        archive.iteration_complete(0, &population);
+        assert_eq!(1, archive.len());
+        assert_eq!(vec![9, 254], archive.get_front()[0].0);
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding genes into phenotypes, the solutions they describe.
+//!
+//! Many problems score and report a solution built from the genes, such as the result of running
+//! them as a program. Implementing `Phenotype` for that solution makes the translation a single
+//! step: a `PhenotypeScorer` decodes each agent and scores the phenotype, and reporting code
+//! decodes with `decode` rather than repeating the translation. A `PhenotypeCache` shared between
+//! the scorer and other code decodes each genome only once.
+
+use super::agent::Agent;
+use super::fitness::{BoundedCache, Score, ScoreError, Scorer};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// A solution decoded from genes, given the data of the run.
+pub trait Phenotype<Gene, Data> {
+    fn decode(genes: &[Gene], data: &Data) -> Self;
+}
+
+/// Decodes the agent's genes into the phenotype.
+pub fn decode<P, Gene, Data>(agent: &Agent<Gene>, data: &Data) -> P
+where
+P: Phenotype<Gene, Data>
+{
+    P::decode(agent.get_genes(), data)
+}
+
+/// Decoded phenotypes kept by genome hash, with the same limits as a score cache. Clones share the
+/// cache, so a clone given to a PhenotypeScorer also serves reporting, local search and constraint
+/// checks.
+pub struct PhenotypeCache <P> {
+    phenotypes: Arc<Mutex<BoundedCache<Arc<P>>>>
+}
+
+impl <P> Clone for PhenotypeCache <P> {
+    fn clone(&self) -> Self {
+        Self { phenotypes: self.phenotypes.clone() }
+    }
+}
+
+impl <P> PhenotypeCache <P> {
+    /// capacity: the most phenotypes kept, forgetting the oldest, or None for no limit.
+    pub fn new(capacity: Option<usize>) -> Self {
+        Self { phenotypes: Arc::new(Mutex::new(BoundedCache::new(capacity))) }
+    }
+
+    /// The agent's phenotype, decoded only if it is not already cached.
+    pub fn get_or_decode<Gene, Data>(&self, agent: &Agent<Gene>, data: &Data) -> Arc<P>
+    where
+    P: Phenotype<Gene, Data>
+    {
+        if let Some(phenotype) = self.phenotypes.lock().unwrap().get(agent.get_hash()) {
+            return phenotype.clone();
+        }
+        // Decoded without holding the lock, so other threads are not held up.
+        let phenotype: Arc<P> = Arc::new(decode(agent, data));
+        self.phenotypes.lock().unwrap().insert(agent.get_hash(), phenotype.clone());
+        phenotype
+    }
+
+    /// Forgets the cached phenotypes, such as when the data they were decoded with changes.
+    pub fn clear(&self) {
+        self.phenotypes.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.phenotypes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Scores a phenotype.
+pub type PhenotypeFitness<P, Data> = fn(&P, &Data) -> Result<Score, ScoreError>;
+
+/// The base of a score provider middleware chain that decodes each agent and scores its phenotype.
+/// See fitness::Scorer.
+pub struct PhenotypeScorer <P, Data> {
+    fitness: PhenotypeFitness<P, Data>,
+    cache: Option<PhenotypeCache<P>>,
+    phenotype: PhantomData<P>
+}
+
+impl <P, Data> Clone for PhenotypeScorer <P, Data> {
+    fn clone(&self) -> Self {
+        Self { fitness: self.fitness, cache: self.cache.clone(), phenotype: PhantomData }
+    }
+}
+
+/// Starts a middleware chain scoring the phenotypes of agents with the fitness function.
+pub fn phenotype_scorer<P, Data>(fitness: PhenotypeFitness<P, Data>) -> PhenotypeScorer<P, Data> {
+    PhenotypeScorer { fitness, cache: None, phenotype: PhantomData }
+}
+
+impl <P, Data> PhenotypeScorer <P, Data> {
+    /// Takes phenotypes from the cache, decoding and adding those it does not have.
+    pub fn with_cache(mut self, cache: PhenotypeCache<P>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+}
+
+impl <Gene, Data, P> Scorer<Gene, Data> for PhenotypeScorer <P, Data>
+where
+P: Phenotype<Gene, Data>
+{
+    fn score(&mut self, agent: &Agent<Gene>, data: &Data) -> Result<Score, ScoreError> {
+        match self.cache {
+            Some(ref cache) => (self.fitness)(&cache.get_or_decode(agent, data), data),
+            None => (self.fitness)(&decode(agent, data), data)
+        }
+    }
+
+    fn data_changed(&mut self) {
+        if let Some(ref cache) = self.cache {
+            cache.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::ScoreProvider;
+
+    /// The running totals of the genes, starting from the data.
+    struct Totals(Vec<u64>);
+
+    impl Phenotype<u8, u64> for Totals {
+        fn decode(genes: &[u8], start: &u64) -> Self {
+            let mut total = *start;
+            Totals(genes.iter().map(|gene| { total += *gene as u64; total }).collect())
+        }
+    }
+
+    fn final_total(totals: &Totals, _start: &u64) -> Result<Score, ScoreError> {
+        totals.0.last().cloned().ok_or_else(|| ScoreError::new("no genes"))
+    }
+
+    #[test]
+    fn scores_decoded_phenotypes() {
+        let agent = Agent::from_genes(vec![1u8, 2, 3]);
+        let totals: Totals = decode(&agent, &10);
+        assert_eq!(vec![11, 13, 16], totals.0);
+
+        let mut provider = phenotype_scorer(final_total).cached().with_noise(0);
+        assert_eq!(16, provider.true_score(&agent, &10).unwrap());
+        assert!(provider.true_score(&Agent::from_genes(Vec::<u8>::new()), &10).is_err());
+    }
+
+    #[test]
+    fn cache_shares_decoded_phenotypes() {
+        let cache = PhenotypeCache::new(Some(1));
+        let mut provider = phenotype_scorer(final_total).with_cache(cache.clone()).with_noise(0);
+        let agent = Agent::from_genes(vec![1u8, 2]);
+        assert_eq!(13, provider.true_score(&agent, &10).unwrap());
+        assert_eq!(1, cache.len());
+        assert_eq!(vec![11, 13], cache.get_or_decode(&agent, &10).0);
+
+        provider.true_score(&Agent::from_genes(vec![5u8]), &10).unwrap();
+        assert_eq!(1, cache.len());
+        provider.data_changed();
+        assert!(cache.is_empty());
+    }
+}
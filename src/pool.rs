@@ -0,0 +1,110 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A pool of gene buffers reused between the agents an operation removes and the children it breeds.
+//!
+//! Each child bred by crossover or mutation starts as a copy of a parent's genes. With a pool, the
+//! buffers of culled agents are kept and reused for those copies, so a large population running
+//! for many generations allocates far less. The pool is empty with a capacity of zero by default,
+//! which keeps nothing. See Population::set_buffer_pool_capacity.
+
+use super::agent::Agent;
+
+/// Gene buffers kept for reuse, up to a capacity.
+/// Cloning gives an empty pool with the same capacity, as spare buffers are not worth copying.
+#[derive(Debug, Default)]
+pub struct BufferPool <Gene> {
+    buffers: Vec<Vec<Gene>>,
+    capacity: usize
+}
+
+impl <Gene> BufferPool <Gene> {
+    /// Creates an empty pool keeping up to capacity buffers.
+    pub fn new(capacity: usize) -> Self {
+        Self { buffers: Vec::new(), capacity }
+    }
+
+    /// Sets the most buffers kept, dropping any beyond it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.buffers.truncate(capacity);
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Takes an empty buffer, reusing a kept one if there is any.
+    pub fn take(&mut self) -> Vec<Gene> {
+        self.buffers.pop().unwrap_or_default()
+    }
+
+    /// Keeps the buffer for reuse if there is room.
+    pub fn give(&mut self, mut buffer: Vec<Gene>) {
+        if self.buffers.len() < self.capacity && buffer.capacity() > 0 {
+            buffer.clear();
+            self.buffers.push(buffer);
+        }
+    }
+
+    /// Keeps the buffer of an agent that is no longer needed.
+    pub fn recycle(&mut self, agent: Agent<Gene>) {
+        if self.buffers.len() < self.capacity {
+            self.give(agent.into_genes());
+        }
+    }
+
+    /// Copies the agent into a buffer from the pool.
+    pub fn copy(&mut self, agent: &Agent<Gene>) -> Agent<Gene> where Gene: Clone {
+        agent.copy_into(self.take())
+    }
+
+    /// The number of buffers kept.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+}
+
+impl <Gene> Clone for BufferPool <Gene> {
+    fn clone(&self) -> Self {
+        Self::new(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_reuses_buffers_up_to_capacity() {
+        let mut pool = BufferPool::new(1);
+        pool.recycle(Agent::from_genes(vec![1u8, 2, 3]));
+        pool.recycle(Agent::from_genes(vec![4u8]));
+        assert_eq!(1, pool.len());
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty() && buffer.capacity() >= 3);
+        assert!(pool.is_empty());
+
+        pool.give(buffer);
+        let copy = pool.copy(&Agent::from_genes(vec![5u8, 6]));
+        assert_eq!(&vec![5u8, 6], copy.get_genes());
+        assert!(copy.has_same_genes(&Agent::from_genes(vec![5u8, 6])));
+        assert_eq!(1, pool.clone().get_capacity());
+    }
+}
@@ -12,21 +12,257 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::agent::Agent;
-use super::fitness::{Score, ScoreProvider};
-use std::collections::{BTreeMap, HashSet};
+use super::random;
+use super::agent::{self, Agent, GenomeHasher};
+use super::fitness::{Score, ScoreError, ScoreProvider};
+use super::pool::BufferPool;
+use super::register::Register;
+use super::history::{Ancestry, Lineage, Trace};
+use super::operations::OperationType;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
+/// The positions, counting from the lowest score, of the agents in the given band when the number
+/// of agents is split into equally sized bands. Band sizes differ by at most one.
+pub(crate) fn band_bounds(len: usize, band: usize, bands: usize) -> Range<usize> {
+    let bands = bands.max(1);
+    let band = band.min(bands);
+    (len * band / bands)..(len * (band + 1) / bands).min(len)
+}
+
+/// Decides whether an agent may join a population, as well as the check for unique agents.
+pub trait AcceptancePolicy <Gene>: Send + Sync {
+    /// score: the score key the agent would be inserted with.
+    /// parents: the score keys of the agents it was bred from, empty when they are not known.
+    fn accept(&self, score: Score, agent: &Agent<Gene>, parents: &[Score], population: &Population<Gene>) -> bool;
+}
+
+impl <Gene, F> AcceptancePolicy<Gene> for F
+where
+F: Fn(Score, &Agent<Gene>, &[Score], &Population<Gene>) -> bool + Send + Sync
+{
+    fn accept(&self, score: Score, agent: &Agent<Gene>, parents: &[Score], population: &Population<Gene>) -> bool {
+        self(score, agent, parents, population)
+    }
+}
+
+/// Rejects children scored lower than all of their parents. Agents without known parents are
+/// accepted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RejectWorseThanParents;
+
+impl <Gene> AcceptancePolicy<Gene> for RejectWorseThanParents {
+    fn accept(&self, score: Score, _agent: &Agent<Gene>, parents: &[Score], _population: &Population<Gene>) -> bool {
+        parents.is_empty() || parents.iter().any(|parent| score >= *parent)
+    }
+}
+
+/// Accepts children scored lower than their better parent with probability exp(-difference / T),
+/// as in simulated annealing, and always accepts others. The temperature T starts at the initial
+/// temperature and is multiplied by the cooling rate each generation, so worse children become
+/// less likely to be kept as the run goes on.
+#[derive(Clone, Copy, Debug)]
+pub struct BoltzmannAcceptance {
+    initial_temperature: f64,
+    cooling_rate: f64
+}
+
+impl BoltzmannAcceptance {
+    /// cooling_rate: between 0.0 and 1.0, with 1.0 keeping the temperature constant.
+    pub fn new(initial_temperature: f64, cooling_rate: f64) -> Self {
+        Self { initial_temperature, cooling_rate }
+    }
+
+    pub fn temperature(&self, generation: u64) -> f64 {
+        self.initial_temperature * self.cooling_rate.powf(generation as f64)
+    }
+
+    /// The probability of accepting a child scored the given amount lower than its better parent.
+    pub fn probability(&self, difference: Score, generation: u64) -> f64 {
+        let temperature = self.temperature(generation);
+        if difference == 0 {
+            1.0
+        } else if temperature <= 0.0 {
+            0.0
+        } else {
+            (-(difference as f64) / temperature).exp()
+        }
+    }
+}
+
+impl <Gene> AcceptancePolicy<Gene> for BoltzmannAcceptance {
+    fn accept(&self, score: Score, _agent: &Agent<Gene>, parents: &[Score], population: &Population<Gene>) -> bool {
+        let difference = match parents.iter().max() {
+            Some(best_parent) => best_parent.saturating_sub(score),
+            None => return true
+        };
+        random::rng().gen::<f64>() < self.probability(difference, population.get_generation())
+    }
+}
+
+/// The SimHashes of the agents in a population, for rejecting near-duplicates.
+#[derive(Clone)]
+struct SimilarityRegister <Gene> {
+    threshold: u32,
+    simhash: fn(&Agent<Gene>) -> u64,
+    simhashes: BTreeMap<Score, u64>
+}
+
+impl <Gene> SimilarityRegister <Gene> {
+    fn has_near_duplicate(&self, agent: &Agent<Gene>) -> bool {
+        let simhash = (self.simhash)(agent);
+        self.simhashes.values().any(|other| (simhash ^ other).count_ones() <= self.threshold)
+    }
+}
+
+/// Why an agent offered to a population was not added. See Population::set_record_rejections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RejectionReason {
+    /// The acceptance policy declined it.
+    Policy,
+    /// An agent with the same genes is in the unique population.
+    Duplicate,
+    /// It is within the near-duplicate threshold of an agent in the population.
+    NearDuplicate,
+    /// The score provider could not score it.
+    ScoreError
+}
+
+/// Counts of the agents a population did not add, by reason, with the first few of each kept as
+/// examples, for diagnosing why a population is not growing.
+#[derive(Clone)]
+pub struct RejectionStats <Gene> {
+    examples_per_reason: usize,
+    copy: fn(&Agent<Gene>) -> Agent<Gene>,
+    counts: HashMap<RejectionReason, u64>,
+    examples: HashMap<RejectionReason, Vec<Agent<Gene>>>
+}
+
+impl <Gene> RejectionStats <Gene> {
+    fn new(examples_per_reason: usize) -> Self where Gene: Clone {
+        Self {
+            examples_per_reason,
+            copy: Agent::clone,
+            counts: HashMap::new(),
+            examples: HashMap::new()
+        }
+    }
+
+    fn record(&mut self, reason: RejectionReason, agent: &Agent<Gene>) {
+        self.tally(reason);
+        let examples = self.examples.entry(reason).or_default();
+        if examples.len() < self.examples_per_reason {
+            examples.push((self.copy)(agent));
+        }
+    }
+
+    /// Counts a rejection without keeping an example.
+    fn tally(&mut self, reason: RejectionReason) {
+        *self.counts.entry(reason).or_insert(0) += 1;
+    }
+
+    /// How many more examples of the reason are kept.
+    fn examples_wanted(&self, reason: RejectionReason) -> usize {
+        self.examples_per_reason - self.examples.get(&reason).map_or(0, |examples| examples.len())
+    }
+
+    /// The number of agents rejected for the reason.
+    pub fn count(&self, reason: RejectionReason) -> u64 {
+        self.counts.get(&reason).cloned().unwrap_or(0)
+    }
+
+    /// The number of agents rejected for any reason.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// The first agents rejected for the reason.
+    pub fn examples(&self, reason: RejectionReason) -> &[Agent<Gene>] {
+        self.examples.get(&reason).map_or(&[], |examples| examples.as_slice())
+    }
+}
+
+/// The agents offered to a score provider, kept to find those it drops: the hashes of all of them
+/// and copies of as many as are still wanted as examples.
+pub(crate) struct Offered <Gene> {
+    hashes: Vec<u64>,
+    copies: Vec<Agent<Gene>>
+}
+
+/// The number of agents whose score keys fall in a range, from low to high inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScoreBucket {
+    pub low: Score,
+    pub high: Score,
+    pub count: usize
+}
+
+/// A broken invariant of a population, found by Population::validate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvariantError {
+    details: String
+}
+
+impl InvariantError {
+    pub fn new(details: &str) -> Self {
+        Self {
+            details: details.to_string()
+        }
+    }
+}
+
+impl Display for InvariantError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for InvariantError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// The outcome of checking an agent for insertion.
+enum Admission {
+    Accepted,
+    Rejected,
+    /// Rejected because an agent with the same genes is in the unique population.
+    Duplicate
+}
+
+type HashWith<Gene> = fn(&Agent<Gene>, GenomeHasher) -> u64;
+
 #[derive(Clone)]
 pub struct Population <Gene> {
     agents: BTreeMap<Score, Agent<Gene>>,
-    register: HashSet<u64>,
+    register: Register,
+    // The hasher agents are given as they join, and Agent::hash_with to hash them with it.
+    hasher: Option<(GenomeHasher, HashWith<Gene>)>,
     unique_agents: bool,
-
+    promote_duplicates: bool,
+    generation: u64,
+    lifespan: Option<u64>,
+    protected_elites: usize,
+    max_size: Option<usize>,
+    acceptance_policy: Option<Arc<dyn AcceptancePolicy<Gene>>>,
+    similarity: Option<SimilarityRegister<Gene>>,
+    buffers: BufferPool<Gene>,
+    gene_length: Option<usize>,
+    allow_empty: bool,
+    rejections: Option<RejectionStats<Gene>>,
+    lineage: Option<Lineage>,
+    // The lowest and highest keys, kept up to date as agents are inserted and removed.
+    extremes: Option<(Score, Score)>
 }
 
 impl <Gene> Population <Gene> {
@@ -34,25 +270,60 @@ impl <Gene> Population <Gene> {
     pub fn new_empty(unique: bool) -> Self {
         Self {
             agents: BTreeMap::new(),
-            register: HashSet::new(),
-            unique_agents: unique
+            register: Register::new(),
+            hasher: None,
+            unique_agents: unique,
+            promote_duplicates: false,
+            generation: 0,
+            lifespan: None,
+            protected_elites: 0,
+            max_size: None,
+            acceptance_policy: None,
+            similarity: None,
+            buffers: BufferPool::new(0),
+            gene_length: None,
+            allow_empty: false,
+            rejections: None,
+            lineage: None,
+            extremes: None
         }
     }
 
+    /// Creates a population of up to start_size random agents with the number of genes, scored by
+    /// the score provider, which may be a trait object. Agents that cannot be scored, or that the
+    /// population does not accept, are left out. If the score provider fails for the whole batch,
+    /// as one whose service is down does, the population is empty; try_new returns the error.
     pub fn new<Data, SP>(
         start_size: usize,
         number_of_genes: usize,
         unique: bool,
         data: &Data,
         score_provider: &mut SP,
-    ) -> Population<Gene> 
+    ) -> Population<Gene>
     where
     Standard: Distribution<Gene>,
     Gene: Hash + Clone,
-    SP: ScoreProvider<Gene, Data>
+    SP: ScoreProvider<Gene, Data> + ?Sized
+    {
+        Self::try_new(start_size, number_of_genes, unique, data, score_provider).unwrap_or_else(|_| Population::new_empty(unique))
+    }
+
+    /// As new, returning the score provider's error if it fails for the whole batch, rather than
+    /// an empty population.
+    pub fn try_new<Data, SP>(
+        start_size: usize,
+        number_of_genes: usize,
+        unique: bool,
+        data: &Data,
+        score_provider: &mut SP,
+    ) -> Result<Population<Gene>, ScoreError>
+    where
+    Standard: Distribution<Gene>,
+    Gene: Hash + Clone,
+    SP: ScoreProvider<Gene, Data> + ?Sized
     {
         let mut population = Population::new_empty(unique);
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         let mut agents = Vec::new();
         for _ in 0..start_size {
             let agent = Agent::with_genes(number_of_genes);
@@ -61,25 +332,47 @@ impl <Gene> Population <Gene> {
             }
         }
 
-        let agents = score_provider.evaluate_scores(agents, &data).unwrap();
+        // Agents that cannot be scored are left out by the score provider.
+        let agents = score_provider.evaluate_scores(agents, &data)?;
 
         for agent in agents {
-            let mut score = score_provider.get_score(&agent, &data, &mut rng).unwrap();
+            if let Ok(score) = score_provider.get_score(&agent, &data, &mut rng) {
+                population.insert_ranked(score, agent);
+            }
+        }
 
-            loop {
-                if score == 0 {
-                    break;
-                }
-                if population.contains_score(score) {
-                    score -= 1;
-                } else {
-                    break;
+        Ok(population)
+    }
+
+    /// Builds a population from genes and their score keys without scoring anything, such as when
+    /// restoring a large checkpoint or taking results from an external evaluator. The agents are
+    /// loaded in one pass and the register is built once, rather than admitting each in turn, so
+    /// no acceptance policy applies. A later pair with the same score replaces an earlier one, as
+    /// insert does. With unique set, only the highest scored of agents with the same genes is kept.
+    pub fn from_scored_iter<I>(iter: I, unique: bool) -> Self
+    where
+    I: IntoIterator<Item = (Vec<Gene>, Score)>,
+    Gene: Hash
+    {
+        let iter = iter.into_iter();
+        let mut scored: Vec<(Score, Agent<Gene>)> = Vec::with_capacity(iter.size_hint().0);
+        scored.extend(iter.map(|(genes, score)| (score, Agent::from_genes(genes))));
+
+        let mut population = Self::new_empty(unique);
+        population.agents = scored.into_iter().collect();
+        if unique {
+            population.register.reserve(population.agents.len());
+            let mut duplicates = Vec::new();
+            for (score, agent) in population.agents.iter().rev() {
+                if !population.register.insert(agent.get_hash()) {
+                    duplicates.push(*score);
                 }
             }
-
-            population.insert(score, agent);
+            for score in duplicates {
+                population.agents.remove(&score);
+            }
         }
-
+        population.refresh_extremes();
         population
     }
 
@@ -90,23 +383,377 @@ impl <Gene> Population <Gene> {
     }
 
     pub fn insert(&mut self, score: Score, agent: Agent<Gene>) {
+        self.insert_child(score, agent, &[]);
+    }
+
+    /// Inserts an agent bred from the agents with the parent score keys, which the acceptance
+    /// policy may use to decide whether to accept it.
+    /// See set_promote_duplicates for agents with the same genes as one already in the population.
+    pub fn insert_child(&mut self, score: Score, mut agent: Agent<Gene>, parents: &[Score]) {
+        self.adopt(&mut agent);
+        match self.admit(score, &agent, parents) {
+            Admission::Accepted => {
+                self.record_lineage(&agent);
+                self.place(score, agent)
+            },
+            Admission::Duplicate => {
+                if let Some(existing) = self.take_lower_duplicate(score, &agent) {
+                    self.remove_for_duplicate(score);
+                    self.agents.insert(score, existing);
+                    self.note_inserted(score);
+                    self.update_simhash(score);
+                }
+            },
+            Admission::Rejected => ()
+        }
+    }
+
+    /// Inserts an agent without replacing any other. If an agent already has the score as its
+    /// key, the new agent is given the nearest free key, the one below where two are as near, and
+    /// no other agent moves. Returns the key the agent was given, or None if it was not accepted.
+    pub fn insert_ranked(&mut self, score: Score, agent: Agent<Gene>) -> Option<Score> {
+        self.insert_ranked_child(score, agent, &[])
+    }
+
+    /// As insert_ranked, for an agent bred from the agents with the parent score keys.
+    /// If duplicates are promoted, an agent with the same genes and a lower key is moved up next to
+    /// the score, and None is returned.
+    pub fn insert_ranked_child(&mut self, score: Score, mut agent: Agent<Gene>, parents: &[Score]) -> Option<Score> {
+        self.adopt(&mut agent);
+        match self.admit(score, &agent, parents) {
+            Admission::Accepted => {
+                self.record_lineage(&agent);
+                let key = self.make_room(score);
+                self.place(key, agent);
+                Some(key)
+            },
+            Admission::Duplicate => {
+                if let Some(existing) = self.take_lower_duplicate(score, &agent) {
+                    let key = self.make_room(score);
+                    self.agents.insert(key, existing);
+                    self.note_inserted(key);
+                    self.update_simhash(key);
+                }
+                None
+            },
+            Admission::Rejected => None
+        }
+    }
+
+    /// Gives the agent the population's genome hasher, if one is set.
+    fn adopt(&self, agent: &mut Agent<Gene>) {
+        if let Some((hasher, hash_with)) = self.hasher {
+            let hash = hash_with(agent, hasher);
+            agent.adopt_hash(hasher, hash);
+        }
+    }
+
+    /// The agent's hash under the population's genome hasher, as it would be registered.
+    fn hash_of(&self, agent: &Agent<Gene>) -> u64 {
+        match self.hasher {
+            Some((hasher, hash_with)) => hash_with(agent, hasher),
+            None => agent.get_hash()
+        }
+    }
+
+    /// Checks the agent against the acceptance policy and registers, registering its hash if it
+    /// is accepted.
+    fn admit(&mut self, score: Score, agent: &Agent<Gene>, parents: &[Score]) -> Admission {
+        if let Some(ref policy) = self.acceptance_policy {
+            if !policy.accept(score, agent, parents, self) {
+                self.record_rejection(RejectionReason::Policy, agent);
+                return Admission::Rejected;
+            }
+        }
+        if self.unique_agents && self.register.contains(agent.get_hash()) {
+            self.record_rejection(RejectionReason::Duplicate, agent);
+            return Admission::Duplicate;
+        }
+        if let Some(ref similarity) = self.similarity {
+            if similarity.has_near_duplicate(agent) {
+                self.record_rejection(RejectionReason::NearDuplicate, agent);
+                return Admission::Rejected;
+            }
+        }
         if self.unique_agents {
-            if self.register.contains(&agent.get_hash()) {
+            self.register.insert(agent.get_hash());
+        }
+        Admission::Accepted
+    }
+
+    fn record_rejection(&mut self, reason: RejectionReason, agent: &Agent<Gene>) {
+        if let Some(ref mut rejections) = self.rejections {
+            rejections.record(reason, agent);
+        }
+    }
+
+    /// Notes the agents if rejections are recorded, to find those the score provider drops. Only
+    /// the first agents are copied, as many as are still wanted as examples of score errors.
+    pub(crate) fn copies_for_rejections(&self, agents: &[Agent<Gene>]) -> Offered<Gene> {
+        match self.rejections {
+            Some(ref rejections) => Offered {
+                hashes: agents.iter().map(|agent| agent.get_hash()).collect(),
+                copies: agents.iter().take(rejections.examples_wanted(RejectionReason::ScoreError)).map(rejections.copy).collect()
+            },
+            None => Offered { hashes: Vec::new(), copies: Vec::new() }
+        }
+    }
+
+    /// Records the offered agents that are not among the scored ones as score errors.
+    pub(crate) fn record_unscored(&mut self, offered: Offered<Gene>, scored: &[Agent<Gene>]) {
+        if offered.hashes.is_empty() {
+            return;
+        }
+        let scored: HashSet<u64> = scored.iter().map(|agent| agent.get_hash()).collect();
+        let mut copies: HashMap<u64, Agent<Gene>> = offered.copies.into_iter().map(|agent| (agent.get_hash(), agent)).collect();
+        for hash in offered.hashes.into_iter().filter(|hash| !scored.contains(hash)) {
+            match copies.remove(&hash) {
+                Some(agent) => self.record_rejection(RejectionReason::ScoreError, &agent),
+                None => {
+                    if let Some(ref mut rejections) = self.rejections {
+                        rejections.tally(RejectionReason::ScoreError);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records an agent the score provider could not give a score as a score error.
+    pub(crate) fn record_score_error(&mut self, agent: &Agent<Gene>) {
+        self.record_rejection(RejectionReason::ScoreError, agent);
+    }
+
+    /// Removes and returns the agent with the same genes, if duplicates are promoted and its key is
+    /// lower than the score. Its age is kept.
+    fn take_lower_duplicate(&mut self, score: Score, agent: &Agent<Gene>) -> Option<Agent<Gene>> {
+        if !self.promote_duplicates {
+            return None;
+        }
+        let key = self.agents.range(..score)
+            .find(|(_, existing)| existing.has_same_genes(agent))
+            .map(|(key, _)| *key)?;
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.remove(&key);
+        }
+        let existing = self.agents.remove(&key);
+        self.note_removed(key);
+        existing
+    }
+
+    /// Removes the agent with the key, if any, so that another can take it.
+    fn remove_for_duplicate(&mut self, score: Score) {
+        if let Some(agent) = self.agents.remove(&score) {
+            self.note_removed(score);
+            self.register.remove(agent.get_hash());
+            if let Some(ref mut similarity) = self.similarity {
+                similarity.simhashes.remove(&score);
+            }
+        }
+    }
+
+    /// Widens the cached extremes to include a key just inserted.
+    fn note_inserted(&mut self, key: Score) {
+        self.extremes = match self.extremes {
+            Some((worst, best)) => Some((worst.min(key), best.max(key))),
+            None => Some((key, key))
+        };
+    }
+
+    /// Updates the cached extremes after a key is removed, looking up the new end of the map only
+    /// if the key was one of them.
+    fn note_removed(&mut self, key: Score) {
+        if let Some((worst, best)) = self.extremes {
+            if key == worst || key == best {
+                self.refresh_extremes();
+            }
+        }
+    }
+
+    fn refresh_extremes(&mut self) {
+        self.extremes = match (self.agents.keys().next(), self.agents.keys().next_back()) {
+            (Some(worst), Some(best)) => Some((*worst, *best)),
+            _ => None
+        };
+    }
+
+    /// The highest score in the population, kept as agents are inserted and removed, or None if
+    /// it is empty.
+    pub fn best_score(&self) -> Option<Score> {
+        self.extremes.map(|(_, best)| best)
+    }
+
+    /// The lowest score in the population, or None if it is empty.
+    pub fn worst_score(&self) -> Option<Score> {
+        self.extremes.map(|(worst, _)| worst)
+    }
+
+    fn update_simhash(&mut self, score: Score) {
+        if let Some(ref mut similarity) = self.similarity {
+            if let Some(agent) = self.agents.get(&score) {
+                similarity.simhashes.insert(score, (similarity.simhash)(agent));
+            }
+        }
+    }
+
+    fn place(&mut self, score: Score, mut agent: Agent<Gene>) {
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.insert(score, (similarity.simhash)(&agent));
+        }
+        agent.set_birth(self.generation);
+        // An agent replaced at the same key leaves the register.
+        let replaced = self.agents.insert(score, agent);
+        self.note_inserted(score);
+        if let Some(replaced) = replaced {
+            if self.unique_agents {
+                self.register.remove(replaced.get_hash());
+            }
+            self.buffers.recycle(replaced);
+        }
+    }
+
+    /// The free key nearest the score, the lower where two are as near.
+    fn make_room(&self, score: Score) -> Score {
+        if !self.agents.contains_key(&score) {
+            return score;
+        }
+        // A population cannot hold every key, so a free one is always found.
+        let mut distance: Score = 1;
+        loop {
+            let below = score.checked_sub(distance).filter(|key| !self.agents.contains_key(key));
+            let above = score.checked_add(distance).filter(|key| !self.agents.contains_key(key));
+            if let Some(key) = below.or(above) {
+                return key;
+            }
+            distance += 1;
+        }
+    }
+
+    /// Scores the agents with the score provider and inserts those that could be scored.
+    pub fn score_and_insert<Data, SP>(&mut self, agents: Vec<Agent<Gene>>, data: &Data, score_provider: &mut SP)
+    where
+    SP: ScoreProvider<Gene, Data> + ?Sized
+    {
+        let mut rng = random::rng();
+        let offered = self.copies_for_rejections(&agents);
+        if let Ok(agents) = score_provider.evaluate_scores(agents, data) {
+            self.record_unscored(offered, &agents);
+            for agent in agents {
+                match score_provider.get_score(&agent, data, &mut rng) {
+                    Ok(score) => self.insert(score, agent),
+                    Err(_) => self.record_rejection(RejectionReason::ScoreError, &agent)
+                }
+            }
+        }
+    }
+
+    /// Adds offspring bred from pairs of agents chosen at random from the higher scored half,
+    /// given to breed with the higher scored of each pair first, then scored and inserted.
+    pub(crate) fn breed_from_upper_half<Data, SP, F>(&mut self, offspring: usize, data: &Data, score_provider: &mut SP, mut breed: F)
+    where
+    SP: ScoreProvider<Gene, Data> + ?Sized,
+    F: FnMut(&Agent<Gene>, &Agent<Gene>) -> Agent<Gene>
+    {
+        let mut rng = random::rng();
+        let mut children = Vec::with_capacity(offspring);
+        {
+            let parents: Vec<&Agent<Gene>> = self.agents.values().rev()
+                .take(self.agents.len().div_ceil(2))
+                .collect();
+            if parents.is_empty() {
                 return;
             }
-            self.register.insert(agent.get_hash());
+
+            for _ in 0..offspring {
+                let one = rng.gen_range(0, parents.len());
+                let two = rng.gen_range(0, parents.len());
+                // Parents are ordered from the highest score, so the lower index is fitter.
+                children.push(breed(parents[one.min(two)], parents[one.max(two)]));
+            }
+        }
+
+        self.score_and_insert(children, data, score_provider);
+    }
+
+    /// Scores every agent again, such as after the data has changed, moving each to its new score
+    /// key. Agents that can no longer be scored are removed. Ages are kept. If the score provider
+    /// fails, the error is returned and the population is left as it was.
+    pub fn rescore<Data, SP>(&mut self, data: &Data, score_provider: &mut SP) -> Result<(), ScoreError>
+    where
+    Gene: Clone,
+    SP: ScoreProvider<Gene, Data> + ?Sized
+    {
+        let agents = score_provider.evaluate_scores(self.agents.values().cloned().collect(), data)?;
+        self.agents.clear();
+        self.extremes = None;
+        self.register.clear();
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.clear();
+        }
+
+        let mut rng = random::rng();
+        for agent in agents {
+            if let Ok(score) = score_provider.get_score(&agent, data, &mut rng) {
+                let birth = agent.get_birth();
+                if let Some(key) = self.insert_ranked(score, agent) {
+                    self.agents.get_mut(&key).unwrap().set_birth(birth);
+                }
+            }
         }
-        self.agents.insert(score, agent);
+        Ok(())
     }
 
     pub fn remove(&mut self, score: Score) -> Option<Agent<Gene>> where Gene: Clone {
         let agent = self.agents.remove(&score);
+        if agent.is_some() {
+            self.note_removed(score);
+        }
         if self.unique_agents && agent.is_some() {
-            self.register.remove(&agent.clone().unwrap().get_hash());
+            self.register.remove(agent.clone().unwrap().get_hash());
+        }
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.remove(&score);
         }
         agent
     }
 
+    /// Puts back an agent just taken out with remove, at the free key nearest its old one. Its
+    /// birth is kept, and the acceptance policy is not consulted, as it was already accepted.
+    pub(crate) fn restore(&mut self, score: Score, agent: Agent<Gene>) {
+        let key = self.make_room(score);
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.insert(key, (similarity.simhash)(&agent));
+        }
+        if self.unique_agents {
+            self.register.insert(agent.get_hash());
+        }
+        self.agents.insert(key, agent);
+        self.note_inserted(key);
+    }
+
+    /// Removes the agent with the score, keeping its genes' buffer for reuse.
+    pub(crate) fn discard(&mut self, score: Score) where Gene: Clone {
+        if let Some(agent) = self.remove(score) {
+            self.buffers.recycle(agent);
+        }
+    }
+
+    /// Sets how many buffers of removed agents' genes are kept for operations to reuse when
+    /// breeding children, which saves allocating for every child. Defaults to zero.
+    pub fn set_buffer_pool_capacity(&mut self, capacity: usize) {
+        self.buffers.set_capacity(capacity);
+    }
+
+    /// Takes the buffer pool, leaving an empty one with the same capacity, so that agents can be
+    /// copied into pooled buffers while the population is borrowed. See restore_buffer_pool.
+    pub(crate) fn take_buffer_pool(&mut self) -> BufferPool<Gene> {
+        let capacity = self.buffers.get_capacity();
+        std::mem::replace(&mut self.buffers, BufferPool::new(capacity))
+    }
+
+    pub(crate) fn restore_buffer_pool(&mut self, buffers: BufferPool<Gene>) {
+        self.buffers = buffers;
+    }
+
     pub fn get(&self, score: Score) -> Option<&Agent<Gene>> {
         self.agents.get(&score)
     }
@@ -119,22 +766,126 @@ impl <Gene> Population <Gene> {
         self.agents.len()
     }
 
+    /// Splits the agents into the number of score quantiles, lowest scores first. Bands differ in
+    /// size by at most one agent.
+    pub fn bands(&self, bands: usize) -> Vec<Vec<(Score, &Agent<Gene>)>> {
+        let agents: Vec<(Score, &Agent<Gene>)> = self.agents.iter().map(|(score, agent)| (*score, agent)).collect();
+        (0..bands.max(1)).map(|band| agents[band_bounds(agents.len(), band, bands)].to_vec()).collect()
+    }
+
+    /// Counts the agents in the number of ranges of score keys, as near equal as they can be, from
+    /// the lowest score key to the highest, lowest first. Where there are more buckets than keys
+    /// in that range, it is widened to give every bucket one key. Most agents in one bucket shows
+    /// the scores are compressed, as when the offset is too large for the spread of scores. Empty
+    /// for an empty population.
+    pub fn score_histogram(&self, buckets: usize) -> Vec<ScoreBucket> {
+        let (lowest, highest) = match (self.agents.keys().next(), self.agents.keys().next_back()) {
+            (Some(lowest), Some(highest)) => (*lowest, *highest),
+            _ => return Vec::new()
+        };
+        // Edges are found in u128, as the range may span every key.
+        let buckets = buckets.max(1) as u128;
+        let span = (u128::from(highest - lowest) + 1).max(buckets);
+        let start = u128::from(lowest).min(u128::from(Score::MAX) + 1 - span);
+        let edge = |bucket: u128| start + bucket * span / buckets;
+        let mut histogram: Vec<ScoreBucket> = (0..buckets)
+            .map(|bucket| ScoreBucket { low: edge(bucket) as Score, high: (edge(bucket + 1) - 1) as Score, count: 0 })
+            .collect();
+        for score in self.agents.keys() {
+            // The bucket whose range holds the key.
+            let offset = u128::from(*score) - start;
+            let bucket = ((offset + 1) * buckets).div_ceil(span) - 1;
+            histogram[bucket as usize].count += 1;
+        }
+        histogram
+    }
+
     pub fn cull_all_below(&mut self, score: Score) {
-        self.agents = self.agents.split_off(&score);
-        if self.unique_agents {
-            self.register.clear();
-            for (_, agent) in &self.agents {
-                self.register.insert(agent.get_hash());
-            }
+        let kept = self.agents.split_off(&score);
+        let culled = std::mem::replace(&mut self.agents, kept);
+        self.unregister_culled(culled);
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes = similarity.simhashes.split_off(&score);
         }
     }
 
     pub fn cull_all_above(&mut self, score: Score) {
-        self.agents.split_off(&score);
-        if self.unique_agents {
-            self.register.clear();
-            for (_, agent) in &self.agents {
-                self.register.insert(agent.get_hash());
+        let culled = self.agents.split_off(&score);
+        self.unregister_culled(culled);
+        if let Some(ref mut similarity) = self.similarity {
+            similarity.simhashes.split_off(&score);
+        }
+    }
+
+    /// Removes culled agents' hashes from the register, rather than rebuilding it from the agents
+    /// kept, and keeps their buffers for reuse.
+    fn unregister_culled(&mut self, culled: BTreeMap<Score, Agent<Gene>>) {
+        self.refresh_extremes();
+        for (_, agent) in culled {
+            if self.unique_agents {
+                self.register.remove(agent.get_hash());
+            }
+            self.buffers.recycle(agent);
+        }
+    }
+
+    /// Limits how many generations agents live for. When the generation advances, agents that have
+    /// lived for the lifespan are removed regardless of score, except the protected_elites agents
+    /// with the highest scores.
+    pub fn set_lifespan(&mut self, lifespan: u64, protected_elites: usize) {
+        self.lifespan = Some(lifespan);
+        self.protected_elites = protected_elites;
+    }
+
+    /// Limits the number of agents. When the generation advances, the lowest scored agents beyond
+    /// the size are removed, so a size of zero removes every agent.
+    pub fn set_max_size(&mut self, size: usize) {
+        self.max_size = Some(size);
+    }
+
+    /// Sets a policy that every agent must pass before it is inserted.
+    pub fn set_acceptance_policy<P>(&mut self, policy: P)
+    where
+    P: AcceptancePolicy<Gene> + 'static
+    {
+        self.acceptance_policy = Some(Arc::new(policy));
+    }
+
+    pub(crate) fn set_shared_acceptance_policy(&mut self, policy: Option<Arc<dyn AcceptancePolicy<Gene>>>) {
+        self.acceptance_policy = policy;
+    }
+
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The number of generations since the agent was inserted.
+    pub fn get_age(&self, agent: &Agent<Gene>) -> u64 {
+        self.generation.saturating_sub(agent.get_birth())
+    }
+
+    /// Moves on to the next generation, removing agents that have reached their lifespan and
+    /// trimming the population to its maximum size.
+    /// run_iterations calls this at the end of each iteration.
+    pub fn advance_generation(&mut self) where Gene: Clone {
+        self.generation += 1;
+        if let Some(lifespan) = self.lifespan {
+            let expired: Vec<Score> = self.agents.iter().rev()
+                .skip(self.protected_elites)
+                .filter(|(_, agent)| self.generation.saturating_sub(agent.get_birth()) >= lifespan)
+                .map(|(score, _)| *score)
+                .collect();
+            for score in expired {
+                self.discard(score);
+            }
+        }
+
+        if let Some(max_size) = self.max_size {
+            if self.agents.len() > max_size {
+                match self.agents.keys().nth(self.agents.len() - max_size) {
+                    Some(&lowest_kept) => self.cull_all_below(lowest_kept),
+                    None => self.cull_all_above(0)
+                }
             }
         }
     }
@@ -144,26 +895,235 @@ impl <Gene> Population <Gene> {
     }
 
     pub fn will_accept(&self, agent: &Agent<Gene>) -> bool {
+        if self.unique_agents && self.register.contains(self.hash_of(agent)) {
+            return false;
+        }
+        match self.similarity {
+            Some(ref similarity) => !similarity.has_near_duplicate(agent),
+            None => true
+        }
+    }
+
+    /// Sets the number of genes every agent must have, checked by validate.
+    pub fn set_strict_gene_length(&mut self, length: usize) {
+        self.gene_length = Some(length);
+    }
+
+    /// Sets whether validate accepts an empty population. Defaults to false.
+    pub fn set_allow_empty(&mut self, allow_empty: bool) {
+        self.allow_empty = allow_empty;
+    }
+
+    /// Checks the invariants the population relies on: a unique population's register holds
+    /// exactly the hashes of its agents, every agent's cached hash matches its genes, the
+    /// near-duplicate register matches the agents, agents have the strict gene length if one is
+    /// set, and the population is not empty unless allowed. With the debug-validate feature, this
+    /// is checked after every operation in run_iterations, to catch operations that break them.
+    pub fn validate(&self) -> Result<(), InvariantError> where Gene: Hash {
+        if self.agents.is_empty() && !self.allow_empty {
+            return Err(InvariantError::new("the population is empty"));
+        }
+        for (score, agent) in &self.agents {
+            if agent.get_hasher().hash(agent.get_genes()) != agent.get_hash() {
+                return Err(InvariantError::new(&format!("the agent at {} has a stale hash", score)));
+            }
+            if let Some(length) = self.gene_length {
+                if agent.get_genes().len() != length {
+                    return Err(InvariantError::new(&format!("the agent at {} has {} genes rather than {}", score, agent.get_genes().len(), length)));
+                }
+            }
+        }
         if self.unique_agents {
-            return !self.register.contains(&agent.get_hash());
+            let hashes: HashSet<u64> = self.agents.values().map(|agent| agent.get_hash()).collect();
+            if hashes.len() != self.agents.len() {
+                return Err(InvariantError::new("the unique population holds duplicate agents"));
+            }
+            if hashes.len() != self.register.len() || hashes.iter().any(|hash| !self.register.contains(*hash)) {
+                return Err(InvariantError::new("the register does not match the agents"));
+            }
+        }
+        let extremes = self.agents.keys().next().map(|worst| (*worst, *self.agents.keys().next_back().unwrap()));
+        if extremes != self.extremes {
+            return Err(InvariantError::new("the cached best and worst scores do not match the agents"));
+        }
+        if let Some(ref similarity) = self.similarity {
+            let matches = similarity.simhashes.len() == self.agents.len()
+                && self.agents.iter().all(|(score, agent)| similarity.simhashes.get(score) == Some(&(similarity.simhash)(agent)));
+            if !matches {
+                return Err(InvariantError::new("the near-duplicate register does not match the agents"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets whether a unique population moves an agent to the key of a duplicate inserted with a
+    /// higher score, rather than discarding the duplicate and its better placement. Any agent
+    /// already at that key is replaced, unless inserted with insert_ranked. Defaults to false.
+    pub fn set_promote_duplicates(&mut self, promote: bool) {
+        self.promote_duplicates = promote;
+    }
+
+    /// Sets how a unique population registers the hashes of its agents, such as a sharded register
+    /// with a filter for a very large population. The agents already in the population are
+    /// registered with it, on the register's threads, as they are whenever it is rebuilt.
+    /// Defaults to Register::new.
+    pub fn set_register(&mut self, register: Register) {
+        self.register = register;
+        self.register.clear();
+        if self.unique_agents {
+            let hashes: Vec<u64> = self.agents.values().map(|agent| agent.get_hash()).collect();
+            self.register.insert_all(&hashes);
+        }
+    }
+
+    /// Sets how agents hash their genes. The agents in the population, and those inserted later,
+    /// are rehashed with it, and agents bred from them keep it. Lineage already recorded keeps the
+    /// old hashes, so set it before recording lineage. Defaults to GenomeHasher::Std.
+    ///
+    /// ```
+    /// use xu::agent::{Agent, GenomeHasher};
+    /// use xu::population::Population;
+    ///
+    /// let mut population = Population::new_empty(true);
+    /// population.set_genome_hasher(GenomeHasher::Fx);
+    /// population.insert(1, Agent::from_genes(vec![1u8, 2, 3]));
+    /// assert_eq!(GenomeHasher::Fx.hash(&vec![1u8, 2, 3]), population.get(1).unwrap().get_hash());
+    /// ```
+    pub fn set_genome_hasher(&mut self, hasher: GenomeHasher) where Gene: Hash {
+        self.hasher = Some((hasher, Agent::hash_with));
+        if self.agents.values().any(|agent| agent.get_hasher() != hasher) {
+            for agent in self.agents.values_mut() {
+                agent.set_hasher(hasher);
+            }
+            let register = mem::take(&mut self.register);
+            self.set_register(register);
         }
-        true
+    }
+
+    /// Counts the agents offered to the population but not added, by reason, keeping the first
+    /// examples_per_reason of each. Agents a score provider drops from a batch are kept as examples
+    /// only if among the first of the batch. See get_rejection_stats. Rejections are not recorded
+    /// by default.
+    pub fn set_record_rejections(&mut self, examples_per_reason: usize) where Gene: Clone {
+        self.rejections = Some(RejectionStats::new(examples_per_reason));
+    }
+
+    pub fn get_rejection_stats(&self) -> Option<&RejectionStats<Gene>> {
+        self.rejections.as_ref()
+    }
+
+    /// Sets whether how every agent that joins was made is recorded, so that how an agent was
+    /// built can be traced with trace. Agents already in the population, and agents inserted other
+    /// than by an operation, are recorded as created. Defaults to false. Records are kept until
+    /// prune_lineage.
+    pub fn set_record_lineage(&mut self, record: bool) {
+        if !record {
+            self.lineage = None;
+        } else if self.lineage.is_none() {
+            let mut lineage = Lineage::new();
+            for agent in self.agents.values() {
+                lineage.record(agent.get_hash(), Ancestry::created(agent.get_birth()));
+            }
+            self.lineage = Some(lineage);
+        }
+    }
+
+    pub fn get_lineage(&self) -> Option<&Lineage> {
+        self.lineage.as_ref()
+    }
+
+    /// The mutations and crossovers that built the agent with the score key, if lineage is
+    /// recorded. See Lineage::trace.
+    pub fn trace(&self, score: Score) -> Option<Trace> {
+        let agent = self.agents.get(&score)?;
+        self.lineage.as_ref().map(|lineage| lineage.trace(agent.get_hash()))
+    }
+
+    /// Forgets the lineage of genomes that are neither in the population nor ancestors of those
+    /// that are.
+    pub fn prune_lineage(&mut self) {
+        if let Some(ref mut lineage) = self.lineage {
+            lineage.prune(self.agents.values().map(|agent| agent.get_hash()));
+        }
+    }
+
+    /// Notes the operation that bred the agent and its parents' genome hashes, taken as it is
+    /// bred, to be recorded if it is inserted.
+    pub(crate) fn note_ancestry(&mut self, agent: &Agent<Gene>, operation: OperationType, parents: Vec<u64>) {
+        if let Some(ref mut lineage) = self.lineage {
+            let provenance = agent.get_provenance().cloned();
+            lineage.expect(agent.get_hash(), Ancestry { generation: self.generation, operation: Some(operation), parents, provenance });
+        }
+    }
+
+    /// Forgets the ancestries noted for agents that were not inserted.
+    pub(crate) fn forget_noted_ancestries(&mut self) {
+        if let Some(ref mut lineage) = self.lineage {
+            lineage.clear_expected();
+        }
+    }
+
+    fn record_lineage(&mut self, agent: &Agent<Gene>) {
+        if let Some(ref mut lineage) = self.lineage {
+            lineage.record_joined(agent.get_hash(), self.generation);
+        }
+    }
+
+    /// Rejects agents whose SimHash differs in no more than the number of bits from that of an
+    /// agent already in the population, as near-clones of it. Agents already in the population
+    /// are kept. The check compares against every agent, so suits long genomes, where exact
+    /// uniqueness misses near-clones, more than large populations.
+    pub fn set_near_duplicate_threshold(&mut self, bits: u32)
+    where
+    Gene: Hash
+    {
+        let simhashes = self.agents.iter().map(|(score, agent)| (*score, agent::simhash(agent))).collect();
+        self.similarity = Some(SimilarityRegister { threshold: bits, simhash: agent::simhash, simhashes });
+    }
+
+    /// The k highest scored agents with their score keys, highest first, without collecting every
+    /// key.
+    pub fn top_k(&self, k: usize) -> Vec<(Score, &Agent<Gene>)> {
+        self.agents.iter().rev().take(k).map(|(score, agent)| (*score, agent)).collect()
+    }
+
+    /// The k lowest scored agents with their score keys, lowest first.
+    pub fn bottom_k(&self, k: usize) -> Vec<(Score, &Agent<Gene>)> {
+        self.agents.iter().take(k).map(|(score, agent)| (*score, agent)).collect()
     }
 
     pub fn get_scores(&self) -> Vec<Score> {
         self.agents.keys().map(|k| *k).collect()
     }
 
+    /// Finds the agent with the highest fitness according to the score provider, ignoring the
+    /// noise in the score keys. Agents that cannot be scored are skipped.
+    pub fn true_best<Data, SP>(&self, score_provider: &mut SP, data: &Data) -> Option<(Score, &Agent<Gene>)>
+    where
+    SP: ScoreProvider<Gene, Data>
+    {
+        let mut best: Option<(Score, &Agent<Gene>)> = None;
+        for agent in self.agents.values() {
+            if let Ok(score) = score_provider.true_score(agent, data) {
+                match best {
+                    Some((highest, _)) if highest >= score => (),
+                    _ => best = Some((score, agent))
+                }
+            }
+        }
+        best
+    }
+
     pub fn get_random_score(&self) -> Score {
-        let mut rng = rand::thread_rng();
-        self.get_scores()[rng.gen_range(0, self.len())]
+        let mut rng = random::rng();
+        *self.agents.keys().nth(rng.gen_range(0, self.len())).unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::fitness::{GeneralScoreProvider, ScoreError};
+    use super::super::fitness::GeneralScoreProvider;
 
     #[test]
     fn new_empty() {
@@ -178,6 +1138,14 @@ mod tests {
         Ok(score)
     }
 
+    #[test]
+    fn new_takes_trait_objects() {
+        let score_provider: &mut dyn ScoreProvider<u8, u8> = &mut GeneralScoreProvider::new(get_score_index, 0);
+        let population = Population::new(5, 6, true, &0, score_provider);
+        assert_eq!(5, population.len());
+        assert!(population.get_agents().iter().all(|(score, agent)| *score <= agent.get_genes()[0] as Score + 5));
+    }
+
     #[test]
     fn new_with_false_unique() {
         let mut population = Population::new(5, 6, false, &0, &mut GeneralScoreProvider::new(get_score_index, 25));
@@ -236,6 +1204,39 @@ mod tests {
         assert_eq!(5, population.get_scores().len());
     }
 
+    #[test]
+    fn true_best_ignores_score_keys() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 25);
+        let mut population = Population::new_empty(false);
+        population.insert(100, Agent::from_genes(vec![3u8]));
+        population.insert(50, Agent::from_genes(vec![7u8]));
+
+        let (score, agent) = population.true_best(&mut score_provider, &0).unwrap();
+        assert_eq!(7, score);
+        assert_eq!(&vec![7u8], agent.get_genes());
+    }
+
+    #[test]
+    fn lifespan_removes_old_agents_except_elites() {
+        let mut population = Population::new_empty(true);
+        population.set_lifespan(2, 1);
+        population.insert(10, Agent::from_genes(vec![1u8]));
+        population.insert(20, Agent::from_genes(vec![2u8]));
+        population.insert(30, Agent::from_genes(vec![3u8]));
+        population.advance_generation();
+        population.insert(5, Agent::from_genes(vec![4u8]));
+
+        assert_eq!(1, population.get_age(population.get(10).unwrap()));
+        assert_eq!(0, population.get_age(population.get(5).unwrap()));
+
+        population.advance_generation();
+        assert_eq!(vec![5, 30], population.get_scores());
+        assert!(population.will_accept(&Agent::from_genes(vec![1u8])));
+
+        population.advance_generation();
+        assert_eq!(vec![30], population.get_scores());
+    }
+
     #[test]
     fn cull_all_below() {
         let mut population = Population::new(5, 6, true, &0, &mut GeneralScoreProvider::new(get_score_index, 25));
@@ -286,4 +1287,403 @@ mod tests {
         assert_eq!(4, population.get_agents().len());
         assert_eq!(4, population.get_scores().len());
     }
+
+    #[test]
+    fn acceptance_policy_filters_inserts() {
+        let mut population = Population::new_empty(false);
+        population.set_acceptance_policy(RejectWorseThanParents);
+        population.insert_child(5, Agent::from_genes(vec![1u8]), &[6, 8]);
+        population.insert_child(7, Agent::from_genes(vec![2u8]), &[6, 8]);
+        population.insert(1, Agent::from_genes(vec![3u8]));
+        assert_eq!(vec![1, 7], population.get_scores());
+
+        // Only even genes are allowed.
+        population.set_acceptance_policy(|_score: Score, agent: &Agent<u8>, _parents: &[Score], _population: &Population<u8>| {
+            agent.get_genes()[0] % 2 == 0
+        });
+        population.insert(10, Agent::from_genes(vec![5u8]));
+        population.insert(11, Agent::from_genes(vec![4u8]));
+        assert_eq!(vec![1, 7, 11], population.get_scores());
+    }
+
+    #[test]
+    fn boltzmann_acceptance_cools() {
+        let policy = BoltzmannAcceptance::new(100.0, 0.5);
+        assert_eq!(25.0, policy.temperature(2));
+        assert_eq!(1.0, policy.probability(0, 5));
+        assert!((policy.probability(100, 0) - (-1.0f64).exp()).abs() < 1e-9);
+        assert!(policy.probability(100, 10) < 1e-30);
+
+        let mut population = Population::new_empty(false);
+        population.set_acceptance_policy(policy);
+        for _ in 0..10 {
+            population.advance_generation();
+        }
+        population.insert_child(5, Agent::from_genes(vec![1u8]), &[4, 200]);
+        population.insert_child(300, Agent::from_genes(vec![2u8]), &[4, 200]);
+        population.insert(1, Agent::from_genes(vec![3u8]));
+        assert_eq!(vec![1, 300], population.get_scores());
+    }
+
+    #[test]
+    fn score_histogram_counts_ranges() {
+        let mut population = Population::new_empty(false);
+        assert!(population.score_histogram(3).is_empty());
+        for score in &[10, 11, 12, 19, 40] {
+            population.insert(*score, Agent::from_genes(vec![*score as u8]));
+        }
+
+        let histogram = population.score_histogram(3);
+        assert_eq!(ScoreBucket { low: 10, high: 19, count: 4 }, histogram[0]);
+        assert_eq!(ScoreBucket { low: 20, high: 29, count: 0 }, histogram[1]);
+        assert_eq!(ScoreBucket { low: 30, high: 40, count: 1 }, histogram[2]);
+
+        // Ten keys in ten buckets, one each.
+        let mut ten = Population::new_empty(false);
+        for score in 0..10 {
+            ten.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        assert!(ten.score_histogram(10).iter().enumerate().all(|(bucket, counted)| *counted == ScoreBucket { low: bucket as Score, high: bucket as Score, count: 1 }));
+
+        // Fewer keys than buckets widens the range.
+        let mut single = Population::new_empty(false);
+        single.insert(5, Agent::from_genes(vec![5u8]));
+        assert_eq!(vec![(5, 5, 1), (6, 6, 0), (7, 7, 0), (8, 8, 0)], single.score_histogram(4).iter().map(|bucket| (bucket.low, bucket.high, bucket.count)).collect::<Vec<_>>());
+        let mut top = Population::new_empty(false);
+        top.insert(Score::MAX, Agent::from_genes(vec![1u8]));
+        assert_eq!(ScoreBucket { low: Score::MAX, high: Score::MAX, count: 1 }, top.score_histogram(2)[1]);
+
+        // Every key.
+        let mut extremes = Population::new_empty(false);
+        extremes.insert(0, Agent::from_genes(vec![0u8]));
+        extremes.insert(Score::MAX, Agent::from_genes(vec![1u8]));
+        let histogram = extremes.score_histogram(2);
+        assert_eq!(ScoreBucket { low: 0, high: Score::MAX / 2, count: 1 }, histogram[0]);
+        assert_eq!(ScoreBucket { low: Score::MAX / 2 + 1, high: Score::MAX, count: 1 }, histogram[1]);
+    }
+
+    #[test]
+    fn top_and_bottom_k() {
+        let mut population = Population::new_empty(false);
+        for score in 1..6 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        let top: Vec<Score> = population.top_k(2).iter().map(|(score, _)| *score).collect();
+        let bottom: Vec<Score> = population.bottom_k(9).iter().map(|(score, _)| *score).collect();
+        assert_eq!(vec![5, 4], top);
+        assert_eq!(vec![1, 2, 3, 4, 5], bottom);
+        assert_eq!(&vec![5u8], population.top_k(1)[0].1.get_genes());
+    }
+
+    #[test]
+    fn best_and_worst_follow_changes() {
+        let mut population = Population::new_empty(true);
+        assert_eq!(None, population.best_score());
+        for score in 1..6 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        assert_eq!((Some(5), Some(1)), (population.best_score(), population.worst_score()));
+
+        population.remove(5);
+        population.remove(3);
+        assert_eq!((Some(4), Some(1)), (population.best_score(), population.worst_score()));
+        population.insert_ranked(1, Agent::from_genes(vec![9u8]));
+        assert_eq!(Some(0), population.worst_score());
+        population.cull_all_below(2);
+        assert_eq!((Some(4), Some(2)), (population.best_score(), population.worst_score()));
+        population.cull_all_above(3);
+        assert_eq!((Some(2), Some(2)), (population.best_score(), population.worst_score()));
+        population.set_allow_empty(true);
+        population.remove(2);
+        assert_eq!(None, population.best_score());
+        assert!(population.validate().is_ok());
+    }
+
+    #[test]
+    fn scored_iter_loads_like_inserts() {
+        let scored = vec![(vec![1u8], 10), (vec![2u8], 20), (vec![1u8], 30), (vec![3u8], 20), (vec![4u8], 5)];
+        let population = Population::from_scored_iter(scored.clone(), true);
+        assert_eq!(vec![5, 20, 30], population.get_scores());
+        assert_eq!(vec![3u8], *population.get_agents()[&20].get_genes());
+        assert_eq!((Some(30), Some(5)), (population.best_score(), population.worst_score()));
+        assert!(population.validate().is_ok());
+
+        let mut inserted = Population::new_empty(false);
+        for (genes, score) in scored.clone() {
+            inserted.insert(score, Agent::from_genes(genes));
+        }
+        let loaded = Population::from_scored_iter(scored, false);
+        assert_eq!(inserted.get_scores(), loaded.get_scores());
+        assert!(loaded.validate().is_ok());
+    }
+
+    #[test]
+    fn lineage_keeps_parents_noted_when_bred() {
+        let mut population = Population::new_empty(false);
+        let first = Agent::from_genes(vec![1u8, 1]);
+        let second = Agent::from_genes(vec![2u8, 2]);
+        population.insert(1, first.clone());
+        population.set_record_lineage(true);
+        population.advance_generation();
+        population.insert(2, second.clone());
+
+        let child = Agent::from_genes(vec![1u8, 2]);
+        population.note_ancestry(&child, OperationType::Crossover, vec![first.get_hash(), second.get_hash()]);
+        // An earlier child takes a parent's key before this one is inserted.
+        population.insert(1, Agent::from_genes(vec![9u8, 9]));
+        population.insert_child(3, child, &[1, 2]);
+        population.forget_noted_ancestries();
+
+        let trace = population.trace(3).unwrap();
+        let steps: Vec<(u64, Option<OperationType>)> = trace.steps.iter().map(|step| (step.ancestry.generation, step.ancestry.operation)).collect();
+        assert_eq!(vec![(0, None), (1, None), (1, Some(OperationType::Crossover))], steps);
+        assert_eq!(vec![first.get_hash(), second.get_hash()], trace.steps[2].ancestry.parents);
+
+        // Notes for children that were not inserted are forgotten.
+        let rejected = Agent::from_genes(vec![3u8, 3]);
+        population.note_ancestry(&rejected, OperationType::Mutate, vec![first.get_hash()]);
+        population.forget_noted_ancestries();
+        population.insert(4, rejected);
+        assert_eq!(None, population.trace(4).unwrap().steps[0].ancestry.operation);
+
+        population.remove(2);
+        population.prune_lineage();
+        assert_eq!(5, population.get_lineage().unwrap().len());
+        population.set_record_lineage(false);
+        assert!(population.trace(3).is_none());
+    }
+
+    #[test]
+    fn bands_split_by_score() {
+        let mut population = Population::new_empty(false);
+        for score in 0..10 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        let bands = population.bands(4);
+        let scores: Vec<Vec<Score>> = bands.iter().map(|band| band.iter().map(|(score, _)| *score).collect()).collect();
+        assert_eq!(vec![vec![0, 1], vec![2, 3, 4], vec![5, 6], vec![7, 8, 9]], scores);
+    }
+
+    #[test]
+    fn near_duplicates_rejected() {
+        let genes: Vec<u16> = (0..200).collect();
+        let mut similar = genes.clone();
+        similar[0] = 999;
+
+        let mut population = Population::new_empty(false);
+        population.insert(1, Agent::from_genes(genes));
+        population.set_near_duplicate_threshold(8);
+        assert!(!population.will_accept(&Agent::from_genes(similar.clone())));
+        population.insert(2, Agent::from_genes(similar.clone()));
+        population.insert(3, Agent::from_genes((500..700).collect()));
+        assert_eq!(vec![1, 3], population.get_scores());
+
+        population.remove(1);
+        assert!(population.will_accept(&Agent::from_genes(similar)));
+    }
+
+    // Fails for agents whose first gene is zero.
+    fn score_nonzero(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        match agent.get_genes()[0] {
+            0 => Err(ScoreError::new("zero")),
+            gene => Ok(gene as Score)
+        }
+    }
+
+    #[test]
+    fn rejections_recorded_by_reason() {
+        let mut population = Population::new_empty(true);
+        population.set_record_rejections(1);
+        population.set_acceptance_policy(|score: Score, _agent: &Agent<u8>, _parents: &[Score], _population: &Population<u8>| score < 100);
+        population.insert(1, Agent::from_genes(vec![1u8, 1]));
+        population.insert(2, Agent::from_genes(vec![1u8, 1]));
+        population.insert(3, Agent::from_genes(vec![1u8, 1]));
+        population.insert(100, Agent::from_genes(vec![2u8, 2]));
+        let mut score_provider = GeneralScoreProvider::new(score_nonzero, 0);
+        population.score_and_insert(vec![Agent::from_genes(vec![0u8, 3]), Agent::from_genes(vec![4u8, 4])], &0, &mut score_provider);
+
+        let stats = population.get_rejection_stats().unwrap();
+        assert_eq!(2, stats.count(RejectionReason::Duplicate));
+        assert_eq!(1, stats.examples(RejectionReason::Duplicate).len());
+        assert_eq!(1, stats.count(RejectionReason::Policy));
+        assert_eq!(&vec![0u8, 3], stats.examples(RejectionReason::ScoreError)[0].get_genes());
+        assert_eq!(0, stats.count(RejectionReason::NearDuplicate));
+        assert_eq!(4, stats.total());
+        assert_eq!(vec![1, 4], population.get_scores());
+
+        // With the example kept, later score errors are only counted.
+        population.score_and_insert(vec![Agent::from_genes(vec![0u8, 5]), Agent::from_genes(vec![0u8, 6])], &0, &mut score_provider);
+        let stats = population.get_rejection_stats().unwrap();
+        assert_eq!(3, stats.count(RejectionReason::ScoreError));
+        assert_eq!(1, stats.examples(RejectionReason::ScoreError).len());
+    }
+
+    // A score provider whose service is down.
+    struct Unavailable;
+
+    impl ScoreProvider<u8, u8> for Unavailable {
+        fn evaluate_scores(&mut self, _agents: Vec<Agent<u8>>, _data: &u8) -> Result<Vec<Agent<u8>>, ScoreError> {
+            Err(ScoreError::new("unavailable"))
+        }
+
+        fn get_score(&mut self, _agent: &Agent<u8>, _data: &u8, _rng: &mut random::ThreadGenerator) -> Result<Score, ScoreError> {
+            Err(ScoreError::new("unavailable"))
+        }
+
+        fn true_score(&mut self, _agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+            Err(ScoreError::new("unavailable"))
+        }
+    }
+
+    #[test]
+    fn genome_hasher_rehashes_members_and_newcomers() {
+        let mut population = Population::new_empty(true);
+        population.insert(1, Agent::from_genes(vec![1u8, 2]));
+        population.set_genome_hasher(GenomeHasher::Fx);
+        assert_eq!(GenomeHasher::Fx.hash(&vec![1u8, 2]), population.get(1).unwrap().get_hash());
+
+        // A newcomer hashed the standard way is still recognised as a duplicate.
+        assert!(!population.will_accept(&Agent::from_genes(vec![1u8, 2])));
+        population.insert(2, Agent::from_genes(vec![1u8, 2]));
+        assert!(!population.contains_score(2));
+        population.insert(3, Agent::from_genes(vec![3u8, 4]));
+        assert_eq!(GenomeHasher::Fx, population.get(3).unwrap().get_hasher());
+        assert!(population.validate().is_ok());
+    }
+
+    #[test]
+    fn try_new_returns_the_score_providers_error() {
+        assert!(Population::<u8>::try_new(5, 2, false, &0, &mut Unavailable).is_err());
+        assert_eq!(0, Population::<u8>::new(5, 2, false, &0, &mut Unavailable).len());
+        let mut score_provider = GeneralScoreProvider::new(|_: &Agent<u8>, _: &u8| Ok(1), 0);
+        assert_eq!(5, Population::try_new(5, 2, false, &0, &mut score_provider).unwrap().len());
+    }
+
+    #[test]
+    fn failed_rescore_keeps_population() {
+        let mut population = Population::new_empty(false);
+        population.insert(9, Agent::from_genes(vec![0u8]));
+        population.insert(5, Agent::from_genes(vec![5u8]));
+        assert!(population.rescore(&0, &mut Unavailable).is_err());
+        assert_eq!(vec![5, 9], population.get_scores());
+
+        assert!(population.rescore(&0, &mut GeneralScoreProvider::new(score_nonzero, 0)).is_ok());
+        assert_eq!(vec![5], population.get_scores());
+    }
+
+    #[test]
+    fn insert_ranked_keeps_every_agent_in_order() {
+        let mut population = Population::new_empty(false);
+        population.insert(5, Agent::from_genes(vec![5u8]));
+        population.insert(4, Agent::from_genes(vec![4u8]));
+        population.insert(2, Agent::from_genes(vec![2u8]));
+
+        // Ties take the nearest free key, leaving every other agent where it was.
+        assert_eq!(Some(6), population.insert_ranked(5, Agent::from_genes(vec![6u8])));
+        assert_eq!(Some(3), population.insert_ranked(4, Agent::from_genes(vec![3u8])));
+        assert_eq!(Some(1), population.insert_ranked(2, Agent::from_genes(vec![1u8])));
+        let keys: Vec<(Score, u8)> = population.get_agents().iter().map(|(key, agent)| (*key, agent.get_genes()[0])).collect();
+        assert_eq!(vec![(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)], keys);
+
+        // At either end of the range, the free key is on the other side.
+        let mut population = Population::new_empty(false);
+        population.insert(0, Agent::from_genes(vec![0u8]));
+        assert_eq!(Some(1), population.insert_ranked(0, Agent::from_genes(vec![1u8])));
+        population.insert(Score::MAX, Agent::from_genes(vec![2u8]));
+        assert_eq!(Some(Score::MAX - 1), population.insert_ranked(Score::MAX, Agent::from_genes(vec![3u8])));
+        assert_eq!(Some(Score::MAX - 2), population.insert_ranked(Score::MAX, Agent::from_genes(vec![4u8])));
+        assert_eq!(5, population.len());
+    }
+
+    #[test]
+    fn better_duplicate_moves_existing_agent() {
+        let mut population = Population::new_empty(true);
+        population.set_promote_duplicates(true);
+        population.insert(3, Agent::from_genes(vec![1u8]));
+        population.advance_generation();
+        population.insert(7, Agent::from_genes(vec![2u8]));
+
+        // A lower copy is ignored, a higher one moves the agent, displacing any agent at the key.
+        population.insert(1, Agent::from_genes(vec![1u8]));
+        assert_eq!(vec![3, 7], population.get_scores());
+        population.insert(7, Agent::from_genes(vec![1u8]));
+        assert_eq!(vec![7], population.get_scores());
+        assert_eq!(&vec![1u8], population.get(7).unwrap().get_genes());
+        assert_eq!(0, population.get(7).unwrap().get_birth());
+        assert!(population.will_accept(&Agent::from_genes(vec![2u8])));
+
+        assert_eq!(None, population.insert_ranked(9, Agent::from_genes(vec![1u8])));
+        assert_eq!(vec![9], population.get_scores());
+    }
+
+    #[test]
+    fn culled_buffers_are_kept_for_reuse() {
+        let mut population = Population::new_empty(false);
+        population.set_buffer_pool_capacity(2);
+        for score in 0..5 {
+            population.insert(score, Agent::from_genes(vec![score as u8; 3]));
+        }
+
+        population.cull_all_below(3);
+        assert_eq!(2, population.buffers.len());
+        let mut buffers = population.take_buffer_pool();
+        assert!(population.buffers.is_empty());
+        assert!(buffers.take().capacity() >= 3);
+        population.restore_buffer_pool(buffers);
+        assert_eq!(1, population.buffers.len());
+    }
+
+    #[test]
+    fn validate_finds_broken_invariants() {
+        let mut population: Population<u8> = Population::new_empty(true);
+        assert!(population.validate().is_err());
+        population.set_allow_empty(true);
+        assert_eq!(Ok(()), population.validate());
+
+        population.insert(1, Agent::from_genes(vec![1u8, 2]));
+        population.set_strict_gene_length(3);
+        assert!(population.validate().is_err());
+        population.set_strict_gene_length(2);
+        assert_eq!(Ok(()), population.validate());
+
+        // Replacing an agent in place leaves its hash in the register.
+        population.agents.insert(1, Agent::from_genes(vec![3u8, 4]));
+        assert_eq!("the register does not match the agents", population.validate().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn sharded_register_rejects_duplicates() {
+        let mut population = Population::new_empty(true);
+        for score in 1..20 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        // The agents already in the population are registered on several threads.
+        population.set_register(Register::sharded(8).with_filter(16).with_threads(3));
+        for score in 20..40 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        population.insert(50, Agent::from_genes(vec![1u8]));
+        population.remove(2);
+        population.insert(51, Agent::from_genes(vec![2u8]));
+        assert_eq!(39, population.len());
+        assert!(!population.will_accept(&Agent::from_genes(vec![39u8])));
+        assert_eq!(Ok(()), population.validate());
+    }
+
+    #[test]
+    fn max_size_trims_lowest_scores() {
+        let mut population = Population::new_empty(false);
+        population.set_max_size(3);
+        for score in 0..5 {
+            population.insert(score, Agent::from_genes(vec![score as u8]));
+        }
+        assert_eq!(5, population.len());
+
+        population.advance_generation();
+        assert_eq!(vec![2, 3, 4], population.get_scores());
+
+        population.set_max_size(0);
+        population.advance_generation();
+        assert_eq!(0, population.len());
+    }
 }
@@ -0,0 +1,268 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Independent evolutions in separate processes, for fitness functions that are not thread-safe,
+//! such as those using global state or FFI libraries.
+//!
+//! A `ProcessRunner` starts one worker process per configuration, by default by running the
+//! current executable again with the `XU_PROCESS_WORKER` environment variable set. The program's
+//! main should call `is_worker` first and, in a worker, hand over to `run_worker`, which reads the
+//! configuration, runs the evolution and reports the resulting population.
+//!
+//! The protocol is line based text. The runner writes the configuration to the worker's standard
+//! input and closes it. The worker writes `AGENT <score> <hex>` to standard output for each agent,
+//! where `<hex>` is the genes encoded by the `GeneCodec`, followed by `DONE`.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::Score;
+use super::population::Population;
+use std::env;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+/// Set in the environment of worker processes started by a ProcessRunner with its default command.
+pub const WORKER_VARIABLE: &str = "XU_PROCESS_WORKER";
+
+/// Whether this process was started as a worker by a ProcessRunner.
+pub fn is_worker() -> bool {
+    env::var_os(WORKER_VARIABLE).is_some()
+}
+
+/// Runs the evolution in a worker process: reads the configuration from standard input, passes it
+/// to the run function, and writes the population it returns to standard output.
+pub fn run_worker<Gene, C>(run: fn(&str) -> Population<Gene>, codec: &C) -> io::Result<()>
+where
+C: GeneCodec<Gene>
+{
+    let mut configuration = String::new();
+    io::stdin().read_to_string(&mut configuration)?;
+    let population = run(&configuration);
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    write_population(&mut output, &population, codec)?;
+    output.flush()
+}
+
+fn write_population<Gene, C, W>(output: &mut W, population: &Population<Gene>, codec: &C) -> io::Result<()>
+where
+C: GeneCodec<Gene>,
+W: Write
+{
+    for (score, agent) in population.get_agents() {
+        writeln!(output, "AGENT {} {}", score, fingerprint::to_hex(&fingerprint::encode_genes(agent.get_genes(), codec)))?;
+    }
+    writeln!(output, "DONE")
+}
+
+fn read_agents<Gene, C, R>(input: R, codec: &C) -> io::Result<Vec<(Score, Agent<Gene>)>>
+where
+Gene: Hash,
+C: GeneCodec<Gene>,
+R: BufRead
+{
+    let invalid = |details: &str| io::Error::new(io::ErrorKind::InvalidData, details.to_string());
+    let mut agents = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let mut parts = line.trim().split(' ');
+        match parts.next() {
+            Some("DONE") => return Ok(agents),
+            Some("AGENT") => {
+                let score = parts.next().and_then(|score| score.parse().ok())
+                    .ok_or_else(|| invalid("the worker sent an invalid score"))?;
+                let genes = parts.next().and_then(fingerprint::from_hex)
+                    .and_then(|bytes| fingerprint::decode_genes(&bytes, codec))
+                    .ok_or_else(|| invalid("the worker sent genes that could not be decoded"))?;
+                agents.push((score, Agent::from_genes(genes)));
+            },
+            // Anything else the worker prints is ignored.
+            _ => ()
+        }
+    }
+    Err(invalid("the worker finished without sending DONE"))
+}
+
+/// Starts worker processes, gives each a configuration and merges the populations they return.
+pub struct ProcessRunner <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    program: PathBuf,
+    arguments: Vec<String>,
+    codec: C,
+    unique: bool,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> ProcessRunner <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene>
+{
+    /// Creates a runner that starts workers by running the current executable again, with
+    /// XU_PROCESS_WORKER set.
+    pub fn new(codec: C) -> io::Result<Self> {
+        Ok(Self::with_command(env::current_exe()?, Vec::new(), codec))
+    }
+
+    /// Creates a runner that starts workers with the given program and arguments. XU_PROCESS_WORKER
+    /// is set for them too.
+    pub fn with_command<P: Into<PathBuf>>(program: P, arguments: Vec<String>, codec: C) -> Self {
+        Self {
+            program: program.into(),
+            arguments,
+            codec,
+            unique: false,
+            gene: PhantomData
+        }
+    }
+
+    /// Sets whether the merged population only keeps one agent with each set of genes.
+    pub fn set_unique(&mut self, unique: bool) {
+        self.unique = unique;
+    }
+
+    /// Runs a worker for each configuration at the same time, waits for them all, and merges their
+    /// populations. Agents with the same score key from different workers replace each other.
+    /// Returns an error if a worker cannot be started, fails, or sends an invalid population.
+    pub fn run(&self, configurations: &[String]) -> io::Result<Population<Gene>> {
+        let mut children = Vec::with_capacity(configurations.len());
+        for configuration in configurations {
+            match self.start(configuration) {
+                Ok(child) => children.push(child),
+                Err(error) => {
+                    children.into_iter().for_each(stop);
+                    return Err(error);
+                }
+            }
+        }
+
+        let mut population = Population::new_empty(self.unique);
+        let mut first_error = None;
+        for mut child in children {
+            let result = match child.stdout.take() {
+                Some(stdout) => read_agents(BufReader::new(stdout), &self.codec),
+                None => Err(io::Error::other("the worker's output was not captured"))
+            };
+            if result.is_err() {
+                // A worker whose output was not understood may still be running, or be blocked
+                // writing to a pipe no longer read, so waiting for it could take forever.
+                let _ = child.kill();
+            }
+            let status = match child.wait() {
+                Ok(status) => status,
+                Err(error) => {
+                    stop(child);
+                    first_error.get_or_insert(error);
+                    continue;
+                }
+            };
+            match result {
+                Ok(agents) if status.success() => {
+                    for (score, agent) in agents {
+                        population.insert(score, agent);
+                    }
+                },
+                Ok(_) => { first_error.get_or_insert(io::Error::other(format!("a worker failed with {}", status))); },
+                Err(error) => { first_error.get_or_insert(error); }
+            }
+        }
+
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(population)
+        }
+    }
+
+    fn start(&self, configuration: &str) -> io::Result<Child> {
+        let mut child = Command::new(&self.program)
+            .args(&self.arguments)
+            .env(WORKER_VARIABLE, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        // Dropping standard input closes it, so the worker knows the configuration is complete.
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(error) = stdin.write_all(configuration.as_bytes()) {
+                drop(stdin);
+                stop(child);
+                return Err(error);
+            }
+        }
+        Ok(child)
+    }
+}
+
+/// Kills a worker that is no longer wanted and waits for it, so it is neither left running nor
+/// left behind as a zombie. A worker that has already exited is only reaped.
+fn stop(mut child: Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+
+    #[test]
+    fn population_round_trip() {
+        let mut population = Population::new_empty(false);
+        population.insert(5, Agent::from_genes(vec![1u8, 2]));
+        population.insert(9, Agent::from_genes(vec![3u8]));
+
+        let mut output = Vec::new();
+        write_population(&mut output, &population, &PrimitiveCodec).unwrap();
+        assert_eq!("AGENT 5 0102\nAGENT 9 03\nDONE\n", String::from_utf8(output.clone()).unwrap());
+
+        let agents: Vec<(Score, Agent<u8>)> = read_agents(&output[..], &PrimitiveCodec).unwrap();
+        assert_eq!(9, agents[1].0);
+        assert_eq!(&vec![3], agents[1].1.get_genes());
+        assert!(read_agents::<u8, _, _>(&b"AGENT 5 0102\n"[..], &PrimitiveCodec).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runner_merges_worker_populations() {
+        // Each worker echoes its configuration back as an agent.
+        let script = "read config; echo \"AGENT $config 0$config\"; echo DONE".to_string();
+        let runner: ProcessRunner<u8, _> = ProcessRunner::with_command("sh", vec!["-c".to_string(), script], PrimitiveCodec);
+        let population = runner.run(&["1".to_string(), "2".to_string()]).unwrap();
+        assert_eq!(vec![1, 2], population.get_scores());
+        assert_eq!(&vec![2], population.get(2).unwrap().get_genes());
+
+        let failing: ProcessRunner<u8, _> = ProcessRunner::with_command("sh", vec!["-c".to_string(), "exit 1".to_string()], PrimitiveCodec);
+        assert!(failing.run(&["1".to_string()]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runner_stops_workers_it_cannot_use() {
+        // A worker that sends nonsense and then hangs is killed rather than waited for.
+        let script = "echo 'AGENT x 00'; exec sleep 60".to_string();
+        let hanging: ProcessRunner<u8, _> = ProcessRunner::with_command("sh", vec!["-c".to_string(), script], PrimitiveCodec);
+        let start = std::time::Instant::now();
+        assert_eq!(io::ErrorKind::InvalidData, hanging.run(&["1".to_string()]).err().unwrap().kind());
+        assert!(start.elapsed().as_secs() < 30);
+
+        // A worker that exits without reading its configuration cannot be given it.
+        let deaf: ProcessRunner<u8, _> = ProcessRunner::with_command("sh", vec!["-c".to_string(), "exit 0".to_string()], PrimitiveCodec);
+        assert_eq!(io::ErrorKind::BrokenPipe, deaf.run(&["1".repeat(1 << 20)]).err().unwrap().kind());
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Python bindings, enabled with the `python` feature.
+//!
+//! Genes are exposed to Python as lists of integers from 0 to 255. The fitness function is any Python
+//! callable taking the list of genes and the data object, and returning a non-negative integer score.
+//!
+//! ```python
+//! import xu
+//!
+//! def fitness(genes, data):
+//!     return sum(genes)
+//!
+//! manager = xu.Manager(fitness, None)
+//! manager.set_number_of_genes(5, True)
+//! manager.run(1250)
+//! print(manager.population().best())
+//! ```
+//!
+//! To build the extension module, compile the crate as a `cdylib` with the `python` and
+//! `pyo3/extension-module` features enabled (for example with maturin).
+
+// The pyo3 expansion of methods returning PyResult triggers this lint.
+#![allow(clippy::useless_conversion)]
+
+use super::agent::Agent;
+use super::fitness::{GeneralScoreProvider, Score, ScoreError};
+use super::manager;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::Arc;
+
+/// The data passed through the Rust core, carrying the Python fitness callable and user data.
+#[derive(Clone)]
+pub struct PythonData {
+    fitness_function: Arc<PyObject>,
+    data: Arc<PyObject>
+}
+
+fn python_fitness(agent: &Agent<u8>, data: &PythonData) -> Result<Score, ScoreError> {
+    Python::with_gil(|py| {
+        let result = data.fitness_function
+            .call1(py, (agent.get_genes().clone(), data.data.clone_ref(py)))
+            .map_err(|error| ScoreError::new(&error.to_string()))?;
+        result.extract::<Score>(py).map_err(|error| ScoreError::new(&error.to_string()))
+    })
+}
+
+/// Runs the genetic algorithm, calling back into Python for fitness.
+#[pyclass(name = "Manager")]
+pub struct PyManager {
+    manager: manager::Manager<u8, PythonData, GeneralScoreProvider<u8, PythonData>>
+}
+
+#[pymethods]
+impl PyManager {
+    #[new]
+    fn new(fitness_function: PyObject, data: PyObject) -> Self {
+        let data = PythonData {
+            fitness_function: Arc::new(fitness_function),
+            data: Arc::new(data)
+        };
+        Self {
+            manager: manager::create_manager(python_fitness, data)
+        }
+    }
+
+    fn set_number_of_genes(&mut self, number: usize, strict: bool) {
+        self.manager.set_number_of_genes(number, strict);
+    }
+
+    fn set_initial_population_size(&mut self, size: usize) {
+        self.manager.set_initial_population_size(size);
+    }
+
+    fn set_max_child_threads(&mut self, max_number: u8) {
+        self.manager.set_max_child_threads(max_number);
+    }
+
+    fn set_iterations_per_cycle(&mut self, number: usize) {
+        self.manager.set_iterations_per_cycle(number);
+    }
+
+    /// Runs until the fitness of the best agent in the population is at least the goal.
+    /// The GIL is released so that the fitness function can be called from the worker threads.
+    fn run(&mut self, py: Python, goal: Score) {
+        let manager = &mut self.manager;
+        py.allow_threads(move || manager.run(manager::Goal::AtLeast(goal)));
+    }
+
+    fn population(&self) -> PyPopulation {
+        PyPopulation {
+            agents: self.manager.get_population().get_agents().iter()
+                .map(|(score, agent)| (*score, agent.get_genes().clone()))
+                .collect()
+        }
+    }
+}
+
+/// A snapshot of a population as (score, genes) pairs, ordered from lowest to highest score.
+#[pyclass(name = "Population")]
+pub struct PyPopulation {
+    agents: Vec<(Score, Vec<u8>)>
+}
+
+#[pymethods]
+impl PyPopulation {
+    fn agents(&self) -> Vec<(Score, Vec<u8>)> {
+        self.agents.clone()
+    }
+
+    fn best(&self) -> PyResult<(Score, Vec<u8>)> {
+        self.agents.last().cloned().ok_or_else(|| PyValueError::new_err("the population is empty"))
+    }
+
+    fn __len__(&self) -> usize {
+        self.agents.len()
+    }
+}
+
+/// Adds the xu classes to a Python module. Use this when wrapping xu in another extension module.
+pub fn register(module: &Bound<PyModule>) -> PyResult<()> {
+    module.add_class::<PyManager>()?;
+    module.add_class::<PyPopulation>()?;
+    Ok(())
+}
+
+#[pymodule]
+fn xu(module: &Bound<PyModule>) -> PyResult<()> {
+    register(module)
+}
@@ -0,0 +1,150 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The random number generator the crate draws from, which can be seeded so that a run can be
+//! replayed exactly.
+//!
+//! Each thread has a generator of its own, seeded from entropy like `rand::thread_rng` unless it
+//! is given a seed. Use `rng()` and `random()` in place of the functions of the same name in rand
+//! so that seeding covers the code.
+
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::StdRng;
+use rand::{Error, FromEntropy, Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static GENERATOR: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// A handle to the current thread's generator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadGenerator;
+
+impl RngCore for ThreadGenerator {
+    fn next_u32(&mut self) -> u32 {
+        GENERATOR.with(|generator| generator.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        GENERATOR.with(|generator| generator.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        GENERATOR.with(|generator| generator.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        GENERATOR.with(|generator| generator.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+/// The current thread's generator.
+pub fn rng() -> ThreadGenerator {
+    ThreadGenerator
+}
+
+/// A random value from the current thread's generator.
+pub fn random<T>() -> T
+where
+Standard: Distribution<T>
+{
+    rng().gen()
+}
+
+/// Reseeds the current thread's generator, so everything it generates from now on is the same
+/// each time.
+pub fn seed(seed: u64) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Runs the function with the current thread's generator seeded, then restores the generator, as
+/// when a child population runs on the calling thread.
+pub fn with_seed<T, F: FnOnce() -> T>(seed: u64, function: F) -> T {
+    let previous = GENERATOR.with(|generator| std::mem::replace(&mut *generator.borrow_mut(), StdRng::seed_from_u64(seed)));
+    let result = function();
+    GENERATOR.with(|generator| *generator.borrow_mut() = previous);
+    result
+}
+
+/// Applies the function to the jobs on up to the number of threads, returning the results in the
+/// order of the jobs. Each thread's generator is seeded from the current thread's, so a seeded run
+/// repeats for the same number of threads. With fewer than two threads or jobs, the jobs are run
+/// on the current thread without drawing seeds.
+pub(crate) fn map_in_threads<J, O, F>(jobs: Vec<J>, threads: usize, function: F) -> Vec<O>
+where
+J: Send,
+O: Send,
+F: Fn(J) -> O + Sync
+{
+    if threads < 2 || jobs.len() < 2 {
+        return jobs.into_iter().map(function).collect();
+    }
+    let chunk_size = jobs.len().div_ceil(threads);
+    let mut chunks = Vec::new();
+    let mut jobs = jobs.into_iter();
+    loop {
+        let chunk: Vec<J> = jobs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push((random::<u64>(), chunk));
+    }
+    let function = &function;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks.into_iter()
+            .map(|(seed, chunk)| scope.spawn(move || with_seed(seed, || chunk.into_iter().map(function).collect::<Vec<O>>())))
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// A seed drawn from entropy, whether or not the current thread's generator has been seeded.
+pub fn entropy_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeding_repeats_values() {
+        seed(7);
+        let first: Vec<u32> = (0..5).map(|_| random()).collect();
+        seed(7);
+        let second: Vec<u32> = (0..5).map(|_| rng().gen_range(0, u32::MAX)).collect();
+        seed(7);
+        assert_eq!(first, (0..5).map(|_| random::<u32>()).collect::<Vec<u32>>());
+        assert_ne!(first, second);
+
+        seed(7);
+        let inner: u64 = with_seed(8, random);
+        assert_eq!(first[0], random::<u32>());
+        seed(8);
+        assert_eq!(inner, random::<u64>());
+    }
+
+    #[test]
+    fn threads_keep_order_and_seeds() {
+        let draw = |job: u32| (job, random::<u32>());
+        seed(3);
+        let first = map_in_threads((0..10).collect(), 4, draw);
+        seed(3);
+        let second = map_in_threads((0..10).collect(), 4, draw);
+        assert_eq!((0..10).collect::<Vec<u32>>(), first.iter().map(|(job, _)| *job).collect::<Vec<u32>>());
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The register of genome hashes a unique population checks new agents against.
+//!
+//! A single hash set serves most populations. For populations of hundreds of thousands of agents,
+//! the register can be split into shards, each a smaller set that grows and rehashes on its own,
+//! so no insertion pauses to rehash every hash at once. Because no two shards share a hash, a
+//! register given threads fills its shards in parallel when hashes are added in bulk, such as when
+//! a population's register is rebuilt. A counting Bloom filter can also be put in
+//! front of the shards: most new agents are not duplicates, and the filter rules them out without
+//! a lookup in the exact sets, which still decide every possible match.
+//! Run `cargo bench --bench register` to compare the layouts.
+
+use std::collections::HashSet;
+use std::thread;
+
+/// The number of filter positions each hash sets.
+const FILTER_HASHES: u64 = 4;
+
+/// A counting Bloom filter of hashes, whose counters allow hashes to be removed. A counter that
+/// reaches its maximum is never decremented, so the filter never rules out a hash it holds.
+#[derive(Clone, Debug)]
+struct CountingFilter {
+    counters: Vec<u8>,
+    mask: u64
+}
+
+impl CountingFilter {
+    fn new(expected: usize) -> Self {
+        let size = (expected.max(1) * 8).next_power_of_two();
+        Self { counters: vec![0; size], mask: size as u64 - 1 }
+    }
+
+    /// The counter positions of the hash, by double hashing.
+    fn positions(mask: u64, hash: u64) -> impl Iterator<Item = usize> {
+        let step = hash.rotate_left(32) | 1;
+        (0..FILTER_HASHES).map(move |i| (hash.wrapping_add(i.wrapping_mul(step)) & mask) as usize)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for position in Self::positions(self.mask, hash) {
+            self.counters[position] = self.counters[position].saturating_add(1);
+        }
+    }
+
+    fn remove(&mut self, hash: u64) {
+        for position in Self::positions(self.mask, hash) {
+            if self.counters[position] < u8::MAX {
+                self.counters[position] -= 1;
+            }
+        }
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        Self::positions(self.mask, hash).all(|position| self.counters[position] > 0)
+    }
+
+    fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|counter| *counter = 0);
+    }
+}
+
+/// Genome hashes split between shards by hash, optionally behind a counting Bloom filter.
+/// See Population::set_register.
+#[derive(Clone, Debug)]
+pub struct Register {
+    shards: Vec<HashSet<u64>>,
+    filter: Option<CountingFilter>,
+    threads: usize,
+    len: usize
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Register {
+    /// A register of one shard with no filter, the default.
+    pub fn new() -> Self {
+        Self::sharded(1)
+    }
+
+    /// A register split into the number of shards.
+    pub fn sharded(shards: usize) -> Self {
+        Self {
+            shards: vec![HashSet::new(); shards.max(1)],
+            filter: None,
+            threads: 1,
+            len: 0
+        }
+    }
+
+    /// Fills the shards on up to the number of threads when hashes are added with insert_all,
+    /// each shard on one thread alone, so the threads never wait on each other. Defaults to 1.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Puts a counting Bloom filter sized for the expected number of hashes in front of the shards,
+    /// using a byte per eight expected hashes. More hashes than expected make the filter rule out
+    /// fewer lookups, but never give a wrong answer.
+    pub fn with_filter(mut self, expected: usize) -> Self {
+        let mut filter = CountingFilter::new(expected);
+        for hash in self.iter() {
+            filter.insert(hash);
+        }
+        self.filter = Some(filter);
+        self
+    }
+
+    fn shard(&self, hash: u64) -> usize {
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    pub fn contains(&self, hash: u64) -> bool {
+        if let Some(ref filter) = self.filter {
+            if !filter.may_contain(hash) {
+                return false;
+            }
+        }
+        self.shards[self.shard(hash)].contains(&hash)
+    }
+
+    /// Adds the hash, returning whether it was not already registered.
+    pub fn insert(&mut self, hash: u64) -> bool {
+        let shard = self.shard(hash);
+        if !self.shards[shard].insert(hash) {
+            return false;
+        }
+        if let Some(ref mut filter) = self.filter {
+            filter.insert(hash);
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Adds the hashes, returning the number not already registered. With more than one shard and
+    /// thread, the shards are filled in parallel and the filter is updated afterwards.
+    pub fn insert_all(&mut self, hashes: &[u64]) -> usize {
+        let threads = self.threads.min(self.shards.len());
+        if threads < 2 {
+            return hashes.iter().filter(|hash| self.insert(**hash)).count();
+        }
+
+        let mut batches = vec![Vec::new(); self.shards.len()];
+        for &hash in hashes {
+            batches[self.shard(hash)].push(hash);
+        }
+        let per_thread = self.shards.len().div_ceil(threads);
+        let added: Vec<u64> = thread::scope(|scope| {
+            let handles: Vec<_> = self.shards.chunks_mut(per_thread).zip(batches.chunks(per_thread))
+                .map(|(shards, batches)| scope.spawn(move || {
+                    let mut added = Vec::new();
+                    for (shard, batch) in shards.iter_mut().zip(batches) {
+                        added.extend(batch.iter().filter(|hash| shard.insert(**hash)));
+                    }
+                    added
+                }))
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+
+        if let Some(ref mut filter) = self.filter {
+            for &hash in &added {
+                filter.insert(hash);
+            }
+        }
+        self.len += added.len();
+        added.len()
+    }
+
+    /// Removes the hash, returning whether it was registered.
+    pub fn remove(&mut self, hash: u64) -> bool {
+        let shard = self.shard(hash);
+        if !self.shards[shard].remove(&hash) {
+            return false;
+        }
+        if let Some(ref mut filter) = self.filter {
+            filter.remove(hash);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Makes room for at least the number of additional hashes, spread across the shards.
+    pub fn reserve(&mut self, additional: usize) {
+        let per_shard = additional.div_ceil(self.shards.len());
+        self.shards.iter_mut().for_each(|shard| shard.reserve(per_shard));
+    }
+
+    pub fn clear(&mut self) {
+        self.shards.iter_mut().for_each(|shard| shard.clear());
+        if let Some(ref mut filter) = self.filter {
+            filter.clear();
+        }
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The registered hashes, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.shards.iter().flat_map(|shard| shard.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharded_filtered_register_matches_a_set() {
+        let mut register = Register::sharded(4).with_filter(100);
+        let mut set = HashSet::new();
+        for hash in (0..500u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)) {
+            assert_eq!(set.insert(hash), register.insert(hash));
+        }
+        for hash in (0..500u64).step_by(3).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)) {
+            assert_eq!(set.remove(&hash), register.remove(hash));
+        }
+        assert_eq!(set.len(), register.len());
+        assert!((0..1000u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).all(|hash| set.contains(&hash) == register.contains(hash)));
+        assert_eq!(set, register.iter().collect::<HashSet<u64>>());
+
+        register.clear();
+        assert!(register.is_empty() && !register.contains(0x9e37_79b9_7f4a_7c15));
+    }
+
+    #[test]
+    fn threaded_insert_all_matches_a_set() {
+        let hashes: Vec<u64> = (0..500u64).chain(0..100).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect();
+        let mut register = Register::sharded(8).with_filter(500).with_threads(3);
+        assert_eq!(500, register.insert_all(&hashes));
+        assert_eq!(0, register.insert_all(&hashes[..50]));
+
+        assert_eq!(500, register.len());
+        assert_eq!(hashes.iter().cloned().collect::<HashSet<u64>>(), register.iter().collect::<HashSet<u64>>());
+        assert!(hashes.iter().all(|hash| register.contains(*hash)));
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scoring by an external service over JSON-RPC.
+//!
+//! Each batch of agents is sent as an HTTP POST with a JSON-RPC 2.0 body of the form
+//! `{"jsonrpc":"2.0","id":1,"method":"score","params":{"genomes":["0a01ff", ...]}}`, where each
+//! genome is the genes encoded by the `GeneCodec` and written as hexadecimal.
+//! The service must reply with `{"jsonrpc":"2.0","id":1,"result":[12, null, ...]}`, giving a score
+//! for each genome in the same order, or null for genomes it could not score. Scores must be
+//! whole numbers no less than zero.
+//!
+//! Requires the `serde` feature.
+
+use super::agent::Agent;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::{InMemoryScoreCache, Score, ScoreCacheStore, ScoreError, ScoreProvider, jitter_score};
+use super::random::ThreadGenerator;
+use serde_json::{self, Value};
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A ScoreProvider that calls a JSON-RPC endpoint to score agents.
+#[derive(Clone)]
+pub struct RemoteScoreProvider <Gene, C>
+where
+C: GeneCodec<Gene> + Clone
+{
+    address: String,
+    path: String,
+    method: String,
+    codec: C,
+    offset: Score,
+    batch_size: usize,
+    retries: usize,
+    timeout: Duration,
+    request_id: u64,
+    score_cache: InMemoryScoreCache,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> RemoteScoreProvider <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene> + Clone
+{
+    /// address: host and port of the service, e.g. "localhost:8000".
+    /// path: the HTTP path of the JSON-RPC endpoint, e.g. "/rpc".
+    pub fn new(address: &str, path: &str, codec: C, offset: Score) -> Self {
+        Self {
+            address: address.to_string(),
+            path: path.to_string(),
+            method: "score".to_string(),
+            codec,
+            offset,
+            batch_size: 100,
+            retries: 2,
+            timeout: Duration::from_secs(30),
+            request_id: 0,
+            score_cache: InMemoryScoreCache::new(),
+            gene: PhantomData
+        }
+    }
+
+    /// Sets the JSON-RPC method name. Defaults to "score".
+    pub fn set_method(&mut self, method: &str) {
+        self.method = method.to_string();
+    }
+
+    /// Sets the maximum number of genomes sent in one request. Defaults to 100.
+    pub fn set_batch_size(&mut self, size: usize) {
+        self.batch_size = size.max(1);
+    }
+
+    /// Sets how many times a failed request is repeated before giving up. Defaults to 2.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Sets the timeout for connecting, sending and receiving. Defaults to 30 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn request_scores(&mut self, agents: &[&Agent<Gene>]) -> Result<Vec<Option<Score>>, ScoreError> {
+        let mut scores = Vec::with_capacity(agents.len());
+        for batch in agents.chunks(self.batch_size) {
+            let genomes: Vec<String> = batch.iter()
+                .map(|agent| format!("\"{}\"", fingerprint::to_hex(&fingerprint::encode_genes(agent.get_genes(), &self.codec))))
+                .collect();
+            self.request_id += 1;
+            let method = serde_json::to_string(&self.method).map_err(|error| ScoreError::new(&error.to_string()))?;
+            let body = format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":{},\"params\":{{\"genomes\":[{}]}}}}",
+                self.request_id, method, genomes.join(",")
+            );
+            let request_id = self.request_id;
+
+            let mut attempt = 0;
+            let batch_scores = loop {
+                match self.post(&body).and_then(|response| parse_result(&response, request_id)) {
+                    Ok(batch_scores) => break batch_scores,
+                    Err(error) => {
+                        if attempt >= self.retries {
+                            return Err(error);
+                        }
+                        attempt += 1;
+                    }
+                }
+            };
+
+            if batch_scores.len() != batch.len() {
+                return Err(ScoreError::new("service returned a different number of scores to genomes sent"));
+            }
+            scores.extend(batch_scores);
+        }
+
+        Ok(scores)
+    }
+
+    fn post(&self, body: &str) -> Result<String, ScoreError> {
+        let to_error = |error: std::io::Error| ScoreError::new(&format!("request to {} failed: {}", self.address, error));
+
+        let address = self.address.to_socket_addrs().map_err(to_error)?.next()
+            .ok_or_else(|| ScoreError::new(&format!("could not resolve {}", self.address)))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout).map_err(to_error)?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(to_error)?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(to_error)?;
+
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path, self.address, body.len(), body
+        );
+        stream.write_all(request.as_bytes()).map_err(to_error)?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).map_err(to_error)?;
+        http_body(&response)
+    }
+}
+
+/// The position of the first occurrence of the needle in the bytes.
+fn find(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Extracts the body from an HTTP response, checking the status and handling chunked encoding.
+/// Chunk sizes count bytes, so the body is decoded before it is read as UTF-8.
+fn http_body(response: &[u8]) -> Result<String, ScoreError> {
+    let (head, body) = match find(response, b"\r\n\r\n") {
+        Some(index) => (String::from_utf8_lossy(&response[..index]), &response[index + 4..]),
+        None => return Err(ScoreError::new("incomplete HTTP response"))
+    };
+
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(ScoreError::new(&format!("service responded with {}", status)));
+    }
+
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    let body = if chunked { dechunk(body)? } else { body.to_vec() };
+    String::from_utf8(body).map_err(|_| ScoreError::new("response body is not UTF-8"))
+}
+
+/// Joins the chunks of a body sent with chunked transfer encoding.
+fn dechunk(body: &[u8]) -> Result<Vec<u8>, ScoreError> {
+    let mut decoded = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = find(rest, b"\r\n").ok_or_else(|| ScoreError::new("invalid chunked response"))?;
+        let size_line = String::from_utf8_lossy(&rest[..line_end]);
+        let size_text = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_text, 16).map_err(|_| ScoreError::new("invalid chunk size"))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = rest.get(line_end + 2..line_end + 2 + size).ok_or_else(|| ScoreError::new("incomplete chunk"))?;
+        decoded.extend_from_slice(chunk);
+        rest = rest.get(line_end + 4 + size..).unwrap_or(&[]);
+    }
+}
+
+/// Reads the "result" array from a JSON-RPC response to the request with the given id.
+fn parse_result(response: &str, request_id: u64) -> Result<Vec<Option<Score>>, ScoreError> {
+    let response: Value = serde_json::from_str(response).map_err(|error| ScoreError::new(&format!("invalid response: {}", error)))?;
+    if response.get("id").and_then(Value::as_u64) != Some(request_id) {
+        return Err(ScoreError::new(&format!("response is not for request {}", request_id)));
+    }
+    if let Some(error) = response.get("error") {
+        return Err(ScoreError::new(&format!("service returned an error: {}", error)));
+    }
+
+    let result = response.get("result").ok_or_else(|| ScoreError::new("response has no result"))?;
+    let values = result.as_array().ok_or_else(|| ScoreError::new("result is not an array"))?;
+    values.iter().map(|value| match value {
+        Value::Null => Ok(None),
+        value => to_score(value).map(Some).ok_or_else(|| ScoreError::new(&format!("invalid score {}", value)))
+    }).collect()
+}
+
+/// Converts a JSON number to a Score, if it is a whole number in range. Whole numbers written as
+/// floats, such as 12.0, are accepted.
+fn to_score(value: &Value) -> Option<Score> {
+    if let Some(score) = value.as_u64() {
+        return Some(score);
+    }
+    let score = value.as_f64()?;
+    if score >= 0.0 && score.fract() == 0.0 && score < Score::MAX as f64 {
+        Some(score as Score)
+    } else {
+        None
+    }
+}
+
+impl <Gene, Data, C> ScoreProvider<Gene, Data> for RemoteScoreProvider <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene> + Clone
+{
+    fn evaluate_scores(&mut self, agents: Vec<Agent<Gene>>, _data: &Data) -> Result<Vec<Agent<Gene>>, ScoreError> {
+        let uncached: Vec<&Agent<Gene>> = agents.iter().filter(|agent| self.score_cache.get(agent.get_hash()).is_none()).collect();
+        let scores = self.request_scores(&uncached)?;
+        for (agent, score) in uncached.iter().zip(scores) {
+            if let Some(score) = score {
+                self.score_cache.put(agent.get_hash(), score);
+            }
+        }
+
+        // Agents that could not be scored are skipped.
+        Ok(agents.into_iter().filter(|agent| self.score_cache.get(agent.get_hash()).is_some()).collect())
+    }
+
+    fn get_score(&mut self, agent: &Agent<Gene>, data: &Data, rng: &mut ThreadGenerator) -> Result<Score, ScoreError> {
+        let score = self.true_score(agent, data)?;
+        Ok(jitter_score(score, self.offset, rng))
+    }
+
+    fn true_score(&mut self, agent: &Agent<Gene>, _data: &Data) -> Result<Score, ScoreError> {
+        if let Some(score) = self.score_cache.get(agent.get_hash()) {
+            return Ok(score);
+        }
+
+        let score = self.request_scores(&[agent])?[0].ok_or_else(|| ScoreError::new("service could not score the agent"))?;
+        self.score_cache.put(agent.get_hash(), score);
+        Ok(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn parse_result_with_nulls() {
+        let response = "{\"jsonrpc\": \"2.0\", \"id\": 3, \"result\": [12, null, 4.0]}";
+        assert_eq!(vec![Some(12), None, Some(4)], parse_result(response, 3).unwrap());
+        assert!(parse_result(response, 4).is_err());
+        assert!(parse_result("{\"jsonrpc\":\"2.0\",\"id\":3,\"error\":{\"code\":-32601}}", 3).is_err());
+        // A result of "[1]" inside a string must not be mistaken for the result.
+        assert!(parse_result("{\"jsonrpc\":\"2.0\",\"id\":3,\"error\":{\"message\":\"\\\"result\\\":[1]\"}}", 3).is_err());
+    }
+
+    #[test]
+    fn parse_result_rejects_unrepresentable_scores() {
+        for score in &["-1", "4.7", "1e30", "-0.5"] {
+            let response = format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[{}]}}", score);
+            assert!(parse_result(&response, 1).is_err(), "{} was accepted", score);
+        }
+    }
+
+    #[test]
+    fn http_body_chunked() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n";
+        assert_eq!("{\"a\":1}", http_body(response).unwrap());
+        assert!(http_body(b"HTTP/1.1 500 Internal Server Error\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn http_body_joins_characters_split_between_chunks() {
+        // The two bytes of the \u{e9} are sent in different chunks.
+        let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n7\r\n{\"a\":\"".to_vec();
+        response.extend_from_slice(&[0xc3, b'\r', b'\n', b'3', b'\r', b'\n', 0xa9]);
+        response.extend_from_slice(b"\"}\r\n0\r\n\r\n");
+        assert_eq!("{\"a\":\"\u{e9}\"}", http_body(&response).unwrap());
+
+        let invalid = b"HTTP/1.1 200 OK\r\n\r\n{\"a\":\"\xff\"}";
+        assert!(http_body(invalid).is_err());
+    }
+
+    #[test]
+    fn scores_batches_from_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            // Two batches of up to two genomes. Each score is the number of genomes in the batch.
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                assert_eq!("score \"quoted\"", request["method"]);
+                let count = request["params"]["genomes"].as_array().unwrap().len();
+                let result = vec![count.to_string(); count].join(",");
+                let body = format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":[{}]}}", request["id"], result);
+                let mut stream = stream;
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+            }
+        });
+
+        let mut provider = RemoteScoreProvider::new(&address, "/rpc", PrimitiveCodec, 0);
+        provider.set_batch_size(2);
+        provider.set_method("score \"quoted\"");
+        let agents = vec![Agent::from_genes(vec![1u8]), Agent::from_genes(vec![2u8]), Agent::from_genes(vec![3u8])];
+        let scored = provider.evaluate_scores(agents, &0).unwrap();
+
+        assert_eq!(3, scored.len());
+        let mut rng = super::super::random::rng();
+        assert_eq!(2, provider.get_score(&scored[0], &0, &mut rng).unwrap());
+        assert_eq!(1, provider.get_score(&scored[2], &0, &mut rng).unwrap());
+    }
+}
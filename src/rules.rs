@@ -0,0 +1,299 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rule set genomes, for evolving interpretable classifiers over tabular data.
+//!
+//! Each row of data is a list of features, each one a small symbol such as a category or a bucket
+//! of a numeric column. A genome is an `Agent<Rule>`, read as a decision list: the first rule whose
+//! conditions all match a row gives its action, and rows no rule matches get the default action.
+
+use super::random;
+use super::agent::{self, Agent};
+use super::fitness::{Score, ScoreError, ScoreProvider};
+use super::population::Population;
+use rand::Rng;
+use std::fmt;
+
+/// A condition on one feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Condition {
+    /// Matches any value.
+    Any,
+    /// Matches only this value.
+    Is(u8)
+}
+
+/// If every condition matches the features in the same position, the row gets the action.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rule {
+    pub conditions: Vec<Condition>,
+    pub action: u8
+}
+
+impl Rule {
+    /// Whether the rule applies to the row. Features beyond the conditions are ignored, and
+    /// conditions beyond the features only match if they are Any.
+    pub fn matches(&self, features: &[u8]) -> bool {
+        self.conditions.iter().enumerate().all(|(position, condition)| match *condition {
+            Condition::Any => true,
+            Condition::Is(value) => features.get(position) == Some(&value)
+        })
+    }
+
+    /// The number of conditions that are not wildcards.
+    pub fn specificity(&self) -> usize {
+        self.conditions.iter().filter(|condition| **condition != Condition::Any).count()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for condition in &self.conditions {
+            match *condition {
+                Condition::Any => write!(f, "# ")?,
+                Condition::Is(value) => write!(f, "{} ", value)?
+            }
+        }
+        write!(f, "=> {}", self.action)
+    }
+}
+
+/// Labelled rows to classify, used as the Data of a run.
+#[derive(Clone, Debug, Default)]
+pub struct TabularData {
+    pub rows: Vec<Vec<u8>>,
+    pub labels: Vec<u8>,
+    /// The action for rows no rule matches.
+    pub default_action: u8
+}
+
+impl TabularData {
+    pub fn new(rows: Vec<Vec<u8>>, labels: Vec<u8>, default_action: u8) -> Self {
+        Self { rows, labels, default_action }
+    }
+}
+
+/// The action of the first rule matching the features, if any.
+pub fn classify(rules: &Agent<Rule>, features: &[u8]) -> Option<u8> {
+    rules.get_genes().iter().find(|rule| rule.matches(features)).map(|rule| rule.action)
+}
+
+/// The proportion of rows the rule set classifies correctly, from 0.0 to 1.0.
+pub fn accuracy(rules: &Agent<Rule>, data: &TabularData) -> f64 {
+    if data.rows.is_empty() {
+        return 0.0;
+    }
+    correct(rules, data) as f64 / data.rows.len() as f64
+}
+
+/// A fitness function for classification: 1000 for each correctly classified row, less one for
+/// each rule so that smaller rule sets win ties.
+pub fn accuracy_fitness(rules: &Agent<Rule>, data: &TabularData) -> Result<Score, ScoreError> {
+    if data.rows.len() != data.labels.len() {
+        return Err(ScoreError::new("the number of rows and labels differ"));
+    }
+    Ok((correct(rules, data) as Score * 1000).saturating_sub(rules.get_genes().len() as Score))
+}
+
+fn correct(rules: &Agent<Rule>, data: &TabularData) -> usize {
+    data.rows.iter().zip(data.labels.iter())
+        .filter(|(row, label)| classify(rules, row).unwrap_or(data.default_action) == **label)
+        .count()
+}
+
+/// Describes the possible rules, and creates, mutates and reproduces rule sets.
+#[derive(Clone, Debug)]
+pub struct RuleSpace {
+    /// The number of values each feature can take, so feature i takes values 0 to values[i] - 1.
+    values: Vec<u8>,
+    actions: u8,
+    wildcard_rate: f64,
+    max_rules: usize
+}
+
+impl RuleSpace {
+    /// values: the number of values each feature can take. actions: the number of actions.
+    pub fn new(values: Vec<u8>, actions: u8) -> Self {
+        Self {
+            values,
+            actions: actions.max(1),
+            wildcard_rate: 0.5,
+            max_rules: 20
+        }
+    }
+
+    /// Sets the chance that each condition of a new rule is a wildcard.
+    pub fn set_wildcard_rate(&mut self, rate: f64) {
+        self.wildcard_rate = rate;
+    }
+
+    /// Sets the most rules a rule set can grow to by mutation.
+    pub fn set_max_rules(&mut self, max_rules: usize) {
+        self.max_rules = max_rules.max(1);
+    }
+
+    pub fn random_rule(&self) -> Rule {
+        let mut rng = random::rng();
+        Rule {
+            conditions: self.values.iter().map(|values| self.random_condition(*values, &mut rng)).collect(),
+            action: rng.gen_range(0, self.actions)
+        }
+    }
+
+    /// Creates a rule set of between one and the given number of random rules.
+    pub fn random_rule_set(&self, max_rules: usize) -> Agent<Rule> {
+        let count = random::rng().gen_range(1, max_rules.max(1) + 1);
+        Agent::from_genes((0..count).map(|_| self.random_rule()).collect())
+    }
+
+    /// Returns a copy of the rule set changed by one of: generalising a condition to a wildcard,
+    /// specialising a wildcard, changing a condition's value, changing an action, adding a rule,
+    /// removing a rule or moving a rule earlier, which gives it priority.
+    pub fn mutate(&self, rules: &Agent<Rule>) -> Agent<Rule> {
+        let mut rng = random::rng();
+        let mut genes = rules.get_genes().clone();
+        if genes.is_empty() {
+            return Agent::from_genes(vec![self.random_rule()]);
+        }
+
+        let index = rng.gen_range(0, genes.len());
+        match rng.gen_range(0, 7) {
+            0..=2 if !self.values.is_empty() => {
+                let position = rng.gen_range(0, self.values.len().min(genes[index].conditions.len()).max(1));
+                if let Some(condition) = genes[index].conditions.get_mut(position) {
+                    *condition = match *condition {
+                        // Generalise, change the value, or specialise.
+                        Condition::Is(_) if rng.gen::<bool>() => Condition::Any,
+                        _ => Condition::Is(rng.gen_range(0, self.values[position].max(1)))
+                    };
+                }
+            },
+            3 => genes[index].action = rng.gen_range(0, self.actions),
+            4 if genes.len() < self.max_rules => genes.insert(index, self.random_rule()),
+            5 if genes.len() > 1 => {
+                genes.remove(index);
+            },
+            _ => {
+                let rule = genes.remove(index);
+                genes.insert(rng.gen_range(0, index + 1), rule);
+            }
+        }
+
+        Agent::from_genes(genes)
+    }
+
+    /// Creates a scored population of random rule sets.
+    pub fn initial_population<Data, SP>(&self, size: usize, data: &Data, score_provider: &mut SP) -> Population<Rule>
+    where
+    SP: ScoreProvider<Rule, Data>
+    {
+        let agents = (0..size).map(|_| self.random_rule_set(self.max_rules)).collect();
+        let mut population = Population::new_empty(true);
+        population.score_and_insert(agents, data, score_provider);
+        population
+    }
+
+    /// Adds offspring to the population, crossing over rule sets from the higher scored half and
+    /// mutating the children.
+    pub fn reproduce<Data, SP>(&self, mut population: Population<Rule>, offspring: usize, data: &Data, score_provider: &mut SP) -> Population<Rule>
+    where
+    SP: ScoreProvider<Rule, Data>
+    {
+        population.breed_from_upper_half(offspring, data, score_provider, |one, two| {
+            let mut child = agent::crossover(one, two);
+            if child.get_genes().is_empty() || child.get_genes().len() > self.max_rules {
+                child = one.clone();
+            }
+            self.mutate(&child)
+        });
+        population
+    }
+
+    fn random_condition<R: Rng>(&self, values: u8, rng: &mut R) -> Condition {
+        if values == 0 || rng.gen::<f64>() < self.wildcard_rate {
+            Condition::Any
+        } else {
+            Condition::Is(rng.gen_range(0, values))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::GeneralScoreProvider;
+
+    fn rule(conditions: Vec<Condition>, action: u8) -> Rule {
+        Rule { conditions, action }
+    }
+
+    #[test]
+    fn decision_list_uses_first_matching_rule() {
+        let rules = Agent::from_genes(vec![
+            rule(vec![Condition::Is(1), Condition::Any], 1),
+            rule(vec![Condition::Any, Condition::Is(0)], 2)
+        ]);
+        assert_eq!(Some(1), classify(&rules, &[1, 0]));
+        assert_eq!(Some(2), classify(&rules, &[0, 0]));
+        assert_eq!(None, classify(&rules, &[0, 1]));
+        assert_eq!("1 # => 1", rules.get_genes()[0].to_string());
+
+        let data = TabularData::new(vec![vec![1, 0], vec![0, 0], vec![0, 1]], vec![1, 2, 0], 0);
+        assert_eq!(1.0, accuracy(&rules, &data));
+        assert_eq!(2998, accuracy_fitness(&rules, &data).unwrap());
+    }
+
+    #[test]
+    fn mutation_keeps_rules_in_space() {
+        let space = RuleSpace::new(vec![2, 3, 4], 2);
+        let mut rules = space.random_rule_set(5);
+        for _ in 0..200 {
+            rules = space.mutate(&rules);
+            assert!(!rules.get_genes().is_empty() && rules.get_genes().len() <= 20);
+            for rule in rules.get_genes() {
+                assert!(rule.action < 2);
+                for (position, condition) in rule.conditions.iter().enumerate() {
+                    if let Condition::Is(value) = *condition {
+                        assert!(value < [2, 3, 4][position]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn evolves_simple_classifier() {
+        // The label is 1 exactly when the first feature is 2.
+        let mut rows = Vec::new();
+        let mut labels = Vec::new();
+        for first in 0..3 {
+            for second in 0..3 {
+                rows.push(vec![first, second]);
+                labels.push(if first == 2 { 1 } else { 0 });
+            }
+        }
+        let data = TabularData::new(rows, labels, 0);
+        let space = RuleSpace::new(vec![3, 3], 2);
+        let mut score_provider = GeneralScoreProvider::new(accuracy_fitness, 0);
+
+        let mut population = space.initial_population(50, &data, &mut score_provider);
+        for _ in 0..50 {
+            population = space.reproduce(population, 50, &data, &mut score_provider);
+            population = super::super::operations::cull_lowest_agents(population, 0.5, 50);
+        }
+
+        let (_, best) = population.true_best(&mut score_provider, &data).unwrap();
+        assert_eq!(1.0, accuracy(best, &data));
+    }
+}
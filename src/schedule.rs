@@ -0,0 +1,237 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A two-level encoding for scheduling and timetabling.
+//!
+//! A genome is an `Agent<Assignment>` holding every task exactly once. The order of the genes is
+//! the order tasks are scheduled in, and each gene assigns its task to a resource, such as a
+//! machine, room or timeslot. The operators keep both levels valid: orderings stay permutations
+//! and every task keeps an assignment.
+
+use super::random;
+use super::agent::Agent;
+use super::fitness::ScoreProvider;
+use super::population::Population;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// A task and the resource it is assigned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Assignment {
+    pub task: usize,
+    pub resource: usize
+}
+
+/// A task placed in a decoded schedule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScheduledTask {
+    pub task: usize,
+    pub resource: usize,
+    pub start: u64,
+    pub end: u64
+}
+
+/// The numbers of tasks and resources, and operators on schedules of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduleSpace {
+    tasks: usize,
+    resources: usize,
+    reassign_rate: f64
+}
+
+impl ScheduleSpace {
+    pub fn new(tasks: usize, resources: usize) -> Self {
+        Self {
+            tasks,
+            resources: resources.max(1),
+            reassign_rate: 0.1
+        }
+    }
+
+    /// Sets the chance that each task is moved to another resource when mutating.
+    pub fn set_reassign_rate(&mut self, rate: f64) {
+        self.reassign_rate = rate;
+    }
+
+    /// Creates a schedule with the tasks in random order on random resources.
+    pub fn random_schedule(&self) -> Agent<Assignment> {
+        let mut rng = random::rng();
+        let mut tasks: Vec<usize> = (0..self.tasks).collect();
+        for index in (1..tasks.len()).rev() {
+            tasks.swap(index, rng.gen_range(0, index + 1));
+        }
+        Agent::from_genes(tasks.into_iter().map(|task| Assignment { task, resource: rng.gen_range(0, self.resources) }).collect())
+    }
+
+    /// Returns a copy with tasks moved to random resources at the reassign rate, and two tasks
+    /// swapped in the order.
+    pub fn mutate(&self, schedule: &Agent<Assignment>) -> Agent<Assignment> {
+        let mut rng = random::rng();
+        let mut genes = schedule.get_genes().clone();
+        for gene in genes.iter_mut() {
+            if rng.gen::<f64>() < self.reassign_rate {
+                gene.resource = rng.gen_range(0, self.resources);
+            }
+        }
+        if genes.len() > 1 {
+            let one = rng.gen_range(0, genes.len());
+            let two = rng.gen_range(0, genes.len());
+            genes.swap(one, two);
+        }
+        Agent::from_genes(genes)
+    }
+
+    /// Creates a scored population of random schedules.
+    pub fn initial_population<Data, SP>(&self, size: usize, data: &Data, score_provider: &mut SP) -> Population<Assignment>
+    where
+    SP: ScoreProvider<Assignment, Data>
+    {
+        let mut population = Population::new_empty(true);
+        population.score_and_insert((0..size).map(|_| self.random_schedule()).collect(), data, score_provider);
+        population
+    }
+
+    /// Adds offspring to the population by order crossover of schedules from the higher scored
+    /// half, then mutation.
+    pub fn reproduce<Data, SP>(&self, mut population: Population<Assignment>, offspring: usize, data: &Data, score_provider: &mut SP) -> Population<Assignment>
+    where
+    SP: ScoreProvider<Assignment, Data>
+    {
+        population.breed_from_upper_half(offspring, data, score_provider, |one, two| self.mutate(&order_crossover(one, two)));
+        population
+    }
+}
+
+/// Order crossover: the child keeps a random slice of the first parent in place, with its
+/// assignments, and the remaining tasks follow in the order and with the assignments they have in
+/// the second parent. Both parents must hold the same tasks.
+pub fn order_crossover(one: &Agent<Assignment>, two: &Agent<Assignment>) -> Agent<Assignment> {
+    let one_genes = one.get_genes();
+    if one_genes.is_empty() {
+        return one.clone();
+    }
+
+    let mut rng = random::rng();
+    let first = rng.gen_range(0, one_genes.len());
+    let last = rng.gen_range(first, one_genes.len()) + 1;
+    let kept: HashSet<usize> = one_genes[first..last].iter().map(|gene| gene.task).collect();
+
+    let mut rest = two.get_genes().iter().filter(|gene| !kept.contains(&gene.task));
+    let mut genes = Vec::with_capacity(one_genes.len());
+    for (position, gene) in one_genes.iter().enumerate() {
+        if position >= first && position < last {
+            genes.push(*gene);
+        } else if let Some(other) = rest.next() {
+            genes.push(*other);
+        }
+    }
+    Agent::from_genes(genes)
+}
+
+/// Counts the pairs of conflicting tasks assigned the same resource, such as two exams sharing
+/// students placed in the same timeslot.
+pub fn conflicts(schedule: &Agent<Assignment>, conflicting: &[(usize, usize)]) -> usize {
+    let mut resources = vec![None; schedule.get_genes().iter().map(|gene| gene.task + 1).max().unwrap_or(0)];
+    for gene in schedule.get_genes() {
+        resources[gene.task] = Some(gene.resource);
+    }
+
+    conflicting.iter()
+        .filter(|(one, two)| match (resources.get(*one), resources.get(*two)) {
+            (Some(Some(one)), Some(Some(two))) => one == two,
+            _ => false
+        })
+        .count()
+}
+
+/// Decodes the schedule into start and end times: each resource runs its tasks one after another
+/// in genome order, each taking its duration. Tasks without a duration take no time.
+pub fn decode(schedule: &Agent<Assignment>, durations: &[u64]) -> Vec<ScheduledTask> {
+    let mut free_at: Vec<u64> = Vec::new();
+    schedule.get_genes().iter()
+        .map(|gene| {
+            if free_at.len() <= gene.resource {
+                free_at.resize(gene.resource + 1, 0);
+            }
+            let start = free_at[gene.resource];
+            let end = start + durations.get(gene.task).cloned().unwrap_or(0);
+            free_at[gene.resource] = end;
+            ScheduledTask { task: gene.task, resource: gene.resource, start, end }
+        })
+        .collect()
+}
+
+/// The time the last task of the decoded schedule ends.
+pub fn makespan(schedule: &Agent<Assignment>, durations: &[u64]) -> u64 {
+    decode(schedule, durations).iter().map(|task| task.end).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::{GeneralScoreProvider, Score, ScoreError};
+
+    fn schedule(genes: &[(usize, usize)]) -> Agent<Assignment> {
+        Agent::from_genes(genes.iter().map(|(task, resource)| Assignment { task: *task, resource: *resource }).collect())
+    }
+
+    fn tasks(agent: &Agent<Assignment>) -> Vec<usize> {
+        let mut tasks: Vec<usize> = agent.get_genes().iter().map(|gene| gene.task).collect();
+        tasks.sort();
+        tasks
+    }
+
+    #[test]
+    fn operators_keep_every_task_once() {
+        let space = ScheduleSpace::new(8, 3);
+        let one = space.random_schedule();
+        let two = space.random_schedule();
+        for _ in 0..50 {
+            let child = space.mutate(&order_crossover(&one, &two));
+            assert_eq!((0..8).collect::<Vec<usize>>(), tasks(&child));
+            assert!(child.get_genes().iter().all(|gene| gene.resource < 3));
+        }
+    }
+
+    #[test]
+    fn conflicts_and_makespan() {
+        let agent = schedule(&[(2, 0), (0, 1), (1, 0)]);
+        assert_eq!(1, conflicts(&agent, &[(1, 2), (0, 1), (0, 5)]));
+
+        let decoded = decode(&agent, &[4, 2, 3]);
+        assert_eq!(ScheduledTask { task: 1, resource: 0, start: 3, end: 5 }, decoded[2]);
+        assert_eq!(5, makespan(&agent, &[4, 2, 3]));
+    }
+
+    #[allow(clippy::ptr_arg)]
+    fn no_conflicts(agent: &Agent<Assignment>, conflicting: &Vec<(usize, usize)>) -> Result<Score, ScoreError> {
+        Ok(100 - conflicts(agent, conflicting) as Score)
+    }
+
+    #[test]
+    fn reproduce_removes_conflicts() {
+        // A ring of six tasks, each conflicting with its neighbours, can be coloured with two resources.
+        let conflicting: Vec<(usize, usize)> = (0..6).map(|task| (task, (task + 1) % 6)).collect();
+        let space = ScheduleSpace::new(6, 2);
+        let mut score_provider = GeneralScoreProvider::new(no_conflicts, 0);
+        let best = random::with_seed(3, || {
+            let mut population = space.initial_population(20, &conflicting, &mut score_provider);
+            for _ in 0..30 {
+                population = space.reproduce(population, 20, &conflicting, &mut score_provider);
+            }
+            population.true_best(&mut score_provider, &conflicting).unwrap().0
+        });
+        assert_eq!(100, best);
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording population snapshots to a file, and replaying them later.
+//!
+//! The file starts with the 8 bytes `XUSNAP1\n`, followed by snapshots appended one after another.
+//! Each snapshot is the iteration number (u64) and the number of agents (u64), then for each agent
+//! its score (u64), the length of its encoded genes (u64) and the genes encoded by the `GeneCodec`.
+//! All numbers are little-endian.
+
+use super::agent::Agent;
+use super::evolution::Observer;
+use super::fingerprint::{self, GeneCodec};
+use super::fitness::Score;
+use super::population::Population;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"XUSNAP1\n";
+
+/// The agents of a population at the end of an iteration.
+pub struct Snapshot <Gene> {
+    pub iteration: u64,
+    pub agents: BTreeMap<Score, Agent<Gene>>
+}
+
+/// An Observer that appends a snapshot of the population to a file every few iterations.
+/// Iterations are counted across all runs the recorder observes.
+pub struct SnapshotRecorder <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    writer: BufWriter<File>,
+    codec: C,
+    interval: u64,
+    iteration: u64,
+    since_recorded: u64,
+    last_error: Option<io::Error>,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> SnapshotRecorder <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    /// Opens the file for appending, creating it if needed.
+    /// interval: the number of iterations between snapshots.
+    pub fn create<P: AsRef<Path>>(path: P, codec: C, interval: u64) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        }
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            codec,
+            interval: interval.max(1),
+            iteration: 0,
+            since_recorded: 0,
+            last_error: None,
+            gene: PhantomData
+        })
+    }
+
+    /// Appends a snapshot of the population now, regardless of the interval.
+    pub fn record(&mut self, iteration: u64, population: &Population<Gene>) -> io::Result<()> {
+        let agents = population.get_agents();
+        self.writer.write_all(&iteration.to_le_bytes())?;
+        self.writer.write_all(&(agents.len() as u64).to_le_bytes())?;
+        for (score, agent) in agents {
+            let genes = fingerprint::encode_genes(agent.get_genes(), &self.codec);
+            self.writer.write_all(&score.to_le_bytes())?;
+            self.writer.write_all(&(genes.len() as u64).to_le_bytes())?;
+            self.writer.write_all(&genes)?;
+        }
+        self.writer.flush()
+    }
+
+    /// Takes the last error that occurred while recording as an observer, if any.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.last_error.take()
+    }
+}
+
+impl <Gene, C> Observer<Gene> for SnapshotRecorder <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    fn iteration_complete(&mut self, _iteration: usize, population: &Population<Gene>) {
+        self.iteration += 1;
+        self.since_recorded += 1;
+        if self.since_recorded == self.interval {
+            self.since_recorded = 0;
+            let iteration = self.iteration;
+            if let Err(error) = self.record(iteration, population) {
+                self.last_error = Some(error);
+            }
+        }
+    }
+}
+
+/// Reads the snapshots from a file written by a SnapshotRecorder, in the order they were written.
+pub struct SnapshotReader <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    reader: BufReader<File>,
+    codec: C,
+    gene: PhantomData<Gene>
+}
+
+impl <Gene, C> SnapshotReader <Gene, C>
+where
+C: GeneCodec<Gene>
+{
+    pub fn open<P: AsRef<Path>>(path: P, codec: C) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a snapshot file"));
+        }
+
+        Ok(Self {
+            reader,
+            codec,
+            gene: PhantomData
+        })
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_snapshot(&mut self, iteration: u64) -> io::Result<Snapshot<Gene>>
+    where
+    Gene: Hash
+    {
+        let count = self.read_u64()?;
+        let mut agents = BTreeMap::new();
+        for _ in 0..count {
+            let score = self.read_u64()?;
+            let length = self.read_u64()?;
+            // The buffer grows only with the bytes actually read, so a corrupt length cannot ask
+            // for more memory than the file holds.
+            let mut genes = Vec::new();
+            (&mut self.reader).take(length).read_to_end(&mut genes)?;
+            if genes.len() as u64 != length {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the file ends within an agent's genes"));
+            }
+            let genes = fingerprint::decode_genes(&genes, &self.codec)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "genes could not be decoded"))?;
+            agents.insert(score, Agent::from_genes(genes));
+        }
+
+        Ok(Snapshot { iteration, agents })
+    }
+}
+
+impl <Gene, C> Iterator for SnapshotReader <Gene, C>
+where
+Gene: Hash,
+C: GeneCodec<Gene>
+{
+    type Item = io::Result<Snapshot<Gene>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The end of the file is only expected at the start of a snapshot.
+        let mut bytes = [0; 8];
+        match self.reader.read(&mut bytes[..1]) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(error) => return Some(Err(error))
+        }
+        if let Err(error) = self.reader.read_exact(&mut bytes[1..]) {
+            return Some(Err(error));
+        }
+
+        Some(self.read_snapshot(u64::from_le_bytes(bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fingerprint::PrimitiveCodec;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn record_and_replay() {
+        let path = env::temp_dir().join(format!("xu_snapshot_test_{}.bin", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut population = Population::new_empty(false);
+        population.insert(5, Agent::from_genes(vec![1u8, 2]));
+        population.insert(9, Agent::from_genes(vec![3u8, 4]));
+
+        {
+            let mut recorder = SnapshotRecorder::create(&path, PrimitiveCodec, 2).unwrap();
+            for iteration in 0..4 {
+                recorder.iteration_complete(iteration, &population);
+            }
+            assert!(recorder.take_error().is_none());
+        }
+        {
+            // Reopening appends rather than overwriting.
+            let mut recorder = SnapshotRecorder::create(&path, PrimitiveCodec, 1).unwrap();
+            population.insert(12, Agent::from_genes(vec![5u8, 6]));
+            recorder.record(100, &population).unwrap();
+        }
+
+        let snapshots: Vec<Snapshot<u8>> = SnapshotReader::open(&path, PrimitiveCodec).unwrap().map(|snapshot| snapshot.unwrap()).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(vec![2, 4, 100], snapshots.iter().map(|snapshot| snapshot.iteration).collect::<Vec<u64>>());
+        assert_eq!(2, snapshots[0].agents.len());
+        assert_eq!(&vec![3u8, 4], snapshots[1].agents[&9].get_genes());
+        assert_eq!(3, snapshots[2].agents.len());
+    }
+
+    #[test]
+    fn corrupt_gene_length_is_an_error() {
+        let path = env::temp_dir().join(format!("xu_snapshot_corrupt_test_{}.bin", std::process::id()));
+        let mut bytes = MAGIC.to_vec();
+        for number in &[1, 1, 5, u64::MAX] {
+            bytes.extend_from_slice(&u64::to_le_bytes(*number));
+        }
+        bytes.extend_from_slice(&[1, 2]);
+        fs::write(&path, bytes).unwrap();
+
+        let snapshots: Vec<io::Result<Snapshot<u8>>> = SnapshotReader::open(&path, PrimitiveCodec).unwrap().collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(1, snapshots.len());
+        assert_eq!(io::ErrorKind::UnexpectedEof, snapshots[0].as_ref().err().unwrap().kind());
+    }
+}
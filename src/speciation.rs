@@ -0,0 +1,219 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Speciation: grouping agents with similar genes so that each group competes mostly within itself.
+//!
+//! Offspring are shared between species in proportion to their adjusted fitness, the mean score of
+//! their members, so a large species cannot crowd out a small one with a new idea. A species is
+//! stagnant when its best agent has been alive for the stagnation limit, meaning no member has
+//! beaten it in that many generations.
+
+use super::agent::Agent;
+use super::fitness::Score;
+use super::population::Population;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Measures how different two agents are.
+pub type DistanceFunction<Gene> = fn(&Agent<Gene>, &Agent<Gene>) -> usize;
+
+/// A group of similar agents, ordered from the highest score to the lowest.
+/// The first member is the representative new agents are compared with.
+pub struct Species <'a, Gene: 'a> {
+    members: Vec<(Score, &'a Agent<Gene>)>
+}
+
+impl <'a, Gene> Species <'a, Gene> {
+    pub fn get_members(&self) -> &Vec<(Score, &'a Agent<Gene>)> {
+        &self.members
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// The highest scored member.
+    pub fn best(&self) -> (Score, &'a Agent<Gene>) {
+        self.members[0]
+    }
+
+    /// The mean score of the members, which shares the species' fitness between them.
+    pub fn adjusted_fitness(&self) -> f64 {
+        self.members.iter().map(|(score, _)| *score as f64).sum::<f64>() / self.members.len() as f64
+    }
+}
+
+/// Settings for dividing a population into species.
+#[derive(Clone)]
+pub struct Speciation <Gene> {
+    threshold: usize,
+    stagnation_limit: Option<u64>,
+    distance: DistanceFunction<Gene>
+}
+
+impl <Gene> Speciation <Gene>
+where
+Gene: Hash
+{
+    /// threshold: the greatest distance from a species' representative at which an agent joins it.
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            stagnation_limit: None,
+            distance: gene_hash_distance
+        }
+    }
+}
+
+impl <Gene> Speciation <Gene> {
+    /// Sets the number of generations a species' best agent can go unbeaten before the species
+    /// is removed. The species holding the best agent in the population is never removed.
+    pub fn set_stagnation_limit(&mut self, generations: u64) {
+        self.stagnation_limit = Some(generations);
+    }
+
+    /// Sets how the distance between agents is measured. Defaults to gene_hash_distance.
+    pub fn set_distance(&mut self, distance: DistanceFunction<Gene>) {
+        self.distance = distance;
+    }
+
+    /// Divides the population into species, visiting agents from the highest score down so each
+    /// species is represented by its best member.
+    pub fn species<'a>(&self, population: &'a Population<Gene>) -> Vec<Species<'a, Gene>> {
+        let threshold = self.threshold;
+        let distance = self.distance;
+        let mut species: Vec<Species<Gene>> = Vec::new();
+        for (score, agent) in population.get_agents().iter().rev() {
+            match species.iter_mut().find(|species| distance(species.best().1, agent) <= threshold) {
+                Some(species) => species.members.push((*score, agent)),
+                None => species.push(Species { members: vec![(*score, agent)] })
+            }
+        }
+        species
+    }
+
+    /// Whether the species' best agent has been alive for the stagnation limit.
+    pub fn is_stagnant(&self, species: &Species<Gene>, population: &Population<Gene>) -> bool {
+        match self.stagnation_limit {
+            Some(limit) => population.get_age(species.best().1) >= limit,
+            None => false
+        }
+    }
+}
+
+/// Divides the total number of offspring between the species in proportion to their adjusted
+/// fitness, rounding so that the quotas add up to the total.
+pub fn offspring_quotas<Gene>(species: &[Species<Gene>], total: usize) -> Vec<usize> {
+    if species.is_empty() {
+        return Vec::new();
+    }
+
+    let fitness: Vec<f64> = species.iter().map(|species| species.adjusted_fitness()).collect();
+    let sum: f64 = fitness.iter().sum();
+    let shares: Vec<f64> = if sum > 0.0 {
+        fitness.iter().map(|fitness| fitness / sum * total as f64).collect()
+    } else {
+        vec![total as f64 / species.len() as f64; species.len()]
+    };
+
+    // Largest remainder rounding.
+    let mut quotas: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+    let mut remainders: Vec<(usize, f64)> = shares.iter().enumerate().map(|(index, share)| (index, share - share.floor())).collect();
+    remainders.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    let assigned: usize = quotas.iter().sum();
+    for (index, _) in remainders.into_iter().take(total - assigned) {
+        quotas[index] += 1;
+    }
+    quotas
+}
+
+/// The number of positions at which the genes differ, comparing genes by their hash, plus the
+/// difference in length. Used as the default distance since genes need only be hashable.
+pub fn gene_hash_distance<Gene: Hash>(a: &Agent<Gene>, b: &Agent<Gene>) -> usize {
+    let a_genes = a.get_genes();
+    let b_genes = b.get_genes();
+    let differing = a_genes.iter().zip(b_genes.iter())
+        .filter(|(gene_a, gene_b)| hash_gene(gene_a) != hash_gene(gene_b))
+        .count();
+    let length_difference = if a_genes.len() > b_genes.len() {
+        a_genes.len() - b_genes.len()
+    } else {
+        b_genes.len() - a_genes.len()
+    };
+
+    differing + length_difference
+}
+
+fn hash_gene<Gene: Hash>(gene: &Gene) -> u64 {
+    let mut s = DefaultHasher::new();
+    gene.hash(&mut s);
+    s.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn population() -> Population<u8> {
+        let mut population = Population::new_empty(false);
+        population.insert(10, Agent::from_genes(vec![0u8, 0, 0, 0]));
+        population.insert(20, Agent::from_genes(vec![0u8, 0, 0, 1]));
+        population.insert(30, Agent::from_genes(vec![9u8, 9, 9, 9]));
+        population.insert(60, Agent::from_genes(vec![9u8, 9, 9, 8]));
+        population.insert(40, Agent::from_genes(vec![5u8, 5, 0, 0]));
+        population
+    }
+
+    #[test]
+    fn species_group_similar_agents() {
+        let population = population();
+        let speciation = Speciation::new(1);
+        let species = speciation.species(&population);
+
+        let scores: Vec<Vec<Score>> = species.iter()
+            .map(|species| species.get_members().iter().map(|(score, _)| *score).collect())
+            .collect();
+        assert_eq!(vec![vec![60, 30], vec![40], vec![20, 10]], scores);
+        assert_eq!(45.0, species[0].adjusted_fitness());
+    }
+
+    #[test]
+    fn quotas_follow_adjusted_fitness() {
+        let population = population();
+        let species = Speciation::new(1).species(&population);
+
+        // Adjusted fitness 45, 40 and 15.
+        assert_eq!(vec![5, 4, 1], offspring_quotas(&species, 10));
+        assert_eq!(7, offspring_quotas(&species, 7).iter().sum::<usize>());
+        assert!(offspring_quotas::<u8>(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn stagnation_uses_age_of_best_member() {
+        let mut population = population();
+        let mut speciation = Speciation::new(1);
+        speciation.set_stagnation_limit(2);
+        population.advance_generation();
+        population.advance_generation();
+        population.insert(25, Agent::from_genes(vec![0u8, 0, 1, 1]));
+
+        let species = speciation.species(&population);
+        assert!(speciation.is_stagnant(&species[0], &population));
+        assert!(!speciation.is_stagnant(&species[2], &population));
+    }
+}
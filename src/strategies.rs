@@ -0,0 +1,162 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A (1+1) evolution strategy, for a quick baseline before configuring a full Manager.
+//!
+//! A single agent is mutated and the child replaces it whenever it scores at least as well. The
+//! number of genes changed by each mutation adapts by the one-fifth success rule: it grows while
+//! more than a fifth of recent children improve on their parent, and shrinks otherwise.
+
+use super::random;
+use super::agent::Agent;
+use super::fitness::{Score, ScoreError, ScoreProvider};
+use super::manager::Goal;
+use rand::distributions::{Distribution, Standard};
+use rand::Rng;
+use std::hash::Hash;
+
+/// The number of children between adaptations of the step size.
+const ADAPTATION_PERIOD: usize = 10;
+const STEP_FACTOR: f64 = 1.5;
+
+/// A (1+1) evolution strategy with an adaptive step size, the number of genes each mutation changes.
+#[derive(Clone, Debug)]
+pub struct OnePlusOne {
+    step: f64,
+    evaluations: usize
+}
+
+impl OnePlusOne {
+    /// evaluations: the most children to score in each solve.
+    pub fn new(evaluations: usize) -> Self {
+        Self {
+            step: 1.0,
+            evaluations
+        }
+    }
+
+    /// Sets the number of genes the first mutation changes. Defaults to one.
+    pub fn set_initial_step(&mut self, step: f64) {
+        self.step = step.max(1.0);
+    }
+
+    /// The current step size, which carries over between solves.
+    pub fn get_step(&self) -> f64 {
+        self.step
+    }
+
+    /// Evolves from a random agent with the number of genes. See solve_from.
+    pub fn solve<Gene, Data, SP>(&mut self, number_of_genes: usize, goal: Goal, data: &Data, score_provider: &mut SP) -> Result<(Score, Agent<Gene>), ScoreError>
+    where
+    Standard: Distribution<Gene>,
+    Gene: Clone + Hash,
+    SP: ScoreProvider<Gene, Data>
+    {
+        self.solve_from(Agent::with_genes(number_of_genes), goal, data, score_provider)
+    }
+
+    /// Evolves from the agent until the goal is met by its true score or the evaluations run out,
+    /// returning the best agent and its true score. Children that cannot be scored are discarded.
+    /// Returns an error if the starting agent cannot be scored.
+    pub fn solve_from<Gene, Data, SP>(&mut self, start: Agent<Gene>, goal: Goal, data: &Data, score_provider: &mut SP) -> Result<(Score, Agent<Gene>), ScoreError>
+    where
+    Standard: Distribution<Gene>,
+    Gene: Clone + Hash,
+    SP: ScoreProvider<Gene, Data>
+    {
+        let mut best_score = score_provider.true_score(&start, data)?;
+        let mut best = start;
+        let mut successes = 0;
+
+        for evaluation in 1..=self.evaluations {
+            if goal.is_met(best_score) {
+                break;
+            }
+
+            let child = self.mutate(&best);
+            if let Ok(score) = score_provider.true_score(&child, data) {
+                if score > best_score {
+                    successes += 1;
+                }
+                if score >= best_score {
+                    best_score = score;
+                    best = child;
+                }
+            }
+
+            if evaluation % ADAPTATION_PERIOD == 0 {
+                self.adapt(successes, best.get_genes().len());
+                successes = 0;
+            }
+        }
+
+        Ok((best_score, best))
+    }
+
+    fn mutate<Gene>(&self, agent: &Agent<Gene>) -> Agent<Gene>
+    where
+    Standard: Distribution<Gene>,
+    Gene: Clone + Hash
+    {
+        let mut genes = agent.get_genes().clone();
+        if genes.is_empty() {
+            return agent.clone();
+        }
+        let mut rng = random::rng();
+        for _ in 0..(self.step.round() as usize).max(1) {
+            let position = rng.gen_range(0, genes.len());
+            genes[position] = rng.gen();
+        }
+        Agent::from_genes(genes)
+    }
+
+    /// Applies the one-fifth success rule, keeping the step between one gene and the whole genome.
+    fn adapt(&mut self, successes: usize, number_of_genes: usize) {
+        if successes * 5 > ADAPTATION_PERIOD {
+            self.step *= STEP_FACTOR;
+        } else if successes * 5 < ADAPTATION_PERIOD {
+            self.step /= STEP_FACTOR;
+        }
+        self.step = self.step.min(number_of_genes.max(1) as f64).max(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::GeneralScoreProvider;
+
+    fn count_true(agent: &Agent<bool>, _data: &()) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().filter(|gene| **gene).count() as Score)
+    }
+
+    #[test]
+    fn solves_one_max() {
+        let mut score_provider = GeneralScoreProvider::new(count_true, 0);
+        let mut strategy = OnePlusOne::new(5000);
+        let (score, agent) = strategy.solve(30, Goal::AtLeast(30), &(), &mut score_provider).unwrap();
+        assert_eq!(30, score);
+        assert!(agent.get_genes().iter().all(|gene| *gene));
+    }
+
+    #[test]
+    fn step_adapts_to_success_rate() {
+        let mut strategy = OnePlusOne::new(0);
+        strategy.adapt(5, 10);
+        assert_eq!(1.5, strategy.get_step());
+        strategy.adapt(0, 10);
+        strategy.adapt(0, 10);
+        assert_eq!(1.0, strategy.get_step());
+    }
+}
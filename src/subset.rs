@@ -0,0 +1,200 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Subset selection with a weight limit, as in the knapsack problem.
+//!
+//! A genome is an `Agent<bool>` with one gene per item, true when the item is chosen. Since bool
+//! genes can be generated randomly, these genomes also work with the general operations, and
+//! `knapsack_fitness` scores any selection by the value of its repaired form.
+
+use super::random;
+use super::agent::Agent;
+use super::fitness::{Score, ScoreError, ScoreProvider};
+use super::population::Population;
+use rand::Rng;
+
+/// An item that can be chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Item {
+    pub weight: u64,
+    pub value: u64
+}
+
+/// The items to choose from and the total weight allowed, used as the Data of a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Knapsack {
+    pub items: Vec<Item>,
+    pub capacity: u64
+}
+
+impl Knapsack {
+    pub fn new(items: Vec<Item>, capacity: u64) -> Self {
+        Self { items, capacity }
+    }
+
+    /// The total weight of the chosen items. Genes beyond the number of items are ignored.
+    pub fn weight(&self, selection: &[bool]) -> u64 {
+        self.chosen(selection).map(|item| item.weight).sum()
+    }
+
+    /// The total value of the chosen items.
+    pub fn value(&self, selection: &[bool]) -> u64 {
+        self.chosen(selection).map(|item| item.value).sum()
+    }
+
+    pub fn is_feasible(&self, selection: &[bool]) -> bool {
+        self.weight(selection) <= self.capacity
+    }
+
+    /// Returns a selection within the capacity, made by dropping the chosen items with the lowest
+    /// value for their weight until the rest fit.
+    pub fn repair(&self, agent: &Agent<bool>) -> Agent<bool> {
+        let mut genes = agent.get_genes().clone();
+        let mut weight = self.weight(&genes);
+        if weight <= self.capacity {
+            return agent.clone();
+        }
+
+        let mut chosen: Vec<usize> = (0..genes.len().min(self.items.len())).filter(|index| genes[*index]).collect();
+        chosen.sort_by(|a, b| self.density(*a).partial_cmp(&self.density(*b)).unwrap());
+        for index in chosen {
+            if weight <= self.capacity {
+                break;
+            }
+            genes[index] = false;
+            weight -= self.items[index].weight;
+        }
+        Agent::from_genes(genes)
+    }
+
+    /// Returns the selection with unchosen items added, highest value for weight first, while
+    /// they fit.
+    pub fn fill(&self, agent: &Agent<bool>) -> Agent<bool> {
+        let mut genes = agent.get_genes().clone();
+        let mut weight = self.weight(&genes);
+        let mut unchosen: Vec<usize> = (0..genes.len().min(self.items.len())).filter(|index| !genes[*index]).collect();
+        unchosen.sort_by(|a, b| self.density(*b).partial_cmp(&self.density(*a)).unwrap());
+        for index in unchosen {
+            if weight + self.items[index].weight <= self.capacity {
+                genes[index] = true;
+                weight += self.items[index].weight;
+            }
+        }
+        Agent::from_genes(genes)
+    }
+
+    /// Adds offspring to the population by constraint-preserving crossover of agents from the
+    /// higher scored half, followed by bit flips at the given rate and repair.
+    pub fn reproduce<SP>(&self, mut population: Population<bool>, offspring: usize, flip_rate: f64, score_provider: &mut SP) -> Population<bool>
+    where
+    SP: ScoreProvider<bool, Knapsack>
+    {
+        population.breed_from_upper_half(offspring, self, score_provider, |one, two| {
+            self.repair(&flip_bits(&constrained_crossover(one, two, self), flip_rate))
+        });
+        population
+    }
+
+    fn chosen<'a>(&'a self, selection: &'a [bool]) -> impl Iterator<Item = &'a Item> + 'a {
+        self.items.iter().zip(selection.iter()).filter(|(_, chosen)| **chosen).map(|(item, _)| item)
+    }
+
+    fn density(&self, index: usize) -> f64 {
+        let item = self.items[index];
+        item.value as f64 / item.weight.max(1) as f64
+    }
+}
+
+/// A fitness function scoring the value of the selection once repaired to fit the capacity, so that
+/// over-weight selections still guide the search.
+pub fn knapsack_fitness(agent: &Agent<bool>, knapsack: &Knapsack) -> Result<Score, ScoreError> {
+    Ok(knapsack.value(knapsack.repair(agent).get_genes()))
+}
+
+/// Returns a copy of the selection with each gene flipped with the given probability.
+pub fn flip_bits(agent: &Agent<bool>, rate: f64) -> Agent<bool> {
+    let mut rng = random::rng();
+    let genes = agent.get_genes().iter().map(|gene| if rng.gen::<f64>() < rate { !gene } else { *gene }).collect();
+    Agent::from_genes(genes)
+}
+
+/// Uniform crossover that keeps items both parents chose, takes each item only one parent chose at
+/// random, and repairs the child to fit the capacity. The child has the length of the first parent.
+pub fn constrained_crossover(one: &Agent<bool>, two: &Agent<bool>, knapsack: &Knapsack) -> Agent<bool> {
+    let mut rng = random::rng();
+    let genes = one.get_genes().iter().enumerate()
+        .map(|(index, gene)| match two.get_genes().get(index) {
+            Some(other) if other != gene => rng.gen(),
+            _ => *gene
+        })
+        .collect();
+    knapsack.repair(&Agent::from_genes(genes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::GeneralScoreProvider;
+
+    fn knapsack() -> Knapsack {
+        Knapsack::new(vec![
+            Item { weight: 10, value: 60 },
+            Item { weight: 20, value: 100 },
+            Item { weight: 30, value: 120 },
+            Item { weight: 5, value: 5 }
+        ], 50)
+    }
+
+    #[test]
+    fn repair_drops_lowest_density_items() {
+        let knapsack = knapsack();
+        let all = Agent::from_genes(vec![true; 4]);
+        assert!(!knapsack.is_feasible(all.get_genes()));
+
+        // Densities are 6, 5, 4 and 1, so the last and then the third item go.
+        let repaired = knapsack.repair(&all);
+        assert_eq!(&vec![true, true, false, false], repaired.get_genes());
+        assert_eq!(160, knapsack_fitness(&all, &knapsack).unwrap());
+
+        let filled = knapsack.fill(&Agent::from_genes(vec![false, false, true, false]));
+        assert_eq!(&vec![true, false, true, true], filled.get_genes());
+    }
+
+    #[test]
+    fn crossover_keeps_shared_items_and_fits() {
+        let knapsack = knapsack();
+        let one = Agent::from_genes(vec![true, true, false, false]);
+        let two = Agent::from_genes(vec![true, false, true, false]);
+        for _ in 0..20 {
+            let child = constrained_crossover(&one, &two, &knapsack);
+            assert!(child.get_genes()[0] && !child.get_genes()[3]);
+            assert!(knapsack.is_feasible(child.get_genes()));
+        }
+
+        assert_eq!(&vec![false, false], flip_bits(&Agent::from_genes(vec![true, true]), 1.0).get_genes());
+    }
+
+    #[test]
+    fn reproduce_finds_optimum() {
+        let knapsack = knapsack();
+        let mut score_provider = GeneralScoreProvider::new(knapsack_fitness, 0);
+        let mut population = Population::new(20, 4, true, &knapsack, &mut score_provider);
+        for _ in 0..20 {
+            population = knapsack.reproduce(population, 20, 0.2, &mut score_provider);
+        }
+
+        let (best, _) = population.true_best(&mut score_provider, &knapsack).unwrap();
+        assert_eq!(220, best);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running a Manager on a single thread, for unit testing configurations and fitness functions.
+//!
+//! A Manager normally runs child populations on threads and merges whichever have finished at the
+//! end of each cycle, so when their agents arrive depends on the machine. With virtual threads,
+//! each child population is run on the calling thread when it would have been spawned, and its
+//! agents arrive after a number of cycles of virtual time given by a `VirtualSchedule`. The
+//! interleaving is then the same on every run. Random choices made by the operations still differ
+//! between runs.
+
+use super::agent::{Agent, GeneBounds};
+use super::fitness::{FitnessFunction, GeneralScoreProvider, Score};
+use super::manager::{create_manager, Manager};
+use rand::distributions::{Distribution, Standard};
+use std::collections::BTreeMap;
+
+/// The number of cycles each virtual child thread takes, assigned to the children in the order
+/// they start, repeating.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VirtualSchedule {
+    durations: Vec<usize>,
+    next: usize
+}
+
+impl VirtualSchedule {
+    /// durations: cycles taken by each child in turn. A duration of one means the child's agents
+    /// arrive at the end of the cycle it started in. An empty list is treated as all ones.
+    pub fn new(durations: Vec<usize>) -> Self {
+        Self { durations, next: 0 }
+    }
+
+    /// Every child finishes in the cycle it started, as if threads were instant.
+    pub fn immediate() -> Self {
+        Self::new(vec![1])
+    }
+
+    fn next_duration(&mut self) -> usize {
+        if self.durations.is_empty() {
+            return 1;
+        }
+        let duration = self.durations[self.next % self.durations.len()];
+        self.next += 1;
+        duration.max(1)
+    }
+}
+
+/// The virtual child threads of a Manager and the agents they will deliver.
+pub(crate) struct Simulation <Gene> {
+    schedule: VirtualSchedule,
+    now: usize,
+    pending: Vec<(usize, BTreeMap<Score, Agent<Gene>>)>
+}
+
+impl <Gene> Simulation <Gene> {
+    pub(crate) fn new(schedule: VirtualSchedule) -> Self {
+        Self { schedule, now: 0, pending: Vec::new() }
+    }
+
+    /// Holds a child's agents until its duration has passed.
+    pub(crate) fn start(&mut self, agents: BTreeMap<Score, Agent<Gene>>) {
+        let finish = self.now + self.schedule.next_duration();
+        self.pending.push((finish, agents));
+    }
+
+    /// Moves virtual time on by a cycle, returning the agents of children that have finished, in
+    /// the order the children started.
+    pub(crate) fn advance(&mut self) -> Vec<BTreeMap<Score, Agent<Gene>>> {
+        self.now += 1;
+        let now = self.now;
+        let (finished, pending): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|(finish, _)| *finish <= now);
+        self.pending = pending;
+        finished.into_iter().map(|(_, agents)| agents).collect()
+    }
+
+    /// The agents of every child still running, as if all had finished.
+    pub(crate) fn finish_all(&mut self) -> Vec<BTreeMap<Score, Agent<Gene>>> {
+        self.pending.drain(..).map(|(_, agents)| agents).collect()
+    }
+}
+
+/// Creates a Manager with virtual threads and small defaults suited to tests: a population of 20
+/// agents, two child populations and 10 iterations per cycle.
+pub fn test_manager<Gene, Data>(
+    fitness_function: FitnessFunction<Gene, Data>,
+    data: Data,
+    schedule: VirtualSchedule
+) -> Manager<Gene, Data, GeneralScoreProvider<Gene, Data>>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    let mut manager = create_manager(fitness_function, data);
+    manager.set_initial_population_size(20);
+    manager.set_max_child_threads(2);
+    manager.set_iterations_per_cycle(10);
+    manager.set_virtual_threads(schedule);
+    manager
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fitness::ScoreError;
+    use super::super::manager::Goal;
+
+    #[test]
+    fn schedule_delivers_children_in_virtual_time() {
+        let mut simulation = Simulation::new(VirtualSchedule::new(vec![1, 3]));
+        let mut agents = BTreeMap::new();
+        agents.insert(1, Agent::from_genes(vec![1u8]));
+        simulation.start(agents.clone());
+        simulation.start(agents);
+
+        assert_eq!(1, simulation.advance().len());
+        assert!(simulation.advance().is_empty());
+        assert_eq!(1, simulation.advance().len());
+        assert!(simulation.finish_all().is_empty());
+    }
+
+    fn count_ones(agent: &Agent<bool>, _data: &()) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes().iter().filter(|gene| **gene).count() as Score)
+    }
+
+    #[test]
+    fn manager_runs_on_one_thread() {
+        let mut manager = test_manager(count_ones, (), VirtualSchedule::new(vec![2]));
+        manager.set_number_of_genes(8, true);
+        manager.set_max_cycles(4);
+        manager.run(Goal::Never);
+        assert_eq!(40, manager.get_population().get_generation());
+        assert!(manager.get_true_best().is_some());
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Separate tiers for exploration and exploitation.
+//!
+//! The main population is left to explore with broad operations, while a small elite tier refines
+//! the best agents found so far with its own operations. Agents flow between the tiers in
+//! controlled numbers, so the elite tier cannot take over the main population on deceptive
+//! problems.
+
+use super::evolution::run_iterations;
+use super::fitness::{Score, ScoreProvider};
+use super::operations::Operation;
+use super::population::Population;
+use rand::distributions::{Distribution, Standard};
+use super::agent::{GeneBounds, GenomeHasher};
+
+/// An elite tier of unique agents, refined separately from the main population.
+/// Set it on a Manager with Manager::set_exploit_tier.
+#[derive(Clone)]
+pub struct ExploitTier <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    size: usize,
+    promotions: usize,
+    returns: usize,
+    genome_hasher: GenomeHasher,
+    operations: Vec<Operation<Gene, Data>>,
+    population: Population<Gene>
+}
+
+impl <Gene, Data> ExploitTier <Gene, Data>
+where
+Standard: Distribution<Gene>,
+Gene: GeneBounds,
+Data: Clone + Send + 'static
+{
+    /// size: the most agents the tier keeps. operations: run on the tier each cycle, typically
+    /// mutation and crossover of the highest scored agents.
+    pub fn new(size: usize, operations: Vec<Operation<Gene, Data>>) -> Self {
+        Self {
+            size: size.max(1),
+            promotions: size.div_ceil(2),
+            returns: 1,
+            genome_hasher: GenomeHasher::Std,
+            operations,
+            population: Population::new_empty(true)
+        }
+    }
+
+    /// Sets how many of the highest scored agents of the main population are promoted into the
+    /// tier each cycle, and how many of the tier's best agents are returned to the main
+    /// population afterwards. Defaults to half the tier's size and one.
+    pub fn set_flow(&mut self, promotions: usize, returns: usize) {
+        self.promotions = promotions;
+        self.returns = returns;
+    }
+
+    pub fn get_population(&self) -> &Population<Gene> {
+        &self.population
+    }
+
+    /// Sets how the tier's agents hash their genes, which should match the main population so
+    /// that promoted and returned agents are recognised as duplicates. A Manager sets it from
+    /// Manager::set_genome_hasher. Defaults to GenomeHasher::Std.
+    pub fn set_genome_hasher(&mut self, hasher: GenomeHasher) {
+        self.genome_hasher = hasher;
+        self.population.set_genome_hasher(hasher);
+    }
+
+    /// Empties the tier, ready for a new run.
+    pub fn clear(&mut self) {
+        self.population = Population::new_empty(true);
+        self.population.set_genome_hasher(self.genome_hasher);
+    }
+
+    /// Promotes agents from the main population, runs the tier's operations for the iterations,
+    /// cuts the tier back to its size, and returns its best agents to the main population.
+    pub fn run_cycle<SP>(&mut self, main: &mut Population<Gene>, iterations: usize, data: &Data, score_provider: &mut SP)
+    where
+    SP: Clone + ScoreProvider<Gene, Data>
+    {
+        for (score, agent) in main.get_agents().iter().rev().take(self.promotions) {
+            self.population.insert(*score, agent.clone());
+        }
+
+        let population = std::mem::replace(&mut self.population, Population::new_empty(true));
+        let mut population = run_iterations(population, iterations, data, &self.operations, score_provider);
+        let scores = population.get_scores();
+        if scores.len() > self.size {
+            population.cull_all_below(scores[scores.len() - self.size]);
+        }
+        self.population = population;
+
+        let returning: Vec<(Score, &_)> = self.population.get_agents().iter().rev().take(self.returns).map(|(score, agent)| (*score, agent)).collect();
+        for (score, agent) in returning {
+            let present = match main.get(score) {
+                Some(existing) => existing.has_same_genes(agent),
+                None => false
+            };
+            if !present {
+                main.insert_ranked(score, agent.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::agent::Agent;
+    use super::super::fitness::{GeneralScoreProvider, ScoreError};
+    use super::super::operations::{OperationType, Selection, SelectionType};
+    use super::super::random;
+
+    fn get_score_index(agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        Ok(agent.get_genes()[0] as Score)
+    }
+
+    fn get_constant_score(_agent: &Agent<u8>, _data: &u8) -> Result<Score, ScoreError> {
+        Ok(10)
+    }
+
+    #[test]
+    fn returned_agents_do_not_replace_main_agents() {
+        // Every agent bred in the tier scores the same as the best agent of the main population.
+        let mut score_provider = GeneralScoreProvider::new(get_constant_score, 0);
+        let mut main = Population::new_empty(false);
+        for gene in 1..=10u8 {
+            main.insert(gene as Score, Agent::from_genes(vec![gene, 0]));
+        }
+
+        let mut tier = ExploitTier::new(4, vec![
+            Operation::new(OperationType::Mutate, Selection::with_values(SelectionType::HighestScore, 1.0, 0))
+        ]);
+        tier.set_flow(3, 2);
+        random::with_seed(7, || tier.run_cycle(&mut main, 5, &0, &mut score_provider));
+
+        for gene in 1..=10u8 {
+            assert!(main.get_agents().values().any(|agent| agent.get_genes() == &vec![gene, 0]), "agent {} was lost", gene);
+        }
+        let returned = tier.get_population().get_agents().values().rev().take(2);
+        assert!(returned.clone().any(|agent| agent.get_genes() != &vec![10, 0]));
+        assert!(returned.into_iter().all(|agent| main.get_agents().values().any(|existing| existing.has_same_genes(agent))));
+    }
+
+    #[test]
+    fn tier_hashes_with_the_main_populations_hasher() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 0);
+        let mut main = Population::new_empty(true);
+        main.set_genome_hasher(GenomeHasher::Fx);
+        for gene in 1..=10u8 {
+            main.insert(gene as Score, Agent::from_genes(vec![gene, 0]));
+        }
+
+        let mut tier = ExploitTier::new(4, vec![
+            Operation::new(OperationType::Mutate, Selection::with_values(SelectionType::HighestScore, 1.0, 0)),
+            Operation::new(OperationType::Crossover, Selection::with_values(SelectionType::HighestScore, 1.0, 0))
+        ]);
+        tier.set_genome_hasher(GenomeHasher::Fx);
+        random::with_seed(2, || tier.run_cycle(&mut main, 5, &0, &mut score_provider));
+
+        assert!(tier.get_population().get_agents().values().all(|agent| agent.get_hasher() == GenomeHasher::Fx));
+        assert_eq!(Ok(()), tier.get_population().validate());
+        assert_eq!(Ok(()), main.validate());
+    }
+
+    #[test]
+    fn cycle_moves_agents_between_tiers() {
+        let mut score_provider = GeneralScoreProvider::new(get_score_index, 0);
+        let mut main = Population::new_empty(false);
+        for gene in 1..=10u8 {
+            main.insert(gene as Score, Agent::from_genes(vec![gene, 0]));
+        }
+        let originals: Vec<Agent<u8>> = main.get_agents().values().cloned().collect();
+
+        let mut tier = ExploitTier::new(4, vec![
+            Operation::new(OperationType::Mutate, Selection::with_values(SelectionType::HighestScore, 1.0, 0))
+        ]);
+        tier.set_flow(3, 2);
+        tier.run_cycle(&mut main, 5, &0, &mut score_provider);
+
+        let elite = tier.get_population();
+        assert!(elite.len() <= 4);
+        assert!(*elite.get_scores().last().unwrap() >= 10);
+
+        // The tier's two best agents are in the main population, which lost nothing.
+        let contains = |agent: &Agent<u8>| main.get_agents().values().any(|existing| existing.has_same_genes(agent));
+        assert!(originals.iter().all(&contains));
+        assert!(elite.get_agents().values().rev().take(2).all(&contains));
+    }
+}
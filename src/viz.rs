@@ -0,0 +1,114 @@
+// Copyright 2019 Brendan Cox
+// 
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Charts of a run, drawn as SVG files with plotters. Enabled with the `viz` feature.
+
+use super::history::RunHistory;
+use plotters::prelude::*;
+use std::error::Error;
+use std::path::Path;
+
+const SIZE: (u32, u32) = (800, 500);
+
+/// Draws the best, mean and worst scores for each iteration.
+pub fn render_convergence<P: AsRef<Path>>(history: &RunHistory, path: P) -> Result<(), Box<dyn Error>> {
+    let stats = history.get_stats();
+    let last_iteration = stats.last().map(|stats| stats.iteration).unwrap_or(1).max(1);
+    let highest = stats.iter().map(|stats| stats.best).max().unwrap_or(1).max(1);
+
+    let root = SVGBackend::new(path.as_ref(), SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Convergence", ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..last_iteration, 0..highest)?;
+    chart.configure_mesh().x_desc("Iteration").y_desc("Score").draw()?;
+
+    chart.draw_series(LineSeries::new(stats.iter().map(|stats| (stats.iteration, stats.best)), &RED))?
+        .label("Best")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    chart.draw_series(LineSeries::new(stats.iter().map(|stats| (stats.iteration, stats.mean as u64)), &BLUE))?
+        .label("Mean")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    chart.draw_series(LineSeries::new(stats.iter().map(|stats| (stats.iteration, stats.worst)), &BLACK))?
+        .label("Worst")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+    chart.configure_series_labels().background_style(WHITE).border_style(BLACK).draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Draws the diversity (proportion of distinct genomes) and population size for each iteration.
+pub fn render_diversity<P: AsRef<Path>>(history: &RunHistory, path: P) -> Result<(), Box<dyn Error>> {
+    let stats = history.get_stats();
+    let last_iteration = stats.last().map(|stats| stats.iteration).unwrap_or(1).max(1);
+    let largest = stats.iter().map(|stats| stats.population_size).max().unwrap_or(1).max(1);
+
+    let root = SVGBackend::new(path.as_ref(), SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (upper, lower) = root.split_vertically(SIZE.1 / 2);
+
+    let mut diversity = ChartBuilder::on(&upper)
+        .caption("Diversity", ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..last_iteration, 0.0..1.0)?;
+    diversity.configure_mesh().y_desc("Distinct genomes").draw()?;
+    diversity.draw_series(LineSeries::new(stats.iter().map(|stats| (stats.iteration, stats.diversity)), &GREEN))?;
+
+    let mut size = ChartBuilder::on(&lower)
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..last_iteration, 0..largest)?;
+    size.configure_mesh().x_desc("Iteration").y_desc("Population").draw()?;
+    size.draw_series(LineSeries::new(stats.iter().map(|stats| (stats.iteration, stats.population_size)), &BLUE))?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::agent::Agent;
+    use super::super::evolution::Observer;
+    use super::super::population::Population;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn renders_svg_files() {
+        let mut history = RunHistory::new();
+        let mut population = Population::new_empty(false);
+        for score in 1..5 {
+            population.insert(score * 10, Agent::from_genes(vec![score as u8]));
+            history.iteration_complete(0, &population);
+        }
+
+        let convergence = env::temp_dir().join(format!("xu_convergence_{}.svg", std::process::id()));
+        let diversity = env::temp_dir().join(format!("xu_diversity_{}.svg", std::process::id()));
+        render_convergence(&history, &convergence).unwrap();
+        render_diversity(&history, &diversity).unwrap();
+
+        assert!(fs::read_to_string(&convergence).unwrap().contains("Convergence"));
+        assert!(fs::read_to_string(&diversity).unwrap().contains("Diversity"));
+        fs::remove_file(convergence).unwrap();
+        fs::remove_file(diversity).unwrap();
+    }
+}